@@ -0,0 +1,197 @@
+//! Reuse of earlier AI evaluations of the same answer, backed by the
+//! `evaluation_cache` table.
+//!
+//! An entry is keyed by the answer as graded (question, expected answer, user answer
+//! and grading context, with whitespace normalized), the model and the version of the
+//! evaluation template, so that changing any of them grades the answer afresh.
+
+use super::evaluator::GradingContext;
+use crate::db::evaluation_cache;
+use crate::logger;
+use rusqlite::Connection;
+use sha1::{Digest, Sha1};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Days a cached evaluation is reused unless `ai.cache_ttl_days` says otherwise
+pub const DEFAULT_TTL_DAYS: u64 = 30;
+
+/// A shared connection to the cache. The worker's tasks take turns on it, each lookup
+/// and store running on the blocking thread pool so that the database never stalls the
+/// async tasks.
+#[derive(Debug, Clone)]
+pub struct EvaluationCache {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl EvaluationCache {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    /// The cache in the app database, if it can be opened.
+    pub fn open() -> Option<Self> {
+        match crate::db::init_db() {
+            Ok(conn) => Some(Self::new(conn)),
+            Err(e) => {
+                logger::log(&format!("Evaluation cache unavailable: {}", e));
+                None
+            }
+        }
+    }
+
+    /// The stored reply for `key`, if there is one younger than `ttl`.
+    pub async fn get(&self, key: &str, ttl: Duration) -> Option<String> {
+        let key = key.to_string();
+        self.with_conn(move |conn| evaluation_cache::lookup(conn, &key, ttl.as_secs()))
+            .await
+            .unwrap_or_else(|e| {
+                logger::log(&format!("Evaluation cache lookup failed: {}", e));
+                None
+            })
+    }
+
+    pub async fn put(&self, key: &str, model: &str, response: &str) {
+        let (key, model, response) = (key.to_string(), model.to_string(), response.to_string());
+        let stored = self
+            .with_conn(move |conn| evaluation_cache::store(conn, &key, &model, &response))
+            .await;
+        if let Err(e) = stored {
+            logger::log(&format!("Evaluation cache store failed: {}", e));
+        }
+    }
+
+    /// Run `f` on the cache's connection on the blocking thread pool.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, String> {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&conn).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+/// The cache key of an evaluation request.
+pub fn cache_key(
+    question: &str,
+    correct_answer: &str,
+    user_answer: &str,
+    grading: &GradingContext,
+    model: &str,
+    template_version: &str,
+) -> String {
+    let mut hasher = Sha1::new();
+    let accepted_answers = grading.accepted_answers.join("\n");
+    for part in [
+        question,
+        correct_answer,
+        user_answer,
+        &accepted_answers,
+        grading.notes.as_deref().unwrap_or_default(),
+        grading.rubric.as_deref().unwrap_or_default(),
+    ] {
+        hasher.update(normalize(part).as_bytes());
+        hasher.update([0]);
+    }
+    // Keys of cards without source passages stay what they were before passages existed
+    for passage in &grading.passages {
+        hasher.update(normalize(&passage.text).as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(template_version.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Collapse runs of whitespace and trim, so reformatting an answer keeps its key.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    #[test]
+    fn test_cache_key_normalizes_whitespace_only() {
+        let grading = GradingContext::default();
+        let key = |user_answer: &str, model: &str, version: &str| {
+            cache_key(
+                "What is AODV?",
+                "A protocol",
+                user_answer,
+                &grading,
+                model,
+                version,
+            )
+        };
+        let base = key("a routing protocol", "m", "v1");
+        assert_eq!(base.len(), 40);
+        assert_eq!(key("  a routing\n protocol ", "m", "v1"), base);
+        assert_ne!(key("A routing protocol", "m", "v1"), base);
+        assert_ne!(key("a routing protocol", "other", "v1"), base);
+        assert_ne!(key("a routing protocol", "m", "v2"), base);
+
+        let strict = GradingContext {
+            rubric: Some("Be strict".to_string()),
+            ..Default::default()
+        };
+        assert_ne!(
+            cache_key(
+                "What is AODV?",
+                "A protocol",
+                "a routing protocol",
+                &strict,
+                "m",
+                "v1"
+            ),
+            base
+        );
+        let grounded = GradingContext {
+            passages: vec![crate::ai::SourcePassage {
+                text: "AODV is reactive.".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_ne!(
+            cache_key(
+                "What is AODV?",
+                "A protocol",
+                "a routing protocol",
+                &grounded,
+                "m",
+                "v1"
+            ),
+            base
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_respects_ttl() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        let cache = EvaluationCache::new(conn);
+
+        assert_eq!(cache.get("k", Duration::from_secs(60)).await, None);
+        cache.put("k", "m", "reply").await;
+        assert_eq!(
+            cache.get("k", Duration::from_secs(60)).await.as_deref(),
+            Some("reply")
+        );
+        assert_eq!(cache.get("k", Duration::ZERO).await, None);
+    }
+}
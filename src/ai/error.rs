@@ -0,0 +1,198 @@
+//! Classified AI failures: what went wrong and whether trying again can help.
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AiError {
+    /// The provider is throttling requests (HTTP 429)
+    RateLimited(String),
+    /// The API key is missing, invalid or not allowed to use the model (HTTP 401/403)
+    Auth(String),
+    /// The server could not be reached or the connection broke
+    Network(String),
+    /// No reply, or no further streamed text, within the time limit
+    Timeout(Duration),
+    /// The provider or the HTTP client gave up waiting, under a limit we did not set
+    /// (HTTP 408 or a client timeout)
+    ProviderTimeout,
+    /// The reply could not be understood
+    InvalidResponse(String),
+    /// Any other error reported by the provider; `status` is `None` for errors sent
+    /// in the middle of a streamed reply
+    Api {
+        status: Option<u16>,
+        message: String,
+    },
+    /// The backend could not be set up from the configuration
+    Config(String),
+    /// This month's calls have cost the `ai.monthly_budget` (in USD)
+    BudgetExceeded { spent: f64, budget: f64 },
+}
+
+impl AiError {
+    /// Classify an HTTP error status and its body.
+    pub fn from_status(status: u16, body: &str) -> Self {
+        let message = body.trim().to_string();
+        match status {
+            401 | 403 => AiError::Auth(message),
+            408 => AiError::ProviderTimeout,
+            429 => AiError::RateLimited(message),
+            _ => AiError::Api {
+                status: Some(status),
+                message,
+            },
+        }
+    }
+
+    /// Whether the same request may well succeed if sent again later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AiError::RateLimited(_)
+            | AiError::Network(_)
+            | AiError::Timeout(_)
+            | AiError::ProviderTimeout => true,
+            AiError::Api { status, .. } => status.is_none_or(|s| s >= 500),
+            AiError::Auth(_)
+            | AiError::InvalidResponse(_)
+            | AiError::Config(_)
+            | AiError::BudgetExceeded { .. } => false,
+        }
+    }
+
+    /// Whether every request will fail until the configuration is fixed, such as an
+    /// invalid key or an unknown model. Other client errors (a prompt too long for the
+    /// model, a rejected parameter) only concern the request that caused them.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            AiError::Auth(_) | AiError::Config(_) | AiError::BudgetExceeded { .. } => true,
+            AiError::Api {
+                status: Some(status),
+                ..
+            } => matches!(status, 401 | 403 | 404),
+            _ => false,
+        }
+    }
+
+    /// Whether another model on the same backend might succeed: not when the key,
+    /// the configuration or the budget is at fault, as every model shares those.
+    pub fn may_fall_back(&self) -> bool {
+        !matches!(
+            self,
+            AiError::Auth(_) | AiError::Config(_) | AiError::BudgetExceeded { .. }
+        )
+    }
+}
+
+impl fmt::Display for AiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiError::RateLimited(message) => write!(f, "rate limited by the provider: {}", message),
+            AiError::Auth(message) => write!(
+                f,
+                "the provider rejected the API key ({}) - check the key in your config",
+                message
+            ),
+            AiError::Network(message) => write!(f, "{}", message),
+            AiError::Timeout(limit) => write!(f, "timed out after {} seconds", limit.as_secs()),
+            AiError::ProviderTimeout => write!(f, "the provider timed out"),
+            AiError::InvalidResponse(message) => write!(f, "{}", message),
+            AiError::Api {
+                status: Some(status),
+                message,
+            } => write!(f, "API error ({}): {}", status, message),
+            AiError::Api {
+                status: None,
+                message,
+            } => write!(f, "API error: {}", message),
+            AiError::Config(message) => write!(f, "{}", message),
+            AiError::BudgetExceeded { spent, budget } => write!(
+                f,
+                "monthly budget of ${:.2} used up (${:.2} spent this month) - raise ai.monthly_budget to continue",
+                budget, spent
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AiError {}
+
+impl From<reqwest::Error> for AiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            AiError::ProviderTimeout
+        } else if error.is_decode() {
+            AiError::InvalidResponse(error.to_string())
+        } else {
+            AiError::Network(error.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_status() {
+        assert!(matches!(
+            AiError::from_status(401, "bad key"),
+            AiError::Auth(_)
+        ));
+        assert!(matches!(
+            AiError::from_status(429, "slow down"),
+            AiError::RateLimited(_)
+        ));
+
+        let overloaded = AiError::from_status(503, " overloaded\n");
+        assert_eq!(overloaded.to_string(), "API error (503): overloaded");
+        assert!(overloaded.is_transient());
+        assert!(!overloaded.is_permanent());
+
+        let unknown_model = AiError::from_status(404, "no such model");
+        assert!(!unknown_model.is_transient());
+        assert!(unknown_model.is_permanent());
+
+        // Only this request is at fault, not the configuration
+        let too_long = AiError::from_status(400, "context length exceeded");
+        assert!(!too_long.is_transient());
+        assert!(!too_long.is_permanent());
+        assert!(!AiError::from_status(422, "bad parameter").is_permanent());
+
+        let provider_timeout = AiError::from_status(408, "");
+        assert_eq!(provider_timeout, AiError::ProviderTimeout);
+        assert!(provider_timeout.is_transient());
+        assert_eq!(provider_timeout.to_string(), "the provider timed out");
+    }
+
+    #[test]
+    fn test_transient_and_permanent_errors() {
+        assert!(AiError::Timeout(Duration::from_secs(30)).is_transient());
+        assert!(AiError::Network("connection refused".to_string()).is_transient());
+        assert!(AiError::Api {
+            status: None,
+            message: "upstream error".to_string()
+        }
+        .is_transient());
+
+        let auth = AiError::Auth("invalid key".to_string());
+        assert!(!auth.is_transient());
+        assert!(auth.is_permanent());
+        assert!(!auth.may_fall_back());
+
+        let budget = AiError::BudgetExceeded {
+            spent: 5.012,
+            budget: 5.0,
+        };
+        assert!(!budget.is_transient());
+        assert!(budget.is_permanent());
+        assert!(budget
+            .to_string()
+            .starts_with("monthly budget of $5.00 used up ($5.01 spent"));
+
+        let garbled = AiError::InvalidResponse("not JSON".to_string());
+        assert!(!garbled.is_transient());
+        assert!(!garbled.is_permanent());
+        assert!(garbled.may_fall_back());
+    }
+}
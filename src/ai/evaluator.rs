@@ -1,8 +1,10 @@
-use crate::ai::client::OpenRouterClient;
+use crate::ai::provider::{AiProvider, ModelConfig};
+use crate::ai::schema::{ResponseFormat, SchemaMismatch};
 use crate::models::SessionAssessment;
 use serde::{Deserialize, Serialize};
 
-fn clean_json_response(response: &str) -> String {
+/// Strip code fences and any text around the outermost JSON object.
+pub(crate) fn clean_json_response(response: &str) -> String {
     let mut cleaned = response.trim().to_string();
 
     if cleaned.starts_with("```") {
@@ -30,13 +32,12 @@ struct SessionAssessmentRaw {
     weaknesses: Vec<String>,
 }
 
-pub fn parse_session_assessment(response: &str) -> Result<SessionAssessment, String> {
-    let cleaned = clean_json_response(response);
-    let raw: SessionAssessmentRaw = serde_json::from_str(&cleaned).map_err(|e| {
-        format!(
-            "Failed to parse session assessment: {}\nRaw: {}\nCleaned: {}",
-            e, response, cleaned
-        )
+/// Parse the model's reply to a session assessment, checking it against
+/// [`ResponseFormat::assessment`].
+pub fn parse_session_assessment(response: &str) -> Result<SessionAssessment, SchemaMismatch> {
+    let value = ResponseFormat::assessment().check(response)?;
+    let raw: SessionAssessmentRaw = serde_json::from_value(value).map_err(|e| SchemaMismatch {
+        problems: vec![e.to_string()],
     })?;
 
     Ok(SessionAssessment {
@@ -59,51 +60,179 @@ pub struct AIFeedback {
     pub suggestions: Vec<String>,
 }
 
+/// Deck- and card-level grading instructions sent along with an answer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GradingContext {
+    /// The deck rubric, applied to every card
+    pub rubric: Option<String>,
+    /// Answers other than the card's answer that count as correct
+    pub accepted_answers: Vec<String>,
+    /// Card-specific grading notes
+    pub notes: Option<String>,
+    /// Passages of the deck's source documents about the question
+    pub passages: Vec<SourcePassage>,
+}
+
+impl GradingContext {
+    /// The prompt lines describing this context, empty when there is nothing to add.
+    pub fn prompt_section(&self) -> String {
+        let mut section = String::new();
+        if !self.accepted_answers.is_empty() {
+            section.push_str("\nAlso accept these answers as correct:\n");
+            for answer in &self.accepted_answers {
+                section.push_str(&format!("- {}\n", answer));
+            }
+        }
+        if let Some(notes) = &self.notes {
+            section.push_str(&format!(
+                "\nGrading notes for this card (follow them when judging correctness):\n{}\n",
+                notes
+            ));
+        }
+        if let Some(rubric) = &self.rubric {
+            section.push_str(&format!(
+                "\nGrading rubric for this deck (follow it when judging correctness):\n{}\n",
+                rubric
+            ));
+        }
+        section.push_str(&sources_section(&self.passages));
+        section
+    }
+}
+
+/// A passage of a deck's source documents, found for a card by
+/// [`crate::ingest::retrieval`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourcePassage {
+    /// File name of the document
+    pub document: String,
+    /// Page or lines of the passage in the document
+    pub location: String,
+    pub text: String,
+}
+
+impl SourcePassage {
+    pub fn citation(&self) -> String {
+        format!("{}, {}", self.document, self.location)
+    }
+}
+
+/// The prompt lines giving `passages` as numbered excerpts, empty when there are none.
+pub fn sources_section(passages: &[SourcePassage]) -> String {
+    if passages.is_empty() {
+        return String::new();
+    }
+    let mut section = String::from(
+        "\nExcerpts from the course material (rely on them where they apply and cite them as [1], [2], ... when you do):\n",
+    );
+    for (i, passage) in passages.iter().enumerate() {
+        section.push_str(&format!(
+            "[{}] {}\n{}\n\n",
+            i + 1,
+            passage.citation(),
+            passage.text.trim()
+        ));
+    }
+    section
+}
+
+/// The passages `explanation` cites as `[1]`, `[2]`, ..., with their numbers; all of them
+/// when it cites none.
+pub fn cited_passages<'a>(
+    explanation: &str,
+    passages: &'a [SourcePassage],
+) -> Vec<(usize, &'a SourcePassage)> {
+    let numbered = passages.iter().enumerate().map(|(i, p)| (i + 1, p));
+    let cited: Vec<_> = numbered
+        .clone()
+        .filter(|(n, _)| explanation.contains(&format!("[{}]", n)))
+        .collect();
+    if cited.is_empty() {
+        numbered.collect()
+    } else {
+        cited
+    }
+}
+
 /// Complete AI evaluation result with raw response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AIEvaluationResult {
     pub feedback: AIFeedback,
     pub raw_response: String,
+    /// Taken from the evaluation cache rather than asked of the model
+    #[serde(default)]
+    pub cached: bool,
 }
 
-/// Evaluate user's answer against correct answer using AI
+/// Evaluate user's answer against correct answer using AI, with `config` overriding
+/// the client's model (such as [`super::AiConfig::model_config`] for evaluations)
 pub async fn evaluate_answer(
-    client: &OpenRouterClient,
+    client: &dyn AiProvider,
     question: &str,
     correct_answer: &str,
     user_answer: &str,
+    grading: &GradingContext,
+    config: Option<&ModelConfig>,
 ) -> Result<AIEvaluationResult, Box<dyn std::error::Error + Send + Sync>> {
     crate::logger::log("Starting AI evaluation");
     let json_response = client
-        .evaluate_answer(question, correct_answer, user_answer, None)
+        .evaluate_answer(question, correct_answer, user_answer, grading, config)
         .await?;
+    Ok(parse_evaluation(&json_response)?)
+}
 
+/// Parse the model's reply to an evaluation request, checking it against
+/// [`ResponseFormat::feedback`].
+pub fn parse_evaluation(json_response: &str) -> Result<AIEvaluationResult, SchemaMismatch> {
     crate::logger::log(&format!("Raw AI response: {}", json_response));
-    let cleaned = clean_json_response(&json_response);
-
-    crate::logger::log(&format!("Cleaned AI response: {}", cleaned));
-
-    let feedback: AIFeedback = serde_json::from_str(&cleaned).map_err(|e| {
-        format!(
-            "Failed to parse AI response as JSON: {}\nRaw: {}\nCleaned: {}",
-            e, json_response, cleaned
-        )
+    let value = ResponseFormat::feedback().check(json_response)?;
+    let feedback: AIFeedback = serde_json::from_value(value).map_err(|e| SchemaMismatch {
+        problems: vec![e.to_string()],
     })?;
 
-    if feedback.correctness_score < 0.0 || feedback.correctness_score > 1.0 {
-        return Err(format!(
-            "Invalid correctness score: {}. Raw: {}",
-            feedback.correctness_score, json_response
-        )
-        .into());
-    }
-
     Ok(AIEvaluationResult {
         feedback,
-        raw_response: json_response,
+        raw_response: json_response.to_string(),
+        cached: false,
     })
 }
 
+/// The part of the `explanation` field received so far in a streamed evaluation reply.
+pub fn partial_explanation(partial_response: &str) -> Option<String> {
+    let key = partial_response.find("\"explanation\"")?;
+    let rest = partial_response[key + "\"explanation\"".len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+
+    let mut text = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('r') => {}
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    let decoded = (code.len() == 4)
+                        .then(|| u32::from_str_radix(&code, 16).ok())
+                        .flatten()
+                        .and_then(char::from_u32);
+                    match decoded {
+                        Some(c) => text.push(c),
+                        // Cut off mid-escape, or a surrogate pair: wait for more
+                        None => break,
+                    }
+                }
+                Some(other) => text.push(other),
+                None => break,
+            },
+            c => text.push(c),
+        }
+    }
+    Some(text)
+}
+
 #[cfg(test)]
 use std::time::Duration;
 #[cfg(test)]
@@ -117,6 +246,13 @@ pub struct MockAiClient {
     current_index: usize,
 }
 
+#[cfg(test)]
+impl Default for MockAiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 impl MockAiClient {
     /// Create a new mock client with default successful responses
@@ -132,6 +268,7 @@ impl MockAiClient {
                         suggestions: vec![],
                     },
                     raw_response: r#"{"is_correct": true, "correctness_score": 1.0, "corrections": [], "explanation": "Perfect answer! Well done.", "suggestions": []}"#.to_string(),
+                    cached: false,
                 },
                 AIEvaluationResult {
                     feedback: AIFeedback {
@@ -142,6 +279,7 @@ impl MockAiClient {
                         suggestions: vec!["Review the key terms".to_string()],
                     },
                     raw_response: r#"{"is_correct": false, "correctness_score": 0.6, "corrections": ["Incorrect terminology"], "explanation": "Good attempt, but there's an error in the terminology.", "suggestions": ["Review the key terms"]}"#.to_string(),
+                    cached: false,
                 },
             ],
             delays: vec![Duration::from_millis(50), Duration::from_millis(75)],
@@ -226,7 +364,7 @@ mod tests {
         }"#;
 
         let feedback: AIFeedback = serde_json::from_str(json).unwrap();
-        assert_eq!(feedback.is_correct, false);
+        assert!(!feedback.is_correct);
         assert_eq!(feedback.correctness_score, 0.75);
         assert_eq!(feedback.corrections, vec!["Missed concept X".to_string()]);
     }
@@ -281,4 +419,66 @@ mod tests {
         let assessment = result.unwrap();
         assert_eq!(assessment.grade_percentage, 70.5);
     }
+
+    #[test]
+    fn test_grading_context_prompt_section() {
+        assert_eq!(GradingContext::default().prompt_section(), "");
+
+        let section = GradingContext {
+            rubric: Some("Be lenient".to_string()),
+            accepted_answers: vec!["AODV".to_string(), "AODV routing".to_string()],
+            notes: Some("Routing is optional".to_string()),
+            passages: vec![SourcePassage {
+                document: "manet.pdf".to_string(),
+                location: "p. 3".to_string(),
+                text: "AODV is reactive.".to_string(),
+            }],
+        }
+        .prompt_section();
+        assert!(
+            section.contains("Also accept these answers as correct:\n- AODV\n- AODV routing\n")
+        );
+        assert!(section.contains("Grading notes for this card"));
+        assert!(section.contains("Routing is optional"));
+        assert!(section.contains("Grading rubric for this deck"));
+        assert!(section.contains("[1] manet.pdf, p. 3\nAODV is reactive.\n"));
+    }
+
+    #[test]
+    fn test_cited_passages() {
+        let passage = |location: &str| SourcePassage {
+            location: location.to_string(),
+            ..Default::default()
+        };
+        let passages = [passage("p. 1"), passage("p. 2"), passage("p. 3")];
+        let cited = cited_passages("AODV floods requests [3], unlike OLSR [1].", &passages);
+        assert_eq!(
+            cited.iter().map(|(n, p)| (*n, p.location.as_str())).collect::<Vec<_>>(),
+            vec![(1, "p. 1"), (3, "p. 3")]
+        );
+        assert_eq!(cited_passages("No citations.", &passages).len(), 3);
+        assert!(cited_passages("[1]", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_partial_explanation() {
+        assert_eq!(
+            partial_explanation(r#"{"is_correct": true, "correctn"#),
+            None
+        );
+        assert_eq!(
+            partial_explanation(r#"{"is_correct": true, "explanation": "AODV is"#).as_deref(),
+            Some("AODV is")
+        );
+        assert_eq!(
+            partial_explanation(r#"{"explanation":"Line one\nSaid \"reactive\"é", "sugg"#)
+                .as_deref(),
+            Some("Line one\nSaid \"reactive\"é")
+        );
+        // An escape cut in half is left for the next fragment
+        assert_eq!(
+            partial_explanation(r#"{"explanation": "caf\u00"#).as_deref(),
+            Some("caf")
+        );
+    }
 }
@@ -0,0 +1,415 @@
+//! A stand-in chat-completions server for testing the AI path end to end.
+//!
+//! It speaks the OpenAI chat-completions API, so the `openai` provider can be pointed
+//! at it with `base_url`. Each request gets the next reply of a script: normal
+//! completions, delayed ones, malformed bodies or HTTP errors. Once the script runs out
+//! the last reply is repeated. Requests with `"stream": true` get their `content` as
+//! server-sent events, a word per event, `chunk_delay_ms` apart. Completions report
+//! their usage, counting a token per word.
+//!
+//! `interactive-flashcards fake-ai` runs it from the command line; a script file is a
+//! JSON array of replies:
+//!
+//! ```json
+//! [
+//!   {"status": 503, "body": "overloaded"},
+//!   {"content": "{\"is_correct\": true, ...}", "delay_ms": 2000},
+//!   {"raw": "not json at all"}
+//! ]
+//! ```
+
+use serde::Deserialize;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Requests larger than this are rejected
+const MAX_REQUEST_BYTES: usize = 1 << 20;
+
+/// One scripted reply
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FakeReply {
+    /// Wait this long before answering
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Pause between the events of a streamed reply
+    #[serde(default)]
+    pub chunk_delay_ms: u64,
+    #[serde(flatten)]
+    pub body: FakeBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum FakeBody {
+    /// A successful completion whose message is `content`
+    Content { content: String },
+    /// An HTTP error status with a plain-text body
+    Status { status: u16, body: String },
+    /// A 200 response with exactly this body (for malformed responses)
+    Raw { raw: String },
+}
+
+impl FakeReply {
+    pub fn content(content: &str) -> Self {
+        Self {
+            delay_ms: 0,
+            chunk_delay_ms: 0,
+            body: FakeBody::Content {
+                content: content.to_string(),
+            },
+        }
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            delay_ms: 0,
+            chunk_delay_ms: 0,
+            body: FakeBody::Status {
+                status,
+                body: body.to_string(),
+            },
+        }
+    }
+
+    pub fn raw(raw: &str) -> Self {
+        Self {
+            delay_ms: 0,
+            chunk_delay_ms: 0,
+            body: FakeBody::Raw {
+                raw: raw.to_string(),
+            },
+        }
+    }
+
+    /// The same reply, sent after `delay`.
+    pub fn after(mut self, delay: Duration) -> Self {
+        self.delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    /// The same reply, streamed with `delay` between events.
+    pub fn chunked(mut self, delay: Duration) -> Self {
+        self.chunk_delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    /// The HTTP status and body to send in answer to `request`.
+    fn response(&self, request_number: usize, request: &serde_json::Value) -> (u16, String) {
+        match &self.body {
+            FakeBody::Content { content } => (
+                200,
+                serde_json::json!({
+                    "id": format!("fake-{}", request_number),
+                    "object": "chat.completion",
+                    "model": "fake",
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": content},
+                        "finish_reason": "stop",
+                    }],
+                    "usage": usage(request, content),
+                })
+                .to_string(),
+            ),
+            FakeBody::Status { status, body } => (*status, body.clone()),
+            FakeBody::Raw { raw } => (200, raw.clone()),
+        }
+    }
+}
+
+/// The usage of a completion, a token per word of the request's messages and the reply
+fn usage(request: &serde_json::Value, content: &str) -> serde_json::Value {
+    let words = |text: &str| text.split_whitespace().count();
+    let prompt_tokens: usize = request["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message["content"].as_str())
+        .map(words)
+        .sum();
+    let completion_tokens = words(content);
+    serde_json::json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+/// A valid answer evaluation, the default reply of `fake-ai`
+pub fn sample_evaluation() -> String {
+    serde_json::json!({
+        "is_correct": true,
+        "correctness_score": 0.9,
+        "corrections": [],
+        "explanation": "Evaluated by the fake AI server.",
+        "suggestions": [],
+    })
+    .to_string()
+}
+
+/// Parse a script file's contents.
+pub fn parse_script(content: &str) -> Result<Vec<FakeReply>, String> {
+    let script: Vec<FakeReply> = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    if script.is_empty() {
+        return Err("the script has no replies".to_string());
+    }
+    Ok(script)
+}
+
+#[derive(Debug)]
+struct State {
+    script: Vec<FakeReply>,
+    /// Bodies of the requests received so far, in order
+    requests: Vec<serde_json::Value>,
+}
+
+/// A running fake server; it stops when dropped.
+#[derive(Debug)]
+pub struct FakeAiServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    handle: JoinHandle<()>,
+}
+
+impl FakeAiServer {
+    /// Start serving `script` on an ephemeral local port.
+    pub async fn start(script: Vec<FakeReply>) -> io::Result<Self> {
+        Self::bind(([127, 0, 0, 1], 0).into(), script).await
+    }
+
+    pub async fn bind(addr: SocketAddr, script: Vec<FakeReply>) -> io::Result<Self> {
+        if script.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the script has no replies",
+            ));
+        }
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            script,
+            requests: Vec::new(),
+        }));
+
+        let server_state = Arc::clone(&state);
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = Arc::clone(&server_state);
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &state).await {
+                        crate::logger::log(&format!("Fake AI server: {}", e));
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// The URL to use as `base_url` of the `openai` provider.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    /// The JSON bodies of the requests received so far.
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Wait until the server is stopped (for the command-line server).
+    pub async fn wait(mut self) {
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for FakeAiServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let Some((path, body)) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    // Health checks are answered without using up the script
+    if path.ends_with("/models") || path.ends_with("/key") {
+        let body = serde_json::json!({"data": [{"id": "fake-model"}]}).to_string();
+        return write_response(stream, 200, &body).await;
+    }
+
+    let body: serde_json::Value =
+        serde_json::from_slice(&body).unwrap_or_else(|_| String::from_utf8_lossy(&body).into());
+    let stream_requested = body.get("stream") == Some(&serde_json::Value::Bool(true));
+    let (reply, number) = {
+        let mut state = state.lock().unwrap();
+        let number = state.requests.len();
+        state.requests.push(body.clone());
+        let reply = state.script[number.min(state.script.len() - 1)].clone();
+        (reply, number)
+    };
+
+    let (status, body) = if path.ends_with("/chat/completions") {
+        if reply.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(reply.delay_ms)).await;
+        }
+        if let (true, FakeBody::Content { content }) = (stream_requested, &reply.body) {
+            let usage =
+                (body["stream_options"]["include_usage"] == true).then(|| usage(&body, content));
+            return stream_content(stream, content, usage, reply.chunk_delay_ms).await;
+        }
+        reply.response(number, &body)
+    } else {
+        (404, format!("no route for {}", path))
+    };
+
+    write_response(stream, status, &body).await
+}
+
+async fn write_response(mut stream: TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Send `content` as a streamed completion, one word per event, followed by an event
+/// with the usage if there is one.
+async fn stream_content(
+    mut stream: TcpStream,
+    content: &str,
+    usage: Option<serde_json::Value>,
+    delay_ms: u64,
+) -> io::Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    for (i, word) in content.split_inclusive(' ').enumerate() {
+        if i > 0 && delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        }
+        let event = serde_json::json!({
+            "object": "chat.completion.chunk",
+            "choices": [{"index": 0, "delta": {"content": word}}],
+        });
+        stream
+            .write_all(format!("data: {}\n\n", event).as_bytes())
+            .await?;
+        stream.flush().await?;
+    }
+    if let Some(usage) = usage {
+        let event = serde_json::json!({
+            "object": "chat.completion.chunk",
+            "choices": [],
+            "usage": usage,
+        });
+        stream
+            .write_all(format!("data: {}\n\n", event).as_bytes())
+            .await?;
+    }
+    stream.write_all(b"data: [DONE]\n\n").await?;
+    stream.shutdown().await
+}
+
+/// Read one HTTP request, returning its path and body.
+async fn read_request(stream: &mut TcpStream) -> io::Result<Option<(String, Vec<u8>)>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request too large",
+            ));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let path = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/")
+        .to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(MAX_REQUEST_BYTES);
+
+    let mut body = buffer.split_off(header_end);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    Ok(Some((path, body)))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = parse_script(
+            r#"[{"content": "hi", "delay_ms": 5, "chunk_delay_ms": 2}, {"status": 500, "body": "boom"}, {"raw": "{"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            script,
+            vec![
+                FakeReply::content("hi")
+                    .after(Duration::from_millis(5))
+                    .chunked(Duration::from_millis(2)),
+                FakeReply::status(500, "boom"),
+                FakeReply::raw("{"),
+            ]
+        );
+        assert!(parse_script("[]").is_err());
+        assert!(parse_script(r#"[{"colour": "red"}]"#).is_err());
+    }
+}
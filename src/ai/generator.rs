@@ -0,0 +1,106 @@
+//! Flashcards written by the model from a passage of a document, see
+//! [`crate::ingest`].
+
+use super::schema::{ResponseFormat, SchemaMismatch};
+use serde::Deserialize;
+
+/// Longest quote kept in a card's citation
+const MAX_QUOTE_CHARS: usize = 200;
+
+/// A card proposed by the model, with the passage it cites
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GeneratedCard {
+    pub question: String,
+    pub answer: String,
+    /// The words of the passage the answer comes from, as the model quoted them
+    pub quote: String,
+    /// Pages or lines of the document the passage was taken from
+    #[serde(default)]
+    pub location: String,
+    /// Whether the quote was found in the passage; a quote that was not may have been
+    /// made up along with the card
+    #[serde(default)]
+    pub quote_verified: bool,
+}
+
+impl GeneratedCard {
+    /// The card's source line: `notes.pdf, p. 4: "the quoted words"`.
+    pub fn citation(&self, document: &str) -> String {
+        let quote = collapse_whitespace(&self.quote);
+        let quote = match quote.char_indices().nth(MAX_QUOTE_CHARS) {
+            Some((end, _)) => format!("{}...", &quote[..end]),
+            None => quote,
+        };
+        match (self.location.is_empty(), quote.is_empty()) {
+            (true, true) => document.to_string(),
+            (true, false) => format!("{}: \"{}\"", document, quote),
+            (false, true) => format!("{}, {}", document, self.location),
+            (false, false) => format!("{}, {}: \"{}\"", document, self.location, quote),
+        }
+    }
+
+    /// Set where the card was written from, and check its quote against `passage`
+    /// ignoring case and differences in whitespace.
+    pub fn cite(&mut self, location: &str, passage: &str) {
+        self.location = location.to_string();
+        let quote = collapse_whitespace(&self.quote).to_lowercase();
+        self.quote_verified =
+            !quote.is_empty() && collapse_whitespace(passage).to_lowercase().contains(&quote);
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Deserialize)]
+struct CardsReply {
+    cards: Vec<GeneratedCard>,
+}
+
+/// Parse the model's reply to a generation request, checking it against
+/// [`ResponseFormat::cards`]. Cards with an empty question or answer are dropped.
+pub fn parse_generated_cards(reply: &str) -> Result<Vec<GeneratedCard>, SchemaMismatch> {
+    let value = ResponseFormat::cards().check(reply)?;
+    let reply: CardsReply = serde_json::from_value(value).map_err(|e| SchemaMismatch {
+        problems: vec![e.to_string()],
+    })?;
+    Ok(reply
+        .cards
+        .into_iter()
+        .filter(|card| !card.question.trim().is_empty() && !card.answer.trim().is_empty())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_cite_generated_cards() {
+        let reply = r#"{"cards": [
+            {"question": "What does AODV stand for?", "answer": "Ad hoc On-Demand Distance Vector", "quote": "AODV  (Ad hoc On-Demand\nDistance Vector)"},
+            {"question": "", "answer": "Nothing", "quote": ""},
+            {"question": "Who invented MANETs?", "answer": "Nobody knows", "quote": "invented by aliens"}
+        ]}"#;
+        let mut cards = parse_generated_cards(reply).unwrap();
+        assert_eq!(cards.len(), 2);
+
+        let passage =
+            "Routing in MANETs.\n\naodv (Ad hoc On-Demand Distance Vector) finds routes on demand.";
+        for card in &mut cards {
+            card.cite("p. 4", passage);
+        }
+        assert!(cards[0].quote_verified);
+        assert!(!cards[1].quote_verified);
+        assert_eq!(
+            cards[0].citation("manet.pdf"),
+            "manet.pdf, p. 4: \"AODV (Ad hoc On-Demand Distance Vector)\""
+        );
+
+        let missing = parse_generated_cards(r#"{"cards": [{"question": "Q"}]}"#).unwrap_err();
+        assert!(missing
+            .problems
+            .contains(&"cards[0].answer: missing".to_string()));
+    }
+}
@@ -0,0 +1,373 @@
+//! Offline grading used when no AI backend is configured.
+//!
+//! The answer is compared with the expected answer (and any accepted alternatives)
+//! word by word: how many of the expected key terms it covers, how much vocabulary the
+//! two share, and whether the numbers match. Words within a small edit distance count
+//! as the same word, so typos are not penalised.
+
+use super::evaluator::{AIFeedback, GradingContext};
+use std::collections::HashSet;
+
+/// Score from which an answer counts as correct, the same bar the session assessment uses
+const CORRECT_THRESHOLD: f32 = 0.7;
+
+/// Words too common to say anything about an answer
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "can", "for", "from", "has", "have", "in",
+    "is", "it", "its", "of", "on", "or", "that", "the", "their", "this", "to", "was", "were",
+    "which", "with",
+];
+
+/// Grade `user_answer` against `correct_answer` and the accepted answers in `grading`.
+pub fn evaluate_offline(
+    question: &str,
+    correct_answer: &str,
+    user_answer: &str,
+    grading: &GradingContext,
+) -> AIFeedback {
+    let question_words: HashSet<String> = content_words(question).into_iter().collect();
+    let answer = content_words(user_answer);
+
+    let best = std::iter::once(correct_answer)
+        .chain(grading.accepted_answers.iter().map(String::as_str))
+        .map(|reference| compare(&content_words(reference), &answer, &question_words))
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+        .expect("the correct answer is always compared");
+
+    feedback(best, correct_answer)
+}
+
+/// How one answer matched one reference answer
+#[derive(Debug)]
+struct Comparison {
+    score: f32,
+    /// Key terms of the reference the answer does not contain
+    missing: Vec<String>,
+    /// Numbers the reference has that the answer gets wrong or leaves out
+    missing_numbers: Vec<String>,
+    /// Numbers in the answer the reference does not have
+    wrong_numbers: Vec<String>,
+    /// (typed, expected) pairs matched despite a typo
+    typos: Vec<(String, String)>,
+    /// Key terms in the reference
+    key_terms: usize,
+}
+
+fn compare(reference: &[String], answer: &[String], question: &HashSet<String>) -> Comparison {
+    let (ref_numbers, ref_words): (Vec<&String>, Vec<&String>) =
+        unique(reference).into_iter().partition(|w| is_number(w));
+    let (answer_numbers, answer_words): (Vec<&String>, Vec<&String>) =
+        unique(answer).into_iter().partition(|w| is_number(w));
+
+    // Words repeated from the question say little about whether the answer is right
+    let mut key_terms: Vec<&String> = ref_words
+        .iter()
+        .copied()
+        .filter(|w| !question.contains(*w))
+        .collect();
+    if key_terms.is_empty() {
+        key_terms = ref_words.clone();
+    }
+
+    let mut missing = Vec::new();
+    let mut typos = Vec::new();
+    for term in &key_terms {
+        match find_match(term, &answer_words) {
+            Some(typed) if typed != *term => typos.push((typed.clone(), (*term).clone())),
+            Some(_) => {}
+            None => missing.push((*term).clone()),
+        }
+    }
+    let coverage = if key_terms.is_empty() {
+        0.0
+    } else {
+        1.0 - missing.len() as f32 / key_terms.len() as f32
+    };
+
+    // Dice coefficient of the two vocabularies
+    let shared = ref_words
+        .iter()
+        .filter(|w| find_match(w, &answer_words).is_some())
+        .count();
+    let overlap = if ref_words.is_empty() && answer_words.is_empty() {
+        0.0
+    } else {
+        2.0 * shared as f32 / (ref_words.len() + answer_words.len()) as f32
+    };
+
+    let missing_numbers: Vec<String> = ref_numbers
+        .iter()
+        .filter(|n| !answer_numbers.iter().any(|a| same_number(n, a)))
+        .map(|n| (*n).clone())
+        .collect();
+    let wrong_numbers: Vec<String> = answer_numbers
+        .iter()
+        .filter(|a| !ref_numbers.iter().any(|n| same_number(n, a)))
+        .map(|a| (*a).clone())
+        .collect();
+
+    let score = if ref_numbers.is_empty() {
+        if key_terms.is_empty() {
+            0.0
+        } else {
+            0.7 * coverage + 0.3 * overlap
+        }
+    } else {
+        let numbers = 1.0 - missing_numbers.len() as f32 / ref_numbers.len() as f32;
+        if key_terms.is_empty() {
+            // A purely numeric answer is right or wrong
+            if missing_numbers.is_empty() && wrong_numbers.is_empty() {
+                1.0
+            } else {
+                0.5 * numbers
+            }
+        } else {
+            0.45 * coverage + 0.2 * overlap + 0.35 * numbers
+        }
+    };
+    // Extra numbers usually mean a wrong figure was given
+    let score = if wrong_numbers.is_empty() || !missing_numbers.is_empty() {
+        score
+    } else {
+        score * 0.8
+    };
+
+    Comparison {
+        score: score.clamp(0.0, 1.0),
+        missing,
+        missing_numbers,
+        wrong_numbers,
+        typos,
+        key_terms: key_terms.len(),
+    }
+}
+
+fn feedback(comparison: Comparison, correct_answer: &str) -> AIFeedback {
+    let is_correct = comparison.score >= CORRECT_THRESHOLD;
+    let covered = comparison.key_terms - comparison.missing.len();
+
+    let mut corrections = Vec::new();
+    if !comparison.missing.is_empty() {
+        corrections.push(format!(
+            "Missing key terms: {}",
+            comparison.missing.join(", ")
+        ));
+    }
+    if !comparison.missing_numbers.is_empty() {
+        corrections.push(format!(
+            "Expected {}",
+            comparison.missing_numbers.join(", ")
+        ));
+    }
+    if !comparison.wrong_numbers.is_empty() {
+        corrections.push(format!(
+            "Not in the expected answer: {}",
+            comparison.wrong_numbers.join(", ")
+        ));
+    }
+
+    let mut explanation = String::from(
+        "Graded offline (no AI backend configured) by comparing your answer with the expected one. ",
+    );
+    if comparison.key_terms > 0 {
+        explanation.push_str(&format!(
+            "It covers {} of {} key terms. ",
+            covered, comparison.key_terms
+        ));
+    }
+    explanation.push_str(&format!("\n\n**Expected answer:** {}", correct_answer));
+
+    let mut suggestions = Vec::new();
+    if !comparison.typos.is_empty() {
+        let typos: Vec<String> = comparison
+            .typos
+            .iter()
+            .map(|(typed, expected)| format!("{} → {}", typed, expected))
+            .collect();
+        suggestions.push(format!("Check the spelling: {}", typos.join(", ")));
+    }
+    if !is_correct {
+        suggestions.push("Compare your answer with the expected answer and retry this card".into());
+    }
+
+    AIFeedback {
+        is_correct,
+        correctness_score: comparison.score,
+        corrections,
+        explanation,
+        suggestions,
+    }
+}
+
+/// Lowercased words and numbers of `text`, without stopwords.
+fn content_words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '.' || c == ','))
+        .flat_map(|token| {
+            // Keep decimal points and thousands separators inside numbers only
+            if is_number(token.trim_matches(['.', ','])) {
+                vec![token.trim_matches(['.', ',']).replace(',', "")]
+            } else {
+                token
+                    .split(['.', ','])
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            }
+        })
+        .filter(|w| !w.is_empty() && !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn unique(words: &[String]) -> Vec<&String> {
+    let mut seen = HashSet::new();
+    words.iter().filter(|w| seen.insert(w.as_str())).collect()
+}
+
+fn is_number(word: &str) -> bool {
+    !word.is_empty()
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word.replace(',', "").parse::<f64>().is_ok()
+}
+
+fn same_number(a: &str, b: &str) -> bool {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => (a - b).abs() < 1e-9,
+        _ => a == b,
+    }
+}
+
+/// The word of `candidates` that matches `term`, allowing a typo or two in longer words.
+fn find_match<'a>(term: &str, candidates: &[&'a String]) -> Option<&'a String> {
+    if let Some(exact) = candidates.iter().find(|c| c.as_str() == term) {
+        return Some(exact);
+    }
+    let tolerance = typo_tolerance(term);
+    if tolerance == 0 {
+        return None;
+    }
+    candidates
+        .iter()
+        .filter(|c| !is_number(c))
+        .map(|c| (levenshtein(term, c), *c))
+        .filter(|(distance, _)| *distance <= tolerance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+fn typo_tolerance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Edit distance between `a` and `b` in characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grade(question: &str, answer: &str, user_answer: &str) -> AIFeedback {
+        evaluate_offline(question, answer, user_answer, &GradingContext::default())
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("protocol", "protocl"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_content_words_keeps_numbers() {
+        assert_eq!(
+            content_words("The speed is 1,000.5 m/s, or about 3.6 km/h."),
+            vec!["speed", "1000.5", "m", "s", "about", "3.6", "km", "h"]
+        );
+    }
+
+    #[test]
+    fn test_exact_and_typo_answers_are_correct() {
+        let question = "What does AODV stand for?";
+        let answer = "Ad hoc On-Demand Distance Vector";
+
+        let exact = grade(question, answer, "ad hoc on-demand distance vector");
+        assert!(exact.is_correct);
+        assert!((exact.correctness_score - 1.0).abs() < 1e-6);
+
+        let typo = grade(question, answer, "Ad hoc on demand distanse vectr");
+        assert!(typo.is_correct, "{:?}", typo);
+        assert!(typo.suggestions[0].contains("distanse → distance"));
+    }
+
+    #[test]
+    fn test_partial_and_wrong_answers() {
+        let question = "Which layers does a router operate on?";
+        let answer = "Physical, data link and network layers";
+
+        let partial = grade(question, answer, "the network layer");
+        assert!(!partial.is_correct);
+        assert!(partial.correctness_score > 0.0 && partial.correctness_score < 0.7);
+        assert_eq!(
+            partial.corrections,
+            vec!["Missing key terms: physical, data, link"]
+        );
+
+        let wrong = grade(question, answer, "I don't know");
+        assert!(!wrong.is_correct);
+        assert_eq!(wrong.correctness_score, 0.0);
+        assert!(wrong.explanation.contains(answer));
+    }
+
+    #[test]
+    fn test_numbers_must_match() {
+        let question = "How many bits are in an IPv4 address?";
+        assert!(grade(question, "32", "32 bits").is_correct);
+
+        let wrong = grade(question, "32", "64");
+        assert!(!wrong.is_correct);
+        assert_eq!(
+            wrong.corrections,
+            vec!["Expected 32", "Not in the expected answer: 64"]
+        );
+
+        let mixed = grade(
+            "Boiling point of water?",
+            "100 degrees Celsius",
+            "100.0 celsius degrees",
+        );
+        assert!(mixed.is_correct);
+    }
+
+    #[test]
+    fn test_accepted_answers_are_compared_too() {
+        let grading = GradingContext {
+            accepted_answers: vec!["TCP".to_string()],
+            ..Default::default()
+        };
+        let feedback = evaluate_offline(
+            "Which protocol guarantees delivery?",
+            "Transmission Control Protocol",
+            "tcp",
+            &grading,
+        );
+        assert!(feedback.is_correct);
+    }
+}
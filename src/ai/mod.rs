@@ -1,6 +1,29 @@
+pub mod cache;
 pub mod client;
+pub mod error;
 pub mod evaluator;
+pub mod fake_server;
+pub mod generator;
+pub mod heuristic;
+pub mod ollama;
+pub mod openai;
+pub mod prompts;
+pub mod provider;
+pub mod schema;
+pub mod streaming;
+pub mod templates;
+pub mod usage;
 
 // Public API exports
-pub use client::{ModelConfig, OpenRouterClient, DEFAULT_MODEL};
-pub use evaluator::{evaluate_answer, parse_session_assessment, AIEvaluationResult, AIFeedback};
+pub use client::{OpenRouterClient, DEFAULT_MODEL};
+pub use error::AiError;
+pub use evaluator::{
+    cited_passages, evaluate_answer, parse_session_assessment, AIEvaluationResult, AIFeedback,
+    GradingContext, SourcePassage,
+};
+pub use heuristic::evaluate_offline;
+pub use ollama::OllamaClient;
+pub use openai::OpenAiCompatibleClient;
+pub use provider::{
+    create_provider, AiConfig, AiProvider, ModelConfig, ProviderKind, TaskConfig,
+};
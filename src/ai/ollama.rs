@@ -0,0 +1,318 @@
+//! Backend for a local Ollama server, using its native `/api/chat` endpoint.
+
+use super::error::AiError;
+use super::provider::{AiProvider, AiResult, ChatMessage, Completion, CompletionRequest};
+use super::streaming::{self, Delta, TextStream};
+use super::usage::Usage;
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+pub const DEFAULT_OLLAMA_MODEL: &str = "llama3.1";
+
+#[derive(Debug)]
+pub struct OllamaClient {
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequestBody<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    /// JSON schema the reply must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+    options: Options,
+}
+
+#[derive(Debug, Serialize)]
+struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseBody {
+    message: ChatMessage,
+    #[serde(flatten)]
+    counts: TokenCounts,
+}
+
+/// One line of a streamed response
+#[derive(Debug, Deserialize)]
+struct ChunkBody {
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(flatten)]
+    counts: TokenCounts,
+}
+
+/// Token counts, sent with the whole reply or the last line of a streamed one
+#[derive(Debug, Deserialize)]
+struct TokenCounts {
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+}
+
+impl TokenCounts {
+    fn usage(&self) -> Option<Usage> {
+        if self.prompt_eval_count.is_none() && self.eval_count.is_none() {
+            return None;
+        }
+        Some(Usage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or_default(),
+            completion_tokens: self.eval_count.unwrap_or_default(),
+            cost: None,
+        })
+    }
+}
+
+/// The models pulled into the local Ollama
+#[derive(Debug, Deserialize)]
+struct TagsBody {
+    #[serde(default)]
+    models: Vec<TagsModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsModel {
+    name: String,
+}
+
+/// Whether `model` is pulled; a name without a tag means `latest`.
+fn has_model(tags: &TagsBody, model: &str) -> bool {
+    tags.models
+        .iter()
+        .any(|m| m.name == model || m.name.strip_suffix(":latest") == Some(model))
+}
+
+impl OllamaClient {
+    pub fn new(base_url: &str, model: String) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+        })
+    }
+
+    /// Post a request, turning error statuses into errors.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> AiResult<reqwest::Response> {
+        let response = self
+            .http
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request_body(request, stream))
+            .send()
+            .await
+            .map_err(|e| {
+                AiError::Network(format!(
+                    "Request to Ollama at {} failed: {}",
+                    self.base_url, e
+                ))
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AiError::from_status(status.as_u16(), &body));
+        }
+        Ok(response)
+    }
+}
+
+fn request_body(request: &CompletionRequest, stream: bool) -> serde_json::Value {
+    serde_json::json!(ChatRequestBody {
+        model: &request.model,
+        messages: &request.messages,
+        stream,
+        format: request
+            .response_format
+            .as_ref()
+            .map(|format| &format.schema),
+        options: Options {
+            temperature: request.temperature,
+            num_predict: request.max_tokens,
+        },
+    })
+}
+
+fn parse_response(body: &str) -> AiResult<Completion> {
+    let response: ChatResponseBody = serde_json::from_str(body)
+        .map_err(|e| AiError::InvalidResponse(format!("Invalid Ollama response: {}", e)))?;
+    Ok(Completion {
+        usage: response.counts.usage(),
+        text: response.message.content,
+    })
+}
+
+/// The text of one line of a streamed response, or the usage on the last line.
+fn parse_stream_line(line: &str) -> AiResult<Option<Delta>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let chunk: ChunkBody = serde_json::from_str(line)
+        .map_err(|e| AiError::InvalidResponse(format!("Invalid Ollama response: {}", e)))?;
+    if let Some(message) = chunk.error {
+        return Err(AiError::Api {
+            status: None,
+            message,
+        });
+    }
+    let text = chunk
+        .message
+        .map(|m| m.content)
+        .filter(|text| !text.is_empty());
+    Ok(text
+        .map(Delta::Text)
+        .or_else(|| chunk.counts.usage().map(Delta::Usage)))
+}
+
+#[async_trait]
+impl AiProvider for OllamaClient {
+    fn name(&self) -> &str {
+        "Ollama"
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    async fn health_check(&self) -> AiResult<()> {
+        let response = self
+            .http
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| {
+                AiError::Network(format!(
+                    "Request to Ollama at {} failed: {}",
+                    self.base_url, e
+                ))
+            })?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(AiError::from_status(status.as_u16(), &body));
+        }
+        let tags: TagsBody = serde_json::from_str(&body)
+            .map_err(|e| AiError::InvalidResponse(format!("Invalid Ollama response: {}", e)))?;
+        if has_model(&tags, &self.model) {
+            Ok(())
+        } else {
+            Err(AiError::Config(format!(
+                "Ollama has no model {} (run `ollama pull {}`)",
+                self.model, self.model
+            )))
+        }
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> AiResult<Completion> {
+        let body = self.send(&request, false).await?.text().await?;
+        parse_response(&body)
+    }
+
+    async fn complete_stream(&self, request: CompletionRequest) -> AiResult<TextStream> {
+        let response = self.send(&request, true).await?;
+        let text = streaming::lines(response).filter_map(|line| {
+            future::ready(match line.and_then(|line| parse_stream_line(&line)) {
+                Ok(Some(delta)) => Some(Ok(delta)),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+        });
+        Ok(Box::pin(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::schema::ResponseFormat;
+
+    #[test]
+    fn test_request_body_and_response() {
+        let request = CompletionRequest {
+            model: "llama3.1".to_string(),
+            messages: vec![ChatMessage::new("system", "be brief")],
+            temperature: None,
+            max_tokens: Some(2048),
+            response_format: None,
+        };
+        assert_eq!(
+            request_body(&request, false),
+            serde_json::json!({
+                "model": "llama3.1",
+                "messages": [{"role": "system", "content": "be brief"}],
+                "stream": false,
+                "options": {"num_predict": 2048},
+            })
+        );
+
+        let structured = CompletionRequest {
+            response_format: Some(ResponseFormat::assessment()),
+            ..request
+        };
+        assert_eq!(
+            request_body(&structured, false)["format"],
+            ResponseFormat::assessment().schema
+        );
+
+        let body = r#"{"model":"llama3.1","message":{"role":"assistant","content":"{\"ok\":1}"},"done":true}"#;
+        assert_eq!(
+            parse_response(body).unwrap(),
+            Completion::text("{\"ok\":1}")
+        );
+        assert!(parse_response(r#"{"error":"model not found"}"#).is_err());
+
+        let line =
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"Hi"},"done":false}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            Ok(Some(Delta::Text("Hi".to_string())))
+        );
+        let last = r#"{"model":"llama3.1","message":{"role":"assistant","content":""},"done":true,
+            "prompt_eval_count":26,"eval_count":290}"#;
+        assert_eq!(
+            parse_stream_line(last),
+            Ok(Some(Delta::Usage(Usage {
+                prompt_tokens: 26,
+                completion_tokens: 290,
+                cost: None,
+            })))
+        );
+        let empty =
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":""},"done":false}"#;
+        assert_eq!(parse_stream_line(empty), Ok(None));
+        assert_eq!(
+            parse_stream_line(r#"{"error":"model not found"}"#),
+            Err(AiError::Api {
+                status: None,
+                message: "model not found".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_has_model() {
+        let tags: TagsBody = serde_json::from_str(
+            r#"{"models":[{"name":"llama3.1:latest"},{"name":"qwen2.5:7b"}]}"#,
+        )
+        .unwrap();
+        assert!(has_model(&tags, "llama3.1"));
+        assert!(has_model(&tags, "llama3.1:latest"));
+        assert!(has_model(&tags, "qwen2.5:7b"));
+        assert!(!has_model(&tags, "qwen2.5"));
+    }
+}
@@ -0,0 +1,388 @@
+//! Backend for any server speaking the OpenAI chat-completions API: OpenAI itself,
+//! vLLM, llama.cpp's `llama-server`, LM Studio and the like.
+
+use super::error::AiError;
+use super::provider::{AiProvider, AiResult, ChatMessage, Completion, CompletionRequest};
+use super::streaming::{self, Delta, TextStream};
+use super::usage::Usage;
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct OpenAiCompatibleClient {
+    http: reqwest::Client,
+    /// Endpoint URL up to (not including) `/chat/completions`, e.g. `http://localhost:8080/v1`
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    /// Provider-specific fields added to every request body
+    extra_body: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequestBody<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    /// Asks for the usage of a streamed reply in a last event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseBody {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<UsageBody>,
+}
+
+/// Token counts, and the cost where the server reports it (OpenRouter)
+#[derive(Debug, Deserialize)]
+struct UsageBody {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    #[serde(default)]
+    cost: Option<f64>,
+}
+
+impl From<UsageBody> for Usage {
+    fn from(body: UsageBody) -> Self {
+        Usage {
+            prompt_tokens: body.prompt_tokens,
+            completion_tokens: body.completion_tokens,
+            cost: body.cost,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// One `data:` event of a streamed response
+#[derive(Debug, Deserialize)]
+struct ChunkBody {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    #[serde(default)]
+    usage: Option<UsageBody>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    delta: ChoiceMessage,
+}
+
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+    Text(String),
+    Usage(Usage),
+    Done,
+}
+
+impl OpenAiCompatibleClient {
+    pub fn new(base_url: &str, api_key: Option<String>, model: String) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            extra_body: None,
+        })
+    }
+
+    /// Add provider-specific fields (such as OpenRouter's routing preferences) to every request.
+    pub fn with_extra_body(mut self, extra: serde_json::Value) -> Self {
+        self.extra_body = Some(extra);
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        format!("{}/chat/completions", self.base_url)
+    }
+
+    /// Post a request, turning error statuses into errors.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> AiResult<reqwest::Response> {
+        let body = request_body(request, stream, self.extra_body.as_ref());
+        let mut http_request = self.http.post(self.endpoint()).json(&body);
+        if let Some(key) = &self.api_key {
+            http_request = http_request.bearer_auth(key);
+        }
+
+        self.checked(http_request).await
+    }
+
+    /// Get `path` under the base URL, e.g. the model list, to see whether the server
+    /// answers and accepts the key.
+    pub async fn check(&self, path: &str) -> AiResult<()> {
+        let mut http_request = self.http.get(format!("{}/{}", self.base_url, path));
+        if let Some(key) = &self.api_key {
+            http_request = http_request.bearer_auth(key);
+        }
+        self.checked(http_request).await.map(|_| ())
+    }
+
+    /// Send a request, turning error statuses into errors.
+    async fn checked(&self, http_request: reqwest::RequestBuilder) -> AiResult<reqwest::Response> {
+        let response = http_request
+            .send()
+            .await
+            .map_err(|e| AiError::Network(format!("Request to {} failed: {}", self.base_url, e)))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AiError::from_status(status.as_u16(), &body));
+        }
+        Ok(response)
+    }
+}
+
+fn request_body(
+    request: &CompletionRequest,
+    stream: bool,
+    extra: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let mut body = serde_json::json!(ChatRequestBody {
+        model: &request.model,
+        messages: &request.messages,
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        stream,
+        stream_options: stream.then(|| serde_json::json!({"include_usage": true})),
+        response_format: request.response_format.as_ref().map(|format| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {"name": format.name, "strict": true, "schema": format.schema},
+            })
+        }),
+    });
+    if let (Some(body), Some(serde_json::Value::Object(extra))) = (body.as_object_mut(), extra) {
+        for (key, value) in extra {
+            body.insert(key.clone(), value.clone());
+        }
+    }
+    body
+}
+
+/// The text of the first choice of a chat-completions response and its usage.
+fn parse_response(body: &str) -> AiResult<Completion> {
+    let response: ChatResponseBody = serde_json::from_str(body).map_err(|e| {
+        AiError::InvalidResponse(format!("Invalid chat completion response: {}", e))
+    })?;
+    let text = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|c| c.message.content.unwrap_or_default())
+        .ok_or_else(|| AiError::InvalidResponse("No response choices received".to_string()))?;
+    Ok(Completion {
+        text,
+        usage: response.usage.map(Usage::from),
+    })
+}
+
+/// Read one line of a server-sent event stream.
+fn parse_stream_line(line: &str) -> AiResult<Option<StreamEvent>> {
+    // Blank lines, comments and other fields carry no text
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(None);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(Some(StreamEvent::Done));
+    }
+
+    let chunk: ChunkBody = serde_json::from_str(data)
+        .map_err(|e| AiError::InvalidResponse(format!("Invalid chat completion chunk: {}", e)))?;
+    if let Some(error) = chunk.error {
+        let message = error["message"].as_str().map(str::to_string);
+        return Err(AiError::Api {
+            status: None,
+            message: message.unwrap_or_else(|| error.to_string()),
+        });
+    }
+    // The usage comes in a last event of its own, with no text
+    let text = chunk
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|c| c.delta.content)
+        .filter(|text| !text.is_empty());
+    Ok(text
+        .map(StreamEvent::Text)
+        .or_else(|| chunk.usage.map(|usage| StreamEvent::Usage(usage.into()))))
+}
+
+#[async_trait]
+impl AiProvider for OpenAiCompatibleClient {
+    fn name(&self) -> &str {
+        "OpenAI-compatible"
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    async fn health_check(&self) -> AiResult<()> {
+        self.check("models").await
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> AiResult<Completion> {
+        let body = self.send(&request, false).await?.text().await?;
+        parse_response(&body)
+    }
+
+    async fn complete_stream(&self, request: CompletionRequest) -> AiResult<TextStream> {
+        let response = self.send(&request, true).await?;
+        // Servers that do not support streaming answer with a whole completion
+        let is_event_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        if !is_event_stream {
+            let body = response.text().await?;
+            return Ok(streaming::single(parse_response(&body)?));
+        }
+
+        let text = streaming::lines(response)
+            .map(|line| line.and_then(|line| parse_stream_line(&line)))
+            .take_while(|event| future::ready(!matches!(event, Ok(Some(StreamEvent::Done)))))
+            .filter_map(|event| {
+                future::ready(match event {
+                    Ok(Some(StreamEvent::Text(text))) => Some(Ok(Delta::Text(text))),
+                    Ok(Some(StreamEvent::Usage(usage))) => Some(Ok(Delta::Usage(usage))),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e)),
+                })
+            });
+        Ok(Box::pin(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::schema::ResponseFormat;
+
+    #[test]
+    fn test_request_body_and_response() {
+        let request = CompletionRequest {
+            model: "qwen".to_string(),
+            messages: vec![ChatMessage::new("user", "hi")],
+            temperature: Some(0.5),
+            max_tokens: None,
+            response_format: None,
+        };
+        assert_eq!(
+            request_body(&request, false, None),
+            serde_json::json!({
+                "model": "qwen",
+                "messages": [{"role": "user", "content": "hi"}],
+                "temperature": 0.5,
+            })
+        );
+        let extra = serde_json::json!({"provider": {"sort": "throughput"}});
+        let body = request_body(&request, true, Some(&extra));
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert_eq!(body["provider"]["sort"], "throughput");
+
+        let structured = CompletionRequest {
+            response_format: Some(ResponseFormat::feedback()),
+            ..request.clone()
+        };
+        let body = request_body(&structured, false, None);
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(
+            body["response_format"]["json_schema"]["name"],
+            "answer_evaluation"
+        );
+        assert_eq!(body["response_format"]["json_schema"]["strict"], true);
+        assert_eq!(
+            body["response_format"]["json_schema"]["schema"],
+            ResponseFormat::feedback().schema
+        );
+
+        let body = r#"{"id":"x","choices":[{"index":0,"message":{"role":"assistant","content":"hello"}}]}"#;
+        assert_eq!(parse_response(body).unwrap(), Completion::text("hello"));
+        let body = r#"{"choices":[{"message":{"content":"hi"}}],
+            "usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15,"cost":0.0004}}"#;
+        assert_eq!(
+            parse_response(body).unwrap().usage,
+            Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+                cost: Some(0.0004),
+            })
+        );
+        assert!(parse_response(r#"{"choices":[]}"#).is_err());
+        assert!(parse_response("not json").is_err());
+
+        let client =
+            OpenAiCompatibleClient::new("http://localhost:8080/v1/", None, "qwen".into()).unwrap();
+        assert_eq!(
+            client.endpoint(),
+            "http://localhost:8080/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn test_parse_stream_line() {
+        assert_eq!(
+            parse_stream_line(r#"data: {"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#),
+            Ok(Some(StreamEvent::Text("Hel".to_string())))
+        );
+        assert_eq!(
+            parse_stream_line(r#"data: {"choices":[{"index":0,"delta":{"role":"assistant"}}]}"#),
+            Ok(None)
+        );
+        assert_eq!(
+            parse_stream_line("data: [DONE]"),
+            Ok(Some(StreamEvent::Done))
+        );
+        assert_eq!(
+            parse_stream_line(
+                r#"data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":4}}"#
+            ),
+            Ok(Some(StreamEvent::Usage(Usage {
+                prompt_tokens: 9,
+                completion_tokens: 4,
+                cost: None,
+            })))
+        );
+        assert_eq!(parse_stream_line(": OPENROUTER PROCESSING"), Ok(None));
+        assert_eq!(parse_stream_line(""), Ok(None));
+        assert_eq!(
+            parse_stream_line(r#"data: {"error":{"message":"rate limited"}}"#),
+            Err(AiError::Api {
+                status: None,
+                message: "rate limited".to_string()
+            })
+        );
+        assert!(parse_stream_line("data: {").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Prompts sent to the model for each kind of request, rendered from the templates in
+//! [`super::templates`].
+
+use super::evaluator::{sources_section, AIFeedback, GradingContext, SourcePassage};
+use super::provider::ChatMessage;
+use super::templates::PromptTemplate;
+
+/// Messages asking the model to grade one answer.
+pub fn evaluation_messages(
+    template: &PromptTemplate,
+    question: &str,
+    correct_answer: &str,
+    user_answer: &str,
+    grading: &GradingContext,
+) -> Vec<ChatMessage> {
+    template
+        .render(&[
+            ("question", question),
+            ("correct_answer", correct_answer),
+            ("user_answer", user_answer),
+            ("grading", &grading.prompt_section()),
+        ])
+        .to_vec()
+}
+
+/// Messages asking the model to assess a whole session.
+pub fn assessment_messages(
+    template: &PromptTemplate,
+    deck_name: &str,
+    flashcards: &[(String, String, Option<String>, Option<AIFeedback>)],
+) -> Vec<ChatMessage> {
+    let mut qa_list = String::new();
+    let mut answered_count = 0;
+    let mut correct_count = 0;
+
+    for (i, (question, answer, user_answer, ai_feedback)) in flashcards.iter().enumerate() {
+        if let Some(user_ans) = user_answer {
+            answered_count += 1;
+            let score = ai_feedback
+                .as_ref()
+                .map(|f| f.correctness_score)
+                .unwrap_or(0.0);
+            if score >= 0.7 {
+                correct_count += 1;
+            }
+
+            qa_list.push_str(&format!("Q{}: {}\n", i + 1, question));
+            qa_list.push_str(&format!("A{}: {}\n", i + 1, answer));
+            qa_list.push_str(&format!("User: {}\n", user_ans));
+            if let Some(feedback) = ai_feedback {
+                qa_list.push_str(&format!(
+                    "AI Score: {:.0}%, Feedback: {}\n",
+                    feedback.correctness_score * 100.0,
+                    feedback.explanation.chars().take(200).collect::<String>()
+                ));
+            }
+            qa_list.push('\n');
+        }
+    }
+
+    template
+        .render(&[
+            ("deck", deck_name),
+            ("total", &flashcards.len().to_string()),
+            ("answered", &answered_count.to_string()),
+            ("correct", &correct_count.to_string()),
+            ("answers", &qa_list),
+        ])
+        .to_vec()
+}
+
+/// Messages asking the model for up to `count` flashcards from a passage of `document`
+/// found at `location`.
+pub fn generation_messages(
+    template: &PromptTemplate,
+    document: &str,
+    location: &str,
+    text: &str,
+    count: usize,
+) -> Vec<ChatMessage> {
+    template
+        .render(&[
+            ("document", document),
+            ("location", location),
+            ("text", text),
+            ("count", &count.to_string()),
+        ])
+        .to_vec()
+}
+
+/// Messages continuing the follow-up chat about one card, grounded in `passages` of the
+/// deck's source documents when there are any.
+#[allow(clippy::too_many_arguments)]
+pub fn chat_messages(
+    template: &PromptTemplate,
+    question: &str,
+    correct_answer: &str,
+    user_answer: &str,
+    initial_feedback: &str,
+    conversation_history: &[(String, String)],
+    user_message: &str,
+    passages: &[SourcePassage],
+) -> Vec<ChatMessage> {
+    let sources = sources_section(passages);
+    let [system, user] = template.render(&[
+        ("question", question),
+        ("correct_answer", correct_answer),
+        ("user_answer", user_answer),
+        ("feedback", initial_feedback),
+        ("message", user_message),
+        ("sources", &sources),
+    ]);
+
+    let mut messages = vec![system];
+
+    for (role, content) in conversation_history {
+        messages.push(ChatMessage::new(role, content));
+    }
+
+    messages.push(user);
+
+    messages
+}
+
+/// The messages of a request whose reply did not follow its schema, followed by that
+/// reply and a request to correct it.
+pub fn repair_messages(
+    messages: &[ChatMessage],
+    reply: &str,
+    problems: &[String],
+) -> Vec<ChatMessage> {
+    let mut messages = messages.to_vec();
+    messages.push(ChatMessage::new("assistant", reply));
+    let problems: Vec<String> = problems.iter().map(|p| format!("- {}", p)).collect();
+    messages.push(ChatMessage::new(
+        "user",
+        &format!(
+            "Your reply does not match the required JSON structure:\n{}\n\nReply again with only the corrected JSON object, with no markdown or other text.",
+            problems.join("\n")
+        ),
+    ));
+    messages
+}
@@ -0,0 +1,607 @@
+//! The [`AiProvider`] trait and the configuration that picks a backend.
+//!
+//! Backends only implement [`AiProvider::complete`]; evaluation, session assessment
+//! and chat are built on it from the prompts in [`super::prompts`].
+//!
+//! ```toml
+//! [ai]
+//! provider = "openai"                  # "openrouter" (default), "openai" or "ollama"
+//! base_url = "http://localhost:8080/v1"
+//! api_key_env = "LOCAL_LLM_KEY"        # or api_key = "..."; optional for local servers
+//! model = "qwen2.5-7b-instruct"
+//! fallback_models = ["qwen2.5-3b-instruct"]  # tried in order when a request fails
+//!
+//! [ai.evaluation]                      # also [ai.assessment], [ai.chat], [ai.generation]
+//! model = "qwen2.5-14b-instruct"       # defaults to ai.model
+//! temperature = 0.2
+//! max_tokens = 1024
+//! ```
+
+use super::client::{OpenRouterClient, DEFAULT_MAX_TOKENS, DEFAULT_MODEL, DEFAULT_TEMPERATURE};
+use super::error::AiError;
+use super::evaluator::{AIFeedback, GradingContext, SourcePassage};
+use super::ollama::{OllamaClient, DEFAULT_OLLAMA_MODEL, DEFAULT_OLLAMA_URL};
+use super::openai::OpenAiCompatibleClient;
+use super::prompts;
+use super::schema::ResponseFormat;
+use super::streaming::{self, TextStream};
+use super::templates::{PromptKind, PromptTemplate};
+use super::usage::{ModelPrice, Usage};
+use async_trait::async_trait;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub type AiResult<T> = Result<T, AiError>;
+
+/// One message of a chat completion request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+/// A backend-independent chat completion request
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Schema the reply must follow, for backends that can enforce one
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// The text of a completion and the tokens it used, where the backend reports them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Option<Usage>,
+}
+
+impl Completion {
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            usage: None,
+        }
+    }
+}
+
+/// Per-request overrides of the provider's model and sampling settings
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelConfig {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+/// The model and sampling settings of one kind of request: an `[ai.evaluation]`,
+/// `[ai.assessment]`, `[ai.chat]` or `[ai.generation]` table. Unset values fall back to
+/// `ai.model` and the request's built-in defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TaskTable")]
+pub struct TaskConfig {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// A [`TaskConfig`] as written; other keys are logged and ignored
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct TaskTable {
+    model: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl From<TaskTable> for TaskConfig {
+    fn from(table: TaskTable) -> Self {
+        crate::config::warn_unknown_keys("an [ai.<task>] table", &table.unknown);
+        Self {
+            model: table.model,
+            temperature: table.temperature,
+            max_tokens: table.max_tokens,
+        }
+    }
+}
+
+#[async_trait]
+pub trait AiProvider: Send + Sync + std::fmt::Debug {
+    /// Backend name shown in the menu and logs
+    fn name(&self) -> &str;
+
+    /// Model used when a request does not name one
+    fn default_model(&self) -> &str;
+
+    /// Send a chat completion and return the text of the first choice.
+    async fn complete(&self, request: CompletionRequest) -> AiResult<Completion>;
+
+    /// Cheaply confirm that the backend is reachable and accepts the configured key,
+    /// without generating anything.
+    async fn health_check(&self) -> AiResult<()>;
+
+    /// Send a chat completion and stream its text as it is generated. Backends that
+    /// cannot stream deliver the whole reply as a single fragment.
+    async fn complete_stream(&self, request: CompletionRequest) -> AiResult<TextStream> {
+        Ok(streaming::single(self.complete(request).await?))
+    }
+
+    /// Grade one answer with the built-in prompt; returns the model's raw (JSON) reply.
+    async fn evaluate_answer(
+        &self,
+        question: &str,
+        correct_answer: &str,
+        user_answer: &str,
+        grading: &GradingContext,
+        config: Option<&ModelConfig>,
+    ) -> AiResult<String> {
+        let request = self.evaluation_request(
+            &PromptTemplate::builtin(PromptKind::Evaluation),
+            question,
+            correct_answer,
+            user_answer,
+            grading,
+            config,
+        );
+        Ok(self.complete(request).await?.text)
+    }
+
+    /// Assess a whole session with the built-in prompt; returns the model's raw (JSON) reply.
+    async fn evaluate_session(
+        &self,
+        deck_name: &str,
+        flashcards: &[(String, String, Option<String>, Option<AIFeedback>)],
+        config: Option<&ModelConfig>,
+    ) -> AiResult<String> {
+        let template = PromptTemplate::builtin(PromptKind::Assessment);
+        let request = self.assessment_request(&template, deck_name, flashcards, config);
+        Ok(self.complete(request).await?.text)
+    }
+
+    /// Continue the follow-up chat about a card with the built-in prompt.
+    async fn chat(
+        &self,
+        question: &str,
+        correct_answer: &str,
+        user_answer: &str,
+        initial_feedback: &str,
+        conversation_history: &[(String, String)],
+        user_message: &str,
+    ) -> AiResult<String> {
+        let request = self.chat_request(
+            &PromptTemplate::builtin(PromptKind::Chat),
+            question,
+            correct_answer,
+            user_answer,
+            initial_feedback,
+            conversation_history,
+            user_message,
+            &[],
+            None,
+        );
+        Ok(self.complete(request).await?.text)
+    }
+
+    /// The request [`AiProvider::evaluate_answer`] sends.
+    fn evaluation_request(
+        &self,
+        template: &PromptTemplate,
+        question: &str,
+        correct_answer: &str,
+        user_answer: &str,
+        grading: &GradingContext,
+        config: Option<&ModelConfig>,
+    ) -> CompletionRequest {
+        let messages =
+            prompts::evaluation_messages(template, question, correct_answer, user_answer, grading);
+        CompletionRequest {
+            response_format: Some(ResponseFormat::feedback()),
+            ..self.request(messages, config, None, None)
+        }
+    }
+
+    /// The request [`AiProvider::evaluate_session`] sends.
+    fn assessment_request(
+        &self,
+        template: &PromptTemplate,
+        deck_name: &str,
+        flashcards: &[(String, String, Option<String>, Option<AIFeedback>)],
+        config: Option<&ModelConfig>,
+    ) -> CompletionRequest {
+        let messages = prompts::assessment_messages(template, deck_name, flashcards);
+        CompletionRequest {
+            response_format: Some(ResponseFormat::assessment()),
+            ..self.request(messages, config, Some(0.5), Some(2048))
+        }
+    }
+
+    /// A request for up to `count` flashcards from a passage of `document`.
+    fn generation_request(
+        &self,
+        template: &PromptTemplate,
+        document: &str,
+        location: &str,
+        text: &str,
+        count: usize,
+        config: Option<&ModelConfig>,
+    ) -> CompletionRequest {
+        let messages = prompts::generation_messages(template, document, location, text, count);
+        CompletionRequest {
+            response_format: Some(ResponseFormat::cards()),
+            ..self.request(messages, config, Some(0.3), Some(4096))
+        }
+    }
+
+    /// The request [`AiProvider::chat`] sends.
+    #[allow(clippy::too_many_arguments)]
+    fn chat_request(
+        &self,
+        template: &PromptTemplate,
+        question: &str,
+        correct_answer: &str,
+        user_answer: &str,
+        initial_feedback: &str,
+        conversation_history: &[(String, String)],
+        user_message: &str,
+        passages: &[SourcePassage],
+        config: Option<&ModelConfig>,
+    ) -> CompletionRequest {
+        let messages = prompts::chat_messages(
+            template,
+            question,
+            correct_answer,
+            user_answer,
+            initial_feedback,
+            conversation_history,
+            user_message,
+            passages,
+        );
+        self.request(
+            messages,
+            config,
+            Some(DEFAULT_TEMPERATURE),
+            Some(DEFAULT_MAX_TOKENS),
+        )
+    }
+
+    /// Build a request for `messages`, taking settings from `config` and falling back
+    /// to the provider's model and the given defaults.
+    fn request(
+        &self,
+        messages: Vec<ChatMessage>,
+        config: Option<&ModelConfig>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> CompletionRequest {
+        CompletionRequest {
+            model: config
+                .map(|c| c.model.clone())
+                .unwrap_or_else(|| self.default_model().to_string()),
+            messages,
+            temperature: config.and_then(|c| c.temperature).or(temperature),
+            max_tokens: config.and_then(|c| c.max_tokens).or(max_tokens),
+            response_format: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenRouter,
+    /// Any server speaking the OpenAI chat-completions API (vLLM, llama.cpp, LM Studio, ...)
+    #[serde(alias = "openai-compatible")]
+    OpenAi,
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ProviderKind::OpenRouter => "OpenRouter",
+            ProviderKind::OpenAi => "OpenAI-compatible",
+            ProviderKind::Ollama => "Ollama",
+        }
+    }
+}
+
+/// The `[ai]` section of the config file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub provider: ProviderKind,
+    /// Server URL; required for `openai`, defaults to the local server for `ollama`
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    /// Environment variable holding the API key (for `openai`; default `OPENAI_API_KEY`)
+    pub api_key_env: Option<String>,
+    pub model: Option<String>,
+    /// Overrides the per-request timeouts of the AI worker
+    pub timeout_secs: Option<u64>,
+    /// How many answer evaluations may run at once; further ones wait in a queue
+    pub max_concurrent: Option<usize>,
+    /// How many times a request that failed for a transient reason is sent again
+    pub max_retries: Option<u32>,
+    /// Whether to send the reply schema with evaluations and assessments. On by default
+    /// for OpenRouter and Ollama; off for `openai`, as many OpenAI-compatible servers
+    /// reject `response_format`
+    pub structured_output: Option<bool>,
+    /// Folder of prompt template overrides, see [`super::templates`]; defaults to
+    /// `prompts` in the config directory
+    pub prompt_dir: Option<PathBuf>,
+    /// Days an evaluation is reused for the same answer; 0 turns the cache off
+    pub cache_ttl_days: Option<u64>,
+    /// USD the calls of a calendar month may cost before AI is turned off, see
+    /// [`super::usage`]
+    pub monthly_budget: Option<f64>,
+    /// Prices of models whose backend does not report the cost of a call, by model name
+    pub prices: HashMap<String, ModelPrice>,
+    pub evaluation: TaskConfig,
+    pub assessment: TaskConfig,
+    pub chat: TaskConfig,
+    pub generation: TaskConfig,
+    /// Models tried in order, on the same backend, when a request to the configured one
+    /// still fails after its retries
+    pub fallback_models: Vec<String>,
+}
+
+impl AiConfig {
+    /// Why AI features cannot be used with this configuration, if they cannot.
+    pub fn unavailable_reason(&self) -> Option<String> {
+        match self.provider {
+            ProviderKind::OpenRouter if std::env::var("OPENROUTER_API_KEY").is_err() => {
+                Some("Set OPENROUTER_API_KEY".to_string())
+            }
+            ProviderKind::OpenAi if self.base_url.is_none() => Some("Set ai.base_url".to_string()),
+            ProviderKind::OpenAi if self.model.is_none() => Some("Set ai.model".to_string()),
+            _ => None,
+        }
+    }
+
+    /// The model requests go to unless they name another.
+    pub fn model(&self) -> &str {
+        match (&self.model, self.provider) {
+            (Some(model), _) => model,
+            (None, ProviderKind::Ollama) => DEFAULT_OLLAMA_MODEL,
+            (None, _) => DEFAULT_MODEL,
+        }
+    }
+
+    /// The settings of one kind of request, as configured.
+    pub fn task(&self, kind: PromptKind) -> &TaskConfig {
+        match kind {
+            PromptKind::Evaluation => &self.evaluation,
+            PromptKind::Assessment => &self.assessment,
+            PromptKind::Chat => &self.chat,
+            PromptKind::Generation => &self.generation,
+        }
+    }
+
+    pub fn task_mut(&mut self, kind: PromptKind) -> &mut TaskConfig {
+        match kind {
+            PromptKind::Evaluation => &mut self.evaluation,
+            PromptKind::Assessment => &mut self.assessment,
+            PromptKind::Chat => &mut self.chat,
+            PromptKind::Generation => &mut self.generation,
+        }
+    }
+
+    /// The model and sampling settings requests of `kind` are sent with.
+    pub fn model_config(&self, kind: PromptKind) -> ModelConfig {
+        let task = self.task(kind);
+        ModelConfig {
+            model: task
+                .model
+                .clone()
+                .unwrap_or_else(|| self.model().to_string()),
+            temperature: task.temperature,
+            max_tokens: task.max_tokens,
+        }
+    }
+
+    /// The fallback models to try after `model`, without `model` itself.
+    pub fn fallback_models(&self, model: &str) -> Vec<String> {
+        self.fallback_models
+            .iter()
+            .filter(|m| m.as_str() != model)
+            .cloned()
+            .collect()
+    }
+
+    /// How long a request may take, `default` unless `timeout_secs` is set.
+    pub fn timeout(&self, default: Duration) -> Duration {
+        self.timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    }
+
+    /// How many evaluations may run at once, `default` unless `max_concurrent` is set.
+    pub fn max_concurrent(&self, default: usize) -> usize {
+        self.max_concurrent.unwrap_or(default).max(1)
+    }
+
+    /// How many times to retry a transient failure, `default` unless `max_retries` is set.
+    pub fn max_retries(&self, default: u32) -> u32 {
+        self.max_retries.unwrap_or(default)
+    }
+
+    /// Whether requests carry their reply schema: `structured_output` if set, otherwise
+    /// whether the provider is known to accept it.
+    pub fn structured_output(&self) -> bool {
+        self.structured_output
+            .unwrap_or(self.provider != ProviderKind::OpenAi)
+    }
+
+    /// How long cached evaluations are reused, `None` if caching is off.
+    pub fn cache_ttl(&self, default_days: u64) -> Option<Duration> {
+        match self.cache_ttl_days.unwrap_or(default_days) {
+            0 => None,
+            days => Some(Duration::from_secs(days * 24 * 60 * 60)),
+        }
+    }
+
+    /// Where prompt template overrides are read from.
+    pub fn prompt_dir(&self) -> PathBuf {
+        self.prompt_dir
+            .clone()
+            .unwrap_or_else(|| crate::config::config_dir().join("prompts"))
+    }
+
+    fn api_key(&self) -> Option<String> {
+        self.api_key.clone().or_else(|| {
+            let var = self.api_key_env.as_deref().unwrap_or("OPENAI_API_KEY");
+            std::env::var(var).ok().filter(|k| !k.is_empty())
+        })
+    }
+}
+
+/// Create the backend described by `config`.
+pub fn create_provider(config: &AiConfig) -> Result<Box<dyn AiProvider>, String> {
+    if let Some(reason) = config.unavailable_reason() {
+        return Err(format!(
+            "{} is not configured: {}",
+            config.provider.label(),
+            reason
+        ));
+    }
+    let model = config.model().to_string();
+    Ok(match config.provider {
+        ProviderKind::OpenRouter => Box::new(OpenRouterClient::new()?.with_model(model)),
+        ProviderKind::OpenAi => Box::new(OpenAiCompatibleClient::new(
+            config.base_url.as_deref().unwrap_or_default(),
+            config.api_key(),
+            model,
+        )?),
+        ProviderKind::Ollama => Box::new(OllamaClient::new(
+            config.base_url.as_deref().unwrap_or(DEFAULT_OLLAMA_URL),
+            model,
+        )?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ai_config_from_toml() {
+        let config: AiConfig = toml::from_str(
+            "provider = \"openai\"\nbase_url = \"http://localhost:8080/v1\"\nmodel = \"qwen\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.provider, ProviderKind::OpenAi);
+        assert_eq!(config.model(), "qwen");
+        assert_eq!(config.unavailable_reason(), None);
+        // Not every OpenAI-compatible server accepts a reply schema
+        assert!(!config.structured_output());
+
+        let config: AiConfig = toml::from_str("provider = \"ollama\"").unwrap();
+        assert_eq!(config.model(), DEFAULT_OLLAMA_MODEL);
+        assert_eq!(config.unavailable_reason(), None);
+        assert!(config.structured_output());
+
+        let config: AiConfig = toml::from_str("provider = \"openai\"").unwrap();
+        assert_eq!(
+            config.unavailable_reason().as_deref(),
+            Some("Set ai.base_url")
+        );
+        assert!(toml::from_str::<AiConfig>("provider = \"bard\"").is_err());
+    }
+
+    #[test]
+    fn test_task_settings_fall_back_to_the_ai_model() {
+        let config: AiConfig = toml::from_str(
+            "provider = \"ollama\"\nmodel = \"phi3\"\nfallback_models = [\"phi3\", \"gemma2\"]\n\
+             [evaluation]\nmodel = \"llama3.1:70b\"\ntemperature = 0.1\n\
+             [chat]\nmax_tokens = 300\n",
+        )
+        .unwrap();
+
+        let evaluation = config.model_config(PromptKind::Evaluation);
+        assert_eq!(evaluation.model, "llama3.1:70b");
+        assert_eq!(evaluation.temperature, Some(0.1));
+        let chat = config.model_config(PromptKind::Chat);
+        assert_eq!((chat.model.as_str(), chat.max_tokens), ("phi3", Some(300)));
+        assert_eq!(
+            config.model_config(PromptKind::Assessment).temperature,
+            None
+        );
+
+        assert_eq!(config.fallback_models("phi3"), vec!["gemma2"]);
+        // A misspelt key is logged, not fatal
+        let config: AiConfig = toml::from_str("[chat]\ntemp = 1.0\nmax_tokens = 300").unwrap();
+        assert_eq!(config.chat.temperature, None);
+        assert_eq!(config.chat.max_tokens, Some(300));
+    }
+
+    #[test]
+    fn test_create_provider_picks_backend() {
+        let ollama = create_provider(&AiConfig {
+            provider: ProviderKind::Ollama,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(ollama.name(), "Ollama");
+        assert_eq!(ollama.default_model(), DEFAULT_OLLAMA_MODEL);
+
+        let openai = create_provider(&AiConfig {
+            provider: ProviderKind::OpenAi,
+            base_url: Some("http://127.0.0.1:9/v1".to_string()),
+            model: Some("local".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(openai.name(), "OpenAI-compatible");
+        assert_eq!(openai.default_model(), "local");
+
+        let error = create_provider(&AiConfig {
+            provider: ProviderKind::OpenAi,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(error.contains("Set ai.base_url"), "{}", error);
+    }
+
+    #[test]
+    fn test_request_uses_overrides_then_defaults() {
+        let provider = create_provider(&AiConfig {
+            provider: ProviderKind::Ollama,
+            model: Some("phi3".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let request = provider.request(Vec::new(), None, Some(0.5), Some(2048));
+        assert_eq!(request.model, "phi3");
+        assert_eq!(request.temperature, Some(0.5));
+        assert_eq!(request.max_tokens, Some(2048));
+
+        let config = ModelConfig {
+            model: "llama3.1:70b".to_string(),
+            temperature: Some(0.0),
+            max_tokens: None,
+        };
+        let request = provider.request(Vec::new(), Some(&config), Some(0.5), Some(2048));
+        assert_eq!(request.model, "llama3.1:70b");
+        assert_eq!(request.temperature, Some(0.0));
+        assert_eq!(request.max_tokens, Some(2048));
+    }
+}
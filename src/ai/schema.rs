@@ -0,0 +1,266 @@
+//! JSON schemas for the structured replies of evaluations, session assessments and card
+//! generation.
+//!
+//! The schema is sent with the request where the backend supports structured output
+//! (`response_format` on OpenAI-compatible servers and OpenRouter, `format` on Ollama),
+//! and every reply is checked against it whether or not the backend enforced it.
+
+use super::evaluator::clean_json_response;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// A named JSON schema the reply to a request must follow
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseFormat {
+    pub name: &'static str,
+    pub schema: Value,
+}
+
+impl ResponseFormat {
+    /// The reply to an answer evaluation, see [`super::AIFeedback`]
+    pub fn feedback() -> Self {
+        Self {
+            name: "answer_evaluation",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "is_correct": {"type": "boolean"},
+                    "correctness_score": {"type": "number", "minimum": 0, "maximum": 1},
+                    "corrections": {"type": "array", "items": {"type": "string"}},
+                    "explanation": {"type": "string"},
+                    "suggestions": {"type": "array", "items": {"type": "string"}},
+                },
+                "required": ["is_correct", "correctness_score", "corrections", "explanation", "suggestions"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    /// The reply to a session assessment, see [`crate::models::SessionAssessment`]
+    pub fn assessment() -> Self {
+        let strings = json!({"type": "array", "items": {"type": "string"}});
+        Self {
+            name: "session_assessment",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "grade_percentage": {"type": "number", "minimum": 0, "maximum": 100},
+                    "mastery_level": {
+                        "type": "string",
+                        "enum": ["Beginner", "Intermediate", "Advanced", "Expert"],
+                    },
+                    "overall_feedback": {"type": "string"},
+                    "suggestions": strings,
+                    "strengths": strings,
+                    "weaknesses": strings,
+                },
+                "required": ["grade_percentage", "mastery_level", "overall_feedback", "suggestions", "strengths", "weaknesses"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    /// The reply to a card generation request, see [`super::generator::GeneratedCard`]
+    pub fn cards() -> Self {
+        Self {
+            name: "generated_cards",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "cards": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "question": {"type": "string"},
+                                "answer": {"type": "string"},
+                                "quote": {"type": "string"},
+                            },
+                            "required": ["question", "answer", "quote"],
+                            "additionalProperties": false,
+                        },
+                    },
+                },
+                "required": ["cards"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    /// Parse `reply` as JSON (tolerating code fences and text around the object) and
+    /// check it against the schema.
+    pub fn check(&self, reply: &str) -> Result<Value, SchemaMismatch> {
+        let cleaned = clean_json_response(reply);
+        let value: Value = serde_json::from_str(&cleaned).map_err(|e| SchemaMismatch {
+            problems: vec![format!("not valid JSON: {}", e)],
+        })?;
+        let mut problems = Vec::new();
+        validate(&value, &self.schema, "", &mut problems);
+        if problems.is_empty() {
+            Ok(value)
+        } else {
+            Err(SchemaMismatch { problems })
+        }
+    }
+}
+
+/// Why a reply does not follow its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaMismatch {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reply does not match the expected format ({})",
+            self.problems.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for SchemaMismatch {}
+
+/// Check `value` against the subset of JSON Schema used above, adding a
+/// `location: message` line to `problems` for everything that does not match.
+fn validate(value: &Value, schema: &Value, location: &str, problems: &mut Vec<String>) {
+    let mut problem = |message: String| {
+        let location = if location.is_empty() {
+            "reply"
+        } else {
+            location
+        };
+        problems.push(format!("{}: {}", location, message));
+    };
+
+    let expected = schema["type"].as_str().unwrap_or_default();
+    let matches = match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        _ => true,
+    };
+    if !matches {
+        problem(format!("expected {} {}", article(expected), expected));
+        return;
+    }
+
+    if let Some(allowed) = schema["enum"]
+        .as_array()
+        .filter(|allowed| !allowed.contains(value))
+    {
+        let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+        problem(format!("{} is not one of {}", value, allowed.join(", ")));
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema["minimum"].as_f64().filter(|&min| number < min) {
+            problem(format!("{} is less than {}", number, minimum));
+        }
+        if let Some(maximum) = schema["maximum"].as_f64().filter(|&max| number > max) {
+            problem(format!("{} is more than {}", number, maximum));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema["properties"].as_object();
+        let field = |name: &str| {
+            if location.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", location, name)
+            }
+        };
+        for required in schema["required"].as_array().into_iter().flatten() {
+            if let Some(name) = required.as_str().filter(|name| !object.contains_key(*name)) {
+                problems.push(format!("{}: missing", field(name)));
+            }
+        }
+        for (name, item) in object {
+            match properties.and_then(|p| p.get(name)) {
+                Some(property) => validate(item, property, &field(name), problems),
+                None if schema["additionalProperties"] == Value::Bool(false) => {
+                    problems.push(format!("{}: unknown field", field(name)))
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(item, schema, &format!("{}[{}]", location, i), problems);
+        }
+    }
+}
+
+fn article(type_name: &str) -> &'static str {
+    match type_name.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(format: &ResponseFormat, reply: &str) -> Vec<String> {
+        format
+            .check(reply)
+            .err()
+            .map(|e| e.problems)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_valid_replies_pass() {
+        let feedback = r#"```json
+{"is_correct": true, "correctness_score": 0.9, "corrections": [], "explanation": "Right.", "suggestions": ["Add detail"]}
+```"#;
+        let value = ResponseFormat::feedback().check(feedback).unwrap();
+        assert_eq!(value["explanation"], "Right.");
+
+        let assessment = r#"Here it is: {"grade_percentage": 75, "mastery_level": "Advanced",
+            "overall_feedback": "Good.", "suggestions": [], "strengths": ["a"], "weaknesses": []}"#;
+        assert!(ResponseFormat::assessment().check(assessment).is_ok());
+    }
+
+    #[test]
+    fn test_mismatches_are_listed_with_paths() {
+        let reply = r#"{"is_correct": "yes", "correctness_score": 1.5, "corrections": [3],
+            "explanation": "x", "confidence": 0.2}"#;
+        assert_eq!(
+            problems(&ResponseFormat::feedback(), reply),
+            vec![
+                "suggestions: missing",
+                "confidence: unknown field",
+                "corrections[0]: expected a string",
+                "correctness_score: 1.5 is more than 1",
+                "is_correct: expected a boolean",
+            ]
+        );
+
+        let reply = r#"{"grade_percentage": 75, "mastery_level": "Guru", "overall_feedback": "",
+            "suggestions": [], "strengths": [], "weaknesses": {}}"#;
+        assert_eq!(
+            problems(&ResponseFormat::assessment(), reply),
+            vec![
+                r#"mastery_level: "Guru" is not one of "Beginner", "Intermediate", "Advanced", "Expert""#,
+                "weaknesses: expected an array",
+            ]
+        );
+
+        let not_json = problems(&ResponseFormat::feedback(), "Sure! The answer looks right");
+        assert_eq!(not_json.len(), 1);
+        assert!(not_json[0].starts_with("not valid JSON"), "{:?}", not_json);
+        assert_eq!(
+            problems(&ResponseFormat::feedback(), "[1]"),
+            vec!["reply: expected an object"]
+        );
+    }
+}
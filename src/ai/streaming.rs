@@ -0,0 +1,65 @@
+//! Incremental responses: backends turn their HTTP bodies into a [`TextStream`] of
+//! text fragments as they arrive.
+
+use super::error::AiError;
+use super::provider::{AiResult, Completion};
+use super::usage::Usage;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+/// One piece of a streamed reply
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delta {
+    /// The next fragment of text; concatenated they make the full reply
+    Text(String),
+    /// The tokens the call used, sent near the end by backends that report them
+    Usage(Usage),
+}
+
+/// The pieces of a reply, in order
+pub type TextStream = Pin<Box<dyn Stream<Item = AiResult<Delta>> + Send>>;
+
+/// A stream yielding a whole completion at once, for backends that cannot stream.
+pub fn single(completion: Completion) -> TextStream {
+    let mut deltas = vec![Ok(Delta::Text(completion.text))];
+    deltas.extend(completion.usage.map(|usage| Ok(Delta::Usage(usage))));
+    Box::pin(futures::stream::iter(deltas))
+}
+
+/// Split a response body into lines (without their line endings) as it arrives.
+pub fn lines(response: reqwest::Response) -> impl Stream<Item = AiResult<String>> + Send {
+    let bytes = Box::pin(response.bytes_stream());
+    futures::stream::unfold(
+        (bytes, Vec::new(), false),
+        |(mut bytes, mut buffer, mut done)| async move {
+            loop {
+                if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    return Some((Ok(decode_line(&line)), (bytes, buffer, done)));
+                }
+                if done {
+                    if buffer.is_empty() {
+                        return None;
+                    }
+                    let line = decode_line(&std::mem::take(&mut buffer));
+                    return Some((Ok(line), (bytes, buffer, done)));
+                }
+                match bytes.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    Some(Err(e)) => {
+                        buffer.clear();
+                        let error = AiError::Network(format!("Response stream failed: {}", e));
+                        return Some((Err(error), (bytes, buffer, true)));
+                    }
+                    None => done = true,
+                }
+            }
+        },
+    )
+}
+
+fn decode_line(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(['\r', '\n'])
+        .to_string()
+}
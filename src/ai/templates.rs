@@ -0,0 +1,408 @@
+//! Prompt templates with `{{name}}` placeholders.
+//!
+//! Every kind of request has a built-in template (`src/ai/templates/*.toml`). A file
+//! with the same name in the prompt folder (`prompts` in the config directory unless
+//! `ai.prompt_dir` says otherwise) overrides it for every deck, and one in
+//! `decks/<deck>/` under that folder overrides it for one deck. Decks in subfolders
+//! also pick up the overrides of each folder above them: for `networking/manet`,
+//! `decks/networking/` applies first and `decks/networking/manet/` last. An override may
+//! set only `system` or only `user`; the other part comes from the next level up.
+//!
+//! The worker reads each deck's templates once and keeps them in a [`TemplateCache`]
+//! until the AI settings change.
+//!
+//! ```toml
+//! # ~/.config/interactive-flashcards/prompts/decks/networking/evaluation.toml
+//! system = "You grade networking exams strictly and explain in Italian."
+//! ```
+
+use super::provider::ChatMessage;
+use crate::logger;
+use regex::Regex;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptKind {
+    Evaluation,
+    Assessment,
+    Chat,
+    /// Writing flashcards from a passage of a document
+    Generation,
+}
+
+impl PromptKind {
+    pub const ALL: [PromptKind; 4] = [
+        PromptKind::Evaluation,
+        PromptKind::Assessment,
+        PromptKind::Chat,
+        PromptKind::Generation,
+    ];
+
+    /// File name (without `.toml`) of the template
+    pub fn name(self) -> &'static str {
+        match self {
+            PromptKind::Evaluation => "evaluation",
+            PromptKind::Assessment => "assessment",
+            PromptKind::Chat => "chat",
+            PromptKind::Generation => "generation",
+        }
+    }
+
+    /// The placeholders the template may use
+    pub fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            PromptKind::Evaluation => &["question", "correct_answer", "user_answer", "grading"],
+            PromptKind::Assessment => &["deck", "total", "answered", "correct", "answers"],
+            PromptKind::Chat => &[
+                "question",
+                "correct_answer",
+                "user_answer",
+                "feedback",
+                "message",
+                "sources",
+            ],
+            PromptKind::Generation => &["document", "location", "text", "count"],
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            PromptKind::Evaluation => include_str!("templates/evaluation.toml"),
+            PromptKind::Assessment => include_str!("templates/assessment.toml"),
+            PromptKind::Chat => include_str!("templates/chat.toml"),
+            PromptKind::Generation => include_str!("templates/generation.toml"),
+        }
+    }
+}
+
+/// The system and user messages of one kind of request
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    pub kind: PromptKind,
+    pub system: String,
+    pub user: String,
+}
+
+/// A template file; parts it leaves out are inherited
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    system: Option<String>,
+    user: Option<String>,
+}
+
+impl PromptTemplate {
+    /// The template compiled into the app.
+    pub fn builtin(kind: PromptKind) -> Self {
+        let file = parse_file(kind, kind.builtin()).expect("built-in templates are valid");
+        Self {
+            kind,
+            system: file.system.unwrap_or_default(),
+            user: file.user.unwrap_or_default(),
+        }
+    }
+
+    /// The template for `deck`: the built-in one, overridden by `dir/<kind>.toml` and
+    /// then by `<kind>.toml` in each folder from `dir/decks/` down to `dir/decks/<deck>/`,
+    /// where those exist. A deck name that could point outside `dir/decks` gets only the
+    /// overrides for every deck.
+    pub fn load(dir: &Path, deck: &str, kind: PromptKind) -> Result<Self, String> {
+        let mut template = Self::builtin(kind);
+        let file_name = format!("{}.toml", kind.name());
+        let mut paths = vec![dir.join(&file_name)];
+        let mut folder = dir.join("decks");
+        for part in deck_folders(deck) {
+            folder.push(part);
+            paths.push(folder.join(&file_name));
+        }
+        for path in paths {
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            };
+            let file =
+                parse_file(kind, &content).map_err(|e| format!("{}: {}", path.display(), e))?;
+            if let Some(system) = file.system {
+                template.system = system;
+            }
+            if let Some(user) = file.user {
+                template.user = user;
+            }
+        }
+        if kind == PromptKind::Chat && !template.uses("sources") {
+            logger::log(&format!(
+                "The chat template for {} has no {{{{sources}}}} placeholder, so the chat is not \
+                 given the passages of the deck's source documents",
+                deck
+            ));
+        }
+        Ok(template)
+    }
+
+    /// Whether the system or user message has the placeholder `name`.
+    pub fn uses(&self, name: &str) -> bool {
+        [&self.system, &self.user]
+            .into_iter()
+            .any(|text| PLACEHOLDER.captures_iter(text).any(|caps| &caps[1] == name))
+    }
+
+    /// A short hash of the template text, so that results produced with an older
+    /// version of a prompt can be told apart.
+    pub fn version(&self) -> String {
+        let digest = Sha1::new()
+            .chain_update(self.system.as_bytes())
+            .chain_update([0])
+            .chain_update(self.user.as_bytes())
+            .finalize();
+        digest[..6].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The system and user messages with every placeholder replaced by its value.
+    pub fn render(&self, values: &[(&str, &str)]) -> [ChatMessage; 2] {
+        let fill = |text: &str| {
+            PLACEHOLDER
+                .replace_all(text, |caps: &regex::Captures| {
+                    let name = &caps[1];
+                    values
+                        .iter()
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| value.to_string())
+                        .unwrap_or_default()
+                })
+                .into_owned()
+        };
+        [
+            ChatMessage::new("system", &fill(&self.system)),
+            ChatMessage::new("user", &fill(&self.user)),
+        ]
+    }
+}
+
+/// The folders of `deck`'s name (`networking/manet` is `networking`, `manet`), or none
+/// if any part is not a plain folder name.
+fn deck_folders(deck: &str) -> Vec<&str> {
+    let parts: Vec<&str> = deck.split('/').collect();
+    let plain = |part: &&str| {
+        !part.is_empty() && *part != "." && *part != ".." && !part.contains(['\\', ':'])
+    };
+    if parts.iter().all(plain) {
+        parts
+    } else {
+        Vec::new()
+    }
+}
+
+/// The templates of one prompt folder, each read once per deck and kind. Templates that
+/// fail to load are not kept, so a fixed file is picked up on the next request.
+#[derive(Debug)]
+pub struct TemplateCache {
+    dir: PathBuf,
+    loaded: Mutex<HashMap<(String, PromptKind), PromptTemplate>>,
+}
+
+impl TemplateCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// [`PromptTemplate::load`] for `deck` and `kind`, read from disk the first time only.
+    pub fn get(&self, deck: &str, kind: PromptKind) -> Result<PromptTemplate, String> {
+        let key = (deck.to_string(), kind);
+        if let Some(template) = self.loaded.lock().unwrap().get(&key) {
+            return Ok(template.clone());
+        }
+        let template = PromptTemplate::load(&self.dir, deck, kind)?;
+        self.loaded.lock().unwrap().insert(key, template.clone());
+        Ok(template)
+    }
+}
+
+lazy_static::lazy_static! {
+    /// `{{name}}`, allowing spaces inside the braces
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap();
+}
+
+/// Parse a template file, rejecting placeholders the kind does not provide.
+fn parse_file(kind: PromptKind, content: &str) -> Result<TemplateFile, String> {
+    let file: TemplateFile = toml::from_str(content).map_err(|e| e.message().trim().to_string())?;
+    let known = kind.placeholders();
+    for text in [&file.system, &file.user].into_iter().flatten() {
+        for caps in PLACEHOLDER.captures_iter(text) {
+            if !known.contains(&&caps[1]) {
+                return Err(format!(
+                    "unknown placeholder {{{{{}}}}} (expected one of: {})",
+                    &caps[1],
+                    known.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let [system, user] = PromptTemplate::builtin(PromptKind::Evaluation).render(&[
+            ("question", "What does AODV stand for?"),
+            ("correct_answer", "Ad hoc On-Demand Distance Vector"),
+            ("user_answer", "{{question}}"),
+            ("grading", ""),
+        ]);
+        assert_eq!(system.role, "system");
+        assert!(user.content.starts_with("Evaluate this answer"));
+        assert!(user
+            .content
+            .contains("Question: What does AODV stand for?\n"));
+        // Values are not expanded again
+        assert!(user.content.contains("User's Answer: {{question}}\n"));
+        assert!(user.content.contains("\"is_correct\": boolean"));
+
+        let [_, user] = PromptTemplate::builtin(PromptKind::Chat).render(&[("message", "Why?")]);
+        assert_eq!(user.content, "Why?");
+        for kind in PromptKind::ALL {
+            let template = PromptTemplate::builtin(kind);
+            assert!(!template.system.is_empty() && !template.user.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_overrides_layer_per_user_then_per_deck() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("evaluation.toml"),
+            "system = \"Be strict.\"\nuser = \"Grade: {{ user_answer }}\"\n",
+        )
+        .unwrap();
+        let deck_dir = dir.path().join("decks").join("networking").join("manet");
+        fs::create_dir_all(&deck_dir).unwrap();
+        fs::write(
+            deck_dir.join("evaluation.toml"),
+            "system = \"Rispondi in italiano.\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("decks/networking/evaluation.toml"),
+            "system = \"Networking exam.\"\nuser = \"Network answer: {{user_answer}}\"\n",
+        )
+        .unwrap();
+
+        // The deck's own folder wins over the folder above it
+        let template =
+            PromptTemplate::load(dir.path(), "networking/manet", PromptKind::Evaluation).unwrap();
+        assert_eq!(template.system, "Rispondi in italiano.");
+        assert_eq!(template.user, "Network answer: {{user_answer}}");
+        let [_, user] = template.render(&[("user_answer", "42")]);
+        assert_eq!(user.content, "Network answer: 42");
+
+        let template =
+            PromptTemplate::load(dir.path(), "networking/wifi", PromptKind::Evaluation).unwrap();
+        assert_eq!(template.system, "Networking exam.");
+
+        let template = PromptTemplate::load(dir.path(), "biology", PromptKind::Evaluation).unwrap();
+        assert_eq!(template.system, "Be strict.");
+        assert_eq!(template.version().len(), 12);
+        assert_ne!(
+            template.version(),
+            PromptTemplate::builtin(PromptKind::Evaluation).version()
+        );
+        assert_eq!(
+            PromptTemplate::load(dir.path(), "biology", PromptKind::Chat).unwrap(),
+            PromptTemplate::builtin(PromptKind::Chat)
+        );
+    }
+
+    #[test]
+    fn test_deck_names_cannot_leave_the_decks_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("outside")).unwrap();
+        fs::write(
+            dir.path().join("outside/evaluation.toml"),
+            "system = \"Escaped.\"\n",
+        )
+        .unwrap();
+
+        for deck in [
+            "../outside",
+            "a/../../outside",
+            "/outside",
+            "a\\..\\..\\outside",
+        ] {
+            assert_eq!(
+                PromptTemplate::load(dir.path(), deck, PromptKind::Evaluation).unwrap(),
+                PromptTemplate::builtin(PromptKind::Evaluation),
+                "{}",
+                deck
+            );
+        }
+    }
+
+    #[test]
+    fn test_chat_override_without_sources() {
+        assert!(PromptTemplate::builtin(PromptKind::Chat).uses("sources"));
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("chat.toml"),
+            "system = \"You tutor on {{ question }}.\"\n",
+        )
+        .unwrap();
+        let template = PromptTemplate::load(dir.path(), "manet", PromptKind::Chat).unwrap();
+        assert!(template.uses("question"));
+        assert!(!template.uses("sources"));
+    }
+
+    #[test]
+    fn test_cache_reads_each_template_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.toml");
+        fs::write(&path, "system = \"First.\"\n").unwrap();
+        let cache = TemplateCache::new(dir.path().to_path_buf());
+        assert_eq!(cache.get("d", PromptKind::Chat).unwrap().system, "First.");
+
+        fs::write(&path, "system = \"Second.\"\n").unwrap();
+        assert_eq!(cache.get("d", PromptKind::Chat).unwrap().system, "First.");
+        // Other decks and kinds are read when first asked for
+        assert_eq!(cache.get("e", PromptKind::Chat).unwrap().system, "Second.");
+
+        // Failures are not kept
+        fs::write(dir.path().join("assessment.toml"), "usr = \"typo\"\n").unwrap();
+        assert!(cache.get("d", PromptKind::Assessment).is_err());
+        fs::write(dir.path().join("assessment.toml"), "system = \"Fixed.\"\n").unwrap();
+        assert_eq!(
+            cache.get("d", PromptKind::Assessment).unwrap().system,
+            "Fixed."
+        );
+    }
+
+    #[test]
+    fn test_invalid_overrides_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("assessment.toml");
+
+        fs::write(&path, "user = \"{{question}}\"\n").unwrap();
+        let error = PromptTemplate::load(dir.path(), "d", PromptKind::Assessment).unwrap_err();
+        assert!(error.contains("assessment.toml"), "{}", error);
+        assert!(
+            error.contains("unknown placeholder {{question}}"),
+            "{}",
+            error
+        );
+
+        fs::write(&path, "usr = \"typo\"\n").unwrap();
+        let error = PromptTemplate::load(dir.path(), "d", PromptKind::Assessment).unwrap_err();
+        assert!(error.contains("unknown field"), "{}", error);
+    }
+}
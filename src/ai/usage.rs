@@ -0,0 +1,198 @@
+//! Token usage and cost of AI calls, recorded in the `ai_usage` table.
+//!
+//! Backends report the tokens each call used, and OpenRouter its cost as well. Calls
+//! to a model with a price in `[ai.prices]` are costed from it when the backend reports
+//! no cost; other calls (such as to a local model) cost nothing. Once this month's calls
+//! have cost `ai.monthly_budget`, further calls fail until the next month.
+//!
+//! ```toml
+//! [ai]
+//! monthly_budget = 5.0                 # USD
+//!
+//! [ai.prices."openai/gpt-4o-mini"]     # USD per million tokens
+//! input = 0.15
+//! output = 0.60
+//! ```
+
+use crate::db::ai_usage::{self, UsageRecord};
+use crate::logger;
+use chrono::{Datelike, Local, TimeZone};
+use rusqlite::Connection;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// The tokens one call used, as the backend reported them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// What the call cost in USD, for backends that say
+    pub cost: Option<f64>,
+}
+
+impl Usage {
+    /// What the call cost in USD: as reported, else at `price`, else nothing.
+    pub fn cost(&self, price: Option<&ModelPrice>) -> f64 {
+        match (self.cost, price) {
+            (Some(cost), _) => cost,
+            (None, Some(price)) => {
+                (self.prompt_tokens as f64 * price.input
+                    + self.completion_tokens as f64 * price.output)
+                    / 1_000_000.0
+            }
+            (None, None) => 0.0,
+        }
+    }
+}
+
+/// The price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(from = "PriceTable")]
+pub struct ModelPrice {
+    /// Per million prompt tokens
+    pub input: f64,
+    /// Per million completion tokens
+    pub output: f64,
+}
+
+/// An `[ai.prices]` entry as written; a missing price is 0, and other keys are logged
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct PriceTable {
+    input: f64,
+    output: f64,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl From<PriceTable> for ModelPrice {
+    fn from(table: PriceTable) -> Self {
+        crate::config::warn_unknown_keys("[ai.prices]", &table.unknown);
+        Self {
+            input: table.input,
+            output: table.output,
+        }
+    }
+}
+
+/// A shared connection to the usage table, taken in turns by the worker's tasks like
+/// the [`super::cache::EvaluationCache`].
+#[derive(Debug, Clone)]
+pub struct UsageLog {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl UsageLog {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    /// The usage table in the app database, if it can be opened.
+    pub fn open() -> Option<Self> {
+        match crate::db::init_db() {
+            Ok(conn) => Some(Self::new(conn)),
+            Err(e) => {
+                logger::log(&format!("AI usage log unavailable: {}", e));
+                None
+            }
+        }
+    }
+
+    pub async fn record(&self, record: &UsageRecord) {
+        let record = record.clone();
+        let recorded = self
+            .with_conn(move |conn| ai_usage::record(conn, &record))
+            .await;
+        if let Err(e) = recorded {
+            logger::log(&format!("Failed to record AI usage: {}", e));
+        }
+    }
+
+    /// What this calendar month's calls have cost in USD.
+    pub async fn month_cost(&self) -> f64 {
+        self.with_conn(|conn| ai_usage::cost_since(conn, month_start()))
+            .await
+            .unwrap_or_else(|e| {
+                logger::log(&format!("Failed to read AI usage: {}", e));
+                0.0
+            })
+    }
+
+    /// Run `f` on the log's connection on the blocking thread pool.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, String> {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&conn).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+/// The start of the current calendar month in local time, as a Unix timestamp.
+pub fn month_start() -> u64 {
+    let today = Local::now();
+    Local
+        .with_ymd_and_hms(today.year(), today.month(), 1, 0, 0, 0)
+        .earliest()
+        .map_or(0, |start| start.timestamp().max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_prefers_the_reported_one() {
+        let price = ModelPrice {
+            input: 0.5,
+            output: 2.0,
+        };
+        let usage = Usage {
+            prompt_tokens: 2_000_000,
+            completion_tokens: 500_000,
+            cost: None,
+        };
+        assert_eq!(usage.cost(Some(&price)), 2.0);
+        assert_eq!(usage.cost(None), 0.0);
+        let reported = Usage {
+            cost: Some(0.25),
+            ..usage
+        };
+        assert_eq!(reported.cost(Some(&price)), 0.25);
+        assert!(month_start() <= Local::now().timestamp() as u64);
+    }
+
+    #[test]
+    fn test_prices_ignore_unknown_keys() {
+        let prices: std::collections::HashMap<String, ModelPrice> = toml::from_str(
+            r#"
+            "small" = { input = 1, output = 2.5 }
+            "typo" = { inptu = 0.15, output = 0.6 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            prices["small"],
+            ModelPrice {
+                input: 1.0,
+                output: 2.5
+            }
+        );
+        assert_eq!(
+            prices["typo"],
+            ModelPrice {
+                input: 0.0,
+                output: 0.6
+            }
+        );
+    }
+}
@@ -1,215 +1,1630 @@
-use crate::ai::{evaluate_answer, OpenRouterClient};
+use crate::ai::cache::{cache_key, EvaluationCache, DEFAULT_TTL_DAYS};
+use crate::ai::evaluator::{
+    parse_evaluation, parse_session_assessment, partial_explanation, AIEvaluationResult,
+};
+use crate::ai::generator::parse_generated_cards;
+use crate::ai::prompts;
+use crate::ai::provider::CompletionRequest;
+use crate::ai::schema::SchemaMismatch;
+use crate::ai::streaming::Delta;
+use crate::ai::templates::{PromptKind, PromptTemplate, TemplateCache};
+use crate::ai::usage::{Usage, UsageLog};
+use crate::ai::{create_provider, AiConfig, AiError, AiProvider};
+use crate::db::ai_usage::UsageRecord;
+use crate::ingest::CARDS_PER_CHUNK;
 use crate::logger;
-use crate::models::{AiRequest, AiResponse};
+use crate::models::{AiRequest, AiResponse, RequestId};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::time::{timeout, Duration};
+use tokio::sync::Semaphore;
+use tokio::task::AbortHandle;
+use tokio::time::{timeout, Duration, Instant};
 
-const CHAT_TIMEOUT_SECS: u64 = 30;
+const EVALUATE_TIMEOUT: Duration = Duration::from_secs(30);
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const CHAT_TIMEOUT: Duration = Duration::from_secs(30);
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(120);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Answer evaluations running at once unless `ai.max_concurrent` says otherwise
+const MAX_CONCURRENT_EVALUATIONS: usize = 3;
+/// Retries after a transient failure unless `ai.max_retries` says otherwise
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
 
+/// How long to wait before retry number `retry` (counting from 1): doubling from
+/// `BASE_RETRY_DELAY` up to `MAX_RETRY_DELAY`, minus up to half of that depending on
+/// `jitter` (0 to 1) so that requests failing together do not retry together.
+fn retry_delay(retry: u32, jitter: f64) -> Duration {
+    let delay = BASE_RETRY_DELAY
+        .saturating_mul(1 << retry.saturating_sub(1).min(16))
+        .min(MAX_RETRY_DELAY);
+    delay.mul_f64(1.0 - jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// Counts the attempts at one request and decides whether another is worth making,
+/// moving on to the next fallback model once the current one has used up its retries.
+struct Retries {
+    what: &'static str,
+    attempt: u32,
+    max_retries: u32,
+    /// The model the next attempt goes to
+    model: String,
+    fallbacks: Vec<String>,
+}
+
+impl Retries {
+    fn new(what: &'static str, max_retries: u32, model: &str, ai_config: &AiConfig) -> Self {
+        Self {
+            what,
+            attempt: 1,
+            max_retries,
+            model: model.to_string(),
+            fallbacks: ai_config.fallback_models(model),
+        }
+    }
+
+    /// `request` sent to the current model.
+    fn on_model(&self, request: CompletionRequest) -> CompletionRequest {
+        CompletionRequest {
+            model: self.model.clone(),
+            ..request
+        }
+    }
+
+    /// Log a failed attempt. If the error is transient and retries are left, wait for
+    /// the backoff delay and return true; if not, switch to the next fallback model (when
+    /// one may help) and return true. The caller then tries again.
+    async fn retry(&mut self, error: &AiError) -> bool {
+        logger::log(&format!(
+            "Worker {} attempt {}/{} with {} failed: {}",
+            self.what,
+            self.attempt,
+            self.max_retries + 1,
+            self.model,
+            error
+        ));
+        if self.attempt <= self.max_retries && error.is_transient() {
+            let delay = retry_delay(self.attempt, rand::random());
+            logger::log(&format!(
+                "Worker retrying {} in {} ms",
+                self.what,
+                delay.as_millis()
+            ));
+            tokio::time::sleep(delay).await;
+            self.attempt += 1;
+            return true;
+        }
+        if !error.may_fall_back() || self.fallbacks.is_empty() {
+            return false;
+        }
+        let model = self.fallbacks.remove(0);
+        logger::log(&format!(
+            "Worker falling back from {} to {} for {}",
+            self.model, model, self.what
+        ));
+        self.model = model;
+        self.attempt = 1;
+        true
+    }
+}
+
+/// Records every call made for one request in the usage log and reports it to the UI,
+/// and refuses calls once the monthly budget is used up.
+struct Meter<'a> {
+    log: Option<&'a UsageLog>,
+    config: &'a AiConfig,
+    kind: PromptKind,
+    deck_name: &'a str,
+    session_id: Option<u64>,
+    ai_tx: &'a Sender<AiResponse>,
+}
+
+impl Meter<'_> {
+    /// Fail if this month's calls have already cost the `ai.monthly_budget`.
+    async fn check_budget(&self) -> Result<(), AiError> {
+        let (Some(budget), Some(log)) = (self.config.monthly_budget, self.log) else {
+            return Ok(());
+        };
+        let spent = log.month_cost().await;
+        if spent >= budget {
+            return Err(AiError::BudgetExceeded { spent, budget });
+        }
+        Ok(())
+    }
+
+    async fn record(&self, model: &str, usage: Option<Usage>, latency: Duration) {
+        let usage = usage.unwrap_or_default();
+        let record = UsageRecord {
+            session_id: self.session_id,
+            deck_name: self.deck_name.to_string(),
+            kind: self.kind.name().to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            latency_ms: latency.as_millis() as u64,
+            cost: usage.cost(self.config.prices.get(model)),
+        };
+        logger::log(&format!(
+            "Worker {} call to {}: {} + {} tokens in {} ms, ${:.6}",
+            record.kind,
+            record.model,
+            record.prompt_tokens,
+            record.completion_tokens,
+            record.latency_ms,
+            record.cost
+        ));
+        if let Some(log) = self.log {
+            log.record(&record).await;
+        }
+        let _ = self.ai_tx.send(AiResponse::Usage { record }).await;
+    }
+}
+
+/// Send a completion and wait at most `limit` for it, recording the call.
+async fn complete(
+    client: &dyn AiProvider,
+    request: CompletionRequest,
+    limit: Duration,
+    meter: &Meter<'_>,
+) -> Result<String, AiError> {
+    meter.check_budget().await?;
+    let model = request.model.clone();
+    let started = Instant::now();
+    let completion = match timeout(limit, client.complete(request)).await {
+        Ok(completion) => completion?,
+        Err(_) => return Err(AiError::Timeout(limit)),
+    };
+    meter
+        .record(&model, completion.usage, started.elapsed())
+        .await;
+    Ok(completion.text)
+}
+
+/// Read a streamed reply to the end, recording the call. After each fragment,
+/// `progress` gets the fragment and the text so far and may return an update to send
+/// to the UI. `limit` bounds each wait: for the reply to start and between fragments.
+async fn read_stream(
+    client: &dyn AiProvider,
+    request: CompletionRequest,
+    limit: Duration,
+    meter: &Meter<'_>,
+    mut progress: impl FnMut(&str, &str) -> Option<AiResponse>,
+) -> Result<String, AiError> {
+    meter.check_budget().await?;
+    let model = request.model.clone();
+    let started = Instant::now();
+    let mut stream = match timeout(limit, client.complete_stream(request)).await {
+        Ok(result) => result?,
+        Err(_) => return Err(AiError::Timeout(limit)),
+    };
+
+    let mut text = String::new();
+    let mut usage = None;
+    loop {
+        match timeout(limit, stream.next()).await {
+            Ok(Some(delta)) => match delta? {
+                Delta::Text(fragment) => {
+                    text.push_str(&fragment);
+                    if let Some(update) = progress(&fragment, &text) {
+                        let _ = meter.ai_tx.send(update).await;
+                    }
+                }
+                Delta::Usage(reported) => usage = Some(reported),
+            },
+            Ok(None) => {
+                meter.record(&model, usage, started.elapsed()).await;
+                return Ok(text);
+            }
+            Err(_) => return Err(AiError::Timeout(limit)),
+        }
+    }
+}
+
+/// The configuration requests run with and the client built from it. One client serves
+/// every request, so its connection pool is reused.
+struct Backend {
+    config: AiConfig,
+    client: Result<Arc<dyn AiProvider>, AiError>,
+    templates: TemplateCache,
+}
+
+impl Backend {
+    fn new(config: AiConfig) -> Self {
+        let client = create_provider(&config)
+            .map(Arc::from)
+            .map_err(|e| AiError::Config(format!("Failed to create AI client: {}", e)));
+        let templates = TemplateCache::new(config.prompt_dir());
+        Self {
+            config,
+            client,
+            templates,
+        }
+    }
+}
+
+/// Find out whether the configured backend is reachable and accepts the key, without
+/// generating anything.
+pub async fn check_health(ai_config: &AiConfig) -> Result<(), AiError> {
+    let client = Backend::new(ai_config.clone()).client?;
+    let limit = ai_config.timeout(HEALTH_CHECK_TIMEOUT);
+    let result = match timeout(limit, client.health_check()).await {
+        Ok(result) => result,
+        Err(_) => Err(AiError::Timeout(limit)),
+    };
+    match &result {
+        Ok(()) => logger::log(&format!("AI health check passed for {}", client.name())),
+        Err(e) => logger::log(&format!(
+            "AI health check failed for {}: {}",
+            client.name(),
+            e
+        )),
+    }
+    result
+}
+
+/// Run the AI worker: every request is handled in its own task, so a slow reply never
+/// holds up the others. Evaluations queue for a bounded number of slots (in the order
+/// they were requested); chat, session assessments and card generation start right away.
+/// `Cancel` aborts the task of an evaluation, chat or generation request, wherever it is. The client is built once
+/// and rebuilt only when `Reconfigure` brings a different configuration. Evaluations
+/// are looked up in the app database's evaluation cache first, and every call is
+/// recorded in its usage log.
 pub fn spawn_ai_worker(
+    ai_config: AiConfig,
+    ai_tx: Sender<AiResponse>,
+    ai_rx: Receiver<AiRequest>,
+) -> tokio::task::JoinHandle<()> {
+    let cache = EvaluationCache::open();
+    let usage_log = UsageLog::open();
+    spawn_ai_worker_with(ai_config, cache, usage_log, ai_tx, ai_rx)
+}
+
+/// [`spawn_ai_worker`] with the given evaluation cache and usage log, or without.
+pub fn spawn_ai_worker_with(
+    ai_config: AiConfig,
+    cache: Option<EvaluationCache>,
+    usage_log: Option<UsageLog>,
     ai_tx: Sender<AiResponse>,
     mut ai_rx: Receiver<AiRequest>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         logger::log("AI worker started (async)");
+        let mut evaluation_slots = Arc::new(Semaphore::new(
+            ai_config.max_concurrent(MAX_CONCURRENT_EVALUATIONS),
+        ));
+        let mut backend = Arc::new(Backend::new(ai_config));
+        let mut running: HashMap<RequestId, AbortHandle> = HashMap::new();
         while let Some(request) = ai_rx.recv().await {
-            match request {
-                AiRequest::Evaluate {
-                    flashcard_index,
-                    question,
-                    correct_answer,
-                    user_answer,
-                } => {
-                    logger::log(&format!(
-                        "Worker received request for flashcard {}",
-                        flashcard_index
-                    ));
-
-                    let client = match OpenRouterClient::new() {
-                        Ok(client) => client,
-                        Err(e) => {
-                            let _ = ai_tx
-                                .send(AiResponse::Error {
-                                    flashcard_index,
-                                    error: format!("Failed to create AI client: {}", e),
-                                })
-                                .await;
-                            continue;
-                        }
-                    };
-
-                    // Add network timeout handling
-                    let evaluation_future =
-                        evaluate_answer(&client, &question, &correct_answer, &user_answer);
-
-                    match timeout(Duration::from_secs(30), evaluation_future).await {
-                        Ok(Ok(eval_result)) => {
-                            logger::log("Worker sending evaluation success");
-                            let _ = ai_tx
-                                .send(AiResponse::Evaluation {
-                                    flashcard_index,
-                                    result: eval_result,
-                                })
-                                .await;
-                        }
-                        Ok(Err(e)) => {
-                            logger::log(&format!("Worker evaluation error: {}", e));
-                            let full_error = format!("AI evaluation failed: {}", e);
-                            let _ = ai_tx
-                                .send(AiResponse::Error {
-                                    flashcard_index,
-                                    error: full_error,
-                                })
-                                .await;
-                        }
-                        Err(_) => {
-                            logger::log("Worker timeout error");
-                            let timeout_error =
-                                "AI evaluation timed out after 30 seconds - press Ctrl+E to retry"
-                                    .to_string();
-                            let _ = ai_tx
-                                .send(AiResponse::Error {
-                                    flashcard_index,
-                                    error: timeout_error,
-                                })
-                                .await;
-                        }
+            running.retain(|_, task| !task.is_finished());
+            let request_id = match request {
+                AiRequest::Cancel { request_id } => {
+                    if let Some(task) = running.remove(&request_id) {
+                        task.abort();
+                        logger::log(&format!("Worker cancelled request {}", request_id.0));
                     }
+                    continue;
                 }
-                AiRequest::EvaluateSession {
-                    session_id,
-                    deck_name,
-                    flashcards,
-                } => {
-                    logger::log(&format!(
-                        "Worker received session assessment request for session {}",
-                        session_id
-                    ));
+                AiRequest::Reconfigure { config } => {
+                    // Requests already running finish with the old client
+                    if *config != backend.config {
+                        logger::log("Worker reconfigured");
+                        evaluation_slots = Arc::new(Semaphore::new(
+                            config.max_concurrent(MAX_CONCURRENT_EVALUATIONS),
+                        ));
+                        backend = Arc::new(Backend::new(*config));
+                    }
+                    continue;
+                }
+                AiRequest::Evaluate { request_id, .. }
+                | AiRequest::Chat { request_id, .. }
+                | AiRequest::GenerateCards { request_id, .. } => Some(request_id),
+                AiRequest::EvaluateSession { .. } => None,
+            };
+            let slots = matches!(request, AiRequest::Evaluate { .. })
+                .then(|| Arc::clone(&evaluation_slots));
+            let backend = Arc::clone(&backend);
+            let cache = cache.clone();
+            let usage_log = usage_log.clone();
+            let ai_tx = ai_tx.clone();
+            let task = tokio::spawn(async move {
+                let _slot = match slots {
+                    Some(slots) => Some(slots.acquire_owned().await.expect("never closed")),
+                    None => None,
+                };
+                handle_request(
+                    request,
+                    &backend,
+                    cache.as_ref(),
+                    usage_log.as_ref(),
+                    &ai_tx,
+                )
+                .await;
+            });
+            if let Some(request_id) = request_id {
+                running.insert(request_id, task.abort_handle());
+            }
+        }
+        logger::log("AI worker exiting (channel closed)");
+    })
+}
 
-                    let client = match OpenRouterClient::new() {
-                        Ok(client) => client,
-                        Err(e) => {
-                            let _ = ai_tx
-                                .send(AiResponse::SessionAssessment {
-                                    session_id,
-                                    result: Err(format!("Failed to create AI client: {}", e)),
-                                })
-                                .await;
-                            continue;
-                        }
-                    };
+/// The client and prompt template for a request to do with `deck_name`, or why there
+/// are none.
+fn prepare(
+    backend: &Backend,
+    deck_name: &str,
+    kind: PromptKind,
+) -> Result<(Arc<dyn AiProvider>, PromptTemplate), AiError> {
+    let client = backend.client.clone()?;
+    let template = backend
+        .templates
+        .get(deck_name, kind)
+        .map_err(AiError::Config)?;
+    Ok((client, template))
+}
 
-                    let evaluation_future = client.evaluate_session(&deck_name, &flashcards, None);
-
-                    match timeout(Duration::from_secs(60), evaluation_future).await {
-                        Ok(Ok(eval_result)) => {
-                            logger::log("Worker sending session assessment success");
-                            let assessment = crate::ai::parse_session_assessment(&eval_result);
-                            let _ = ai_tx
-                                .send(AiResponse::SessionAssessment {
-                                    session_id,
-                                    result: assessment,
-                                })
-                                .await;
-                        }
-                        Ok(Err(e)) => {
-                            logger::log(&format!("Worker session assessment error: {}", e));
-                            let full_error = format!("Session assessment failed: {}", e);
-                            let _ = ai_tx
-                                .send(AiResponse::SessionAssessment {
-                                    session_id,
-                                    result: Err(full_error),
-                                })
-                                .await;
-                        }
-                        Err(_) => {
-                            logger::log("Worker session assessment timeout error");
-                            let timeout_error =
-                                "Session assessment timed out after 60 seconds".to_string();
-                            let _ = ai_tx
-                                .send(AiResponse::SessionAssessment {
-                                    session_id,
-                                    result: Err(timeout_error),
-                                })
-                                .await;
+/// A cached evaluation stored under `key`, if there is one and it still parses.
+async fn cached_evaluation(
+    cache: &EvaluationCache,
+    key: &str,
+    ttl: Duration,
+) -> Option<AIEvaluationResult> {
+    let raw = cache.get(key, ttl).await?;
+    let result = parse_evaluation(&raw).ok()?;
+    logger::log("Worker answering evaluation from the cache");
+    Some(AIEvaluationResult {
+        cached: true,
+        ..result
+    })
+}
+
+/// Drop the reply schema from `request` if the configuration turns structured output off.
+fn structured(mut request: CompletionRequest, ai_config: &AiConfig) -> CompletionRequest {
+    if !ai_config.structured_output() {
+        request.response_format = None;
+    }
+    request
+}
+
+/// Parse a structured reply. If it does not follow its schema, show the model what is
+/// wrong and ask once for a corrected reply before giving up.
+async fn parse_or_repair<T>(
+    client: &dyn AiProvider,
+    request: CompletionRequest,
+    reply: String,
+    limit: Duration,
+    meter: &Meter<'_>,
+    parse: fn(&str) -> Result<T, SchemaMismatch>,
+) -> Result<T, AiError> {
+    let mismatch = match parse(&reply) {
+        Ok(parsed) => return Ok(parsed),
+        Err(mismatch) => mismatch,
+    };
+    logger::log(&format!("Worker asking for a repaired reply: {}", mismatch));
+
+    let repair = CompletionRequest {
+        messages: prompts::repair_messages(&request.messages, &reply, &mismatch.problems),
+        ..request
+    };
+    let repaired = complete(client, repair, limit, meter).await?;
+    parse(&repaired).map_err(|mismatch| AiError::InvalidResponse(mismatch.to_string()))
+}
+
+async fn handle_request(
+    request: AiRequest,
+    backend: &Backend,
+    cache: Option<&EvaluationCache>,
+    usage_log: Option<&UsageLog>,
+    ai_tx: &Sender<AiResponse>,
+) {
+    let ai_config = &backend.config;
+    let max_retries = ai_config.max_retries(DEFAULT_MAX_RETRIES);
+    let meter = |kind, deck_name, session_id| Meter {
+        log: usage_log,
+        config: ai_config,
+        kind,
+        deck_name,
+        session_id,
+        ai_tx,
+    };
+
+    match request {
+        AiRequest::Evaluate {
+            request_id,
+            flashcard_index,
+            session_id,
+            deck_name,
+            question,
+            correct_answer,
+            user_answer,
+            grading,
+            force,
+        } => {
+            logger::log(&format!(
+                "Worker received request for flashcard {}",
+                flashcard_index
+            ));
+
+            let limit = ai_config.timeout(EVALUATE_TIMEOUT);
+            let meter = meter(PromptKind::Evaluation, &deck_name, session_id);
+            let model_config = ai_config.model_config(PromptKind::Evaluation);
+            let mut retries =
+                Retries::new("evaluation", max_retries, &model_config.model, ai_config);
+            let result = match prepare(backend, &deck_name, PromptKind::Evaluation) {
+                Ok((client, template)) => {
+                    let request = structured(
+                        client.evaluation_request(
+                            &template,
+                            &question,
+                            &correct_answer,
+                            &user_answer,
+                            &grading,
+                            Some(&model_config),
+                        ),
+                        ai_config,
+                    );
+                    let cache = cache.zip(ai_config.cache_ttl(DEFAULT_TTL_DAYS));
+                    // Replies are stored under the model that gave them, which after a
+                    // fallback is not the one first asked
+                    let key_for = |model: &str| {
+                        cache_key(
+                            &question,
+                            &correct_answer,
+                            &user_answer,
+                            &grading,
+                            model,
+                            &template.version(),
+                        )
+                    };
+                    let hit = match cache {
+                        Some((cache, ttl)) if !force => {
+                            cached_evaluation(cache, &key_for(&request.model), ttl).await
                         }
+                        _ => None,
+                    };
+                    match hit {
+                        Some(result) => Ok(result),
+                        // Each attempt streams the explanation into the quiz anew, replacing
+                        // whatever an earlier attempt showed
+                        None => loop {
+                            let request = retries.on_model(request.clone());
+                            let mut shown = 0;
+                            let reply = read_stream(
+                                client.as_ref(),
+                                request.clone(),
+                                limit,
+                                &meter,
+                                |_, text| {
+                                    let explanation = partial_explanation(text)?;
+                                    (explanation.len() > shown).then(|| {
+                                        shown = explanation.len();
+                                        AiResponse::EvaluationDelta {
+                                            request_id,
+                                            flashcard_index,
+                                            explanation,
+                                        }
+                                    })
+                                },
+                            )
+                            .await;
+                            let result = match reply {
+                                Ok(reply) => {
+                                    parse_or_repair(
+                                        client.as_ref(),
+                                        request.clone(),
+                                        reply,
+                                        limit,
+                                        &meter,
+                                        parse_evaluation,
+                                    )
+                                    .await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Err(e) if retries.retry(&e).await => continue,
+                                Ok(result) => {
+                                    if let Some((cache, _)) = cache {
+                                        cache
+                                            .put(
+                                                &key_for(&request.model),
+                                                &request.model,
+                                                &result.raw_response,
+                                            )
+                                            .await;
+                                    }
+                                    break Ok(result);
+                                }
+                                result => break result,
+                            }
+                        },
                     }
                 }
-                AiRequest::Chat {
-                    flashcard_id,
-                    session_id: _,
-                    question,
-                    correct_answer,
-                    user_answer,
-                    initial_feedback,
-                    conversation_history,
-                    user_message,
-                } => {
-                    logger::log(&format!(
-                        "Worker received chat request for flashcard {}",
-                        flashcard_id
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(eval_result) => {
+                    logger::log("Worker sending evaluation success");
+                    let _ = ai_tx
+                        .send(AiResponse::Evaluation {
+                            request_id,
+                            flashcard_index,
+                            result: eval_result,
+                        })
+                        .await;
+                }
+                Err(error) => {
+                    logger::log(&format!("Worker evaluation error: {}", error));
+                    let _ = ai_tx
+                        .send(AiResponse::Error {
+                            request_id,
+                            flashcard_index,
+                            error,
+                        })
+                        .await;
+                }
+            }
+        }
+        AiRequest::EvaluateSession {
+            session_id,
+            deck_name,
+            flashcards,
+        } => {
+            logger::log(&format!(
+                "Worker received session assessment request for session {}",
+                session_id
+            ));
+
+            let limit = ai_config.timeout(SESSION_TIMEOUT);
+            let meter = meter(PromptKind::Assessment, &deck_name, Some(session_id));
+            let model_config = ai_config.model_config(PromptKind::Assessment);
+            let mut retries = Retries::new(
+                "session assessment",
+                max_retries,
+                &model_config.model,
+                ai_config,
+            );
+            let result = match prepare(backend, &deck_name, PromptKind::Assessment) {
+                Ok((client, template)) => loop {
+                    let request = retries.on_model(structured(
+                        client.assessment_request(
+                            &template,
+                            &deck_name,
+                            &flashcards,
+                            Some(&model_config),
+                        ),
+                        ai_config,
                     ));
+                    let result =
+                        match complete(client.as_ref(), request.clone(), limit, &meter).await {
+                            Ok(reply) => {
+                                parse_or_repair(
+                                    client.as_ref(),
+                                    request,
+                                    reply,
+                                    limit,
+                                    &meter,
+                                    parse_session_assessment,
+                                )
+                                .await
+                            }
+                            Err(e) => Err(e),
+                        };
+                    match result {
+                        Err(e) if retries.retry(&e).await => continue,
+                        result => break result,
+                    }
+                },
+                Err(e) => Err(e),
+            };
 
-                    let client = match OpenRouterClient::new() {
-                        Ok(client) => client,
-                        Err(e) => {
-                            let _ = ai_tx
-                                .send(AiResponse::ChatReply {
-                                    flashcard_id,
-                                    message: None,
-                                    error: Some(format!("Failed to create AI client: {}", e)),
-                                })
-                                .await;
-                            continue;
-                        }
-                    };
+            match &result {
+                Ok(_) => logger::log("Worker sending session assessment success"),
+                Err(e) => logger::log(&format!("Worker session assessment error: {}", e)),
+            }
+            let _ = ai_tx
+                .send(AiResponse::SessionAssessment { session_id, result })
+                .await;
+        }
+        AiRequest::Chat {
+            request_id,
+            flashcard_id,
+            session_id,
+            deck_name,
+            question,
+            correct_answer,
+            user_answer,
+            initial_feedback,
+            conversation_history,
+            user_message,
+            passages,
+        } => {
+            logger::log(&format!(
+                "Worker received chat request for flashcard {}",
+                flashcard_id
+            ));
 
-                    let chat_future = client.chat(
+            let limit = ai_config.timeout(CHAT_TIMEOUT);
+            let meter = meter(PromptKind::Chat, &deck_name, Some(session_id));
+            let model_config = ai_config.model_config(PromptKind::Chat);
+            let mut retries = Retries::new("chat", max_retries, &model_config.model, ai_config);
+            let result = match prepare(backend, &deck_name, PromptKind::Chat) {
+                Ok((client, template)) => loop {
+                    let request = retries.on_model(client.chat_request(
+                        &template,
                         &question,
                         &correct_answer,
                         &user_answer,
                         &initial_feedback,
                         &conversation_history,
                         &user_message,
-                    );
+                        &passages,
+                        Some(&model_config),
+                    ));
+                    let mut streamed = false;
+                    let result =
+                        read_stream(client.as_ref(), request, limit, &meter, |delta, _| {
+                            streamed = true;
+                            Some(AiResponse::ChatDelta {
+                                request_id,
+                                flashcard_id,
+                                delta: delta.to_string(),
+                            })
+                        })
+                        .await;
+                    // Fragments already shown cannot be taken back, so a reply that broke
+                    // off part way is not retried
+                    match result {
+                        Err(e) if !streamed && retries.retry(&e).await => continue,
+                        result => break result,
+                    }
+                },
+                Err(e) => Err(e),
+            };
 
-                    match timeout(Duration::from_secs(CHAT_TIMEOUT_SECS), chat_future).await {
-                        Ok(Ok(reply)) => {
-                            logger::log("Worker sending chat reply success");
-                            let _ = ai_tx
-                                .send(AiResponse::ChatReply {
-                                    flashcard_id,
-                                    message: Some(reply),
-                                    error: None,
-                                })
-                                .await;
-                        }
-                        Ok(Err(e)) => {
-                            logger::log(&format!("Worker chat error: {}", e));
-                            let _ = ai_tx
-                                .send(AiResponse::ChatReply {
-                                    flashcard_id,
-                                    message: None,
-                                    error: Some(format!("Chat failed: {}", e)),
-                                })
-                                .await;
-                        }
-                        Err(_) => {
-                            logger::log("Worker chat timeout");
-                            let _ = ai_tx
-                                .send(AiResponse::ChatReply {
-                                    flashcard_id,
-                                    message: None,
-                                    error: Some(
-                                        "Chat response timed out after 30 seconds".to_string(),
-                                    ),
-                                })
-                                .await;
+            match result {
+                Ok(reply) => {
+                    logger::log("Worker sending chat reply success");
+                    let _ = ai_tx
+                        .send(AiResponse::ChatReply {
+                            request_id,
+                            flashcard_id,
+                            message: Some(reply),
+                            error: None,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    logger::log(&format!("Worker chat error: {}", e));
+                    let _ = ai_tx
+                        .send(AiResponse::ChatReply {
+                            request_id,
+                            flashcard_id,
+                            message: None,
+                            error: Some(format!("Chat failed: {}", e)),
+                        })
+                        .await;
+                }
+            }
+        }
+        AiRequest::GenerateCards {
+            request_id,
+            deck_name,
+            document,
+            chunks,
+        } => {
+            logger::log(&format!(
+                "Worker received generation request for {} ({} passages)",
+                document,
+                chunks.len()
+            ));
+
+            let limit = ai_config.timeout(GENERATION_TIMEOUT);
+            let meter = meter(PromptKind::Generation, &deck_name, None);
+            let model_config = ai_config.model_config(PromptKind::Generation);
+            let prepared = prepare(backend, &deck_name, PromptKind::Generation);
+            // Passages go one at a time, so cards arrive in document order
+            for (index, chunk) in chunks.iter().enumerate() {
+                let mut retries =
+                    Retries::new("generation", max_retries, &model_config.model, ai_config);
+                let result = match &prepared {
+                    Ok((client, template)) => loop {
+                        let request = retries.on_model(structured(
+                            client.generation_request(
+                                template,
+                                &document,
+                                &chunk.location,
+                                &chunk.text,
+                                CARDS_PER_CHUNK,
+                                Some(&model_config),
+                            ),
+                            ai_config,
+                        ));
+                        let result =
+                            match complete(client.as_ref(), request.clone(), limit, &meter).await {
+                                Ok(reply) => {
+                                    parse_or_repair(
+                                        client.as_ref(),
+                                        request,
+                                        reply,
+                                        limit,
+                                        &meter,
+                                        parse_generated_cards,
+                                    )
+                                    .await
+                                }
+                                Err(e) => Err(e),
+                            };
+                        match result {
+                            Err(e) if retries.retry(&e).await => continue,
+                            result => break result,
                         }
+                    },
+                    Err(e) => Err(e.clone()),
+                };
+
+                let result = result.map(|mut cards| {
+                    for card in &mut cards {
+                        card.cite(&chunk.location, &chunk.text);
+                    }
+                    cards
+                });
+                let stop = match &result {
+                    Ok(cards) => {
+                        logger::log(&format!(
+                            "Worker generated {} cards from passage {}/{}",
+                            cards.len(),
+                            index + 1,
+                            chunks.len()
+                        ));
+                        false
+                    }
+                    Err(e) => {
+                        logger::log(&format!("Worker generation error: {}", e));
+                        e.is_permanent()
                     }
+                };
+                let _ = ai_tx
+                    .send(AiResponse::GeneratedCards {
+                        request_id,
+                        chunk: index,
+                        chunks: chunks.len(),
+                        result,
+                    })
+                    .await;
+                if stop {
+                    break;
                 }
             }
         }
-        logger::log("AI worker exiting (channel closed)");
-    })
+        // Handled by the worker loop before a task is spawned
+        AiRequest::Cancel { .. } | AiRequest::Reconfigure { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::fake_server::{sample_evaluation, FakeAiServer, FakeReply};
+    use crate::ai::usage::ModelPrice;
+    use crate::ai::{GradingContext, ProviderKind, SourcePassage, TaskConfig};
+    use tokio::sync::mpsc;
+
+    fn config_for(server: &FakeAiServer) -> AiConfig {
+        AiConfig {
+            provider: ProviderKind::OpenAi,
+            base_url: Some(server.base_url()),
+            model: Some("fake-model".to_string()),
+            // The fake server accepts reply schemas
+            structured_output: Some(true),
+            // Built-in prompts only, whatever the user's config directory holds
+            prompt_dir: Some(std::env::temp_dir().join("no-such-prompt-dir")),
+            ..Default::default()
+        }
+    }
+
+    fn worker_for(
+        server: &FakeAiServer,
+        timeout_secs: Option<u64>,
+    ) -> (Sender<AiRequest>, Receiver<AiResponse>) {
+        start_worker(AiConfig {
+            timeout_secs,
+            ..config_for(server)
+        })
+    }
+
+    fn start_worker(config: AiConfig) -> (Sender<AiRequest>, Receiver<AiResponse>) {
+        let (request_tx, request_rx) = mpsc::channel::<AiRequest>(8);
+        let (response_tx, response_rx) = mpsc::channel::<AiResponse>(8);
+        spawn_ai_worker_with(config, None, None, response_tx, request_rx);
+        (request_tx, response_rx)
+    }
+
+    fn evaluate(flashcard_index: usize) -> AiRequest {
+        evaluate_answer(flashcard_index, "Ad hoc on-demand distance vector", false)
+    }
+
+    fn evaluate_answer(flashcard_index: usize, user_answer: &str, force: bool) -> AiRequest {
+        AiRequest::Evaluate {
+            request_id: RequestId(flashcard_index as u64),
+            flashcard_index,
+            session_id: None,
+            deck_name: "networking".to_string(),
+            question: "What does AODV stand for?".to_string(),
+            correct_answer: "Ad hoc On-Demand Distance Vector".to_string(),
+            user_answer: user_answer.to_string(),
+            grading: GradingContext::default(),
+            force,
+        }
+    }
+
+    async fn next_event(rx: &mut Receiver<AiResponse>) -> AiResponse {
+        timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .expect("worker did not answer")
+            .expect("worker stopped")
+    }
+
+    /// The next response, skipping streamed progress and usage reports.
+    async fn next_response(rx: &mut Receiver<AiResponse>) -> AiResponse {
+        loop {
+            match next_event(rx).await {
+                AiResponse::ChatDelta { .. }
+                | AiResponse::EvaluationDelta { .. }
+                | AiResponse::Usage { .. } => {}
+                response => return response,
+            }
+        }
+    }
+
+    fn expect_error(response: AiResponse) -> AiError {
+        match response {
+            AiResponse::Error { error, .. } => error,
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_through_http() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(3)).await.unwrap();
+        match next_response(&mut rx).await {
+            AiResponse::Evaluation {
+                request_id,
+                flashcard_index,
+                result,
+            } => {
+                assert_eq!(request_id, RequestId(3));
+                assert_eq!(flashcard_index, 3);
+                assert!(result.feedback.is_correct);
+                assert_eq!(result.feedback.correctness_score, 0.9);
+            }
+            other => panic!("expected an evaluation, got {:?}", other),
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["model"], "fake-model");
+        assert_eq!(requests[0]["response_format"]["type"], "json_schema");
+        let prompt = requests[0]["messages"][1]["content"].as_str().unwrap();
+        assert!(prompt.contains("What does AODV stand for?"));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_answer_is_served_from_the_cache() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content(&sample_evaluation()),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::db::run_migrations_for_test(&mut conn).unwrap();
+        let cache = EvaluationCache::new(conn);
+        let (request_tx, request_rx) = mpsc::channel::<AiRequest>(8);
+        let (response_tx, mut rx) = mpsc::channel::<AiResponse>(8);
+        spawn_ai_worker_with(
+            config_for(&server),
+            Some(cache),
+            None,
+            response_tx,
+            request_rx,
+        );
+
+        let mut cached = Vec::new();
+        for (i, user_answer, force) in [
+            (0, "Ad hoc on-demand distance vector", false),
+            // The same answer up to whitespace
+            (1, "  Ad hoc on-demand\n distance   vector ", false),
+            (2, "Ad hoc on-demand distance vector", true),
+        ] {
+            request_tx
+                .send(evaluate_answer(i, user_answer, force))
+                .await
+                .unwrap();
+            match next_response(&mut rx).await {
+                AiResponse::Evaluation { result, .. } => {
+                    assert_eq!(result.feedback.correctness_score, 0.9);
+                    cached.push(result.cached);
+                }
+                other => panic!("expected an evaluation, got {:?}", other),
+            }
+        }
+        assert_eq!(cached, vec![false, true, false]);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_calls_are_metered_until_the_budget_is_used_up() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::db::run_migrations_for_test(&mut conn).unwrap();
+        let usage_log = UsageLog::new(conn);
+        let config = AiConfig {
+            monthly_budget: Some(0.01),
+            prices: [(
+                "fake-model".to_string(),
+                ModelPrice {
+                    input: 1000.0,
+                    output: 1000.0,
+                },
+            )]
+            .into(),
+            ..config_for(&server)
+        };
+        let (request_tx, request_rx) = mpsc::channel::<AiRequest>(8);
+        let (response_tx, mut rx) = mpsc::channel::<AiResponse>(8);
+        spawn_ai_worker_with(
+            config,
+            None,
+            Some(usage_log.clone()),
+            response_tx,
+            request_rx,
+        );
+
+        request_tx.send(evaluate(0)).await.unwrap();
+        let record = loop {
+            match next_event(&mut rx).await {
+                AiResponse::Usage { record } => break record,
+                AiResponse::EvaluationDelta { .. } => {}
+                other => panic!("expected the usage of the call, got {:?}", other),
+            }
+        };
+        assert_eq!(
+            (record.kind.as_str(), record.model.as_str()),
+            ("evaluation", "fake-model")
+        );
+        assert!(record.prompt_tokens > 0 && record.completion_tokens > 0);
+        let tokens = record.prompt_tokens + record.completion_tokens;
+        assert!((record.cost - tokens as f64 / 1000.0).abs() < 1e-9);
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+        assert!((usage_log.month_cost().await - record.cost).abs() < 1e-9);
+
+        // The first call cost more than the budget, so the next one is not made
+        request_tx.send(evaluate(1)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await);
+        assert!(
+            matches!(error, AiError::BudgetExceeded { .. }),
+            "{:?}",
+            error
+        );
+        assert!(error.is_permanent());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deck_prompt_overrides_are_used() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        let prompts = tempfile::tempdir().unwrap();
+        let deck_dir = prompts.path().join("decks").join("networking");
+        std::fs::create_dir_all(&deck_dir).unwrap();
+        std::fs::write(
+            deck_dir.join("evaluation.toml"),
+            "system = \"Grade strictly.\"\nuser = \"Q: {{question}} A: {{user_answer}}\"\n",
+        )
+        .unwrap();
+        std::fs::write(prompts.path().join("chat.toml"), "system = \"{{nope}}\"\n").unwrap();
+        let (tx, mut rx) = start_worker(AiConfig {
+            prompt_dir: Some(prompts.path().to_path_buf()),
+            ..config_for(&server)
+        });
+
+        tx.send(evaluate(0)).await.unwrap();
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+        let requests = server.requests();
+        assert_eq!(requests[0]["messages"][0]["content"], "Grade strictly.");
+        assert_eq!(
+            requests[0]["messages"][1]["content"],
+            "Q: What does AODV stand for? A: Ad hoc on-demand distance vector"
+        );
+
+        // A broken template fails the request without reaching the server
+        tx.send(AiRequest::Chat {
+            request_id: RequestId(2),
+            flashcard_id: 1,
+            session_id: 1,
+            deck_name: "networking".to_string(),
+            question: "What is AODV?".to_string(),
+            correct_answer: "A routing protocol".to_string(),
+            user_answer: "A protocol".to_string(),
+            initial_feedback: String::new(),
+            conversation_history: Vec::new(),
+            user_message: "Which kind?".to_string(),
+            passages: Vec::new(),
+        })
+        .await
+        .unwrap();
+        match next_response(&mut rx).await {
+            AiResponse::ChatReply { error, .. } => {
+                let error = error.unwrap();
+                assert!(error.contains("unknown placeholder {{nope}}"), "{}", error);
+            }
+            other => panic!("expected a chat reply, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_server_times_out() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content(&sample_evaluation()).after(Duration::from_secs(5))
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = start_worker(AiConfig {
+            timeout_secs: Some(1),
+            max_retries: Some(0),
+            ..config_for(&server)
+        });
+
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await);
+        assert_eq!(error, AiError::Timeout(Duration::from_secs(1)));
+        assert!(error.to_string().contains("timed out after 1 seconds"));
+    }
+
+    #[tokio::test]
+    async fn test_transient_error_is_retried() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::status(503, "overloaded"),
+            FakeReply::status(429, "slow down"),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(0)).await.unwrap();
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_give_up_after_the_limit() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::status(503, "overloaded"),
+            FakeReply::status(503, "overloaded"),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = start_worker(AiConfig {
+            max_retries: Some(1),
+            ..config_for(&server)
+        });
+
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await);
+        assert!(error.is_transient());
+        assert_eq!(error.to_string(), "API error (503): overloaded");
+        assert_eq!(server.requests().len(), 2);
+
+        // What Ctrl+E does after a failure
+        tx.send(evaluate(0)).await.unwrap();
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_key_is_not_retried() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::status(401, "invalid api key"),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await);
+        assert_eq!(error, AiError::Auth("invalid api key".to_string()));
+        assert!(error.is_permanent());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failing_model_falls_back_to_the_next() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::status(404, "no such model"),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let config = AiConfig {
+            evaluation: TaskConfig {
+                model: Some("big-model".to_string()),
+                temperature: Some(0.5),
+                max_tokens: None,
+            },
+            fallback_models: vec!["big-model".to_string(), "small-model".to_string()],
+            ..config_for(&server)
+        };
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::db::run_migrations_for_test(&mut conn).unwrap();
+        let cache = EvaluationCache::new(conn);
+        let (tx, request_rx) = mpsc::channel::<AiRequest>(8);
+        let (response_tx, mut rx) = mpsc::channel::<AiResponse>(8);
+        spawn_ai_worker_with(config, Some(cache.clone()), None, response_tx, request_rx);
+
+        tx.send(evaluate(0)).await.unwrap();
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["model"], "big-model");
+        assert_eq!(requests[0]["temperature"], 0.5);
+        assert_eq!(requests[1]["model"], "small-model");
+        assert_eq!(requests[1]["temperature"], 0.5);
+
+        // The reply is cached as the fallback model's, not the one first asked
+        let version = PromptTemplate::builtin(PromptKind::Evaluation).version();
+        let key = |model: &str| {
+            cache_key(
+                "What does AODV stand for?",
+                "Ad hoc On-Demand Distance Vector",
+                "Ad hoc on-demand distance vector",
+                &GradingContext::default(),
+                model,
+                &version,
+            )
+        };
+        let ttl = Duration::from_secs(3600);
+        assert!(cache.get(&key("small-model"), ttl).await.is_some());
+        assert!(cache.get(&key("big-model"), ttl).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generation_reports_each_passage_and_stops_on_a_permanent_error() {
+        let cards = r#"{"cards": [{"question": "What does AODV stand for?", "answer": "Ad hoc On-Demand Distance Vector", "quote": "AODV (Ad hoc On-Demand Distance Vector)"}]}"#;
+        let server = FakeAiServer::start(vec![
+            FakeReply::content(cards),
+            FakeReply::status(401, "invalid key"),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+        let chunk = |text: &str, location: &str| crate::ingest::Chunk {
+            text: text.to_string(),
+            location: location.to_string(),
+        };
+
+        tx.send(AiRequest::GenerateCards {
+            request_id: RequestId(1),
+            deck_name: "manet".to_string(),
+            document: "manet.pdf".to_string(),
+            chunks: vec![
+                chunk(
+                    "AODV (Ad hoc On-Demand Distance Vector) finds routes.",
+                    "p. 1",
+                ),
+                chunk("DSR caches routes.", "p. 2"),
+                chunk("OLSR is proactive.", "p. 3"),
+            ],
+        })
+        .await
+        .unwrap();
+
+        match next_response(&mut rx).await {
+            AiResponse::GeneratedCards {
+                chunk: 0,
+                chunks: 3,
+                result: Ok(cards),
+                ..
+            } => {
+                assert_eq!(cards.len(), 1);
+                assert_eq!(cards[0].location, "p. 1");
+                assert!(cards[0].quote_verified);
+            }
+            other => panic!("expected cards, got {:?}", other),
+        }
+        match next_response(&mut rx).await {
+            AiResponse::GeneratedCards {
+                chunk: 1,
+                result: Err(AiError::Auth(_)),
+                ..
+            } => {}
+            other => panic!("expected an auth error, got {:?}", other),
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0]["response_format"]["json_schema"]["name"],
+            "generated_cards"
+        );
+        let prompt = requests[0]["messages"][1]["content"].as_str().unwrap();
+        assert!(
+            prompt.contains("manet.pdf") && prompt.contains("p. 1"),
+            "{}",
+            prompt
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_with_jitter() {
+        assert_eq!(retry_delay(1, 0.0), Duration::from_millis(500));
+        assert_eq!(retry_delay(2, 0.0), Duration::from_millis(1000));
+        assert_eq!(retry_delay(3, 1.0), Duration::from_millis(1000));
+        assert_eq!(retry_delay(10, 0.0), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX, 0.0), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_responses_are_reported() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content("Sure! The answer looks right to me."),
+            FakeReply::content("Still not JSON, sorry."),
+            FakeReply::raw("{\"choices\": "),
+            FakeReply::content("{\"grade_percentage\": \"lots\"}"),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        // The reply and the repaired reply are both prose
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await).to_string();
+        assert!(
+            error.contains("does not match the expected format (not valid JSON"),
+            "{}",
+            error
+        );
+
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await).to_string();
+        assert!(
+            error.contains("Invalid chat completion response"),
+            "{}",
+            error
+        );
+
+        tx.send(AiRequest::EvaluateSession {
+            session_id: 7,
+            deck_name: "networking".to_string(),
+            flashcards: Vec::new(),
+        })
+        .await
+        .unwrap();
+        match next_response(&mut rx).await {
+            AiResponse::SessionAssessment { session_id, result } => {
+                assert_eq!(session_id, 7);
+                let error = result.unwrap_err().to_string();
+                assert!(
+                    error.contains("grade_percentage: expected a number"),
+                    "{}",
+                    error
+                );
+                assert!(error.contains("mastery_level: missing"), "{}", error);
+            }
+            other => panic!("expected a session assessment, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_reply_is_repaired() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content("The answer is correct, well done."),
+            FakeReply::content(&sample_evaluation()),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(0)).await.unwrap();
+        match next_response(&mut rx).await {
+            AiResponse::Evaluation { result, .. } => assert!(result.feedback.is_correct),
+            other => panic!("expected an evaluation, got {:?}", other),
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let messages = requests[1]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[2]["content"], "The answer is correct, well done.");
+        let repair = messages[3]["content"].as_str().unwrap();
+        assert!(repair.contains("- not valid JSON"), "{}", repair);
+        assert_eq!(
+            requests[1]["response_format"]["json_schema"]["name"],
+            "answer_evaluation"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_through_http() {
+        let server = FakeAiServer::start(vec![FakeReply::content("It is a routing protocol.")])
+            .await
+            .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(AiRequest::Chat {
+            request_id: RequestId(1),
+            flashcard_id: 11,
+            session_id: 1,
+            deck_name: "networking".to_string(),
+            question: "What is AODV?".to_string(),
+            correct_answer: "A routing protocol".to_string(),
+            user_answer: "A protocol".to_string(),
+            initial_feedback: "Mostly right".to_string(),
+            conversation_history: vec![("user".to_string(), "Why?".to_string())],
+            user_message: "Which kind?".to_string(),
+            passages: vec![SourcePassage {
+                document: "manet.pdf".to_string(),
+                location: "p. 4".to_string(),
+                text: "AODV is a reactive routing protocol.".to_string(),
+            }],
+        })
+        .await
+        .unwrap();
+        match next_response(&mut rx).await {
+            AiResponse::ChatReply {
+                flashcard_id,
+                message,
+                error,
+                ..
+            } => {
+                assert_eq!(flashcard_id, 11);
+                assert_eq!(message.as_deref(), Some("It is a routing protocol."));
+                assert_eq!(error, None);
+            }
+            other => panic!("expected a chat reply, got {:?}", other),
+        }
+        let messages = server.requests()[0]["messages"].clone();
+        assert_eq!(messages.as_array().unwrap().len(), 3);
+        let system = messages[0]["content"].as_str().unwrap();
+        assert!(
+            system.contains("[1] manet.pdf, p. 4\nAODV is a reactive routing protocol."),
+            "{}",
+            system
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_chat_arrives_in_pieces() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content("It is a routing protocol.").chunked(Duration::from_millis(10))
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(AiRequest::Chat {
+            request_id: RequestId(1),
+            flashcard_id: 4,
+            session_id: 1,
+            deck_name: "networking".to_string(),
+            question: "What is AODV?".to_string(),
+            correct_answer: "A routing protocol".to_string(),
+            user_answer: "A protocol".to_string(),
+            initial_feedback: "Mostly right".to_string(),
+            conversation_history: Vec::new(),
+            user_message: "Which kind?".to_string(),
+            passages: Vec::new(),
+        })
+        .await
+        .unwrap();
+
+        let mut deltas = Vec::new();
+        let mut usage = None;
+        let reply = loop {
+            match next_event(&mut rx).await {
+                AiResponse::ChatDelta {
+                    flashcard_id,
+                    delta,
+                    ..
+                } => {
+                    assert_eq!(flashcard_id, 4);
+                    deltas.push(delta);
+                }
+                AiResponse::Usage { record } => usage = Some(record),
+                AiResponse::ChatReply { message, .. } => break message,
+                other => panic!("expected chat events, got {:?}", other),
+            }
+        };
+        assert_eq!(deltas, vec!["It ", "is ", "a ", "routing ", "protocol."]);
+        let usage = usage.expect("the call is reported");
+        assert_eq!((usage.kind.as_str(), usage.session_id), ("chat", Some(1)));
+        assert_eq!(usage.completion_tokens, 5);
+        assert_eq!(reply.as_deref(), Some("It is a routing protocol."));
+        assert_eq!(server.requests()[0]["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_evaluation_shows_explanation_first() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(2)).await.unwrap();
+        let mut explanations = Vec::new();
+        loop {
+            match next_event(&mut rx).await {
+                AiResponse::EvaluationDelta {
+                    flashcard_index,
+                    explanation,
+                    ..
+                } => {
+                    assert_eq!(flashcard_index, 2);
+                    explanations.push(explanation);
+                }
+                AiResponse::Usage { .. } => {}
+                AiResponse::Evaluation { result, .. } => {
+                    assert_eq!(
+                        result.feedback.explanation,
+                        "Evaluated by the fake AI server."
+                    );
+                    break;
+                }
+                other => panic!("expected evaluation events, got {:?}", other),
+            }
+        }
+        assert!(explanations.len() > 1, "{:?}", explanations);
+        assert_eq!(
+            explanations.last().map(String::as_str),
+            Some("Evaluated by the fake AI server.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluations_run_concurrently_up_to_the_limit() {
+        let delay = Duration::from_millis(500);
+        let server =
+            FakeAiServer::start(vec![FakeReply::content(&sample_evaluation()).after(delay)])
+                .await
+                .unwrap();
+        let (tx, mut rx) = start_worker(AiConfig {
+            max_concurrent: Some(2),
+            ..config_for(&server)
+        });
+
+        let started = tokio::time::Instant::now();
+        for index in 0..3 {
+            tx.send(evaluate(index)).await.unwrap();
+        }
+        let mut finished = Vec::new();
+        for _ in 0..3 {
+            match next_response(&mut rx).await {
+                AiResponse::Evaluation {
+                    flashcard_index, ..
+                } => finished.push((flashcard_index, started.elapsed())),
+                other => panic!("expected an evaluation, got {:?}", other),
+            }
+        }
+
+        // The first two ran side by side; the third waited for a free slot
+        assert!(finished[1].1 < delay * 2, "{:?}", finished);
+        assert_eq!(finished[2].0, 2);
+        assert!(finished[2].1 >= delay * 2, "{:?}", finished);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_running_request() {
+        let server = FakeAiServer::start(vec![
+            FakeReply::content(&sample_evaluation()).after(Duration::from_millis(500))
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = worker_for(&server, None);
+
+        tx.send(evaluate(0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(AiRequest::Cancel {
+            request_id: RequestId(0),
+        })
+        .await
+        .unwrap();
+
+        assert!(
+            timeout(Duration::from_secs(1), rx.recv()).await.is_err(),
+            "a cancelled request must not answer"
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    /// A local URL nothing listens on.
+    async fn closed_url() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        format!("http://{}/v1", addr)
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        assert_eq!(check_health(&config_for(&server)).await, Ok(()));
+        // The check generates nothing
+        assert!(server.requests().is_empty());
+
+        let unreachable = AiConfig {
+            base_url: Some(closed_url().await),
+            ..config_for(&server)
+        };
+        let error = check_health(&unreachable).await.unwrap_err();
+        assert!(matches!(error, AiError::Network(_)), "{:?}", error);
+
+        let incomplete = AiConfig {
+            model: None,
+            ..config_for(&server)
+        };
+        let error = check_health(&incomplete).await.unwrap_err();
+        assert!(error.is_permanent(), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_switches_backend() {
+        let server = FakeAiServer::start(vec![FakeReply::content(&sample_evaluation())])
+            .await
+            .unwrap();
+        let (tx, mut rx) = start_worker(AiConfig {
+            base_url: Some(closed_url().await),
+            max_retries: Some(0),
+            ..config_for(&server)
+        });
+
+        tx.send(evaluate(0)).await.unwrap();
+        let error = expect_error(next_response(&mut rx).await);
+        assert!(matches!(error, AiError::Network(_)), "{:?}", error);
+
+        tx.send(AiRequest::Reconfigure {
+            config: Box::new(config_for(&server)),
+        })
+        .await
+        .unwrap();
+        tx.send(evaluate(1)).await.unwrap();
+        assert!(matches!(
+            next_response(&mut rx).await,
+            AiResponse::Evaluation { .. }
+        ));
+        assert_eq!(server.requests().len(), 1);
+    }
 }
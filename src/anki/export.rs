@@ -0,0 +1,545 @@
+use super::html::{markdown_to_html, strip_html};
+use super::temp_collection_path;
+use crate::ai::AIFeedback;
+use crate::db::{card, flashcard};
+use crate::models::Flashcard;
+use crate::utils::text::content_hash;
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Id of the note type written into packages. Keeping it fixed lets Anki put the notes
+/// of later exports under the same note type instead of creating a new one each time.
+const MODEL_ID: i64 = 1_700_000_000_001;
+const MODEL_NAME: &str = "Interactive Flashcards";
+const FIELD_NAMES: [&str; 3] = ["Front", "Back", "Extra"];
+
+/// The parts of Anki's schema 11 collection a package needs
+const COLLECTION_SCHEMA: &str = "
+CREATE TABLE col (
+    id INTEGER PRIMARY KEY, crt INTEGER NOT NULL, mod INTEGER NOT NULL, scm INTEGER NOT NULL,
+    ver INTEGER NOT NULL, dty INTEGER NOT NULL, usn INTEGER NOT NULL, ls INTEGER NOT NULL,
+    conf TEXT NOT NULL, models TEXT NOT NULL, decks TEXT NOT NULL, dconf TEXT NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE notes (
+    id INTEGER PRIMARY KEY, guid TEXT NOT NULL, mid INTEGER NOT NULL, mod INTEGER NOT NULL,
+    usn INTEGER NOT NULL, tags TEXT NOT NULL, flds TEXT NOT NULL, sfld INTEGER NOT NULL,
+    csum INTEGER NOT NULL, flags INTEGER NOT NULL, data TEXT NOT NULL
+);
+CREATE TABLE cards (
+    id INTEGER PRIMARY KEY, nid INTEGER NOT NULL, did INTEGER NOT NULL, ord INTEGER NOT NULL,
+    mod INTEGER NOT NULL, usn INTEGER NOT NULL, type INTEGER NOT NULL, queue INTEGER NOT NULL,
+    due INTEGER NOT NULL, ivl INTEGER NOT NULL, factor INTEGER NOT NULL, reps INTEGER NOT NULL,
+    lapses INTEGER NOT NULL, left INTEGER NOT NULL, odue INTEGER NOT NULL, odid INTEGER NOT NULL,
+    flags INTEGER NOT NULL, data TEXT NOT NULL
+);
+CREATE TABLE revlog (
+    id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, usn INTEGER NOT NULL, ease INTEGER NOT NULL,
+    ivl INTEGER NOT NULL, lastIvl INTEGER NOT NULL, factor INTEGER NOT NULL, time INTEGER NOT NULL,
+    type INTEGER NOT NULL
+);
+CREATE TABLE graves (usn INTEGER NOT NULL, oid INTEGER NOT NULL, type INTEGER NOT NULL);
+CREATE INDEX ix_notes_csum ON notes (csum);
+CREATE INDEX ix_cards_nid ON cards (nid);
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Anki package, importable on desktop and mobile
+    Apkg,
+    /// Tab separated text for Anki's File > Import
+    Tsv,
+}
+
+impl ExportFormat {
+    /// Pick the format from an output file name: `.apkg`, or `.tsv`/`.txt` for text.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("apkg") => Some(ExportFormat::Apkg),
+            Some("tsv" | "txt") => Some(ExportFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// A card ready to be exported; every field is Markdown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportCard {
+    pub front: String,
+    pub back: String,
+    /// AI explanation, exported as the note's Extra field
+    pub extra: Option<String>,
+    pub tags: Vec<String>,
+}
+
+fn feedback_extra(feedback: &AIFeedback) -> String {
+    let mut extra = feedback.explanation.trim().to_string();
+    if !feedback.corrections.is_empty() {
+        extra.push_str("\n\n**Corrections:**");
+        for correction in &feedback.corrections {
+            extra.push_str(&format!("\n- {}", correction));
+        }
+    }
+    extra
+}
+
+/// Every card of a deck. With `with_explanations`, each card carries the latest AI
+/// explanation it received in any session; cards are matched to their history by
+/// content, then by question, so a card whose answer was edited keeps its explanation.
+/// Exporting never adds or changes cards.
+pub fn deck_export_cards(
+    conn: &Connection,
+    deck_name: &str,
+    flashcards: &[Flashcard],
+    with_explanations: bool,
+) -> rusqlite::Result<Vec<ExportCard>> {
+    let card_ids = if with_explanations {
+        let pairs: Vec<(String, String)> = flashcards
+            .iter()
+            .map(|fc| (fc.question.clone(), fc.answer.clone()))
+            .collect();
+        card::lookup_cards(conn, deck_name, &pairs)?
+    } else {
+        Vec::new()
+    };
+
+    flashcards
+        .iter()
+        .enumerate()
+        .map(|(i, fc)| {
+            let extra = match card_ids.get(i).copied().flatten() {
+                Some(card_id) => {
+                    flashcard::get_latest_feedback(conn, card_id)?.map(|f| feedback_extra(&f))
+                }
+                None => None,
+            };
+            Ok(ExportCard {
+                front: fc.question.clone(),
+                back: fc.answer.clone(),
+                extra,
+                tags: fc.meta.tags.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The cards the AI graded as incorrect in a session.
+pub fn missed_session_cards(
+    conn: &Connection,
+    session_id: u64,
+    with_explanations: bool,
+) -> rusqlite::Result<Vec<ExportCard>> {
+    Ok(flashcard::load_flashcards(conn, session_id)?
+        .into_iter()
+        .filter_map(|fc| {
+            let feedback = fc.ai_feedback.filter(|f| !f.is_correct)?;
+            Some(ExportCard {
+                front: fc.question,
+                back: fc.answer,
+                extra: with_explanations.then(|| feedback_extra(&feedback)),
+                tags: fc.meta.tags,
+            })
+        })
+        .collect())
+}
+
+/// Anki tags cannot contain spaces and are stored space separated.
+fn anki_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|t| t.split_whitespace().collect::<Vec<_>>().join("_"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn html_fields(card: &ExportCard) -> [String; 3] {
+    [
+        markdown_to_html(&card.front),
+        markdown_to_html(&card.back),
+        card.extra
+            .as_deref()
+            .map(markdown_to_html)
+            .unwrap_or_default(),
+    ]
+}
+
+/// Write cards as a text file for Anki's File > Import, with HTML fields and the
+/// column layout declared in header lines.
+pub fn write_tsv<W: Write>(mut writer: W, cards: &[ExportCard]) -> io::Result<()> {
+    writeln!(writer, "#separator:tab")?;
+    writeln!(writer, "#html:true")?;
+    writeln!(writer, "#columns:{}\tTags", FIELD_NAMES.join("\t"))?;
+    writeln!(writer, "#tags column:{}", FIELD_NAMES.len() + 1)?;
+
+    for card in cards {
+        let mut fields = html_fields(card).to_vec();
+        fields.push(anki_tags(&card.tags));
+        let fields: Vec<String> = fields.iter().map(|f| f.replace('\t', " ")).collect();
+        writeln!(writer, "{}", fields.join("\t"))?;
+    }
+
+    Ok(())
+}
+
+/// Write cards as an `.apkg` package holding one deck named `deck_name`.
+pub fn write_apkg(path: &Path, deck_name: &str, cards: &[ExportCard]) -> Result<(), String> {
+    let tmp = temp_collection_path("export");
+    let collection = build_collection(&tmp, deck_name, cards)
+        .map_err(|e| format!("Cannot build collection: {}", e))
+        .and_then(|()| fs::read(&tmp).map_err(|e| format!("Cannot read collection: {}", e)));
+    let _ = fs::remove_file(&tmp);
+    let collection = collection?;
+
+    let write = || -> zip::result::ZipResult<()> {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&collection)?;
+        // No media files; the media map is still required
+        zip.start_file("media", options)?;
+        zip.write_all(b"{}")?;
+        zip.finish()?;
+        Ok(())
+    };
+    write().map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Stable deck id, so exporting the same deck again updates it in Anki
+fn deck_id(deck_name: &str) -> i64 {
+    let hash = u64::from_str_radix(&content_hash(deck_name, ""), 16).unwrap_or(0);
+    1_000_000_000_000 + (hash % 1_000_000_000_000) as i64
+}
+
+/// Anki's duplicate-check checksum: the first 32 bits of the SHA-1 of the sort field
+fn field_checksum(text: &str) -> i64 {
+    let digest = Sha1::digest(text.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+fn deck_json(id: i64, name: &str, now: i64) -> Value {
+    json!({
+        "id": id, "name": name, "mod": now, "usn": 0, "desc": "", "dyn": 0, "conf": 1,
+        "collapsed": false, "browserCollapsed": false, "extendNew": 0, "extendRev": 0,
+        "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0]
+    })
+}
+
+fn build_collection(path: &Path, deck_name: &str, cards: &[ExportCard]) -> rusqlite::Result<()> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(COLLECTION_SCHEMA)?;
+
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let now = now_ms / 1000;
+    let deck_id = deck_id(deck_name);
+    let anki_deck_name = deck_name.replace('/', "::");
+
+    let fields: Vec<Value> = FIELD_NAMES
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name, "ord": ord, "sticky": false, "rtl": false,
+                "font": "Arial", "size": 20, "media": []
+            })
+        })
+        .collect();
+    let model = json!({
+        "id": MODEL_ID, "name": MODEL_NAME, "type": 0, "mod": now, "usn": 0, "sortf": 0,
+        "did": deck_id, "flds": fields, "tags": [], "vers": [], "req": [[0, "any", [0]]],
+        "tmpls": [{
+            "name": "Card 1", "ord": 0, "did": null, "bqfmt": "", "bafmt": "",
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}{{#Extra}}<br><br>{{Extra}}{{/Extra}}"
+        }],
+        "css": ".card { font-family: arial; font-size: 20px; text-align: left; color: black; background-color: white; }",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false
+    });
+    let mut models = Map::new();
+    models.insert(MODEL_ID.to_string(), model);
+
+    let mut decks = Map::new();
+    decks.insert("1".to_string(), deck_json(1, "Default", now));
+    decks.insert(
+        deck_id.to_string(),
+        deck_json(deck_id, &anki_deck_name, now),
+    );
+
+    let dconf = json!({"1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true,
+        "timer": 0, "replayq": true, "dyn": false,
+        "new": {"delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1,
+                "perDay": 20, "bury": false},
+        "lapse": {"delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0},
+        "rev": {"perDay": 200, "ease4": 1.3, "fuzz": 0.05, "maxIvl": 36500, "ivlFct": 1,
+                "bury": false, "hardFactor": 1.2}
+    }});
+    let conf = json!({
+        "nextPos": cards.len() + 1, "estTimes": true, "activeDecks": [deck_id],
+        "sortType": "noteFld", "timeLim": 0, "sortBackwards": false, "addToCur": true,
+        "curDeck": deck_id, "newSpread": 0, "dueCounts": true, "curModel": MODEL_ID,
+        "collapseTime": 1200
+    });
+
+    conn.execute(
+        "INSERT INTO col VALUES (1, ?, ?, ?, 11, 0, 0, 0, ?, ?, ?, ?, '{}')",
+        rusqlite::params![
+            now,
+            now_ms,
+            now_ms,
+            conf.to_string(),
+            Value::Object(models).to_string(),
+            Value::Object(decks).to_string(),
+            dconf.to_string()
+        ],
+    )?;
+
+    let mut guids = HashSet::new();
+    for (i, card) in cards.iter().enumerate() {
+        // Ids are millisecond timestamps in Anki; consecutive ones keep them unique
+        let id = now_ms + i as i64;
+        let fields = html_fields(card);
+        let sort_field = strip_html(&fields[0]);
+
+        // Content-derived guids let a re-export update notes instead of duplicating them
+        let mut guid = format!("ifc{}", content_hash(&card.front, &card.back));
+        if !guids.insert(guid.clone()) {
+            guid = format!("{}-{}", guid, i);
+            guids.insert(guid.clone());
+        }
+
+        conn.execute(
+            "INSERT INTO notes VALUES (?, ?, ?, ?, 0, ?, ?, ?, ?, 0, '')",
+            rusqlite::params![
+                id,
+                guid,
+                MODEL_ID,
+                now,
+                format!(" {} ", anki_tags(&card.tags)),
+                fields.join("\x1f"),
+                sort_field,
+                field_checksum(&sort_field)
+            ],
+        )?;
+        // A new card (type and queue 0) at position i + 1 in the new-card queue
+        conn.execute(
+            "INSERT INTO cards VALUES (?, ?, ?, 0, ?, 0, 0, 0, ?, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            rusqlite::params![id, id, deck_id, now, i as i64 + 1],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anki::read_apkg;
+    use crate::db::{run_migrations_for_test, session};
+    use crate::models::CardMeta;
+
+    fn export_card(front: &str, back: &str) -> ExportCard {
+        ExportCard {
+            front: front.to_string(),
+            back: back.to_string(),
+            extra: None,
+            tags: vec![],
+        }
+    }
+
+    fn feedback(is_correct: bool, explanation: &str) -> AIFeedback {
+        AIFeedback {
+            is_correct,
+            correctness_score: if is_correct { 1.0 } else { 0.1 },
+            corrections: if is_correct {
+                vec![]
+            } else {
+                vec!["It is reactive".to_string()]
+            },
+            explanation: explanation.to_string(),
+            suggestions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_export_format_for_path() {
+        assert_eq!(
+            ExportFormat::for_path(Path::new("deck.APKG")),
+            Some(ExportFormat::Apkg)
+        );
+        assert_eq!(
+            ExportFormat::for_path(Path::new("deck.txt")),
+            Some(ExportFormat::Tsv)
+        );
+        assert_eq!(ExportFormat::for_path(Path::new("deck.csv")), None);
+    }
+
+    #[test]
+    fn test_write_tsv() {
+        let mut card = export_card("What is **AODV**?", "Ad hoc\tOn-Demand\nDistance Vector");
+        card.extra = Some("Reactive *routing*".to_string());
+        card.tags = vec!["routing".to_string(), "ad hoc".to_string()];
+
+        let mut out = Vec::new();
+        write_tsv(&mut out, &[card]).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "#separator:tab");
+        assert_eq!(lines[2], "#columns:Front\tBack\tExtra\tTags");
+        assert_eq!(
+            lines[4],
+            "What is <b>AODV</b>?\tAd hoc On-Demand<br>Distance Vector\tReactive <i>routing</i>\trouting ad_hoc"
+        );
+    }
+
+    #[test]
+    fn test_write_apkg_round_trips_through_importer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wireless.apkg");
+        let mut first = export_card(
+            "What does **AODV** stand for?",
+            "Ad hoc On-Demand\nDistance Vector",
+        );
+        first.tags = vec!["routing".to_string()];
+        first.extra = Some("Explained".to_string());
+        let cards = vec![first, export_card("Q2", "A2"), export_card("Q2", "A2")];
+
+        write_apkg(&path, "networking/wireless", &cards).unwrap();
+
+        let import = read_apkg(&path).unwrap();
+        assert_eq!(import.cards.len(), 3);
+        assert_eq!(import.cards[0].flashcard.question, cards[0].front);
+        assert_eq!(import.cards[0].flashcard.answer, cards[0].back);
+        assert_eq!(import.cards[0].flashcard.meta.tags, vec!["routing"]);
+
+        // Check the collection fields Anki relies on
+        let file = fs::File::open(&path).unwrap();
+        let mut archive = zip::ZipArchive::new(file).unwrap();
+        let mut collection = Vec::new();
+        io::Read::read_to_end(
+            &mut archive.by_name("collection.anki2").unwrap(),
+            &mut collection,
+        )
+        .unwrap();
+        let db_path = dir.path().join("collection.anki2");
+        fs::write(&db_path, collection).unwrap();
+        let conn = Connection::open(&db_path).unwrap();
+
+        let decks: String = conn
+            .query_row("SELECT decks FROM col", [], |r| r.get(0))
+            .unwrap();
+        assert!(decks.contains("networking::wireless"));
+        let (flds, csum): (String, i64) = conn
+            .query_row(
+                "SELECT flds, csum FROM notes ORDER BY id LIMIT 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert!(flds.ends_with("\x1fExplained"));
+        assert_eq!(csum, field_checksum("What does AODV stand for?"));
+        let distinct_guids: i64 = conn
+            .query_row("SELECT COUNT(DISTINCT guid) FROM notes", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(distinct_guids, 3);
+    }
+
+    #[test]
+    fn test_field_checksum_matches_sha1() {
+        // sha1("abc") = a9993e36...
+        assert_eq!(field_checksum("abc"), 0xa9993e36);
+    }
+
+    #[test]
+    fn test_missed_session_cards() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+
+        let session_id = session::create_session(&conn, "Test Deck", 3).unwrap();
+        let ids = flashcard::initialize_flashcards(
+            &conn,
+            session_id,
+            &[
+                ("Q1".to_string(), "A1".to_string()),
+                ("Q2".to_string(), "A2".to_string()),
+                ("Q3".to_string(), "A3".to_string()),
+            ],
+        )
+        .unwrap();
+        flashcard::update_ai_feedback(&conn, ids[0], &feedback(true, "Right")).unwrap();
+        flashcard::update_ai_feedback(&conn, ids[1], &feedback(false, "Wrong")).unwrap();
+
+        let missed = missed_session_cards(&conn, session_id, true).unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].front, "Q2");
+        assert_eq!(
+            missed[0].extra.as_deref(),
+            Some("Wrong\n\n**Corrections:**\n- It is reactive")
+        );
+
+        let missed = missed_session_cards(&conn, session_id, false).unwrap();
+        assert_eq!(missed[0].extra, None);
+    }
+
+    #[test]
+    fn test_deck_export_cards_with_explanations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+
+        let session_id = session::create_session(&conn, "Test Deck", 1).unwrap();
+        let ids = flashcard::initialize_flashcards(
+            &conn,
+            session_id,
+            &[
+                ("Q1".to_string(), "A1".to_string()),
+                (
+                    "What does AODV stand for?".to_string(),
+                    "Ad hoc On-Demand Distance Vector".to_string(),
+                ),
+            ],
+        )
+        .unwrap();
+        flashcard::update_ai_feedback(&conn, ids[0], &feedback(true, "Right")).unwrap();
+        flashcard::update_ai_feedback(&conn, ids[1], &feedback(true, "Spelled out")).unwrap();
+
+        let deck = vec![
+            crate::csv::flashcard("Q1".to_string(), "A1".to_string(), CardMeta::default()),
+            crate::csv::flashcard("Q9".to_string(), "A9".to_string(), CardMeta::default()),
+            // Answer edited since the session: still the same card
+            crate::csv::flashcard(
+                "What does AODV stand for?".to_string(),
+                "Ad hoc On-Demand Distance Vector routing".to_string(),
+                CardMeta::default(),
+            ),
+        ];
+        let cards_table = |conn: &Connection| -> Vec<(u64, String, String)> {
+            let mut stmt = conn
+                .prepare("SELECT id, question, answer FROM cards ORDER BY id")
+                .unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+        let before = cards_table(&conn);
+        let cards = deck_export_cards(&conn, "Test Deck", &deck, true).unwrap();
+        assert_eq!(cards[0].extra.as_deref(), Some("Right"));
+        assert_eq!(cards[1].extra, None);
+        assert_eq!(cards[2].extra.as_deref(), Some("Spelled out"));
+        // Exporting neither adds the new card nor rewrites the edited one
+        assert_eq!(cards_table(&conn), before);
+
+        let cards = deck_export_cards(&conn, "Test Deck", &deck, false).unwrap();
+        assert_eq!(cards[0].extra, None);
+    }
+}
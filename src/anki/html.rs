@@ -0,0 +1,326 @@
+//! Conversion between Anki's field HTML and the Markdown subset `render_markdown` understands.
+
+use regex::Regex;
+
+/// Convert an Anki field to Markdown. Block elements become line breaks, bold/italic/code
+/// become their Markdown markers, images become `![](file)` and `[sound:...]` tags are
+/// dropped. Any other markup is stripped.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    let mut skip_until: Option<&str> = None;
+    let mut in_pre = false;
+
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            if skip_until.is_none() {
+                push_text(&mut out, rest, in_pre);
+            }
+            break;
+        };
+        if skip_until.is_none() {
+            push_text(&mut out, &rest[..open], in_pre);
+        }
+
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('>') else {
+            // A lone '<' is text, not markup
+            if skip_until.is_none() {
+                push_text(&mut out, &rest[open..], in_pre);
+            }
+            break;
+        };
+        let tag = &after[..close];
+        rest = &after[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        if let Some(end) = skip_until {
+            if closing && name == end {
+                skip_until = None;
+            }
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("script", false) => skip_until = Some("script"),
+            ("style", false) => skip_until = Some("style"),
+            ("br", _) => out.push('\n'),
+            ("p" | "div" | "tr" | "ul" | "ol" | "table" | "blockquote", _) => line_break(&mut out),
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                line_break(&mut out);
+                out.push_str("**");
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => {
+                out.push_str("**");
+                line_break(&mut out);
+            }
+            ("li", false) => {
+                line_break(&mut out);
+                out.push_str("- ");
+            }
+            ("b" | "strong", _) => out.push_str("**"),
+            ("i" | "em", _) => out.push('*'),
+            ("code", _) if !in_pre => out.push('`'),
+            ("pre", false) => {
+                line_break(&mut out);
+                out.push_str("```\n");
+                in_pre = true;
+            }
+            ("pre", true) => {
+                line_break(&mut out);
+                out.push_str("```\n");
+                in_pre = false;
+            }
+            ("td" | "th", true) => out.push(' '),
+            ("img", false) => {
+                if let Some(src) = attribute(tag, "src") {
+                    out.push_str(&format!("![]({})", src));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tidy(&strip_sound_tags(&out))
+}
+
+/// Convert a card's Markdown into field HTML for Anki: bold, italic, code and images
+/// become tags and line breaks become `<br>`. Other Markdown is kept as text.
+pub fn markdown_to_html(markdown: &str) -> String {
+    let bold = Regex::new(r"\*\*(.+?)\*\*").unwrap();
+    let italic = Regex::new(r"\*(.+?)\*").unwrap();
+    let code = Regex::new(r"`(.+?)`").unwrap();
+    let image = Regex::new(r"!\[[^\]]*\]\(([^)\s]+)\)").unwrap();
+
+    markdown
+        .lines()
+        .map(|line| {
+            let html = escape_html(line);
+            let html = image.replace_all(&html, "<img src=\"$1\">");
+            let html = code.replace_all(&html, "<code>$1</code>");
+            let html = bold.replace_all(&html, "<b>$1</b>");
+            italic.replace_all(&html, "<i>$1</i>").into_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// Plain text of a field, as Anki uses for sorting and duplicate checks.
+pub fn strip_html(html: &str) -> String {
+    let tag = Regex::new(r"<[^>]*>").unwrap();
+    decode_entities(&tag.replace_all(html, ""))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode the HTML entities Anki commonly writes.
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let after = &rest[amp + 1..];
+        let decoded = after.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &after[..end];
+            let c = match entity {
+                "nbsp" => Some(' '),
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn push_text(out: &mut String, text: &str, in_pre: bool) {
+    let decoded = decode_entities(text);
+    if in_pre {
+        out.push_str(&decoded);
+    } else {
+        // Source line breaks are insignificant in HTML
+        out.push_str(&decoded.replace(['\r', '\n'], " "));
+    }
+}
+
+fn line_break(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let start = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value
+            .split(|c: char| c.is_whitespace() || c == '>')
+            .next()?,
+    };
+    Some(decode_entities(value))
+}
+
+fn strip_sound_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[sound:") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find(']') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Collapse runs of spaces, trim every line and allow at most one blank line in a row.
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_code = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            lines.push(line.trim().to_string());
+            continue;
+        }
+        if in_code {
+            lines.push(line.trim_end().to_string());
+            continue;
+        }
+        let collapsed = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(collapsed);
+    }
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_is_unchanged() {
+        assert_eq!(html_to_markdown("What is 2+2?"), "What is 2+2?");
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        assert_eq!(
+            html_to_markdown("<b>AODV</b> is <i>reactive</i>, see <code>route</code>"),
+            "**AODV** is *reactive*, see `route`"
+        );
+    }
+
+    #[test]
+    fn test_blocks_and_line_breaks() {
+        assert_eq!(
+            html_to_markdown("<div>First</div><div>Second<br>Third</div>"),
+            "First\nSecond\nThird"
+        );
+        assert_eq!(
+            html_to_markdown("Intro<ul><li>one</li><li>two</li></ul>"),
+            "Intro\n- one\n- two"
+        );
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            html_to_markdown("a&nbsp;&lt;b&gt; &amp; &#39;c&#39; &#x41;"),
+            "a <b> & 'c' A"
+        );
+        assert_eq!(decode_entities("AT&T"), "AT&T");
+    }
+
+    #[test]
+    fn test_media_and_scripts() {
+        assert_eq!(
+            html_to_markdown(
+                "<img src=\"diagram.png\">[sound:ping.mp3]<script>alert(1)</script>Done"
+            ),
+            "![](diagram.png)Done"
+        );
+    }
+
+    #[test]
+    fn test_source_newlines_and_whitespace_collapse() {
+        assert_eq!(
+            html_to_markdown("  one\n   two  <br><br><br>three "),
+            "one two\n\nthree"
+        );
+    }
+
+    #[test]
+    fn test_markdown_to_html() {
+        assert_eq!(
+            markdown_to_html("**AODV** is *reactive* & uses `RREQ`\n- one\n![](a.png)"),
+            "<b>AODV</b> is <i>reactive</i> &amp; uses <code>RREQ</code><br>- one<br><img src=\"a.png\">"
+        );
+        assert_eq!(markdown_to_html("a < b"), "a &lt; b");
+    }
+
+    #[test]
+    fn test_markdown_html_round_trip() {
+        let markdown = "**Bold** and *italic*\n- item";
+        assert_eq!(html_to_markdown(&markdown_to_html(markdown)), markdown);
+    }
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            strip_html("<b>What</b> is&nbsp;<i>2+2</i>?"),
+            "What is 2+2?"
+        );
+    }
+}
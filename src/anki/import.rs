@@ -0,0 +1,878 @@
+use super::html::html_to_markdown;
+use super::temp_collection_path;
+use crate::csv::flashcard;
+use crate::db::card;
+use crate::deck::{check_deck_name, write_markdown_deck, Deck, DeckMeta};
+use crate::models::{CardMeta, Flashcard};
+use regex::Regex;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Collection files inside a package, newest format first. `collection.anki21b` is
+/// zstd-compressed; packages that contain it also carry a placeholder `collection.anki2`.
+const COLLECTION_ENTRIES: [&str; 3] = [
+    "collection.anki21b",
+    "collection.anki21",
+    "collection.anki2",
+];
+
+/// Magic number at the start of a zstd frame; newer packages compress every entry
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+const FRONT_FIELDS: [&str; 3] = ["front", "question", "text"];
+const BACK_FIELDS: [&str; 4] = ["back", "answer", "back extra", "extra"];
+
+/// One entry of Anki's review log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnkiReview {
+    /// Unix time in seconds
+    pub reviewed_at: u64,
+    /// Answer button: 1 Again, 2 Hard, 3 Good, 4 Easy
+    pub ease: u8,
+}
+
+impl AnkiReview {
+    /// The correctness score an answer graded with this button maps to, chosen so that
+    /// SM-2 and FSRS see the same grade Anki recorded.
+    pub fn score(&self) -> f32 {
+        match self.ease {
+            1 => 0.0,
+            2 => 0.6,
+            3 => 0.8,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedCard {
+    pub flashcard: Flashcard,
+    /// Oldest first
+    pub reviews: Vec<AnkiReview>,
+}
+
+/// A media file from the package, stored under the name the cards link to
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnkiImport {
+    pub cards: Vec<ImportedCard>,
+    /// Notes with an empty front or back, which cannot become a flashcard
+    pub skipped_notes: usize,
+    /// Media files the imported cards reference
+    pub media: Vec<MediaFile>,
+}
+
+/// Read the notes (and their review log) of an `.apkg` or `.colpkg` file, along with
+/// the media files they reference.
+pub fn read_apkg(path: &Path) -> Result<AnkiImport, String> {
+    let file =
+        fs::File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("{} is not an Anki package: {}", path.display(), e))?;
+
+    let entry = COLLECTION_ENTRIES
+        .iter()
+        .find(|name| archive.by_name(name).is_ok())
+        .ok_or_else(|| format!("{} contains no Anki collection", path.display()))?;
+    let data = read_entry(&mut archive, entry)?;
+
+    // SQLite needs a file to open
+    let tmp = temp_collection_path("import");
+    fs::write(&tmp, &data).map_err(|e| format!("Cannot unpack collection: {}", e))?;
+
+    let result = Connection::open_with_flags(&tmp, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open collection: {}", e))
+        .and_then(|conn| read_collection(&conn));
+    let _ = fs::remove_file(&tmp);
+    let mut import = result?;
+
+    import.media = read_media(&mut archive, &referenced_media(&import.cards))?;
+    Ok(import)
+}
+
+/// Read a package entry, decompressing it if it is zstd-compressed.
+fn read_entry<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    archive
+        .by_name(name)
+        .and_then(|mut f| f.read_to_end(&mut data).map_err(Into::into))
+        .map_err(|e| format!("Cannot read {}: {}", name, e))?;
+    if data.starts_with(&ZSTD_MAGIC) {
+        data = zstd::decode_all(data.as_slice())
+            .map_err(|e| format!("Cannot decompress {}: {}", name, e))?;
+    }
+    Ok(data)
+}
+
+/// File names of the images the cards link to.
+fn referenced_media(cards: &[ImportedCard]) -> BTreeSet<String> {
+    let image = Regex::new(r"!\[[^\]]*\]\(([^)\s]+)\)").unwrap();
+    cards
+        .iter()
+        .flat_map(|c| [&c.flashcard.question, &c.flashcard.answer])
+        .flat_map(|text| image.captures_iter(text).map(|c| c[1].to_string()))
+        .collect()
+}
+
+/// Read the `wanted` files listed in the package's media map. Names that are not a plain
+/// file name are ignored, so a package cannot write outside the deck folder.
+fn read_media<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    wanted: &BTreeSet<String>,
+) -> Result<Vec<MediaFile>, String> {
+    if wanted.is_empty() || archive.by_name("media").is_err() {
+        return Ok(Vec::new());
+    }
+    let map = read_entry(archive, "media")?;
+
+    let mut media = Vec::new();
+    for (entry, name) in media_names(&map)? {
+        if !wanted.contains(&name) || !is_plain_file_name(&name) {
+            continue;
+        }
+        let data = read_entry(archive, &entry)?;
+        media.push(MediaFile { name, data });
+    }
+    media.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(media)
+}
+
+/// The (zip entry, file name) pairs of a media map. Older packages store a JSON object;
+/// newer ones a protobuf `MediaEntries` list, whose entries are named by list position.
+fn media_names(map: &[u8]) -> Result<Vec<(String, String)>, String> {
+    if map.first() == Some(&b'{') {
+        let names: HashMap<String, String> =
+            serde_json::from_slice(map).map_err(|e| format!("Cannot read media map: {}", e))?;
+        return Ok(names.into_iter().collect());
+    }
+
+    let mut names = Vec::new();
+    let mut entries = map;
+    let mut index = 0;
+    while let Some((field, mut entry)) = proto_field(&mut entries) {
+        if field != 1 {
+            continue;
+        }
+        while let Some((field, value)) = proto_field(&mut entry) {
+            if field == 1 {
+                names.push((
+                    index.to_string(),
+                    String::from_utf8_lossy(value).into_owned(),
+                ));
+                break;
+            }
+        }
+        index += 1;
+    }
+    Ok(names)
+}
+
+fn is_plain_file_name(name: &str) -> bool {
+    !name.contains('\\') && Path::new(name).file_name() == Some(name.as_ref())
+}
+
+fn varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Take the next (field number, payload) of a protobuf message. Scalar fields have an
+/// empty payload; `None` at the end of the message or on malformed input.
+fn proto_field<'a>(data: &mut &'a [u8]) -> Option<(u64, &'a [u8])> {
+    let key = varint(data)?;
+    let len = match key & 7 {
+        0 => {
+            varint(data)?;
+            0
+        }
+        1 => 8,
+        2 => varint(data)? as usize,
+        5 => 4,
+        _ => return None,
+    };
+    if data.len() < len {
+        return None;
+    }
+    let (payload, rest) = data.split_at(len);
+    *data = rest;
+    Some((key >> 3, if key & 7 == 2 { payload } else { &[] }))
+}
+
+/// Read the notes of an open Anki collection database.
+pub fn read_collection(conn: &Connection) -> Result<AnkiImport, String> {
+    let field_names =
+        note_type_fields(conn).map_err(|e| format!("Cannot read note types: {}", e))?;
+    let templates =
+        card_templates(conn).map_err(|e| format!("Cannot read card templates: {}", e))?;
+    let ordinals = note_ordinals(conn).map_err(|e| format!("Cannot read cards: {}", e))?;
+    let mut reviews = review_log(conn).map_err(|e| format!("Cannot read review log: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, mid, flds, tags FROM notes ORDER BY id")
+        .map_err(|e| format!("Cannot read notes: {}", e))?;
+    let notes = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(|e| format!("Cannot read notes: {}", e))?;
+
+    let mut import = AnkiImport::default();
+    for (note_id, model_id, fields, tags) in notes {
+        let fields: Vec<&str> = fields.split('\x1f').collect();
+        let names = field_names.get(&model_id).map(Vec::as_slice).unwrap_or(&[]);
+        let front = field(&fields, names, &FRONT_FIELDS, 0);
+        let back = field(&fields, names, &BACK_FIELDS, 1);
+        let meta = CardMeta {
+            tags: tags.split_whitespace().map(String::from).collect(),
+            ..Default::default()
+        };
+
+        let cards = if cloze_regex().is_match(&front) {
+            cloze_cards(&front, &back)
+        } else {
+            // Notes without cards in the collection still give their front and back
+            let ords = ordinals
+                .get(&note_id)
+                .cloned()
+                .unwrap_or_else(|| BTreeSet::from([0]));
+            ords.into_iter()
+                .filter_map(|ord| {
+                    let (question, answer) = match templates.get(&(model_id, ord)) {
+                        Some((q, a)) => (
+                            named_field(&fields, names, q),
+                            named_field(&fields, names, a),
+                        ),
+                        None if ord == 0 => (front.clone(), back.clone()),
+                        // The reverse card of "Basic (and reversed card)" note types
+                        None if ord == 1 => (back.clone(), front.clone()),
+                        None => return None,
+                    };
+                    basic_card(ord, &question, &answer)
+                })
+                .collect()
+        };
+        if cards.is_empty() {
+            import.skipped_notes += 1;
+            continue;
+        }
+        for (ord, question, answer) in cards {
+            import.cards.push(ImportedCard {
+                flashcard: flashcard(question, answer, meta.clone()),
+                reviews: reviews.remove(&(note_id, ord)).unwrap_or_default(),
+            });
+        }
+    }
+
+    Ok(import)
+}
+
+/// Field names of every note type, in field order.
+fn note_type_fields(conn: &Connection) -> rusqlite::Result<HashMap<i64, Vec<String>>> {
+    let mut names: HashMap<i64, Vec<String>> = HashMap::new();
+
+    let has_fields_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'fields'",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_fields_table {
+        // Schema 18 (Anki 2.1.50+) keeps note types in their own tables
+        let mut stmt = conn.prepare("SELECT ntid, name FROM fields ORDER BY ntid, ord")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (ntid, name) = row?;
+            names.entry(ntid).or_default().push(name);
+        }
+        return Ok(names);
+    }
+
+    // Older collections store note types as JSON in the `col` row
+    let models: String = conn.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+    let models: serde_json::Value = serde_json::from_str(&models).unwrap_or_default();
+    if let Some(models) = models.as_object() {
+        for (id, model) in models {
+            let Ok(id) = id.parse::<i64>() else { continue };
+            let mut fields: Vec<(i64, String)> = model["flds"]
+                .as_array()
+                .map(|flds| {
+                    flds.iter()
+                        .map(|f| {
+                            (
+                                f["ord"].as_i64().unwrap_or(0),
+                                f["name"].as_str().unwrap_or("").to_string(),
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            fields.sort();
+            names.insert(id, fields.into_iter().map(|(_, name)| name).collect());
+        }
+    }
+    Ok(names)
+}
+
+/// The question and answer field each card template shows, keyed by (note type id,
+/// card ordinal). Only older collections keep templates as readable JSON; for schema 18
+/// the map is empty and cards fall back to the front and back fields.
+fn card_templates(conn: &Connection) -> rusqlite::Result<HashMap<(i64, i64), (String, String)>> {
+    let mut templates = HashMap::new();
+    let Ok(models) = conn.query_row("SELECT models FROM col", [], |row| row.get::<_, String>(0))
+    else {
+        return Ok(templates);
+    };
+    let models: serde_json::Value = serde_json::from_str(&models).unwrap_or_default();
+    let Some(models) = models.as_object() else {
+        return Ok(templates);
+    };
+
+    for (id, model) in models {
+        let Ok(id) = id.parse::<i64>() else { continue };
+        for tmpl in model["tmpls"].as_array().into_iter().flatten() {
+            let question = template_fields(tmpl["qfmt"].as_str().unwrap_or(""));
+            let answer = template_fields(tmpl["afmt"].as_str().unwrap_or(""));
+            // The answer side usually repeats the question through {{FrontSide}}
+            let (Some(q), Some(a)) = (
+                question.first(),
+                answer.iter().find(|f| !question.contains(f)),
+            ) else {
+                continue;
+            };
+            templates.insert(
+                (id, tmpl["ord"].as_i64().unwrap_or(0)),
+                (q.clone(), a.clone()),
+            );
+        }
+    }
+    Ok(templates)
+}
+
+/// Fields a template shows, in order, without filters, conditionals or `{{FrontSide}}`.
+fn template_fields(template: &str) -> Vec<String> {
+    let re = Regex::new(r"\{\{([^#^/}][^}]*)\}\}").unwrap();
+    re.captures_iter(template)
+        .map(|c| c[1].rsplit(':').next().unwrap_or("").trim().to_string())
+        .filter(|name| !name.is_empty() && name != "FrontSide")
+        .collect()
+}
+
+/// Ordinals of the cards each note has.
+fn note_ordinals(conn: &Connection) -> rusqlite::Result<HashMap<i64, BTreeSet<i64>>> {
+    let mut ordinals: HashMap<i64, BTreeSet<i64>> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT nid, ord FROM cards")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (note_id, ord) = row?;
+        ordinals.entry(note_id).or_default().insert(ord);
+    }
+    Ok(ordinals)
+}
+
+/// Graded reviews keyed by (note id, card ordinal), oldest first.
+fn review_log(conn: &Connection) -> rusqlite::Result<HashMap<(i64, i64), Vec<AnkiReview>>> {
+    let mut log: HashMap<(i64, i64), Vec<AnkiReview>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT c.nid, c.ord, r.id, r.ease FROM revlog r JOIN cards c ON c.id = r.cid
+         WHERE r.ease BETWEEN 1 AND 4 ORDER BY r.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, u8>(3)?,
+        ))
+    })?;
+    for row in rows {
+        let (note_id, ord, review_ms, ease) = row?;
+        log.entry((note_id, ord)).or_default().push(AnkiReview {
+            reviewed_at: (review_ms / 1000).max(0) as u64,
+            ease,
+        });
+    }
+    Ok(log)
+}
+
+/// Pick a field by name, falling back to its position for note types with other names.
+fn field(fields: &[&str], names: &[String], wanted: &[&str], fallback: usize) -> String {
+    let index = wanted
+        .iter()
+        .find_map(|w| names.iter().position(|n| n.eq_ignore_ascii_case(w)))
+        .unwrap_or(fallback);
+    fields
+        .get(index)
+        .map(|f| html_to_markdown(f))
+        .unwrap_or_default()
+}
+
+/// The field called `name`, as a template refers to it.
+fn named_field(fields: &[&str], names: &[String], name: &str) -> String {
+    names
+        .iter()
+        .position(|n| n == name)
+        .and_then(|i| fields.get(i))
+        .map(|f| html_to_markdown(f))
+        .unwrap_or_default()
+}
+
+fn cloze_regex() -> Regex {
+    Regex::new(r"(?s)\{\{c(\d+)::(.*?)(?:::(.*?))?\}\}").unwrap()
+}
+
+/// A card showing `question` and expecting `answer`, unless either is empty.
+fn basic_card(ord: i64, question: &str, answer: &str) -> Option<(i64, String, String)> {
+    if question.trim().is_empty() || answer.trim().is_empty() {
+        return None;
+    }
+    Some((ord, single_line(question), answer.to_string()))
+}
+
+/// The (card ordinal, question, answer) triples of a cloze note, one per deletion number.
+fn cloze_cards(front: &str, back: &str) -> Vec<(i64, String, String)> {
+    let re = cloze_regex();
+    let numbers: BTreeSet<i64> = re
+        .captures_iter(front)
+        .filter_map(|c| c[1].parse().ok())
+        .collect();
+
+    numbers
+        .into_iter()
+        .map(|n| {
+            let question = re.replace_all(front, |c: &regex::Captures| {
+                if c[1].parse::<i64>().ok() == Some(n) {
+                    format!("[{}]", c.get(3).map(|h| h.as_str()).unwrap_or("..."))
+                } else {
+                    c[2].to_string()
+                }
+            });
+            let deletions: Vec<String> = re
+                .captures_iter(front)
+                .filter(|c| c[1].parse::<i64>().ok() == Some(n))
+                .map(|c| c[2].to_string())
+                .collect();
+            let mut answer = deletions.join("; ");
+            if !back.trim().is_empty() {
+                answer.push_str("\n\n");
+                answer.push_str(back);
+            }
+            (n - 1, single_line(&question), answer)
+        })
+        .collect()
+}
+
+/// Questions become Markdown headings, which cannot span lines.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Write the imported cards as a Markdown deck `<deck_name>.md` in `dir`, with their
+/// media files next to it. An existing deck file is never overwritten, and neither is an
+/// existing media file of the same name.
+pub fn write_native_deck(
+    dir: &Path,
+    deck_name: &str,
+    import: &AnkiImport,
+) -> Result<PathBuf, String> {
+    check_deck_name(deck_name)?;
+    let path = dir.join(format!("{}.md", deck_name));
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+
+    let deck = Deck {
+        meta: DeckMeta {
+            title: Some(deck_name.to_string()),
+            ..Default::default()
+        },
+        flashcards: import.cards.iter().map(|c| c.flashcard.clone()).collect(),
+    };
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    fs::write(&path, write_markdown_deck(&deck))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    for file in &import.media {
+        let media_path = dir.join(&file.name);
+        if !media_path.exists() {
+            fs::write(&media_path, &file.data)
+                .map_err(|e| format!("Cannot write {}: {}", media_path.display(), e))?;
+        }
+    }
+    Ok(path)
+}
+
+/// Replay Anki's review log into the scheduler tables for `deck_name`. Reviews no newer
+/// than a card's last recorded review are skipped, so importing a package twice does
+/// not count its history twice. Returns the number of reviews recorded.
+pub fn import_review_history(
+    conn: &Connection,
+    deck_name: &str,
+    cards: &[ImportedCard],
+) -> rusqlite::Result<usize> {
+    let pairs: Vec<(String, String)> = cards
+        .iter()
+        .map(|c| (c.flashcard.question.clone(), c.flashcard.answer.clone()))
+        .collect();
+    let card_ids = card::resolve_cards(conn, deck_name, &pairs)?;
+
+    let mut recorded = 0;
+    for (imported, card_id) in cards.iter().zip(card_ids) {
+        card::set_card_meta(conn, card_id, &imported.flashcard.meta)?;
+        let last_reviewed_at = card::get_card(conn, card_id)?
+            .and_then(|c| c.last_reviewed_at)
+            .unwrap_or(0);
+        for review in imported
+            .reviews
+            .iter()
+            .filter(|r| r.reviewed_at > last_reviewed_at)
+        {
+            card::record_review_at(
+                conn,
+                card_id,
+                None,
+                None,
+                review.score(),
+                review.reviewed_at,
+            )?;
+            recorded += 1;
+        }
+    }
+
+    Ok(recorded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+    use std::io::Write;
+
+    const DAY: i64 = 86_400_000;
+
+    /// A minimal pre-2.1.50 collection: one Basic note, one Cloze note, one empty note
+    fn legacy_collection(conn: &Connection) {
+        conn.execute_batch(
+            "CREATE TABLE col (models TEXT);
+             CREATE TABLE notes (id INTEGER PRIMARY KEY, mid INTEGER, flds TEXT, tags TEXT);
+             CREATE TABLE cards (id INTEGER PRIMARY KEY, nid INTEGER, ord INTEGER);
+             CREATE TABLE revlog (id INTEGER PRIMARY KEY, cid INTEGER, ease INTEGER);",
+        )
+        .unwrap();
+        let models = r#"{
+            "1": {"name": "Basic", "flds": [{"name": "Back", "ord": 1}, {"name": "Front", "ord": 0}]},
+            "2": {"name": "Cloze", "flds": [{"name": "Text", "ord": 0}, {"name": "Back Extra", "ord": 1}]}
+        }"#;
+        conn.execute("INSERT INTO col (models) VALUES (?)", [models])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO notes VALUES (10, 1, ?, ' routing manet ')",
+            ["What does <b>AODV</b> stand for?\x1fAd hoc On-Demand<br>Distance Vector"],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO notes VALUES (20, 2, ?, '')",
+            ["{{c1::Paris}} is the capital of {{c2::France::country}}\x1f"],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO notes VALUES (30, 1, ?, '')",
+            ["Front only\x1f"],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO cards VALUES (100, 10, 0);
+             INSERT INTO cards VALUES (200, 20, 0);
+             INSERT INTO cards VALUES (201, 20, 1);",
+        )
+        .unwrap();
+        for (id, cid, ease) in [
+            (DAY * 20_000, 100, 3),
+            (DAY * 20_003, 100, 1),
+            (DAY * 20_003 + 1, 100, 0),
+            (DAY * 20_001, 201, 4),
+        ] {
+            conn.execute(
+                "INSERT INTO revlog VALUES (?, ?, ?)",
+                rusqlite::params![id, cid, ease],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_legacy_collection() {
+        let conn = Connection::open_in_memory().unwrap();
+        legacy_collection(&conn);
+        let import = read_collection(&conn).unwrap();
+
+        assert_eq!(import.skipped_notes, 1);
+        assert_eq!(import.cards.len(), 3);
+
+        let basic = &import.cards[0];
+        assert_eq!(basic.flashcard.question, "What does **AODV** stand for?");
+        assert_eq!(basic.flashcard.answer, "Ad hoc On-Demand\nDistance Vector");
+        assert_eq!(basic.flashcard.meta.tags, vec!["routing", "manet"]);
+        // Ease 0 entries (manual reschedules) are not reviews
+        assert_eq!(
+            basic.reviews.iter().map(|r| r.ease).collect::<Vec<_>>(),
+            vec![3, 1]
+        );
+
+        assert_eq!(
+            import.cards[1].flashcard.question,
+            "[...] is the capital of France"
+        );
+        assert_eq!(import.cards[1].flashcard.answer, "Paris");
+        assert!(import.cards[1].reviews.is_empty());
+        assert_eq!(
+            import.cards[2].flashcard.question,
+            "Paris is the capital of [country]"
+        );
+        assert_eq!(import.cards[2].flashcard.answer, "France");
+        assert_eq!(import.cards[2].reviews.len(), 1);
+    }
+
+    #[test]
+    fn test_read_two_template_notes() {
+        let conn = Connection::open_in_memory().unwrap();
+        legacy_collection(&conn);
+        let models = r#"{
+            "1": {"name": "Basic", "flds": [{"name": "Back", "ord": 1}, {"name": "Front", "ord": 0}]},
+            "3": {"name": "Basic (and reversed card)",
+                  "flds": [{"name": "Front", "ord": 0}, {"name": "Back", "ord": 1}],
+                  "tmpls": [
+                      {"ord": 0, "qfmt": "{{Front}}", "afmt": "{{FrontSide}}<hr id=answer>{{Back}}"},
+                      {"ord": 1, "qfmt": "{{text:Back}}", "afmt": "{{FrontSide}}<hr id=answer>{{Front}}"}
+                  ]}
+        }"#;
+        conn.execute("UPDATE col SET models = ?", [models]).unwrap();
+        conn.execute("DELETE FROM notes WHERE mid = 2", []).unwrap();
+        conn.execute(
+            "INSERT INTO notes VALUES (40, 3, ?, '')",
+            ["Capital of France?\x1fParis"],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO cards VALUES (101, 10, 1);
+             INSERT INTO cards VALUES (400, 40, 0);
+             INSERT INTO cards VALUES (401, 40, 1);",
+        )
+        .unwrap();
+        for (id, cid, ease) in [(DAY * 20_010, 101, 2), (DAY * 20_011, 401, 4)] {
+            conn.execute(
+                "INSERT INTO revlog VALUES (?, ?, ?)",
+                rusqlite::params![id, cid, ease],
+            )
+            .unwrap();
+        }
+
+        let import = read_collection(&conn).unwrap();
+        let cards: Vec<(&str, &str, Vec<u8>)> = import
+            .cards
+            .iter()
+            .map(|c| {
+                (
+                    c.flashcard.question.as_str(),
+                    c.flashcard.answer.as_str(),
+                    c.reviews.iter().map(|r| r.ease).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cards,
+            vec![
+                (
+                    "What does **AODV** stand for?",
+                    "Ad hoc On-Demand\nDistance Vector",
+                    vec![3, 1]
+                ),
+                // No templates for this note type: the second card is the reverse
+                (
+                    "Ad hoc On-Demand Distance Vector",
+                    "What does **AODV** stand for?",
+                    vec![2]
+                ),
+                ("Capital of France?", "Paris", vec![]),
+                ("Paris", "Capital of France?", vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_schema_18_collection() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE col (models TEXT);
+             CREATE TABLE fields (ntid INTEGER, ord INTEGER, name TEXT);
+             CREATE TABLE notes (id INTEGER PRIMARY KEY, mid INTEGER, flds TEXT, tags TEXT);
+             CREATE TABLE cards (id INTEGER PRIMARY KEY, nid INTEGER, ord INTEGER);
+             CREATE TABLE revlog (id INTEGER PRIMARY KEY, cid INTEGER, ease INTEGER);
+             INSERT INTO col VALUES ('');
+             INSERT INTO fields VALUES (5, 0, 'Answer');
+             INSERT INTO fields VALUES (5, 1, 'Question');",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO notes VALUES (1, 5, ?, '')",
+            ["Four\x1fWhat is 2+2?"],
+        )
+        .unwrap();
+
+        let import = read_collection(&conn).unwrap();
+        assert_eq!(import.cards[0].flashcard.question, "What is 2+2?");
+        assert_eq!(import.cards[0].flashcard.answer, "Four");
+    }
+
+    #[test]
+    fn test_read_apkg_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let collection_path = dir.path().join("collection");
+        {
+            let conn = Connection::open(&collection_path).unwrap();
+            legacy_collection(&conn);
+            conn.execute(
+                "INSERT INTO notes VALUES (50, 1, ?, '')",
+                ["Which protocol? <img src=\"aodv.png\"><img src=\"../up.png\">\x1fAODV"],
+            )
+            .unwrap();
+        }
+        let collection = fs::read(&collection_path).unwrap();
+
+        let apkg_path = dir.path().join("deck.apkg");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&apkg_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("collection.anki2", options).unwrap();
+        zip.write_all(b"placeholder, should be ignored").unwrap();
+        zip.start_file("collection.anki21b", options).unwrap();
+        zip.write_all(&zstd::encode_all(collection.as_slice(), 0).unwrap())
+            .unwrap();
+        zip.start_file("media", options).unwrap();
+        zip.write_all(br#"{"0": "aodv.png", "1": "unused.mp3", "2": "../up.png"}"#)
+            .unwrap();
+        for entry in ["0", "1", "2"] {
+            zip.start_file(entry, options).unwrap();
+            zip.write_all(entry.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let import = read_apkg(&apkg_path).unwrap();
+        assert_eq!(import.cards.len(), 4);
+        assert_eq!(
+            import.media,
+            vec![MediaFile {
+                name: "aodv.png".to_string(),
+                data: b"0".to_vec()
+            }]
+        );
+
+        let deck_dir = dir.path().join("decks");
+        write_native_deck(&deck_dir, "anki", &import).unwrap();
+        assert_eq!(fs::read(deck_dir.join("aodv.png")).unwrap(), b"0");
+
+        assert!(read_apkg(&collection_path).is_err());
+    }
+
+    #[test]
+    fn test_media_names_from_protobuf_list() {
+        // MediaEntries { entries: [{ name: "a.png", size: 3 }, { name: "b.mp3" }] }
+        let map = [
+            &[0x0a, 9, 0x0a, 5][..],
+            b"a.png",
+            &[0x10, 3, 0x0a, 7, 0x0a, 5],
+            b"b.mp3",
+        ]
+        .concat();
+        assert_eq!(
+            media_names(&map).unwrap(),
+            vec![
+                ("0".to_string(), "a.png".to_string()),
+                ("1".to_string(), "b.mp3".to_string())
+            ]
+        );
+        assert!(!is_plain_file_name("../a.png"));
+        assert!(!is_plain_file_name("sub/a.png"));
+        assert!(is_plain_file_name("a.png"));
+    }
+
+    #[test]
+    fn test_write_native_deck() {
+        let conn = Connection::open_in_memory().unwrap();
+        legacy_collection(&conn);
+        let import = read_collection(&conn).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_native_deck(dir.path(), "anki", &import).unwrap();
+        let deck = crate::deck::load_deck(&path).unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("anki"));
+        assert_eq!(deck.flashcards.len(), 3);
+        assert_eq!(
+            deck.flashcards[0].question,
+            import.cards[0].flashcard.question
+        );
+        assert_eq!(deck.flashcards[0].answer, import.cards[0].flashcard.answer);
+        assert_eq!(deck.flashcards[0].meta.tags, vec!["routing", "manet"]);
+
+        assert!(write_native_deck(dir.path(), "anki", &import).is_err());
+
+        // `--name` cannot place the deck outside the deck folder
+        let decks = dir.path().join("decks");
+        let error = write_native_deck(&decks, "../../x", &import).unwrap_err();
+        assert!(error.contains("not a valid deck name"), "{}", error);
+        assert!(!dir.path().join("x.md").exists());
+        assert!(!decks.exists());
+    }
+
+    #[test]
+    fn test_import_review_history_is_idempotent() {
+        let anki = Connection::open_in_memory().unwrap();
+        legacy_collection(&anki);
+        let import = read_collection(&anki).unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+
+        assert_eq!(
+            import_review_history(&conn, "anki", &import.cards).unwrap(),
+            3
+        );
+        assert_eq!(
+            import_review_history(&conn, "anki", &import.cards).unwrap(),
+            0
+        );
+
+        let card = card::find_card(&conn, "anki", &import.cards[0].flashcard.question)
+            .unwrap()
+            .unwrap();
+        assert_eq!(card::get_review_count(&conn, card.id).unwrap(), 2);
+        // The lapse on the second review resets SM-2 repetitions
+        assert_eq!(card.sm2.repetitions, 0);
+        assert_eq!(card.last_reviewed_at, Some((DAY * 20_003 / 1000) as u64));
+        assert_eq!(
+            card::get_card_meta(&conn, card.id).unwrap().unwrap().tags,
+            vec!["routing", "manet"]
+        );
+    }
+}
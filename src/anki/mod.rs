@@ -0,0 +1,25 @@
+pub mod export;
+pub mod html;
+pub mod import;
+
+use std::path::PathBuf;
+
+pub use export::{
+    deck_export_cards, missed_session_cards, write_apkg, write_tsv, ExportCard, ExportFormat,
+};
+pub use html::{html_to_markdown, markdown_to_html};
+pub use import::{import_review_history, read_apkg, write_native_deck, AnkiImport, ImportedCard};
+
+/// A fresh path in the temp directory for unpacking or building a collection database.
+pub(crate) fn temp_collection_path(purpose: &str) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    std::env::temp_dir().join(format!(
+        "flashcards-{}-{}-{}.db",
+        purpose,
+        std::process::id(),
+        nanos
+    ))
+}
@@ -0,0 +1,306 @@
+//! User configuration, read from `config.toml` in the config directory
+//! (`$XDG_CONFIG_HOME/interactive-flashcards`, or `~/.config/interactive-flashcards`).
+//!
+//! ```toml
+//! # Folders searched (recursively) for decks, in priority order
+//! deck_dirs = ["~/notes/flashcards", "/srv/shared-decks"]
+//!
+//! # AI backend, see `ai::provider` (defaults to OpenRouter)
+//! [ai]
+//! provider = "ollama"
+//! model = "llama3.1"
+//! ```
+//!
+//! A missing file is the same as an empty one. Unknown keys are ignored so that an
+//! older build can still read a newer config. The settings screen writes the per-task
+//! model settings back with [`save_model_settings`], leaving the rest of the file as is.
+
+use crate::ai::templates::PromptKind;
+use crate::ai::{AiConfig, TaskConfig};
+use crate::logger;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+/// Environment variable listing deck folders, separated like `PATH`
+pub const DECK_DIRS_ENV: &str = "FLASHCARDS_DIR";
+
+/// Where decks are looked for when nothing is configured
+pub const DEFAULT_DECK_DIR: &str = "flashcards";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Deck roots; relative paths are relative to the config file
+    pub deck_dirs: Vec<PathBuf>,
+    pub ai: AiConfig,
+}
+
+/// The user's home directory. Without one (`HOME` unset or empty) the current directory
+/// stands in for it, with a warning in the log.
+fn home_dir() -> PathBuf {
+    let var = if cfg!(target_os = "windows") {
+        "USERPROFILE"
+    } else {
+        "HOME"
+    };
+    match std::env::var_os(var) {
+        Some(home) if !home.is_empty() => PathBuf::from(home),
+        _ => {
+            let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+            logger::log(&format!(
+                "{} is not set; using {} as the home directory",
+                var,
+                cwd.display()
+            ));
+            cwd
+        }
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("interactive-flashcards"),
+        _ => home_dir().join(".config").join("interactive-flashcards"),
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+/// Load the config file; a missing file gives the defaults.
+pub fn load_config() -> io::Result<Config> {
+    load_config_from(&config_path())
+}
+
+pub fn load_config_from(path: &Path) -> io::Result<Config> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e),
+    };
+    let mut config = parse_config(&content).map_err(|e| invalid_data(path, e))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    for dir in &mut config.deck_dirs {
+        *dir = base.join(expand_home(dir));
+    }
+    Ok(config)
+}
+
+pub fn parse_config(content: &str) -> Result<Config, String> {
+    toml::from_str(content).map_err(|e| e.message().trim().to_string())
+}
+
+/// Log the keys of `table` that nothing reads, such as a misspelt `inptu`, which are
+/// otherwise ignored like any other unknown key.
+pub fn warn_unknown_keys(table: &str, unknown: &BTreeMap<String, IgnoredAny>) {
+    for key in unknown.keys() {
+        logger::log(&format!("Ignoring unknown key `{}` in {}", key, table));
+    }
+}
+
+fn invalid_data(path: &Path, message: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// Write the per-task tables (`[ai.evaluation]` and so on) and `ai.fallback_models` of
+/// `ai` into the config file at `path`, creating it if needed.
+/// Everything else in the file, comments included, is kept.
+pub fn save_model_settings(path: &Path, ai: &AiConfig) -> io::Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut doc: DocumentMut = content.parse().map_err(|e| invalid_data(path, e))?;
+    let ai_table = doc
+        .entry("ai")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| invalid_data(path, "ai is not a table"))?;
+
+    for kind in PromptKind::ALL {
+        let task = ai.task(kind);
+        if *task == TaskConfig::default() {
+            ai_table.remove(kind.name());
+            continue;
+        }
+        let table = ai_table
+            .entry(kind.name())
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| invalid_data(path, format!("ai.{} is not a table", kind.name())))?;
+        set_or_remove(table, "model", task.model.as_deref().map(value));
+        // Through the shortest decimal form, so 0.7 is not written as 0.699999988079071
+        let temperature = task
+            .temperature
+            .and_then(|t| t.to_string().parse::<f64>().ok());
+        set_or_remove(table, "temperature", temperature.map(value));
+        set_or_remove(
+            table,
+            "max_tokens",
+            task.max_tokens.map(|n| value(i64::from(n))),
+        );
+    }
+
+    if ai.fallback_models.is_empty() {
+        ai_table.remove("fallback_models");
+    } else {
+        let models: Array = ai.fallback_models.iter().map(String::as_str).collect();
+        ai_table.insert("fallback_models", value(models));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, doc.to_string())
+}
+
+fn set_or_remove(table: &mut Table, key: &str, item: Option<Item>) {
+    match item {
+        Some(item) => {
+            table.insert(key, item);
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+/// Replace a leading `~` with the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// The deck roots to search, from the first source that names any: `--decks` flags,
+/// then [`DECK_DIRS_ENV`], then the config file, then [`DEFAULT_DECK_DIR`].
+pub fn resolve_deck_dirs(cli: &[PathBuf], env: Option<&OsStr>, config: &Config) -> Vec<PathBuf> {
+    if !cli.is_empty() {
+        return cli.iter().map(|p| expand_home(p)).collect();
+    }
+    if let Some(env) = env {
+        let dirs: Vec<PathBuf> = std::env::split_paths(env)
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| expand_home(&p))
+            .collect();
+        if !dirs.is_empty() {
+            return dirs;
+        }
+    }
+    if !config.deck_dirs.is_empty() {
+        return config.deck_dirs.clone();
+    }
+    vec![PathBuf::from(DEFAULT_DECK_DIR)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config("deck_dirs = [\"a\", \"/b\"]\nfuture_key = 1\n").unwrap();
+        assert_eq!(
+            config.deck_dirs,
+            vec![PathBuf::from("a"), PathBuf::from("/b")]
+        );
+        assert_eq!(parse_config("").unwrap(), Config::default());
+        assert!(parse_config("deck_dirs = \"a\"").is_err());
+
+        let config = parse_config("[ai]\nprovider = \"ollama\"\nmodel = \"phi3\"\n").unwrap();
+        assert_eq!(config.ai.provider, crate::ai::ProviderKind::Ollama);
+        assert_eq!(config.ai.model.as_deref(), Some("phi3"));
+    }
+
+    #[test]
+    fn test_load_config_resolves_relative_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(load_config_from(&path).unwrap(), Config::default());
+
+        fs::write(&path, "deck_dirs = [\"decks\", \"/abs\"]\n").unwrap();
+        let config = load_config_from(&path).unwrap();
+        assert_eq!(
+            config.deck_dirs,
+            vec![dir.path().join("decks"), PathBuf::from("/abs")]
+        );
+    }
+
+    #[test]
+    fn test_save_model_settings_keeps_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "# my decks\ndeck_dirs = [\"decks\"]\n\n[ai]\nprovider = \"ollama\"\n\n\
+             [ai.chat]\nmodel = \"old\"\n",
+        )
+        .unwrap();
+
+        let mut config = load_config_from(&path).unwrap();
+        config.ai.evaluation.model = Some("llama3.1:70b".to_string());
+        config.ai.evaluation.temperature = Some(0.7);
+        config.ai.chat = TaskConfig::default();
+        config.ai.fallback_models = vec!["phi3".to_string()];
+        save_model_settings(&path, &config.ai).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my decks\n"), "{}", content);
+        assert!(content.contains("temperature = 0.7\n"), "{}", content);
+        assert!(!content.contains("[ai.chat]"), "{}", content);
+        let saved = load_config_from(&path).unwrap();
+        assert_eq!(saved.ai, config.ai);
+        assert_eq!(saved.deck_dirs, config.deck_dirs);
+
+        let path = dir.path().join("new").join("config.toml");
+        save_model_settings(&path, &config.ai).unwrap();
+        assert_eq!(
+            load_config_from(&path).unwrap().ai.evaluation,
+            config.ai.evaluation
+        );
+    }
+
+    #[test]
+    fn test_resolve_deck_dirs_precedence() {
+        let config = Config {
+            deck_dirs: vec![PathBuf::from("/from-config")],
+            ..Default::default()
+        };
+        let env = std::env::join_paths(["/env-a", "/env-b"]).unwrap();
+
+        assert_eq!(
+            resolve_deck_dirs(&[PathBuf::from("/cli")], Some(&env), &config),
+            vec![PathBuf::from("/cli")]
+        );
+        assert_eq!(
+            resolve_deck_dirs(&[], Some(&env), &config),
+            vec![PathBuf::from("/env-a"), PathBuf::from("/env-b")]
+        );
+        assert_eq!(
+            resolve_deck_dirs(&[], Some(OsStr::new("")), &config),
+            vec![PathBuf::from("/from-config")]
+        );
+        assert_eq!(
+            resolve_deck_dirs(&[], None, &Config::default()),
+            vec![PathBuf::from(DEFAULT_DECK_DIR)]
+        );
+    }
+}
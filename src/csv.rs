@@ -1,88 +1,434 @@
-use crate::models::Flashcard;
+use crate::deck::structured::{Issue, Severity};
+use crate::logger;
+use crate::models::{CardMeta, Flashcard};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
-
-pub fn get_csv_files() -> Vec<PathBuf> {
-    let flashcards_dir = PathBuf::from("flashcards");
-    let mut files = Vec::new();
-
-    if flashcards_dir.exists() && flashcards_dir.is_dir()
-        && let Ok(entries) = fs::read_dir(&flashcards_dir) {
-            for entry in entries.flatten() {
-                if let Some(ext) = entry.path().extension()
-                    && ext == "csv" {
-                        files.push(entry.path());
-                    }
-            }
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// A parse error with the (1-based) physical line it occurred on
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// How a deck file should be read. `None` fields are detected from the file itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CsvOptions {
+    /// Field separator: `,`, `;` or tab. Detected from the first line when `None`.
+    pub delimiter: Option<char>,
+    /// Whether the first record is a header row. Detected when `None`: a first row
+    /// naming the question and answer columns is treated as a header.
+    pub has_header: Option<bool>,
+}
+
+impl CsvOptions {
+    /// Default options for a deck file; `.tsv` files are tab separated.
+    pub fn for_path(path: &Path) -> Self {
+        let delimiter = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") => Some('\t'),
+            _ => None,
+        };
+        Self {
+            delimiter,
+            has_header: None,
         }
+    }
+}
 
-    files.sort();
-    files
+/// One CSV record and the line it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRecord {
+    pub line: usize,
+    pub fields: Vec<String>,
 }
 
-pub fn load_csv(path: &PathBuf) -> std::io::Result<Vec<Flashcard>> {
-    let content = fs::read_to_string(path)?;
-    let mut flashcards = Vec::new();
+impl CsvRecord {
+    fn is_blank(&self) -> bool {
+        self.fields.iter().all(|f| f.trim().is_empty())
+    }
+}
 
-    for line in content.lines() {
-        if let Some((question, answer)) = parse_csv_line(line)
-            && !question.trim().is_empty() && !answer.trim().is_empty() {
-                flashcards.push(Flashcard {
-                    question,
-                    answer,
-                    user_answer: None,
-                    ai_feedback: None,
-                    written_to_file: false,
-                    id: None,
-                });
-            }
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldState {
+    Start,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+}
+
+/// Streaming RFC 4180 record reader.
+///
+/// Quoted fields may contain delimiters, doubled quotes and line breaks; CRLF and LF
+/// line endings are both accepted and a leading UTF-8 BOM is skipped.
+pub struct CsvReader<R> {
+    reader: R,
+    delimiter: char,
+    line: usize,
+    buf: String,
+    finished: bool,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R, delimiter: char) -> Self {
+        Self {
+            reader,
+            delimiter,
+            line: 0,
+            buf: String::new(),
+            finished: false,
+        }
     }
 
-    Ok(flashcards)
+    fn error(&self, line: usize, message: String) -> Option<Result<CsvRecord, CsvError>> {
+        Some(Err(CsvError { line, message }))
+    }
 }
 
-pub fn parse_csv_line(line: &str) -> Option<(String, String)> {
-    let mut chars = line.chars().peekable();
-    let mut question = String::new();
-    let mut answer = String::new();
-    let mut current_field = &mut question;
-    let mut in_quotes = false;
-    let mut field_index = 0;
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<CsvRecord, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let start_line = self.line + 1;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut state = FieldState::Start;
+
+        loop {
+            self.buf.clear();
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => {
+                    self.finished = true;
+                    if self.line < start_line {
+                        return None;
+                    }
+                    if state == FieldState::Quoted {
+                        return self.error(
+                            start_line,
+                            "unterminated quoted field (missing closing '\"')".to_string(),
+                        );
+                    }
+                    fields.push(field);
+                    return Some(Ok(CsvRecord {
+                        line: start_line,
+                        fields,
+                    }));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.finished = true;
+                    return self.error(self.line + 1, e.to_string());
+                }
+            }
+            self.line += 1;
 
-    while let Some(c) = chars.next() {
-        match c {
-            '"' if !in_quotes => {
-                in_quotes = true;
+            let mut text = self.buf.as_str();
+            if self.line == 1 {
+                text = text.strip_prefix('\u{feff}').unwrap_or(text);
             }
-            '"' if in_quotes => {
-                if chars.peek() == Some(&',') {
-                    chars.next();
-                    in_quotes = false;
-                    if field_index == 0 {
-                        current_field = &mut answer;
-                        field_index = 1;
+            let has_newline = text.ends_with('\n');
+            let text = text.trim_end_matches('\n').trim_end_matches('\r');
+
+            for c in text.chars() {
+                state = match (state, c) {
+                    (FieldState::Start, '"') => FieldState::Quoted,
+                    (FieldState::Start | FieldState::Unquoted | FieldState::QuoteInQuoted, d)
+                        if d == self.delimiter =>
+                    {
+                        fields.push(std::mem::take(&mut field));
+                        FieldState::Start
+                    }
+                    (FieldState::Start | FieldState::Unquoted, c) => {
+                        field.push(c);
+                        FieldState::Unquoted
                     }
-                } else if chars.peek() == Some(&'"') {
-                    chars.next();
-                    current_field.push('"');
-                } else {
-                    in_quotes = false;
-                    if field_index == 0 {
-                        current_field = &mut answer;
-                        field_index = 1;
+                    (FieldState::Quoted, '"') => FieldState::QuoteInQuoted,
+                    (FieldState::Quoted, c) => {
+                        field.push(c);
+                        FieldState::Quoted
                     }
+                    (FieldState::QuoteInQuoted, '"') => {
+                        field.push('"');
+                        FieldState::Quoted
+                    }
+                    // Padding between a closing quote and the delimiter is ignored
+                    (FieldState::QuoteInQuoted, ' ' | '\t') => FieldState::QuoteInQuoted,
+                    (FieldState::QuoteInQuoted, c) => {
+                        return self.error(
+                            self.line,
+                            format!("unexpected character '{}' after closing quote", c),
+                        );
+                    }
+                };
+            }
+
+            if state == FieldState::Quoted {
+                if has_newline {
+                    field.push('\n');
                 }
+                continue;
+            }
+
+            fields.push(field);
+            return Some(Ok(CsvRecord {
+                line: start_line,
+                fields,
+            }));
+        }
+    }
+}
+
+/// Pick the delimiter that occurs most often (outside quotes) in the first line.
+/// Commas win ties, so ordinary two-column decks are unaffected.
+pub fn detect_delimiter(sample: &str) -> char {
+    let first_line = sample.lines().next().unwrap_or("");
+    let mut counts = [(',', 0usize), (';', 0), ('\t', 0)];
+    let mut in_quotes = false;
+
+    for c in first_line.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && let Some(entry) = counts.iter_mut().find(|(d, _)| *d == c) {
+            entry.1 += 1;
+        }
+    }
+
+    counts
+        .iter()
+        .fold(
+            (',', 0),
+            |best, &(d, n)| if n > best.1 { (d, n) } else { best },
+        )
+        .0
+}
+
+/// Column positions of a deck file, taken from its header row if it has one
+#[derive(Debug, Clone, PartialEq)]
+struct DeckColumns {
+    question: usize,
+    answer: usize,
+    tags: Option<usize>,
+    hint: Option<usize>,
+    source: Option<usize>,
+    difficulty: Option<usize>,
+}
+
+impl Default for DeckColumns {
+    fn default() -> Self {
+        Self {
+            question: 0,
+            answer: 1,
+            tags: None,
+            hint: None,
+            source: None,
+            difficulty: None,
+        }
+    }
+}
+
+/// Read column positions from a header row. Unless `forced`, the row only counts as a
+/// header when it names both the question and the answer column.
+fn header_columns(fields: &[String], forced: bool) -> Option<DeckColumns> {
+    let find = |names: &[&str]| {
+        fields
+            .iter()
+            .position(|f| names.contains(&f.trim().to_lowercase().as_str()))
+    };
+    let question = find(&["question", "front"]);
+    let answer = find(&["answer", "back"]);
+    if !forced && (question.is_none() || answer.is_none()) {
+        return None;
+    }
+
+    Some(DeckColumns {
+        question: question.unwrap_or(0),
+        answer: answer.unwrap_or(1),
+        tags: find(&["tags", "tag"]),
+        hint: find(&["hint"]),
+        source: find(&["source", "reference"]),
+        difficulty: find(&["difficulty", "level"]),
+    })
+}
+
+pub(crate) fn flashcard(question: String, answer: String, meta: CardMeta) -> Flashcard {
+    Flashcard {
+        question,
+        answer,
+        user_answer: None,
+        ai_feedback: None,
+        written_to_file: false,
+        id: None,
+        meta,
+    }
+}
+
+fn skipped(line: usize, message: String) -> Issue {
+    Issue {
+        severity: Severity::Error,
+        location: format!("line {}", line),
+        message,
+    }
+}
+
+/// Read flashcards from CSV text. Blank records are skipped. A malformed record is
+/// reported in `issues` with its line number and left out, so the rest of the deck still
+/// loads; only a problem the reader cannot get past (an unterminated quoted field, a
+/// read error) fails the whole file.
+pub fn read_flashcards<R: BufRead>(
+    mut reader: R,
+    options: &CsvOptions,
+    issues: &mut Vec<Issue>,
+) -> Result<Vec<Flashcard>, CsvError> {
+    let delimiter = match options.delimiter {
+        Some(d) => d,
+        None => {
+            let sample = reader.fill_buf().map_err(|e| CsvError {
+                line: 1,
+                message: e.to_string(),
+            })?;
+            detect_delimiter(&String::from_utf8_lossy(sample))
+        }
+    };
+
+    let mut columns = DeckColumns::default();
+    let mut flashcards = Vec::new();
+    let mut first = true;
+
+    let mut records = CsvReader::new(reader, delimiter);
+    while let Some(record) = records.next() {
+        let record = match record {
+            Ok(record) => record,
+            // The reader carries on with the next line unless it hit the end of the input
+            Err(e) if !records.finished => {
+                issues.push(skipped(e.line, e.message));
+                continue;
             }
-            ',' if !in_quotes && field_index == 0 => {
-                field_index = 1;
-                current_field = &mut answer;
+            Err(e) => return Err(e),
+        };
+        if record.is_blank() {
+            continue;
+        }
+
+        if first {
+            first = false;
+            let header = match options.has_header {
+                Some(true) => header_columns(&record.fields, true),
+                Some(false) => None,
+                None => header_columns(&record.fields, false),
+            };
+            if let Some(header) = header {
+                columns = header;
+                continue;
             }
+        }
+
+        let get = |index: usize| record.fields.get(index).map(|f| f.trim().to_string());
+        let (question, answer) = match (get(columns.question), get(columns.answer)) {
+            (Some(q), Some(a)) => (q, a),
             _ => {
-                current_field.push(c);
+                issues.push(skipped(
+                    record.line,
+                    format!(
+                        "expected at least {} fields separated by '{}', found {}",
+                        columns.question.max(columns.answer) + 1,
+                        delimiter.escape_default(),
+                        record.fields.len()
+                    ),
+                ));
+                continue;
             }
+        };
+
+        if question.is_empty() || answer.is_empty() {
+            let empty = if question.is_empty() {
+                "question"
+            } else {
+                "answer"
+            };
+            issues.push(skipped(record.line, format!("empty {}", empty)));
+            continue;
+        }
+
+        let optional = |index: Option<usize>| {
+            index
+                .and_then(|i| record.fields.get(i))
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+        };
+        let meta = CardMeta {
+            tags: optional(columns.tags)
+                .map(|t| CardMeta::parse_tags(&t))
+                .unwrap_or_default(),
+            hint: optional(columns.hint),
+            source: optional(columns.source),
+            difficulty: optional(columns.difficulty),
+            ..Default::default()
+        };
+
+        flashcards.push(flashcard(question, answer, meta));
+    }
+
+    Ok(flashcards)
+}
+
+pub fn load_csv(path: &Path) -> io::Result<Vec<Flashcard>> {
+    load_csv_with_options(path, &CsvOptions::for_path(path))
+}
+
+/// Load a deck file. Like a JSON or TOML deck, a file with any malformed record is
+/// refused with `InvalidData`, naming the first one and how many others there are.
+pub fn load_csv_with_options(path: &Path, options: &CsvOptions) -> io::Result<Vec<Flashcard>> {
+    let mut issues = Vec::new();
+    let cards = read_csv_deck(path, options, &mut issues)?;
+    if let Some(issue) = issues.first() {
+        for issue in &issues {
+            logger::log(&format!("{}: {}", path.display(), issue));
+        }
+        let mut message = format!("{}: {}", issue.location, issue.message);
+        if issues.len() > 1 {
+            message.push_str(&format!(" (and {} more malformed records)", issues.len() - 1));
         }
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
     }
+    Ok(cards)
+}
+
+/// The records of a deck file that would be left out when it is loaded.
+pub fn validate_csv_deck(path: &Path) -> io::Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+    read_csv_deck(path, &CsvOptions::for_path(path), &mut issues)?;
+    Ok(issues)
+}
+
+fn read_csv_deck(
+    path: &Path,
+    options: &CsvOptions,
+    issues: &mut Vec<Issue>,
+) -> io::Result<Vec<Flashcard>> {
+    let file = fs::File::open(path)?;
+    read_flashcards(BufReader::new(file), options, issues)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
 
+/// Parse a single comma separated line into its first two fields.
+pub fn parse_csv_line(line: &str) -> Option<(String, String)> {
+    let record = CsvReader::new(line.as_bytes(), ',').next()?.ok()?;
+    let mut fields = record.fields.into_iter();
+    let question = fields.next().unwrap_or_default();
+    let answer = fields.next().unwrap_or_default();
     Some((question, answer))
 }
 
@@ -222,17 +568,19 @@ mod tests {
         let mut flashcards = Vec::new();
 
         for line in content.lines() {
-            if let Some((question, answer)) = parse_csv_line(line) {
-                if !question.trim().is_empty() && !answer.trim().is_empty() {
-                    flashcards.push(Flashcard {
-                        question,
-                        answer,
-                        user_answer: None,
-                        ai_feedback: None,
-                        written_to_file: false,
-                        id: None,
-                    });
-                }
+            if let Some((question, answer)) = parse_csv_line(line)
+                && !question.trim().is_empty()
+                && !answer.trim().is_empty()
+            {
+                flashcards.push(Flashcard {
+                    question,
+                    answer,
+                    user_answer: None,
+                    ai_feedback: None,
+                    written_to_file: false,
+                    id: None,
+                    meta: Default::default(),
+                });
             }
         }
 
@@ -248,17 +596,19 @@ mod tests {
         let mut flashcards = Vec::new();
 
         for line in content.lines() {
-            if let Some((question, answer)) = parse_csv_line(line) {
-                if !question.trim().is_empty() && !answer.trim().is_empty() {
-                    flashcards.push(Flashcard {
-                        question,
-                        answer,
-                        user_answer: None,
-                        ai_feedback: None,
-                        written_to_file: false,
-                        id: None,
-                    });
-                }
+            if let Some((question, answer)) = parse_csv_line(line)
+                && !question.trim().is_empty()
+                && !answer.trim().is_empty()
+            {
+                flashcards.push(Flashcard {
+                    question,
+                    answer,
+                    user_answer: None,
+                    ai_feedback: None,
+                    written_to_file: false,
+                    id: None,
+                    meta: Default::default(),
+                });
             }
         }
 
@@ -266,4 +616,141 @@ mod tests {
         assert_eq!(flashcards[0].question, "Q1");
         assert_eq!(flashcards[0].answer, "A1");
     }
+
+    fn read(content: &str) -> Result<Vec<Flashcard>, CsvError> {
+        let mut issues = Vec::new();
+        let cards = read_flashcards(content.as_bytes(), &CsvOptions::default(), &mut issues)?;
+        assert_eq!(issues, Vec::new());
+        Ok(cards)
+    }
+
+    fn read_with_issues(content: &str) -> (Vec<Flashcard>, Vec<String>) {
+        let mut issues = Vec::new();
+        let cards =
+            read_flashcards(content.as_bytes(), &CsvOptions::default(), &mut issues).unwrap();
+        (cards, issues.iter().map(|i| i.to_string()).collect())
+    }
+
+    #[test]
+    fn test_read_multiline_quoted_field() {
+        let content = "Q1,\"Line one\nLine two, with comma\"\nQ2,A2\n";
+        let cards = read(content).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].answer, "Line one\nLine two, with comma");
+        assert_eq!(cards[1].question, "Q2");
+    }
+
+    #[test]
+    fn test_read_crlf_and_bom() {
+        let content = "\u{feff}Q1,A1\r\n\"Q2\",\"Multi\r\nline\"\r\n";
+        let cards = read(content).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].question, "Q1");
+        assert_eq!(cards[0].answer, "A1");
+        assert_eq!(cards[1].answer, "Multi\nline");
+    }
+
+    #[test]
+    fn test_read_header_and_extra_columns() {
+        let content = "Answer,Question,Notes\nFour,What is 2+2?,easy\n";
+        let cards = read(content).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question, "What is 2+2?");
+        assert_eq!(cards[0].answer, "Four");
+
+        // Extra columns are not glued into the answer
+        let cards = read("Q1,A1,extra\n").unwrap();
+        assert_eq!(cards[0].answer, "A1");
+
+        let options = CsvOptions {
+            delimiter: None,
+            has_header: Some(true),
+        };
+        let cards = read_flashcards(
+            "front side,back side\nQ1,A1\n".as_bytes(),
+            &options,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question, "Q1");
+    }
+
+    #[test]
+    fn test_read_optional_columns() {
+        let content = "question,answer,tags,hint,source,difficulty\n\
+                       What is 2+2?,Four,\"math, #basics\",Count on your fingers,Book p. 3,easy\n\
+                       What is 3+3?,Six,,,,\n";
+        let cards = read(content).unwrap();
+        assert_eq!(cards.len(), 2);
+
+        let meta = &cards[0].meta;
+        assert_eq!(meta.tags, vec!["math", "basics"]);
+        assert!(meta.has_tag("Math"));
+        assert_eq!(meta.hint.as_deref(), Some("Count on your fingers"));
+        assert_eq!(meta.source.as_deref(), Some("Book p. 3"));
+        assert_eq!(meta.difficulty.as_deref(), Some("easy"));
+
+        assert_eq!(cards[1].meta, CardMeta::default());
+
+        // Without a header, extra columns carry no meaning
+        let cards = read("Q1,A1,math,hint\n").unwrap();
+        assert_eq!(cards[0].meta, CardMeta::default());
+    }
+
+    #[test]
+    fn test_read_custom_delimiters() {
+        let cards = read("Question;Answer\nWhat is 2,5 + 1?;3,5\n").unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question, "What is 2,5 + 1?");
+        assert_eq!(cards[0].answer, "3,5");
+
+        let cards = read("Question\tAnswer\nQ1\tA1, with comma\n").unwrap();
+        assert_eq!(cards[0].answer, "A1, with comma");
+
+        let options = CsvOptions::for_path(Path::new("deck.tsv"));
+        assert_eq!(options.delimiter, Some('\t'));
+    }
+
+    #[test]
+    fn test_read_skips_malformed_records() {
+        let (cards, issues) = read_with_issues(
+            "Q1,A1\nQ2,\"A2\nstill A2\"\nOnly one field\n\"Q3\"x,A3\nQ4,\nQ5,A5\n",
+        );
+        let questions: Vec<&str> = cards.iter().map(|c| c.question.as_str()).collect();
+        assert_eq!(questions, vec!["Q1", "Q2", "Q5"]);
+        assert_eq!(
+            issues,
+            vec![
+                "error: line 4: expected at least 2 fields separated by ',', found 1",
+                "error: line 5: unexpected character 'x' after closing quote",
+                "error: line 6: empty answer",
+            ]
+        );
+
+        // Nothing after an unterminated quote can be read
+        let err = read("Q1,A1\n\"Q2\",\"never closed\n\nQ3,A3\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("unterminated"));
+    }
+
+    #[test]
+    fn test_load_csv_maps_errors_to_invalid_data() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("deck.csv");
+        fs::write(&path, "Q1,A1\nQ2,A2\n").unwrap();
+        assert_eq!(load_csv(&path).unwrap().len(), 2);
+
+        // A single malformed record refuses the whole deck rather than dropping it
+        fs::write(&path, "Q1,A1\nbroken\n").unwrap();
+        assert_eq!(validate_csv_deck(&path).unwrap().len(), 1);
+        let err = load_csv(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+
+        fs::write(&path, "Q1,\nbroken\nQ3,A3\n").unwrap();
+        let err = load_csv(&path).unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"), "{}", err);
+        assert!(err.to_string().ends_with("(and 1 more malformed records)"), "{}", err);
+    }
 }
@@ -0,0 +1,199 @@
+use rusqlite::{Connection, Result, Row};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One AI call as stored in `ai_usage`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageRecord {
+    pub session_id: Option<u64>,
+    pub deck_name: String,
+    /// `evaluation`, `assessment` or `chat`
+    pub kind: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency_ms: u64,
+    /// USD, reported by the backend or estimated from `[ai.prices]`
+    pub cost: f64,
+}
+
+/// Totals over a set of calls
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency_ms: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.calls += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        self.latency_ms += record.latency_ms;
+        self.cost += record.cost;
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Average time a call took, in milliseconds
+    pub fn average_latency_ms(&self) -> u64 {
+        self.latency_ms.checked_div(self.calls).unwrap_or(0)
+    }
+}
+
+/// `3 calls, 4.2k tokens, $0.0031`
+impl fmt::Display for UsageTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calls = if self.calls == 1 { "call" } else { "calls" };
+        write!(f, "{} {}, ", self.calls, calls)?;
+        match self.tokens() {
+            tokens if tokens < 1000 => write!(f, "{} tokens", tokens)?,
+            tokens => write!(f, "{:.1}k tokens", tokens as f64 / 1000.0)?,
+        }
+        if self.cost < 1.0 {
+            write!(f, ", ${:.4}", self.cost)
+        } else {
+            write!(f, ", ${:.2}", self.cost)
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+const TOTALS: &str =
+    "COUNT(*), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0),
+     COALESCE(SUM(latency_ms), 0), COALESCE(SUM(cost), 0)";
+
+fn totals_from_row(row: &Row, first: usize) -> Result<UsageTotals> {
+    Ok(UsageTotals {
+        calls: row.get(first)?,
+        prompt_tokens: row.get(first + 1)?,
+        completion_tokens: row.get(first + 2)?,
+        latency_ms: row.get(first + 3)?,
+        cost: row.get(first + 4)?,
+    })
+}
+
+pub fn record(conn: &Connection, record: &UsageRecord) -> Result<()> {
+    conn.execute(
+        "INSERT INTO ai_usage (session_id, deck_name, kind, model, prompt_tokens,
+             completion_tokens, latency_ms, cost, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            record.session_id,
+            record.deck_name,
+            record.kind,
+            record.model,
+            record.prompt_tokens,
+            record.completion_tokens,
+            record.latency_ms,
+            record.cost,
+            now(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// What the calls made at or after `since` (a Unix timestamp) cost in USD.
+pub fn cost_since(conn: &Connection, since: u64) -> Result<f64> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(cost), 0) FROM ai_usage WHERE created_at >= ?",
+        [since],
+        |row| row.get(0),
+    )
+}
+
+pub fn session_totals(conn: &Connection, session_id: u64) -> Result<UsageTotals> {
+    conn.query_row(
+        &format!("SELECT {} FROM ai_usage WHERE session_id = ?", TOTALS),
+        [session_id],
+        |row| totals_from_row(row, 0),
+    )
+}
+
+/// Totals per deck, the most expensive first.
+pub fn deck_totals(conn: &Connection) -> Result<Vec<(String, UsageTotals)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT deck_name, {} FROM ai_usage GROUP BY deck_name
+         ORDER BY SUM(cost) DESC, COUNT(*) DESC, deck_name",
+        TOTALS
+    ))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, totals_from_row(row, 1)?)))?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    fn setup_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        conn
+    }
+
+    fn call(session_id: Option<u64>, deck_name: &str, kind: &str, cost: f64) -> UsageRecord {
+        UsageRecord {
+            session_id,
+            deck_name: deck_name.to_string(),
+            kind: kind.to_string(),
+            model: "m".to_string(),
+            prompt_tokens: 100,
+            completion_tokens: 20,
+            latency_ms: 400,
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_totals_per_session_and_deck() {
+        let conn = setup_db();
+        record(&conn, &call(Some(1), "networking", "evaluation", 0.01)).unwrap();
+        record(&conn, &call(Some(1), "networking", "chat", 0.02)).unwrap();
+        record(&conn, &call(Some(2), "biology", "evaluation", 0.5)).unwrap();
+        record(&conn, &call(None, "networking", "assessment", 0.0)).unwrap();
+        record(&conn, &call(None, "biology", "generation", 0.0)).unwrap();
+
+        let session = session_totals(&conn, 1).unwrap();
+        assert_eq!(
+            (
+                session.calls,
+                session.tokens(),
+                session.average_latency_ms()
+            ),
+            (2, 240, 400)
+        );
+        assert!((session.cost - 0.03).abs() < 1e-9);
+        assert_eq!(session_totals(&conn, 3).unwrap(), UsageTotals::default());
+
+        let decks = deck_totals(&conn).unwrap();
+        let names: Vec<&str> = decks.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["biology", "networking"]);
+        assert_eq!(decks[1].1.calls, 3);
+
+        assert!((cost_since(&conn, 0).unwrap() - 0.53).abs() < 1e-9);
+        assert_eq!(cost_since(&conn, now() + 60).unwrap(), 0.0);
+
+        let mut totals = UsageTotals::default();
+        totals.add(&call(None, "d", "chat", 0.5));
+        assert_eq!(totals.to_string(), "1 call, 120 tokens, $0.5000");
+        assert_eq!(
+            deck_totals(&conn).unwrap()[1].1.to_string(),
+            "3 calls, 360 tokens, $0.0300"
+        );
+        totals.prompt_tokens = 4_180;
+        totals.cost = 12.345;
+        assert_eq!(totals.to_string(), "1 call, 4.2k tokens, $12.35");
+    }
+}
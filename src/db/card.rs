@@ -0,0 +1,948 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::deck::{self, DeckSettings};
+use crate::models::{CardMeta, Flashcard};
+use crate::scheduler::fsrs::{self, ReviewHistory};
+use crate::scheduler::{
+    quality_from_score, FsrsState, Grade, SchedulerKind, Sm2State, SECONDS_PER_DAY,
+};
+use crate::utils::text::{content_hash, normalize_text, similarity};
+
+/// Persistent per-card review state, shared by every session of a deck
+#[derive(Debug, Clone)]
+pub struct CardData {
+    pub id: u64,
+    pub deck_name: String,
+    pub question: String,
+    pub answer: String,
+    pub sm2: Sm2State,
+    /// FSRS memory state, `None` until the card's first review
+    pub fsrs: Option<FsrsState>,
+    pub due_at: Option<u64>,
+    pub last_reviewed_at: Option<u64>,
+}
+
+impl CardData {
+    /// FSRS probability of recalling the card at unix time `at`.
+    pub fn retrievability(&self, at: u64) -> Option<f32> {
+        let state = self.fsrs?;
+        let last = self.last_reviewed_at?;
+        let elapsed_days = at.saturating_sub(last) as f32 / SECONDS_PER_DAY as f32;
+        Some(fsrs::retrievability(elapsed_days, state.stability))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn fsrs_state(stability: Option<f32>, difficulty: Option<f32>) -> Option<FsrsState> {
+    match (stability, difficulty) {
+        (Some(stability), Some(difficulty)) => Some(FsrsState {
+            stability,
+            difficulty,
+        }),
+        _ => None,
+    }
+}
+
+fn card_from_row(row: &rusqlite::Row) -> Result<CardData> {
+    Ok(CardData {
+        id: row.get(0)?,
+        deck_name: row.get(1)?,
+        question: row.get(2)?,
+        answer: row.get(3)?,
+        sm2: Sm2State {
+            ease_factor: row.get(4)?,
+            interval_days: row.get(5)?,
+            repetitions: row.get(6)?,
+        },
+        fsrs: fsrs_state(row.get(9)?, row.get(10)?),
+        due_at: row.get(7)?,
+        last_reviewed_at: row.get(8)?,
+    })
+}
+
+const CARD_COLUMNS: &str =
+    "id, deck_name, question, answer, ease_factor, interval_days, repetitions, due_at, \
+     last_reviewed_at, stability, difficulty";
+
+/// Next due date of a card reviewed at `reviewed_at`, according to the deck's scheduler.
+/// Cards reviewed before FSRS was available fall back to their SM-2 interval.
+fn due_at_for(
+    settings: &DeckSettings,
+    sm2: &Sm2State,
+    fsrs: Option<FsrsState>,
+    reviewed_at: u64,
+) -> u64 {
+    let interval_days = match (settings.scheduler, fsrs) {
+        (SchedulerKind::Fsrs, Some(state)) => settings.fsrs_params.next_interval(state.stability),
+        _ => sm2.interval_days,
+    };
+    reviewed_at + interval_days as u64 * SECONDS_PER_DAY
+}
+
+pub fn get_card(conn: &Connection, card_id: u64) -> Result<Option<CardData>> {
+    conn.query_row(
+        &format!("SELECT {} FROM cards WHERE id = ?", CARD_COLUMNS),
+        [card_id],
+        card_from_row,
+    )
+    .optional()
+}
+
+pub fn find_card(conn: &Connection, deck_name: &str, question: &str) -> Result<Option<CardData>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM cards WHERE deck_name = ? AND question = ? ORDER BY id LIMIT 1",
+            CARD_COLUMNS
+        ),
+        rusqlite::params![deck_name, question],
+        card_from_row,
+    )
+    .optional()
+}
+
+/// Minimum question (and answer) similarity for an edited deck entry to keep the
+/// identity of an existing card
+pub const FUZZY_MATCH_THRESHOLD: f32 = 0.85;
+
+struct CardIdentity {
+    id: u64,
+    question: String,
+    answer: String,
+    content_hash: Option<String>,
+}
+
+fn load_identities(conn: &Connection, deck_name: &str) -> Result<Vec<CardIdentity>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, question, answer, content_hash FROM cards WHERE deck_name = ? ORDER BY id",
+    )?;
+    let cards = stmt
+        .query_map([deck_name], |row| {
+            Ok(CardIdentity {
+                id: row.get(0)?,
+                question: row.get(1)?,
+                answer: row.get(2)?,
+                content_hash: row.get(3)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(cards)
+}
+
+/// Map deck entries to persistent card ids, creating cards for new entries.
+///
+/// Entries are matched in order of confidence: identical content hash, then the
+/// same question with an edited answer, then a lightly edited question (see
+/// [`FUZZY_MATCH_THRESHOLD`]). Exact matches are resolved for the whole batch
+/// first so an edited entry can never claim the card of another entry. Matched
+/// cards are updated to the entry's current text so history follows the edit.
+pub fn resolve_cards(
+    conn: &Connection,
+    deck_name: &str,
+    entries: &[(String, String)],
+) -> Result<Vec<u64>> {
+    let cards = load_identities(conn, deck_name)?;
+    let mut claimed: HashSet<u64> = HashSet::new();
+    let mut resolved: Vec<Option<u64>> = vec![None; entries.len()];
+
+    let hashes: Vec<String> = entries.iter().map(|(q, a)| content_hash(q, a)).collect();
+
+    // Pass 1: identical content
+    for (i, hash) in hashes.iter().enumerate() {
+        if let Some(card) = cards
+            .iter()
+            .find(|c| !claimed.contains(&c.id) && c.content_hash.as_deref() == Some(hash))
+        {
+            claimed.insert(card.id);
+            resolved[i] = Some(card.id);
+        }
+    }
+
+    // Pass 2: same question, answer edited (or card created before hashes existed)
+    for (i, (question, _)) in entries.iter().enumerate() {
+        if resolved[i].is_some() {
+            continue;
+        }
+        let normalized = normalize_text(question);
+        if let Some(card) = cards
+            .iter()
+            .find(|c| !claimed.contains(&c.id) && normalize_text(&c.question) == normalized)
+        {
+            claimed.insert(card.id);
+            resolved[i] = Some(card.id);
+        }
+    }
+
+    // Pass 3: lightly edited question, keeping the most similar unclaimed card
+    for (i, (question, answer)) in entries.iter().enumerate() {
+        if resolved[i].is_some() {
+            continue;
+        }
+        let best = cards
+            .iter()
+            .filter(|c| !claimed.contains(&c.id))
+            .map(|c| (c, similarity(&c.question, question)))
+            .filter(|(c, score)| {
+                *score >= FUZZY_MATCH_THRESHOLD
+                    && similarity(&c.answer, answer) >= FUZZY_MATCH_THRESHOLD
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((card, _)) = best {
+            claimed.insert(card.id);
+            resolved[i] = Some(card.id);
+        }
+    }
+
+    let ts = now();
+    let mut ids = Vec::with_capacity(entries.len());
+    for (i, (question, answer)) in entries.iter().enumerate() {
+        let hash = &hashes[i];
+        let id = match resolved[i].and_then(|id| cards.iter().find(|c| c.id == id)) {
+            Some(card) => {
+                update_card_text(conn, card, question, answer, hash, ts)?;
+                card.id
+            }
+            None => insert_card(conn, deck_name, question, answer, hash, ts)?,
+        };
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
+
+/// Bring a matched card up to the entry's current text, unless it already has it.
+fn update_card_text(
+    conn: &Connection,
+    card: &CardIdentity,
+    question: &str,
+    answer: &str,
+    hash: &str,
+    ts: u64,
+) -> Result<()> {
+    let unchanged = card.question == question
+        && card.answer == answer
+        && card.content_hash.as_deref() == Some(hash);
+    if !unchanged {
+        conn.execute(
+            "UPDATE cards SET question = ?, answer = ?, content_hash = ?, updated_at = ? WHERE id = ?",
+            rusqlite::params![question, answer, hash, ts, card.id],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_card(
+    conn: &Connection,
+    deck_name: &str,
+    question: &str,
+    answer: &str,
+    hash: &str,
+    ts: u64,
+) -> Result<u64> {
+    let initial = Sm2State::default();
+    conn.execute(
+        "INSERT INTO cards (deck_name, question, answer, content_hash, ease_factor, interval_days,
+         repetitions, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            deck_name,
+            question,
+            answer,
+            hash,
+            initial.ease_factor,
+            initial.interval_days,
+            initial.repetitions,
+            ts,
+            ts
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+/// The card with identical content, else the one with the same question.
+fn find_exact_match<'a>(
+    cards: &'a [CardIdentity],
+    question: &str,
+    hash: &str,
+) -> Option<&'a CardIdentity> {
+    let normalized = normalize_text(question);
+    cards
+        .iter()
+        .find(|c| c.content_hash.as_deref() == Some(hash))
+        .or_else(|| {
+            cards
+                .iter()
+                .find(|c| normalize_text(&c.question) == normalized)
+        })
+}
+
+/// Map deck entries to the ids of their existing cards without creating or updating
+/// any: the card with identical content, else the one with the same question.
+pub fn lookup_cards(
+    conn: &Connection,
+    deck_name: &str,
+    entries: &[(String, String)],
+) -> Result<Vec<Option<u64>>> {
+    let cards = load_identities(conn, deck_name)?;
+    let ids = entries
+        .iter()
+        .map(|(question, answer)| {
+            find_exact_match(&cards, question, &content_hash(question, answer)).map(|c| c.id)
+        })
+        .collect();
+    Ok(ids)
+}
+
+/// Return the id of the card for this deck entry, creating it if needed. Only the card
+/// with identical content or the same question is reused: without the rest of the deck
+/// a lightly edited entry cannot be told apart from a different card.
+pub fn upsert_card(
+    conn: &Connection,
+    deck_name: &str,
+    question: &str,
+    answer: &str,
+) -> Result<u64> {
+    let cards = load_identities(conn, deck_name)?;
+    let hash = content_hash(question, answer);
+    let ts = now();
+    match find_exact_match(&cards, question, &hash) {
+        Some(card) => {
+            update_card_text(conn, card, question, answer, &hash, ts)?;
+            Ok(card.id)
+        }
+        None => insert_card(conn, deck_name, question, answer, &hash, ts),
+    }
+}
+
+/// Record a graded answer for a card and advance both its SM-2 and FSRS state.
+/// The due date follows whichever scheduler the card's deck has selected.
+///
+/// Re-evaluating the same session flashcard replaces its earlier review instead of
+/// counting twice: the card is rolled back to the state stored with that review
+/// before the new grade is applied.
+pub fn record_review(
+    conn: &Connection,
+    card_id: u64,
+    session_id: Option<u64>,
+    flashcard_id: Option<u64>,
+    score: f32,
+) -> Result<CardData> {
+    record_review_at(conn, card_id, session_id, flashcard_id, score, now())
+}
+
+/// [`record_review`] with an explicit review time, for replaying imported history.
+pub fn record_review_at(
+    conn: &Connection,
+    card_id: u64,
+    session_id: Option<u64>,
+    flashcard_id: Option<u64>,
+    score: f32,
+    reviewed_at: u64,
+) -> Result<CardData> {
+    let card = match get_card(conn, card_id)? {
+        Some(card) => card,
+        None => return Err(rusqlite::Error::QueryReturnedNoRows),
+    };
+
+    type PreviousReview = (u64, Sm2State, Option<FsrsState>, Option<f32>);
+    let previous_review: Option<PreviousReview> = match flashcard_id {
+        Some(fid) => conn
+            .query_row(
+                "SELECT id, prev_ease_factor, prev_interval_days, prev_repetitions,
+                        prev_stability, prev_difficulty, elapsed_days FROM card_reviews
+                 WHERE card_id = ? AND flashcard_id = ?
+                   AND id = (SELECT MAX(id) FROM card_reviews WHERE card_id = ?)",
+                rusqlite::params![card_id, fid, card_id],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        Sm2State {
+                            ease_factor: row.get(1)?,
+                            interval_days: row.get(2)?,
+                            repetitions: row.get(3)?,
+                        },
+                        fsrs_state(row.get(4)?, row.get(5)?),
+                        row.get(6)?,
+                    ))
+                },
+            )
+            .optional()?,
+        None => None,
+    };
+
+    let (before, fsrs_before, elapsed_days) = match previous_review {
+        Some((_, sm2, fsrs, elapsed)) => (sm2, fsrs, elapsed.unwrap_or(0.0)),
+        None => (
+            card.sm2,
+            card.fsrs,
+            card.last_reviewed_at
+                .map(|last| reviewed_at.saturating_sub(last) as f32 / SECONDS_PER_DAY as f32)
+                .unwrap_or(0.0),
+        ),
+    };
+
+    let settings = deck::get_deck_settings(conn, &card.deck_name)?;
+    let quality = quality_from_score(score);
+    let grade = Grade::from_score(score);
+    let after = before.review(quality);
+    let fsrs_after = settings
+        .fsrs_params
+        .next_state(fsrs_before, elapsed_days, grade);
+    let due_at = due_at_for(&settings, &after, Some(fsrs_after), reviewed_at);
+
+    if let Some((review_id, ..)) = previous_review {
+        conn.execute("DELETE FROM card_reviews WHERE id = ?", [review_id])?;
+    }
+
+    conn.execute(
+        "INSERT INTO card_reviews
+         (card_id, session_id, flashcard_id, score, quality, prev_ease_factor, prev_interval_days, prev_repetitions,
+          ease_factor, interval_days, repetitions, grade, elapsed_days, prev_stability, prev_difficulty,
+          stability, difficulty, reviewed_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            card_id,
+            session_id,
+            flashcard_id,
+            score,
+            quality,
+            before.ease_factor,
+            before.interval_days,
+            before.repetitions,
+            after.ease_factor,
+            after.interval_days,
+            after.repetitions,
+            grade as u8,
+            elapsed_days,
+            fsrs_before.map(|s| s.stability),
+            fsrs_before.map(|s| s.difficulty),
+            fsrs_after.stability,
+            fsrs_after.difficulty,
+            reviewed_at
+        ],
+    )?;
+
+    conn.execute(
+        "UPDATE cards SET ease_factor = ?, interval_days = ?, repetitions = ?, stability = ?, difficulty = ?,
+         due_at = ?, last_reviewed_at = ?, updated_at = ? WHERE id = ?",
+        rusqlite::params![
+            after.ease_factor,
+            after.interval_days,
+            after.repetitions,
+            fsrs_after.stability,
+            fsrs_after.difficulty,
+            due_at,
+            reviewed_at,
+            reviewed_at,
+            card_id
+        ],
+    )?;
+
+    get_card(conn, card_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+}
+
+/// The `cards` columns holding a [`CardMeta`], in the order [`meta_from_row`] reads them
+pub const META_COLUMNS: [&str; 7] = [
+    "tags",
+    "hint",
+    "source",
+    "difficulty_label",
+    "accepted_answers",
+    "grading_notes",
+    "media",
+];
+
+fn json_list(value: Option<String>) -> Vec<String> {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+fn json_list_column(list: &[String]) -> Result<Option<String>> {
+    if list.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(list)
+        .map(Some)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Build a [`CardMeta`] from the [`META_COLUMNS`] of a row, starting at column `start`.
+pub fn meta_from_row(row: &rusqlite::Row, start: usize) -> Result<CardMeta> {
+    Ok(CardMeta {
+        tags: json_list(row.get(start)?),
+        hint: row.get(start + 1)?,
+        source: row.get(start + 2)?,
+        difficulty: row.get(start + 3)?,
+        accepted_answers: json_list(row.get(start + 4)?),
+        grading_notes: row.get(start + 5)?,
+        media: json_list(row.get(start + 6)?),
+    })
+}
+
+pub fn get_card_meta(conn: &Connection, card_id: u64) -> Result<Option<CardMeta>> {
+    conn.query_row(
+        &format!("SELECT {} FROM cards WHERE id = ?", META_COLUMNS.join(", ")),
+        [card_id],
+        |row| meta_from_row(row, 0),
+    )
+    .optional()
+}
+
+/// Replace the optional deck columns of a card with the deck's current values.
+pub fn set_card_meta(conn: &Connection, card_id: u64, meta: &CardMeta) -> Result<()> {
+    conn.execute(
+        "UPDATE cards SET tags = ?, hint = ?, source = ?, difficulty_label = ?, accepted_answers = ?,
+         grading_notes = ?, media = ?, updated_at = ? WHERE id = ?",
+        rusqlite::params![
+            json_list_column(&meta.tags)?,
+            meta.hint,
+            meta.source,
+            meta.difficulty,
+            json_list_column(&meta.accepted_answers)?,
+            meta.grading_notes,
+            json_list_column(&meta.media)?,
+            now(),
+            card_id
+        ],
+    )?;
+    Ok(())
+}
+
+/// Recompute the due date of every reviewed card of a deck, e.g. after switching schedulers.
+pub fn reschedule_deck(conn: &Connection, settings: &DeckSettings) -> Result<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cards WHERE deck_name = ? AND last_reviewed_at IS NOT NULL",
+        CARD_COLUMNS
+    ))?;
+    let cards: Vec<CardData> = stmt
+        .query_map([&settings.deck_name], card_from_row)?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    for card in cards {
+        if let Some(reviewed_at) = card.last_reviewed_at {
+            let due_at = due_at_for(settings, &card.sm2, card.fsrs, reviewed_at);
+            conn.execute(
+                "UPDATE cards SET due_at = ? WHERE id = ?",
+                rusqlite::params![due_at, card.id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Per-card review histories of a deck, oldest first, for fitting FSRS parameters.
+/// Reviews recorded before FSRS was tracked derive their grade and elapsed time
+/// from the stored score and review timestamps.
+pub fn get_review_histories(conn: &Connection, deck_name: &str) -> Result<Vec<ReviewHistory>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.card_id, r.score, r.grade, r.elapsed_days, r.reviewed_at
+         FROM card_reviews r JOIN cards c ON c.id = r.card_id
+         WHERE c.deck_name = ?
+         ORDER BY r.card_id, r.reviewed_at, r.id",
+    )?;
+
+    let rows = stmt
+        .query_map([deck_name], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, f32>(1)?,
+                row.get::<_, Option<u8>>(2)?,
+                row.get::<_, Option<f32>>(3)?,
+                row.get::<_, u64>(4)?,
+            ))
+        })?
+        .filter_map(|r| r.ok());
+
+    let mut histories: Vec<ReviewHistory> = Vec::new();
+    let mut current_card = None;
+    let mut last_reviewed_at = 0;
+
+    for (card_id, score, grade, elapsed_days, reviewed_at) in rows {
+        if current_card != Some(card_id) {
+            histories.push(Vec::new());
+            current_card = Some(card_id);
+            last_reviewed_at = reviewed_at;
+        }
+
+        let grade = grade
+            .map(Grade::from_u8)
+            .unwrap_or_else(|| Grade::from_score(score));
+        let elapsed_days = elapsed_days.unwrap_or_else(|| {
+            reviewed_at.saturating_sub(last_reviewed_at) as f32 / SECONDS_PER_DAY as f32
+        });
+        last_reviewed_at = reviewed_at;
+
+        if let Some(history) = histories.last_mut() {
+            history.push((elapsed_days, grade));
+        }
+    }
+
+    Ok(histories)
+}
+
+/// Questions of a deck that have been scheduled past `until`.
+/// Anything not in this set (including cards never reviewed) is due.
+pub fn get_not_due_questions(
+    conn: &Connection,
+    deck_name: &str,
+    until: u64,
+) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT question FROM cards WHERE deck_name = ? AND due_at IS NOT NULL AND due_at > ?",
+    )?;
+
+    let questions = stmt
+        .query_map(rusqlite::params![deck_name, until], |row| row.get(0))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(questions)
+}
+
+/// Keep only the cards of a deck whose next review falls on or before `until`.
+pub fn filter_due_cards(
+    conn: &Connection,
+    deck_name: &str,
+    cards: Vec<Flashcard>,
+    until: u64,
+) -> Result<Vec<Flashcard>> {
+    let not_due = get_not_due_questions(conn, deck_name, until)?;
+    Ok(cards
+        .into_iter()
+        .filter(|c| !not_due.contains(&c.question))
+        .collect())
+}
+
+pub fn get_review_count(conn: &Connection, card_id: u64) -> Result<usize> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM card_reviews WHERE card_id = ?",
+        [card_id],
+        |row| row.get(0),
+    )
+}
+
+/// Answer statistics for one card across every (non-deleted) session of its deck
+#[derive(Debug, Clone)]
+pub struct CardStats {
+    pub card_id: u64,
+    pub deck_name: String,
+    pub question: String,
+    pub answer: String,
+    pub times_answered: usize,
+    pub times_missed: usize,
+    pub average_score: f32,
+    pub last_answered_at: Option<u64>,
+}
+
+impl CardStats {
+    pub fn miss_rate(&self) -> f32 {
+        if self.times_answered == 0 {
+            0.0
+        } else {
+            self.times_missed as f32 / self.times_answered as f32
+        }
+    }
+}
+
+const CARD_STATS_QUERY: &str = "SELECT c.id, c.deck_name, c.question, c.answer,
+        COUNT(f.id) AS times_answered,
+        COALESCE(SUM(CASE WHEN json_extract(f.ai_feedback, '$.is_correct') THEN 0 ELSE 1 END), 0) AS times_missed,
+        COALESCE(AVG(json_extract(f.ai_feedback, '$.correctness_score')), 0.0) AS average_score,
+        MAX(f.answered_at)
+     FROM cards c
+     JOIN flashcards f ON f.card_id = c.id AND f.ai_feedback IS NOT NULL
+     JOIN sessions s ON s.id = f.session_id AND s.deleted_at IS NULL";
+
+fn card_stats_from_row(row: &rusqlite::Row) -> Result<CardStats> {
+    Ok(CardStats {
+        card_id: row.get(0)?,
+        deck_name: row.get(1)?,
+        question: row.get(2)?,
+        answer: row.get(3)?,
+        times_answered: row.get(4)?,
+        times_missed: row.get(5)?,
+        average_score: row.get::<_, f64>(6)? as f32,
+        last_answered_at: row.get(7)?,
+    })
+}
+
+pub fn get_card_stats(conn: &Connection, card_id: u64) -> Result<Option<CardStats>> {
+    conn.query_row(
+        &format!("{} WHERE c.id = ? GROUP BY c.id", CARD_STATS_QUERY),
+        [card_id],
+        card_stats_from_row,
+    )
+    .optional()
+}
+
+/// Cards answered incorrectly most often ("cards I always miss"), optionally limited to one deck.
+/// Cards that were never missed are left out.
+pub fn get_most_missed_cards(
+    conn: &Connection,
+    deck_name: Option<&str>,
+    limit: usize,
+) -> Result<Vec<CardStats>> {
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE (?1 IS NULL OR c.deck_name = ?1)
+         GROUP BY c.id
+         HAVING times_missed > 0
+         ORDER BY times_missed DESC, CAST(times_missed AS REAL) / times_answered DESC, average_score ASC
+         LIMIT ?2",
+        CARD_STATS_QUERY
+    ))?;
+
+    let cards = stmt
+        .query_map(rusqlite::params![deck_name, limit], card_stats_from_row)?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(cards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+    use crate::scheduler::FsrsParams;
+
+    fn setup_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_upsert_card_is_idempotent() {
+        let conn = setup_db();
+        let id1 = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+        let id2 = upsert_card(&conn, "Deck", "Q1", "A1 revised").unwrap();
+        assert_eq!(id1, id2);
+
+        let card = get_card(&conn, id1).unwrap().unwrap();
+        assert_eq!(card.answer, "A1 revised");
+        assert_eq!(card.sm2, Sm2State::default());
+        assert!(card.due_at.is_none());
+
+        let other_deck = upsert_card(&conn, "Other", "Q1", "A1").unwrap();
+        assert_ne!(id1, other_deck);
+    }
+
+    #[test]
+    fn test_upsert_card_never_claims_a_similar_card() {
+        let conn = setup_db();
+        let entries = [(
+            "What is the capital of France?".to_string(),
+            "Paris".to_string(),
+        )];
+        let france = resolve_cards(&conn, "Deck", &entries).unwrap()[0];
+
+        // Close enough for a deck-wide match, but a card of its own
+        let similar =
+            upsert_card(&conn, "Deck", "What was the capital of France?", "Paris").unwrap();
+        assert_ne!(similar, france);
+        let card = get_card(&conn, france).unwrap().unwrap();
+        assert_eq!(card.question, "What is the capital of France?");
+
+        let same = upsert_card(
+            &conn,
+            "Deck",
+            "what is the capital of  France?",
+            "Paris, France",
+        )
+        .unwrap();
+        assert_eq!(same, france);
+    }
+
+    #[test]
+    fn test_resolve_cards_matches_edited_entries() {
+        let conn = setup_db();
+        let original = resolve_cards(
+            &conn,
+            "Deck",
+            &[
+                (
+                    "What is the capital of France?".to_string(),
+                    "Paris".to_string(),
+                ),
+                ("What is 2+2?".to_string(), "4".to_string()),
+            ],
+        )
+        .unwrap();
+
+        // Answer edited, question lightly edited, plus a similar-looking new card
+        let edited = resolve_cards(
+            &conn,
+            "Deck",
+            &[
+                ("What is 2+3?".to_string(), "5".to_string()),
+                (
+                    "What's the capital of France?".to_string(),
+                    "Paris".to_string(),
+                ),
+                ("What is 2+2?".to_string(), "Four".to_string()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(edited[1], original[0]);
+        assert_eq!(edited[2], original[1]);
+        assert!(!original.contains(&edited[0]));
+
+        let card = get_card(&conn, original[0]).unwrap().unwrap();
+        assert_eq!(card.question, "What's the capital of France?");
+    }
+
+    #[test]
+    fn test_most_missed_cards() {
+        let conn = setup_db();
+        let feedback = |is_correct: bool| crate::ai::AIFeedback {
+            is_correct,
+            correctness_score: if is_correct { 1.0 } else { 0.0 },
+            corrections: vec![],
+            explanation: String::new(),
+            suggestions: vec![],
+        };
+
+        for correct in [false, false, true] {
+            let session_id = crate::db::session::create_session(&conn, "Deck", 2).unwrap();
+            let ids = crate::db::flashcard::initialize_flashcards(
+                &conn,
+                session_id,
+                &[
+                    ("Hard".to_string(), "A".to_string()),
+                    ("Easy".to_string(), "B".to_string()),
+                ],
+            )
+            .unwrap();
+            crate::db::flashcard::update_ai_feedback(&conn, ids[0], &feedback(correct)).unwrap();
+            crate::db::flashcard::update_ai_feedback(&conn, ids[1], &feedback(true)).unwrap();
+        }
+
+        let missed = get_most_missed_cards(&conn, Some("Deck"), 10).unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].question, "Hard");
+        assert_eq!(missed[0].times_answered, 3);
+        assert_eq!(missed[0].times_missed, 2);
+        assert!((missed[0].average_score - 1.0 / 3.0).abs() < 1e-5);
+
+        assert!(get_most_missed_cards(&conn, Some("Other"), 10)
+            .unwrap()
+            .is_empty());
+
+        let easy = get_card_stats(&conn, find_card(&conn, "Deck", "Easy").unwrap().unwrap().id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(easy.times_missed, 0);
+        assert_eq!(easy.miss_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_record_review_schedules_card() {
+        let conn = setup_db();
+        let card_id = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+
+        let card = record_review(&conn, card_id, None, None, 1.0).unwrap();
+        assert_eq!(card.sm2.repetitions, 1);
+        assert_eq!(card.sm2.interval_days, 1);
+        assert!(card.fsrs.is_some());
+
+        let card = get_card(&conn, card_id).unwrap().unwrap();
+        let reviewed_at = card.last_reviewed_at.unwrap();
+        assert_eq!(card.due_at, Some(reviewed_at + SECONDS_PER_DAY));
+        assert_eq!(get_review_count(&conn, card_id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_re_evaluation_replaces_review() {
+        let conn = setup_db();
+        let session_id = crate::db::session::create_session(&conn, "Deck", 1).unwrap();
+        let ids = crate::db::flashcard::initialize_flashcards(
+            &conn,
+            session_id,
+            &[("Q1".to_string(), "A1".to_string())],
+        )
+        .unwrap();
+        let card_id = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+
+        record_review(&conn, card_id, Some(session_id), Some(ids[0]), 0.2).unwrap();
+        let card = record_review(&conn, card_id, Some(session_id), Some(ids[0]), 1.0).unwrap();
+
+        assert_eq!(get_review_count(&conn, card_id).unwrap(), 1);
+        assert_eq!(card.sm2, Sm2State::default().review(5));
+        assert_eq!(
+            card.fsrs,
+            Some(FsrsParams::default().next_state(None, 0.0, Grade::Easy))
+        );
+    }
+
+    #[test]
+    fn test_not_due_questions() {
+        let conn = setup_db();
+        let reviewed = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+        upsert_card(&conn, "Deck", "Q2", "A2").unwrap();
+        record_review(&conn, reviewed, None, None, 1.0).unwrap();
+
+        let not_due = get_not_due_questions(&conn, "Deck", now()).unwrap();
+        assert!(not_due.contains("Q1"));
+        assert!(!not_due.contains("Q2"));
+
+        let tomorrow = now() + 2 * SECONDS_PER_DAY;
+        assert!(get_not_due_questions(&conn, "Deck", tomorrow)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_fsrs_deck_uses_stability_for_due_date() {
+        let conn = setup_db();
+        deck::set_scheduler(&conn, "Deck", SchedulerKind::Fsrs).unwrap();
+        let card_id = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+
+        let card = record_review(&conn, card_id, None, None, 0.8).unwrap();
+        let interval = FsrsParams::default().next_interval(card.fsrs.unwrap().stability);
+        let reviewed_at = card.last_reviewed_at.unwrap();
+        assert_eq!(
+            card.due_at,
+            Some(reviewed_at + interval as u64 * SECONDS_PER_DAY)
+        );
+        assert!(card.retrievability(reviewed_at).unwrap() > 0.99);
+
+        // Switching back to SM-2 reschedules from the SM-2 interval
+        deck::set_scheduler(&conn, "Deck", SchedulerKind::Sm2).unwrap();
+        let card = get_card(&conn, card_id).unwrap().unwrap();
+        assert_eq!(
+            card.due_at,
+            Some(reviewed_at + card.sm2.interval_days as u64 * SECONDS_PER_DAY)
+        );
+    }
+
+    #[test]
+    fn test_review_histories_group_by_card() {
+        let conn = setup_db();
+        let first = upsert_card(&conn, "Deck", "Q1", "A1").unwrap();
+        let second = upsert_card(&conn, "Deck", "Q2", "A2").unwrap();
+        upsert_card(&conn, "Other", "Q1", "A1").unwrap();
+
+        record_review(&conn, first, None, None, 1.0).unwrap();
+        record_review(&conn, first, None, None, 0.0).unwrap();
+        record_review(&conn, second, None, None, 0.8).unwrap();
+
+        let histories = get_review_histories(&conn, "Deck").unwrap();
+        assert_eq!(histories.len(), 2);
+        assert_eq!(histories[0].len(), 2);
+        assert_eq!(histories[0][0].1, Grade::Easy);
+        assert_eq!(histories[0][1].1, Grade::Again);
+        assert_eq!(histories[1], vec![(0.0, Grade::Good)]);
+    }
+}
@@ -0,0 +1,118 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::card;
+use crate::logger;
+use crate::scheduler::{FsrsParams, SchedulerKind};
+
+/// Per-deck scheduling preferences. Decks without a row use SM-2 with default FSRS weights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeckSettings {
+    pub deck_name: String,
+    pub scheduler: SchedulerKind,
+    pub fsrs_params: FsrsParams,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn get_deck_settings(conn: &Connection, deck_name: &str) -> Result<DeckSettings> {
+    let row: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT scheduler, fsrs_params FROM deck_settings WHERE deck_name = ?",
+            [deck_name],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let (scheduler, params_json) = row.unwrap_or_else(|| ("sm2".to_string(), None));
+    let fsrs_params = match params_json {
+        Some(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+            logger::log(&format!(
+                "Invalid FSRS parameters stored for deck '{}', using defaults: {}",
+                deck_name, e
+            ));
+            FsrsParams::default()
+        }),
+        None => FsrsParams::default(),
+    };
+
+    Ok(DeckSettings {
+        deck_name: deck_name.to_string(),
+        scheduler: SchedulerKind::parse(&scheduler).unwrap_or_default(),
+        fsrs_params,
+    })
+}
+
+/// Switch the scheduler of a deck and recompute the due dates of its reviewed cards.
+pub fn set_scheduler(conn: &Connection, deck_name: &str, scheduler: SchedulerKind) -> Result<()> {
+    conn.execute(
+        "INSERT INTO deck_settings (deck_name, scheduler, updated_at) VALUES (?, ?, ?)
+         ON CONFLICT(deck_name) DO UPDATE SET scheduler = excluded.scheduler, updated_at = excluded.updated_at",
+        rusqlite::params![deck_name, scheduler.as_str(), now()],
+    )?;
+
+    let settings = get_deck_settings(conn, deck_name)?;
+    card::reschedule_deck(conn, &settings)?;
+    Ok(())
+}
+
+/// Store refitted FSRS parameters for a deck and reschedule it if FSRS is active.
+pub fn save_fsrs_params(conn: &Connection, deck_name: &str, params: &FsrsParams) -> Result<()> {
+    let json = serde_json::to_string(params)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    conn.execute(
+        "INSERT INTO deck_settings (deck_name, fsrs_params, updated_at) VALUES (?, ?, ?)
+         ON CONFLICT(deck_name) DO UPDATE SET fsrs_params = excluded.fsrs_params, updated_at = excluded.updated_at",
+        rusqlite::params![deck_name, json, now()],
+    )?;
+
+    let settings = get_deck_settings(conn, deck_name)?;
+    if settings.scheduler == SchedulerKind::Fsrs {
+        card::reschedule_deck(conn, &settings)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    fn setup_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_defaults_without_settings_row() {
+        let conn = setup_db();
+        let settings = get_deck_settings(&conn, "Deck").unwrap();
+        assert_eq!(settings.scheduler, SchedulerKind::Sm2);
+        assert_eq!(settings.fsrs_params, FsrsParams::default());
+    }
+
+    #[test]
+    fn test_set_scheduler_and_params_are_independent() {
+        let conn = setup_db();
+        let mut params = FsrsParams::default();
+        params.weights[0] = 1.0;
+
+        save_fsrs_params(&conn, "Deck", &params).unwrap();
+        set_scheduler(&conn, "Deck", SchedulerKind::Fsrs).unwrap();
+
+        let settings = get_deck_settings(&conn, "Deck").unwrap();
+        assert_eq!(settings.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(settings.fsrs_params, params);
+        assert_eq!(
+            get_deck_settings(&conn, "Other").unwrap().scheduler,
+            SchedulerKind::Sm2
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Totals over the whole cache, for `interactive-flashcards cache`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: u64,
+    /// Entries older than the TTL, removed on their next lookup
+    pub expired: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups answered from the cache, 0 to 1
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn count(conn: &Connection, name: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO evaluation_cache_counters (name, count) VALUES (?, 1)
+         ON CONFLICT(name) DO UPDATE SET count = count + 1",
+        [name],
+    )?;
+    Ok(())
+}
+
+/// The stored reply for `key` if it is younger than `ttl_secs`, counting the lookup as a
+/// hit or a miss. An expired entry is removed.
+pub fn lookup(conn: &Connection, key: &str, ttl_secs: u64) -> Result<Option<String>> {
+    let entry: Option<(String, u64)> = conn
+        .query_row(
+            "SELECT response, created_at FROM evaluation_cache WHERE cache_key = ?",
+            [key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    match entry {
+        Some((response, created_at)) if now().saturating_sub(created_at) < ttl_secs => {
+            conn.execute(
+                "UPDATE evaluation_cache SET hits = hits + 1 WHERE cache_key = ?",
+                [key],
+            )?;
+            count(conn, "hits")?;
+            Ok(Some(response))
+        }
+        expired => {
+            if expired.is_some() {
+                remove(conn, key)?;
+            }
+            count(conn, "misses")?;
+            Ok(None)
+        }
+    }
+}
+
+/// Store the reply for `key`, replacing an older one.
+pub fn store(conn: &Connection, key: &str, model: &str, response: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO evaluation_cache (cache_key, model, response, hits, created_at)
+         VALUES (?, ?, ?, 0, ?)",
+        rusqlite::params![key, model, response, now()],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &Connection, key: &str) -> Result<()> {
+    conn.execute("DELETE FROM evaluation_cache WHERE cache_key = ?", [key])?;
+    Ok(())
+}
+
+pub fn stats(conn: &Connection, ttl_secs: u64) -> Result<CacheStats> {
+    let (entries, expired) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(created_at <= ?), 0) FROM evaluation_cache",
+        [now().saturating_sub(ttl_secs)],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let counter = |name: &str| -> Result<u64> {
+        conn.query_row(
+            "SELECT count FROM evaluation_cache_counters WHERE name = ?",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(Option::unwrap_or_default)
+    };
+    Ok(CacheStats {
+        entries,
+        expired,
+        hits: counter("hits")?,
+        misses: counter("misses")?,
+    })
+}
+
+/// Remove every entry and reset the counters; returns the number of entries removed.
+pub fn clear(conn: &Connection) -> Result<usize> {
+    let removed = conn.execute("DELETE FROM evaluation_cache", [])?;
+    conn.execute("DELETE FROM evaluation_cache_counters", [])?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    fn setup_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_lookup_counts_hits_and_misses() {
+        let conn = setup_db();
+        assert_eq!(lookup(&conn, "k", 60).unwrap(), None);
+
+        store(&conn, "k", "model", "{\"ok\": 1}").unwrap();
+        assert_eq!(
+            lookup(&conn, "k", 60).unwrap().as_deref(),
+            Some("{\"ok\": 1}")
+        );
+        assert_eq!(
+            lookup(&conn, "k", 60).unwrap().as_deref(),
+            Some("{\"ok\": 1}")
+        );
+
+        let stats = stats(&conn, 60).unwrap();
+        assert_eq!(
+            stats,
+            CacheStats {
+                entries: 1,
+                expired: 0,
+                hits: 2,
+                misses: 1,
+            }
+        );
+        assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+        let hits: u64 = conn
+            .query_row("SELECT hits FROM evaluation_cache", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(hits, 2);
+
+        assert_eq!(clear(&conn).unwrap(), 1);
+        assert_eq!(super::stats(&conn, 60).unwrap(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_expired_entries_are_misses() {
+        let conn = setup_db();
+        store(&conn, "k", "model", "old").unwrap();
+        conn.execute(
+            "UPDATE evaluation_cache SET created_at = created_at - 100",
+            [],
+        )
+        .unwrap();
+        assert_eq!(stats(&conn, 60).unwrap().expired, 1);
+
+        assert_eq!(lookup(&conn, "k", 60).unwrap(), None);
+        let stats = stats(&conn, 60).unwrap();
+        assert_eq!((stats.entries, stats.misses), (0, 1));
+    }
+}
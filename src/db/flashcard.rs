@@ -1,5 +1,7 @@
 use crate::ai::AIFeedback;
-use rusqlite::{Connection, Result};
+use crate::db::card;
+use crate::models::CardMeta;
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde_json;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -15,6 +17,10 @@ pub struct FlashcardData {
     pub ai_feedback: Option<AIFeedback>,
     pub answered_at: Option<u64>,
     pub display_order: usize,
+    /// Persistent deck card this session row refers to
+    pub card_id: Option<u64>,
+    /// Optional deck columns of the referenced card
+    pub meta: CardMeta,
 }
 
 fn now() -> u64 {
@@ -28,6 +34,59 @@ pub fn initialize_flashcards(
     conn: &Connection,
     session_id: u64,
     flashcards: &[(String, String)],
+) -> Result<Vec<u64>> {
+    let deck_name: Option<String> = conn
+        .query_row(
+            "SELECT deck_name FROM sessions WHERE id = ?",
+            [session_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let card_ids = match deck_name {
+        Some(deck_name) => card::resolve_cards(conn, &deck_name, flashcards)?
+            .into_iter()
+            .map(Some)
+            .collect(),
+        None => vec![None; flashcards.len()],
+    };
+
+    insert_flashcards(conn, session_id, flashcards, &card_ids)
+}
+
+/// Like [`initialize_flashcards`], for a session mixing several decks (a whole folder):
+/// each entry is linked to the card of `decks[i]` rather than of the session's deck, so
+/// reviews count towards the deck the card came from.
+pub fn initialize_flashcards_from_decks(
+    conn: &Connection,
+    session_id: u64,
+    flashcards: &[(String, String)],
+    decks: &[String],
+) -> Result<Vec<u64>> {
+    let mut card_ids = vec![None; flashcards.len()];
+    let mut deck_names: Vec<&String> = decks.iter().collect();
+    deck_names.sort();
+    deck_names.dedup();
+
+    for deck_name in deck_names {
+        let indices: Vec<usize> = (0..flashcards.len())
+            .filter(|&i| &decks[i] == deck_name)
+            .collect();
+        let entries: Vec<(String, String)> =
+            indices.iter().map(|&i| flashcards[i].clone()).collect();
+        let ids = card::resolve_cards(conn, deck_name, &entries)?;
+        for (i, id) in indices.into_iter().zip(ids) {
+            card_ids[i] = Some(id);
+        }
+    }
+
+    insert_flashcards(conn, session_id, flashcards, &card_ids)
+}
+
+fn insert_flashcards(
+    conn: &Connection,
+    session_id: u64,
+    flashcards: &[(String, String)],
+    card_ids: &[Option<u64>],
 ) -> Result<Vec<u64>> {
     let created_at = now();
     let updated_at = created_at;
@@ -35,9 +94,17 @@ pub fn initialize_flashcards(
 
     for (index, (question, answer)) in flashcards.iter().enumerate() {
         conn.execute(
-            "INSERT INTO flashcards (session_id, created_at, updated_at, question, answer, display_order)
-             VALUES (?, ?, ?, ?, ?, ?)",
-            rusqlite::params![session_id, created_at, updated_at, question, answer, index],
+            "INSERT INTO flashcards (session_id, created_at, updated_at, question, answer, display_order, card_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                session_id,
+                created_at,
+                updated_at,
+                question,
+                answer,
+                index,
+                card_ids[index]
+            ],
         )?;
         ids.push(conn.last_insert_rowid() as u64);
     }
@@ -85,10 +152,17 @@ pub fn save_answer(
 }
 
 pub fn load_flashcards(conn: &Connection, session_id: u64) -> Result<Vec<FlashcardData>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, session_id, created_at, updated_at, question, answer, user_answer, ai_feedback, answered_at, display_order
-         FROM flashcards WHERE session_id = ? ORDER BY display_order",
-    )?;
+    let meta_columns: Vec<String> = card::META_COLUMNS
+        .iter()
+        .map(|c| format!("c.{}", c))
+        .collect();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT f.id, f.session_id, f.created_at, f.updated_at, f.question, f.answer, f.user_answer, f.ai_feedback,
+                f.answered_at, f.display_order, f.card_id, {}
+         FROM flashcards f LEFT JOIN cards c ON c.id = f.card_id
+         WHERE f.session_id = ? ORDER BY f.display_order",
+        meta_columns.join(", ")
+    ))?;
 
     let flashcards = stmt
         .query_map([session_id], |row| {
@@ -108,6 +182,8 @@ pub fn load_flashcards(conn: &Connection, session_id: u64) -> Result<Vec<Flashca
                 ai_feedback: ai_feedback_parsed,
                 answered_at: row.get(8)?,
                 display_order: row.get(9)?,
+                card_id: row.get(10)?,
+                meta: card::meta_from_row(row, 11)?,
             })
         })?
         .filter_map(|r| r.ok())
@@ -133,6 +209,43 @@ pub fn update_ai_feedback(
     Ok(())
 }
 
+pub fn get_card_id(conn: &Connection, flashcard_id: u64) -> Result<Option<u64>> {
+    conn.query_row(
+        "SELECT card_id FROM flashcards WHERE id = ?",
+        [flashcard_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+/// Copy the optional deck columns of each session flashcard onto its deck card, in one
+/// transaction.
+pub fn sync_card_meta(conn: &Connection, flashcards: &[(u64, &CardMeta)]) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    for (flashcard_id, meta) in flashcards {
+        if let Some(card_id) = get_card_id(&tx, *flashcard_id)? {
+            card::set_card_meta(&tx, card_id, meta)?;
+        }
+    }
+    tx.commit()
+}
+
+/// The most recent AI feedback given for a deck card in any (non-deleted) session.
+pub fn get_latest_feedback(conn: &Connection, card_id: u64) -> Result<Option<AIFeedback>> {
+    let feedback: Option<String> = conn
+        .query_row(
+            "SELECT f.ai_feedback FROM flashcards f
+             JOIN sessions s ON s.id = f.session_id AND s.deleted_at IS NULL
+             WHERE f.card_id = ? AND f.ai_feedback IS NOT NULL
+             ORDER BY f.updated_at DESC, f.id DESC LIMIT 1",
+            [card_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(feedback.and_then(|f| serde_json::from_str(&f).ok()))
+}
+
 pub fn get_answer_count(conn: &Connection, session_id: u64) -> Result<usize> {
     let count: usize = conn.query_row(
         "SELECT COUNT(*) FROM flashcards WHERE session_id = ? AND user_answer IS NOT NULL",
@@ -174,6 +287,85 @@ mod tests {
         assert!(loaded[1].user_answer.is_none());
     }
 
+    #[test]
+    fn test_sessions_share_card_identity() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_db_path = temp_dir.path().join("test.db");
+        let mut conn = Connection::open(&test_db_path).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let first = create_session(&conn, "Test Deck", 1).unwrap();
+        initialize_flashcards(&conn, first, &[("Q1".to_string(), "A1".to_string())]).unwrap();
+
+        // Same card after a light edit of the CSV line
+        let second = create_session(&conn, "Test Deck", 1).unwrap();
+        let ids =
+            initialize_flashcards(&conn, second, &[("Q1 ".to_string(), "A1".to_string())]).unwrap();
+
+        let first_card = load_flashcards(&conn, first).unwrap()[0].card_id;
+        let second_card = load_flashcards(&conn, second).unwrap()[0].card_id;
+        assert!(first_card.is_some());
+        assert_eq!(first_card, second_card);
+        assert_eq!(get_card_id(&conn, ids[0]).unwrap(), second_card);
+    }
+
+    #[test]
+    fn test_folder_session_links_cards_to_their_decks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_db_path = temp_dir.path().join("test.db");
+        let mut conn = Connection::open(&test_db_path).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let deck_session = create_session(&conn, "net/manet", 1).unwrap();
+        initialize_flashcards(&conn, deck_session, &[("Q1".to_string(), "A1".to_string())])
+            .unwrap();
+
+        let folder_session = create_session(&conn, "net/", 2).unwrap();
+        let ids = initialize_flashcards_from_decks(
+            &conn,
+            folder_session,
+            &[
+                ("Q2".to_string(), "A2".to_string()),
+                ("Q1".to_string(), "A1".to_string()),
+            ],
+            &["net/wifi".to_string(), "net/manet".to_string()],
+        )
+        .unwrap();
+
+        let manet = card::find_card(&conn, "net/manet", "Q1").unwrap().unwrap();
+        let wifi = card::find_card(&conn, "net/wifi", "Q2").unwrap().unwrap();
+        assert_eq!(get_card_id(&conn, ids[0]).unwrap(), Some(wifi.id));
+        assert_eq!(get_card_id(&conn, ids[1]).unwrap(), Some(manet.id));
+        assert!(card::find_card(&conn, "net/", "Q1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_flashcards_includes_card_meta() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_db_path = temp_dir.path().join("test.db");
+        let mut conn = Connection::open(&test_db_path).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let session_id = create_session(&conn, "Test Deck", 1).unwrap();
+        let ids =
+            initialize_flashcards(&conn, session_id, &[("Q1".to_string(), "A1".to_string())]).unwrap();
+        let meta = CardMeta {
+            tags: vec!["math".to_string(), "basics".to_string()],
+            hint: Some("Think".to_string()),
+            source: None,
+            difficulty: Some("hard".to_string()),
+            accepted_answers: vec!["Uno".to_string()],
+            grading_notes: Some("Accept digits".to_string()),
+            media: vec!["img/one.png".to_string()],
+        };
+        sync_card_meta(&conn, &[(ids[0], &meta)]).unwrap();
+        let card_id = get_card_id(&conn, ids[0]).unwrap().unwrap();
+
+        let loaded = load_flashcards(&conn, session_id).unwrap();
+        assert_eq!(loaded[0].meta, meta);
+        assert_eq!(card::get_card_meta(&conn, card_id).unwrap(), Some(meta));
+    }
+
     #[test]
     fn test_save_answer_with_ai_feedback() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -205,7 +397,7 @@ mod tests {
 
         let loaded = load_flashcards(&conn, session_id).unwrap();
         assert!(loaded[0].ai_feedback.is_some());
-        assert_eq!(loaded[0].ai_feedback.clone().unwrap().is_correct, true);
+        assert!(loaded[0].ai_feedback.clone().unwrap().is_correct);
     }
 
     #[test]
@@ -242,7 +434,7 @@ mod tests {
         assert!(loaded_after_update[0].ai_feedback.is_some());
 
         let saved_feedback = loaded_after_update[0].ai_feedback.as_ref().unwrap();
-        assert_eq!(saved_feedback.is_correct, true);
+        assert!(saved_feedback.is_correct);
         assert_eq!(saved_feedback.correctness_score, 0.85);
         assert_eq!(
             saved_feedback.corrections,
@@ -330,7 +522,7 @@ mod tests {
         assert!(loaded_after_ai[0].ai_feedback.is_some());
 
         let saved_feedback = loaded_after_ai[0].ai_feedback.as_ref().unwrap();
-        assert_eq!(saved_feedback.is_correct, false);
+        assert!(!saved_feedback.is_correct);
         assert_eq!(saved_feedback.correctness_score, 0.60);
         assert_eq!(
             saved_feedback.corrections,
@@ -397,6 +589,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_latest_feedback() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_db_path = temp_dir.path().join("test.db");
+        let mut conn = Connection::open(&test_db_path).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let cards = [("Q1".to_string(), "A1".to_string())];
+        let first = create_session(&conn, "Test Deck", 1).unwrap();
+        let first_ids = initialize_flashcards(&conn, first, &cards).unwrap();
+        let card_id = get_card_id(&conn, first_ids[0]).unwrap().unwrap();
+        assert!(get_latest_feedback(&conn, card_id).unwrap().is_none());
+
+        let feedback = |explanation: &str| AIFeedback {
+            is_correct: false,
+            correctness_score: 0.2,
+            corrections: vec![],
+            explanation: explanation.to_string(),
+            suggestions: vec![],
+        };
+        update_ai_feedback(&conn, first_ids[0], &feedback("older")).unwrap();
+        let second = create_session(&conn, "Test Deck", 1).unwrap();
+        let second_ids = initialize_flashcards(&conn, second, &cards).unwrap();
+        update_ai_feedback(&conn, second_ids[0], &feedback("newer")).unwrap();
+
+        let latest = get_latest_feedback(&conn, card_id).unwrap().unwrap();
+        assert_eq!(latest.explanation, "newer");
+    }
+
     #[test]
     fn test_get_answer_count() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -0,0 +1,5 @@
+-- V10__card_grading.sql
+-- Grading data from structured (JSON/TOML) decks; lists are stored as JSON arrays of strings
+ALTER TABLE cards ADD COLUMN accepted_answers TEXT;
+ALTER TABLE cards ADD COLUMN grading_notes TEXT;
+ALTER TABLE cards ADD COLUMN media TEXT;
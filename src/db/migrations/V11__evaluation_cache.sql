@@ -0,0 +1,16 @@
+-- V11__evaluation_cache.sql
+-- AI evaluations reused when the same answer is graded again. `cache_key` hashes the
+-- normalized question, answers and grading context with the model and prompt template.
+CREATE TABLE evaluation_cache (
+    cache_key TEXT PRIMARY KEY,
+    model TEXT NOT NULL,
+    response TEXT NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+-- Running totals of cache lookups: one row each for `hits` and `misses`
+CREATE TABLE evaluation_cache_counters (
+    name TEXT PRIMARY KEY,
+    count INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,20 @@
+-- V12__ai_usage.sql
+-- One row per AI call that got a reply: what it used, how long it took and what it cost.
+-- `session_id` is NULL for calls made outside a stored session; it is not a foreign key
+-- so that the totals outlive deleted sessions.
+CREATE TABLE ai_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id INTEGER,
+    deck_name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('evaluation', 'assessment', 'chat')),
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    latency_ms INTEGER NOT NULL,
+    cost REAL NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX idx_ai_usage_session ON ai_usage(session_id);
+CREATE INDEX idx_ai_usage_deck ON ai_usage(deck_name);
+CREATE INDEX idx_ai_usage_created ON ai_usage(created_at);
@@ -0,0 +1,23 @@
+-- V13__generation_usage.sql
+-- Card generation from documents is metered too; SQLite cannot alter a CHECK constraint,
+-- so the table is rebuilt with 'generation' allowed.
+CREATE TABLE ai_usage_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id INTEGER,
+    deck_name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('evaluation', 'assessment', 'chat', 'generation')),
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    latency_ms INTEGER NOT NULL,
+    cost REAL NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+INSERT INTO ai_usage_new SELECT * FROM ai_usage;
+DROP TABLE ai_usage;
+ALTER TABLE ai_usage_new RENAME TO ai_usage;
+
+CREATE INDEX idx_ai_usage_session ON ai_usage(session_id);
+CREATE INDEX idx_ai_usage_deck ON ai_usage(deck_name);
+CREATE INDEX idx_ai_usage_created ON ai_usage(created_at);
@@ -0,0 +1,19 @@
+-- V14__source_passages.sql
+-- Full-text index over the documents decks name as their sources. A document is indexed
+-- again when its size or modification time changes.
+CREATE TABLE source_documents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified_at INTEGER NOT NULL,
+    indexed_at INTEGER NOT NULL
+);
+
+-- Passages of about 1200 characters, ranked with FTS5's built-in BM25
+CREATE VIRTUAL TABLE source_passages USING fts5(
+    text,
+    location UNINDEXED,
+    document_id UNINDEXED,
+    tokenize = 'porter unicode61'
+);
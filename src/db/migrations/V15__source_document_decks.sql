@@ -0,0 +1,8 @@
+-- V15__source_document_decks.sql
+-- The decks that name each indexed source document, so that documents no deck names any
+-- more can be dropped from the index.
+CREATE TABLE source_document_decks (
+    deck_name TEXT NOT NULL,
+    document_id INTEGER NOT NULL,
+    PRIMARY KEY (deck_name, document_id)
+);
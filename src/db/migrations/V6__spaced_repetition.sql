@@ -0,0 +1,39 @@
+-- V6__spaced_repetition.sql
+CREATE TABLE IF NOT EXISTS cards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    deck_name TEXT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    ease_factor REAL NOT NULL DEFAULT 2.5,
+    interval_days INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    due_at INTEGER,
+    last_reviewed_at INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_cards_deck_question ON cards(deck_name, question);
+CREATE INDEX IF NOT EXISTS idx_cards_deck_due ON cards(deck_name, due_at);
+
+CREATE TABLE IF NOT EXISTS card_reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    card_id INTEGER NOT NULL,
+    session_id INTEGER,
+    flashcard_id INTEGER,
+    score REAL NOT NULL,
+    quality INTEGER NOT NULL,
+    prev_ease_factor REAL NOT NULL,
+    prev_interval_days INTEGER NOT NULL,
+    prev_repetitions INTEGER NOT NULL,
+    ease_factor REAL NOT NULL,
+    interval_days INTEGER NOT NULL,
+    repetitions INTEGER NOT NULL,
+    reviewed_at INTEGER NOT NULL,
+    FOREIGN KEY (card_id) REFERENCES cards(id),
+    FOREIGN KEY (session_id) REFERENCES sessions(id),
+    FOREIGN KEY (flashcard_id) REFERENCES flashcards(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_card_reviews_card ON card_reviews(card_id);
+CREATE INDEX IF NOT EXISTS idx_card_reviews_flashcard ON card_reviews(flashcard_id);
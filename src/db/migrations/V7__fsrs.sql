@@ -0,0 +1,18 @@
+-- V7__fsrs.sql
+-- FSRS memory state, kept alongside the SM-2 state so a deck can switch schedulers
+ALTER TABLE cards ADD COLUMN stability REAL;
+ALTER TABLE cards ADD COLUMN difficulty REAL;
+
+ALTER TABLE card_reviews ADD COLUMN grade INTEGER;
+ALTER TABLE card_reviews ADD COLUMN elapsed_days REAL;
+ALTER TABLE card_reviews ADD COLUMN prev_stability REAL;
+ALTER TABLE card_reviews ADD COLUMN prev_difficulty REAL;
+ALTER TABLE card_reviews ADD COLUMN stability REAL;
+ALTER TABLE card_reviews ADD COLUMN difficulty REAL;
+
+CREATE TABLE IF NOT EXISTS deck_settings (
+    deck_name TEXT PRIMARY KEY,
+    scheduler TEXT NOT NULL DEFAULT 'sm2',
+    fsrs_params TEXT,
+    updated_at INTEGER NOT NULL
+);
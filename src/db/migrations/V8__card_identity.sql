@@ -0,0 +1,25 @@
+-- V8__card_identity.sql
+-- Cards are identified by deck + content hash; session flashcards reference them
+ALTER TABLE cards ADD COLUMN content_hash TEXT;
+CREATE INDEX IF NOT EXISTS idx_cards_deck_hash ON cards(deck_name, content_hash);
+
+ALTER TABLE flashcards ADD COLUMN card_id INTEGER REFERENCES cards(id);
+CREATE INDEX IF NOT EXISTS idx_flashcards_card ON flashcards(card_id);
+
+-- Create cards for questions answered before cards existed.
+-- content_hash is filled in by the application the next time the deck is studied.
+INSERT INTO cards (deck_name, question, answer, created_at, updated_at)
+SELECT s.deck_name, f.question, f.answer, MIN(f.created_at), MAX(f.updated_at)
+FROM flashcards f
+JOIN sessions s ON s.id = f.session_id
+WHERE NOT EXISTS (
+    SELECT 1 FROM cards c WHERE c.deck_name = s.deck_name AND c.question = f.question
+)
+GROUP BY s.deck_name, f.question;
+
+UPDATE flashcards SET card_id = (
+    SELECT c.id FROM cards c
+    JOIN sessions s ON s.id = flashcards.session_id
+    WHERE c.deck_name = s.deck_name AND c.question = flashcards.question
+    ORDER BY c.id LIMIT 1
+);
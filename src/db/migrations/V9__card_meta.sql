@@ -0,0 +1,7 @@
+-- V9__card_meta.sql
+-- Optional deck columns; tags are stored as a JSON array of strings
+ALTER TABLE cards ADD COLUMN tags TEXT;
+ALTER TABLE cards ADD COLUMN hint TEXT;
+ALTER TABLE cards ADD COLUMN source TEXT;
+-- `difficulty` already holds the FSRS difficulty, so the deck value gets its own name
+ALTER TABLE cards ADD COLUMN difficulty_label TEXT;
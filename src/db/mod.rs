@@ -1,9 +1,14 @@
 use rusqlite::{Connection, Result};
 use std::path::PathBuf;
 
+pub mod ai_usage;
+pub mod card;
 pub mod chat;
+pub mod deck;
+pub mod evaluation_cache;
 pub mod flashcard;
 pub mod session;
+pub mod sources;
 
 mod embedded_migrations {
     use refinery::embed_migrations;
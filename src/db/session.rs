@@ -2,6 +2,7 @@ use rusqlite::{Connection, OptionalExtension, Result};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::db::flashcard::{load_flashcards, FlashcardData};
+use crate::scheduler::SchedulerKind;
 
 #[derive(Debug, Clone)]
 pub struct SessionSummary {
@@ -272,6 +273,10 @@ pub struct DeckStatus {
     pub times_studied: usize,
     pub last_scores: Vec<f32>,
     pub last_studied_at: Option<u64>,
+    /// Cards due for review today; filled in by the caller from the deck contents
+    pub due_count: usize,
+    /// Scheduler selected for the deck; filled in by the caller from the deck settings
+    pub scheduler: SchedulerKind,
 }
 
 pub fn get_last_session_status(conn: &Connection, deck_name: &str) -> Result<DeckStatus> {
@@ -311,6 +316,8 @@ pub fn get_last_session_status(conn: &Connection, deck_name: &str) -> Result<Dec
         times_studied,
         last_scores,
         last_studied_at,
+        due_count: 0,
+        scheduler: SchedulerKind::default(),
     })
 }
 
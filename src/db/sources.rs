@@ -0,0 +1,263 @@
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source document as it was when indexed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceDocument {
+    pub id: i64,
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    pub modified_at: u64,
+}
+
+/// A passage found by [`search_passages`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassageHit {
+    /// Name of the document the passage is from
+    pub document: String,
+    pub location: String,
+    pub text: String,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn find_document(conn: &Connection, path: &str) -> Result<Option<SourceDocument>> {
+    conn.query_row(
+        "SELECT id, path, name, size, modified_at FROM source_documents WHERE path = ?",
+        [path],
+        |row| {
+            Ok(SourceDocument {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                name: row.get(2)?,
+                size: row.get(3)?,
+                modified_at: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Store the passages of the document at `path` as (location, text) pairs, replacing
+/// whatever was indexed for it before. Returns the document's id, which stays the same
+/// when it is indexed again.
+pub fn replace_document(
+    conn: &Connection,
+    path: &str,
+    name: &str,
+    size: u64,
+    modified_at: u64,
+    passages: &[(String, String)],
+) -> Result<i64> {
+    let tx = conn.unchecked_transaction()?;
+    let id = match find_document(&tx, path)? {
+        Some(old) => {
+            tx.execute(
+                "DELETE FROM source_passages WHERE document_id = ?",
+                [old.id],
+            )?;
+            tx.execute(
+                "UPDATE source_documents SET name = ?, size = ?, modified_at = ?, indexed_at = ?
+                 WHERE id = ?",
+                params![name, size, modified_at, now(), old.id],
+            )?;
+            old.id
+        }
+        None => {
+            tx.execute(
+                "INSERT INTO source_documents (path, name, size, modified_at, indexed_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params![path, name, size, modified_at, now()],
+            )?;
+            tx.last_insert_rowid()
+        }
+    };
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO source_passages (text, location, document_id) VALUES (?, ?, ?)",
+        )?;
+        for (location, text) in passages {
+            insert.execute(params![text, location, id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Record that `deck_name` names the documents `document_ids` and no others, then remove
+/// the documents that no deck names any more, with their passages. Returns how many
+/// documents were removed.
+pub fn set_deck_documents(
+    conn: &Connection,
+    deck_name: &str,
+    document_ids: &[i64],
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM source_document_decks WHERE deck_name = ?",
+        [deck_name],
+    )?;
+    for id in document_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO source_document_decks (deck_name, document_id) VALUES (?, ?)",
+            params![deck_name, id],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM source_passages
+         WHERE document_id NOT IN (SELECT document_id FROM source_document_decks)",
+        [],
+    )?;
+    let removed = tx.execute(
+        "DELETE FROM source_documents
+         WHERE id NOT IN (SELECT document_id FROM source_document_decks)",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// The `limit` passages of the given documents that best match an FTS5 `query`, best
+/// first by BM25.
+pub fn search_passages(
+    conn: &Connection,
+    document_ids: &[i64],
+    query: &str,
+    limit: usize,
+) -> Result<Vec<PassageHit>> {
+    if document_ids.is_empty() || query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids = vec!["?"; document_ids.len()].join(", ");
+    let sql = format!(
+        "SELECT d.name, p.location, p.text
+         FROM source_passages p
+         JOIN source_documents d ON d.id = p.document_id
+         WHERE source_passages MATCH ? AND p.document_id IN ({})
+         ORDER BY bm25(source_passages)
+         LIMIT {}",
+        ids, limit
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let values = std::iter::once(rusqlite::types::Value::from(query.to_string()))
+        .chain(document_ids.iter().map(|&id| id.into()));
+    let hits = stmt.query_map(params_from_iter(values), |row| {
+        Ok(PassageHit {
+            document: row.get(0)?,
+            location: row.get(1)?,
+            text: row.get(2)?,
+        })
+    })?;
+    hits.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    #[test]
+    fn test_index_and_search_passages() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        let passage = |location: &str, text: &str| (location.to_string(), text.to_string());
+
+        let manet = replace_document(
+            &conn,
+            "/docs/manet.pdf",
+            "manet.pdf",
+            10,
+            1,
+            &[
+                passage(
+                    "p. 1",
+                    "AODV discovers routes on demand by flooding route requests.",
+                ),
+                passage("p. 2", "DSR keeps a route cache and uses source routing."),
+                passage(
+                    "p. 3",
+                    "OLSR is proactive: multipoint relays flood topology control messages.",
+                ),
+            ],
+        )
+        .unwrap();
+        let other = replace_document(
+            &conn,
+            "/docs/biology.md",
+            "biology.md",
+            5,
+            1,
+            &[passage("line 1", "Route of the blood through the heart.")],
+        )
+        .unwrap();
+
+        let hits = search_passages(&conn, &[manet], "\"route\" OR \"requests\"", 5).unwrap();
+        let locations: Vec<&str> = hits.iter().map(|h| h.location.as_str()).collect();
+        assert_eq!(locations, vec!["p. 1", "p. 2"]);
+        assert_eq!(hits[0].document, "manet.pdf");
+        assert_eq!(
+            search_passages(&conn, &[manet, other], "\"heart\"", 5)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(search_passages(&conn, &[], "\"route\"", 5)
+            .unwrap()
+            .is_empty());
+
+        // Indexing the document again replaces its passages
+        let again = replace_document(
+            &conn,
+            "/docs/manet.pdf",
+            "manet.pdf",
+            12,
+            2,
+            &[passage("p. 1", "Nothing about it any more.")],
+        )
+        .unwrap();
+        assert_eq!(again, manet);
+        assert!(search_passages(&conn, &[manet], "\"route\"", 5)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            find_document(&conn, "/docs/manet.pdf")
+                .unwrap()
+                .map(|d| (d.size, d.modified_at)),
+            Some((12, 2))
+        );
+    }
+
+    #[test]
+    fn test_documents_no_deck_names_are_removed() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        let passages = [("p. 1".to_string(), "AODV finds routes.".to_string())];
+        let manet =
+            replace_document(&conn, "/docs/manet.pdf", "manet.pdf", 1, 1, &passages).unwrap();
+        let olsr = replace_document(&conn, "/docs/olsr.pdf", "olsr.pdf", 1, 1, &passages).unwrap();
+
+        assert_eq!(
+            set_deck_documents(&conn, "manet", &[manet, olsr]).unwrap(),
+            0
+        );
+        assert_eq!(set_deck_documents(&conn, "routing", &[olsr]).unwrap(), 0);
+        // olsr.pdf is still named by the routing deck
+        assert_eq!(set_deck_documents(&conn, "manet", &[]).unwrap(), 1);
+        assert!(find_document(&conn, "/docs/manet.pdf").unwrap().is_none());
+        assert!(search_passages(&conn, &[manet], "\"aodv\"", 5)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            search_passages(&conn, &[olsr], "\"aodv\"", 5)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
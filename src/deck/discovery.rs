@@ -0,0 +1,270 @@
+//! Finding deck files under the configured deck roots.
+//!
+//! Roots are searched recursively. A deck's name is its path relative to the root,
+//! without the extension and with `/` separators (`networking/manet`), so folders
+//! act as deck groups. Top-level decks keep the plain file stem they always had,
+//! which keeps their history in the database.
+
+use super::is_deck_file;
+use crate::logger;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Folders nested deeper than this are not searched (guards against symlink loops)
+const MAX_DEPTH: usize = 16;
+
+/// A deck file found under one of the deck roots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeckEntry {
+    pub path: PathBuf,
+    /// Path relative to the root without the extension, `/`-separated
+    pub name: String,
+}
+
+impl DeckEntry {
+    /// The folder the deck is in (`networking` for `networking/manet`), empty at the root.
+    pub fn group(&self) -> &str {
+        self.name.rsplit_once('/').map_or("", |(group, _)| group)
+    }
+
+    /// The deck's own name, without its folder.
+    pub fn label(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map_or(&self.name, |(_, label)| label)
+    }
+
+    /// Whether the deck is in `group` or one of its sub-folders.
+    pub fn in_group(&self, group: &str) -> bool {
+        self.name
+            .strip_prefix(group)
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Find every deck under `roots`, sorted by name with each folder's decks together.
+/// When two roots contain a deck with the same name, the earlier root wins.
+pub fn discover_decks(roots: &[PathBuf]) -> Vec<DeckEntry> {
+    let mut decks = Vec::new();
+    let mut seen = HashSet::new();
+
+    for root in roots {
+        let mut found = Vec::new();
+        collect_decks(root, root, 0, &mut found);
+        for entry in found {
+            if seen.insert(entry.name.clone()) {
+                decks.push(entry);
+            } else {
+                logger::log(&format!(
+                    "Skipping {}: a deck named '{}' was already found",
+                    entry.path.display(),
+                    entry.name
+                ));
+            }
+        }
+    }
+
+    decks.sort_by(|a, b| a.name.split('/').cmp(b.name.split('/')));
+    decks
+}
+
+fn collect_decks(root: &Path, dir: &Path, depth: usize, found: &mut Vec<DeckEntry>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            collect_decks(root, &path, depth + 1, found);
+        } else if is_deck_file(&path)
+            && let Some(name) = deck_name(root, &path)
+        {
+            found.push(DeckEntry { path, name });
+        }
+    }
+}
+
+fn deck_name(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?.with_extension("");
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// One line of the menu's deck tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeckTreeRow {
+    /// A folder; `group` is its full path (`networking/wireless`)
+    Folder {
+        group: String,
+        depth: usize,
+        collapsed: bool,
+    },
+    /// The deck at this index of the discovered list
+    Deck { index: usize, depth: usize },
+}
+
+impl DeckTreeRow {
+    pub fn depth(&self) -> usize {
+        match self {
+            DeckTreeRow::Folder { depth, .. } | DeckTreeRow::Deck { depth, .. } => *depth,
+        }
+    }
+}
+
+/// Lay out `decks` (as returned by [`discover_decks`]) as a tree, hiding the contents
+/// of every folder in `collapsed`.
+pub fn deck_tree_rows(decks: &[DeckEntry], collapsed: &HashSet<String>) -> Vec<DeckTreeRow> {
+    let mut rows = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+
+    for (index, deck) in decks.iter().enumerate() {
+        let folders: Vec<&str> = match deck.group() {
+            "" => Vec::new(),
+            group => group.split('/').collect(),
+        };
+        let common = open
+            .iter()
+            .zip(&folders)
+            .take_while(|(a, b)| a == b)
+            .count();
+        open.truncate(common);
+
+        for depth in common..folders.len() {
+            open.push(folders[depth]);
+            let group = folders[..=depth].join("/");
+            if !hidden(&folders[..depth], collapsed) {
+                rows.push(DeckTreeRow::Folder {
+                    collapsed: collapsed.contains(&group),
+                    group,
+                    depth,
+                });
+            }
+        }
+
+        if !hidden(&folders, collapsed) {
+            rows.push(DeckTreeRow::Deck {
+                index,
+                depth: folders.len(),
+            });
+        }
+    }
+
+    rows
+}
+
+/// Whether a row inside `folders` is hidden by one of them being collapsed.
+fn hidden(folders: &[&str], collapsed: &HashSet<String>) -> bool {
+    (1..=folders.len()).any(|n| collapsed.contains(&folders[..n].join("/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> DeckEntry {
+        DeckEntry {
+            path: PathBuf::from(format!("{}.csv", name)),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_discover_decks_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("networking/wireless")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        for file in [
+            "maths.csv",
+            "networking/manet.md",
+            "networking/wireless/wifi.toml",
+            "networking/notes.txt",
+            ".git/config.csv",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let decks = discover_decks(&[root.to_path_buf()]);
+        let names: Vec<&str> = decks.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["maths", "networking/manet", "networking/wireless/wifi"]
+        );
+        assert_eq!(decks[1].path, root.join("networking/manet.md"));
+        assert_eq!(decks[2].group(), "networking/wireless");
+        assert_eq!(decks[2].label(), "wifi");
+        assert!(decks[2].in_group("networking"));
+        assert!(!decks[0].in_group("networking"));
+    }
+
+    #[test]
+    fn test_earlier_root_wins_on_duplicate_names() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        fs::write(first.path().join("maths.csv"), "").unwrap();
+        fs::write(second.path().join("maths.md"), "").unwrap();
+        fs::write(second.path().join("physics.csv"), "").unwrap();
+
+        let decks = discover_decks(&[
+            first.path().to_path_buf(),
+            PathBuf::from("/does/not/exist"),
+            second.path().to_path_buf(),
+        ]);
+        assert_eq!(decks.len(), 2);
+        assert_eq!(decks[0].path, first.path().join("maths.csv"));
+        assert_eq!(decks[1].name, "physics");
+    }
+
+    #[test]
+    fn test_deck_tree_rows() {
+        let decks = vec![
+            entry("maths"),
+            entry("networking/manet"),
+            entry("networking/wireless/wifi"),
+            entry("networking/zigbee"),
+            entry("physics/optics"),
+        ];
+
+        let rows = deck_tree_rows(&decks, &HashSet::new());
+        let folder = |group: &str, depth, collapsed| DeckTreeRow::Folder {
+            group: group.to_string(),
+            depth,
+            collapsed,
+        };
+        assert_eq!(
+            rows,
+            vec![
+                DeckTreeRow::Deck { index: 0, depth: 0 },
+                folder("networking", 0, false),
+                DeckTreeRow::Deck { index: 1, depth: 1 },
+                folder("networking/wireless", 1, false),
+                DeckTreeRow::Deck { index: 2, depth: 2 },
+                DeckTreeRow::Deck { index: 3, depth: 1 },
+                folder("physics", 0, false),
+                DeckTreeRow::Deck { index: 4, depth: 1 },
+            ]
+        );
+
+        let collapsed: HashSet<String> = ["networking".to_string()].into();
+        assert_eq!(
+            deck_tree_rows(&decks, &collapsed),
+            vec![
+                DeckTreeRow::Deck { index: 0, depth: 0 },
+                folder("networking", 0, true),
+                folder("physics", 0, false),
+                DeckTreeRow::Deck { index: 4, depth: 1 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,513 @@
+//! Markdown deck format.
+//!
+//! ```markdown
+//! ---
+//! title: Wireless Networks
+//! description: MANET routing and 802.11 basics
+//! mode: due
+//! rubric: |
+//!   Accept answers that name the protocol even without the acronym.
+//! sources: notes/manet.pdf, slides.md
+//! ---
+//!
+//! ## What does AODV stand for?
+//!
+//! **Ad hoc On-Demand Distance Vector** routing.
+//!
+//! Tags: routing, manet
+//! Hint: It is reactive
+//! ```
+//!
+//! Each heading starts a card: the heading text is the question and everything up to
+//! the next heading of the same level is the answer, kept as Markdown. Deeper headings
+//! belong to the answer. A lone top-level heading above the cards is read as the deck
+//! title. `Tags:`, `Hint:`, `Source:` and `Difficulty:` lines at the very end of a card
+//! fill in the same optional fields as the CSV columns.
+
+use super::{Deck, DeckError, DeckMeta, DeckMode};
+use crate::csv::flashcard;
+use crate::models::CardMeta;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FRONT_MATTER_FENCE: &str = "---";
+
+fn error(line: usize, message: impl Into<String>) -> DeckError {
+    DeckError {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Load a Markdown deck; parse errors are returned as `InvalidData` carrying the [`DeckError`].
+pub fn load_markdown_deck(path: &Path) -> io::Result<Deck> {
+    let content = fs::read_to_string(path)?;
+    parse_markdown_deck(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Parse a Markdown deck from its text.
+pub fn parse_markdown_deck(content: &str) -> Result<Deck, DeckError> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let lines: Vec<&str> = content.lines().collect();
+
+    let (mut meta, body_start) = match lines.first() {
+        Some(first) if first.trim_end() == FRONT_MATTER_FENCE => {
+            let end = lines[1..]
+                .iter()
+                .position(|l| matches!(l.trim_end(), FRONT_MATTER_FENCE | "..."))
+                .map(|i| i + 1)
+                .ok_or_else(|| error(1, "front matter is not closed with '---'"))?;
+            (parse_front_matter(&lines[1..end], 2)?, end + 1)
+        }
+        _ => (DeckMeta::default(), 0),
+    };
+
+    let headings = find_headings(&lines, body_start);
+    let mut card_headings: &[Heading] = &headings;
+
+    // A single top-level heading followed by deeper ones is the document title
+    if let Some(first) = headings.first()
+        && headings[1..].iter().all(|h| h.level > first.level)
+        && headings.len() > 1
+    {
+        if meta.title.is_none() {
+            meta.title = Some(first.text.clone());
+        }
+        card_headings = &headings[1..];
+    }
+
+    let card_level = match card_headings.iter().map(|h| h.level).min() {
+        Some(level) => level,
+        None => {
+            return Err(error(
+                body_start + 1,
+                "no cards found (each card is a heading followed by its answer)",
+            ));
+        }
+    };
+
+    let starts: Vec<&Heading> = card_headings
+        .iter()
+        .filter(|h| h.level == card_level)
+        .collect();
+    let mut flashcards = Vec::with_capacity(starts.len());
+
+    for (i, heading) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map(|h| h.index).unwrap_or(lines.len());
+        let mut body: Vec<&str> = lines[heading.index + 1..end].to_vec();
+        let card_meta = take_card_meta(&mut body);
+        let answer = trim_blank_lines(&body).join("\n");
+
+        if heading.text.is_empty() {
+            return Err(error(
+                heading.index + 1,
+                "card heading has no question text",
+            ));
+        }
+        if answer.trim().is_empty() {
+            return Err(error(
+                heading.index + 1,
+                format!("card \"{}\" has no answer", heading.text),
+            ));
+        }
+
+        flashcards.push(flashcard(heading.text.clone(), answer, card_meta));
+    }
+
+    Ok(Deck { meta, flashcards })
+}
+
+#[derive(Debug)]
+struct Heading {
+    /// Index into the deck's lines
+    index: usize,
+    level: usize,
+    text: String,
+}
+
+/// ATX headings outside fenced code blocks, starting at line index `from`.
+fn find_headings(lines: &[&str], from: usize) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut in_fence = false;
+
+    for (index, line) in lines.iter().enumerate().skip(from) {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence || line.len() - trimmed.len() > 3 {
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let rest = &trimmed[level..];
+        if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t'])) {
+            let text = rest.trim().trim_end_matches('#').trim_end().to_string();
+            headings.push(Heading { index, level, text });
+        }
+    }
+
+    headings
+}
+
+/// Strip the trailing `Key: value` metadata lines from a card body.
+fn take_card_meta(body: &mut Vec<&str>) -> CardMeta {
+    let mut meta = CardMeta::default();
+
+    while body.last().is_some_and(|l| l.trim().is_empty()) {
+        body.pop();
+    }
+
+    while let Some(line) = body.last() {
+        let Some((key, value)) = line.split_once(':') else {
+            break;
+        };
+        let value = value.trim();
+        let value_opt = (!value.is_empty()).then(|| value.to_string());
+        match key.trim().to_ascii_lowercase().as_str() {
+            "tags" | "tag" => meta.tags = CardMeta::parse_tags(value),
+            "hint" => meta.hint = value_opt,
+            "source" => meta.source = value_opt,
+            "difficulty" => meta.difficulty = value_opt,
+            _ => break,
+        }
+        body.pop();
+    }
+
+    meta
+}
+
+fn trim_blank_lines<'a>(lines: &'a [&'a str]) -> &'a [&'a str] {
+    let start = lines
+        .iter()
+        .position(|l| !l.trim().is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map(|i| i + 1)
+        .unwrap_or(start);
+    &lines[start..end]
+}
+
+/// Parse the `key: value` lines of the front matter. `first_line` is the 1-based line
+/// number of `lines[0]`. Block scalars (`key: |` keeps line breaks, `key: >` folds them)
+/// take the indented lines that follow. Unknown keys are ignored.
+fn parse_front_matter(lines: &[&str], first_line: usize) -> Result<DeckMeta, DeckError> {
+    let mut meta = DeckMeta::default();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line_no = first_line + i;
+        let line = lines[i];
+        i += 1;
+
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return Err(error(
+                line_no,
+                format!("expected `key: value`, got \"{}\"", line.trim()),
+            ));
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let value = match value {
+            "|" | "|-" | ">" | ">-" => {
+                let start = i;
+                while i < lines.len()
+                    && (lines[i].trim().is_empty() || lines[i].starts_with([' ', '\t']))
+                {
+                    i += 1;
+                }
+                block_scalar(&lines[start..i], value.starts_with('>'))
+            }
+            _ => unquote(value).to_string(),
+        };
+        let value_opt = (!value.is_empty()).then(|| value.clone());
+
+        match key.as_str() {
+            "title" => meta.title = value_opt,
+            "description" => meta.description = value_opt,
+            "rubric" => meta.rubric = value_opt,
+            "sources" | "source" => {
+                meta.sources = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            "mode" | "default_mode" | "default-mode" => {
+                meta.default_mode = DeckMode::parse(&value).ok_or_else(|| {
+                    error(
+                        line_no,
+                        format!(
+                            "unknown mode \"{}\" (expected {})",
+                            value,
+                            DeckMode::expected('"')
+                        ),
+                    )
+                })?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(meta)
+}
+
+fn block_scalar(lines: &[&str], folded: bool) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let dedented: Vec<&str> = lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end())
+        .collect();
+
+    if !folded {
+        return trim_blank_lines(&dedented).join("\n");
+    }
+
+    // Folded: lines of a paragraph join with spaces, blank lines separate paragraphs
+    trim_blank_lines(&dedented)
+        .split(|l| l.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// Serialize a deck in the format read by [`parse_markdown_deck`]. Cards are written as
+/// `##` headings, so line breaks in a question are joined with spaces.
+pub fn write_markdown_deck(deck: &Deck) -> String {
+    let meta = &deck.meta;
+    let mut front = String::new();
+    for (key, value) in [
+        ("title", meta.title.as_deref()),
+        ("description", meta.description.as_deref()),
+        (
+            "mode",
+            (meta.default_mode != DeckMode::default()).then_some(meta.default_mode.name()),
+        ),
+        ("rubric", meta.rubric.as_deref()),
+        (
+            "sources",
+            Some(meta.sources.join(", ")).filter(|s| !s.is_empty()).as_deref(),
+        ),
+    ] {
+        if let Some(value) = value {
+            front.push_str(&front_matter_line(key, value));
+        }
+    }
+
+    let mut out = String::new();
+    if !front.is_empty() {
+        out.push_str(&format!(
+            "{}\n{}{}\n\n",
+            FRONT_MATTER_FENCE, front, FRONT_MATTER_FENCE
+        ));
+    }
+
+    for card in &deck.flashcards {
+        let question = card
+            .question
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        out.push_str(&format!("## {}\n\n{}\n", question, card.answer.trim()));
+
+        let mut meta_lines = Vec::new();
+        if !card.meta.tags.is_empty() {
+            meta_lines.push(format!("Tags: {}", card.meta.tags.join(", ")));
+        }
+        for (key, value) in [
+            ("Hint", &card.meta.hint),
+            ("Source", &card.meta.source),
+            ("Difficulty", &card.meta.difficulty),
+        ] {
+            if let Some(value) = value {
+                meta_lines.push(format!("{}: {}", key, value));
+            }
+        }
+        if !meta_lines.is_empty() {
+            out.push_str(&format!("\n{}\n", meta_lines.join("\n")));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+fn front_matter_line(key: &str, value: &str) -> String {
+    if value.contains('\n') {
+        let indented: Vec<String> = value
+            .lines()
+            .map(|l| {
+                if l.is_empty() {
+                    String::new()
+                } else {
+                    format!("  {}", l)
+                }
+            })
+            .collect();
+        return format!("{}: |\n{}\n", key, indented.join("\n"));
+    }
+    // Quote values that would otherwise be read back as quoted or as a block scalar
+    if value.starts_with(['"', '\'']) || matches!(value, "|" | "|-" | ">" | ">-") {
+        return format!("{}: \"{}\"\n", key, value);
+    }
+    format!("{}: {}\n", key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cards_without_front_matter() {
+        let deck = parse_markdown_deck(
+            "## What is 2+2?\n\nFour\n\n## Capital of France?\n\n**Paris**, on the Seine.\n",
+        )
+        .unwrap();
+        assert_eq!(deck.meta, DeckMeta::default());
+        assert_eq!(deck.flashcards.len(), 2);
+        assert_eq!(deck.flashcards[0].question, "What is 2+2?");
+        assert_eq!(deck.flashcards[0].answer, "Four");
+        assert_eq!(deck.flashcards[1].answer, "**Paris**, on the Seine.");
+    }
+
+    #[test]
+    fn test_parse_front_matter() {
+        let content = "---\ntitle: \"Wireless Networks\"\ndescription: MANET basics\nmode: due\nrubric: |\n  Be lenient with acronyms.\n  Require the layer.\nsources: manet.pdf, ,slides.md\nunknown: ignored\n---\n# Q\nA\n";
+        let deck = parse_markdown_deck(content).unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("Wireless Networks"));
+        assert_eq!(deck.meta.description.as_deref(), Some("MANET basics"));
+        assert_eq!(deck.meta.default_mode, DeckMode::Due);
+        assert_eq!(
+            deck.meta.rubric.as_deref(),
+            Some("Be lenient with acronyms.\nRequire the layer.")
+        );
+        assert_eq!(deck.meta.sources, vec!["manet.pdf", "slides.md"]);
+        assert_eq!(deck.flashcards.len(), 1);
+    }
+
+    #[test]
+    fn test_folded_block_scalar() {
+        let content = "---\ndescription: >\n  one\n  two\n\n  three\n---\n# Q\nA\n";
+        let deck = parse_markdown_deck(content).unwrap();
+        assert_eq!(deck.meta.description.as_deref(), Some("one two\nthree"));
+    }
+
+    #[test]
+    fn test_front_matter_errors() {
+        let err = parse_markdown_deck("---\ntitle: x\n# Q\nA\n").unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let err = parse_markdown_deck("---\ntitle: x\nmode: cram\n---\n# Q\nA\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("cram"));
+
+        let err = parse_markdown_deck("---\njust text\n---\n# Q\nA\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_deeper_headings_and_code_belong_to_answer() {
+        let content = "## Explain TCP\n\n### Handshake\nSYN, SYN-ACK, ACK\n\n```\n## not a card\n```\n\n## Next\nB\n";
+        let deck = parse_markdown_deck(content).unwrap();
+        assert_eq!(deck.flashcards.len(), 2);
+        assert!(deck.flashcards[0].answer.starts_with("### Handshake"));
+        assert!(deck.flashcards[0].answer.contains("## not a card"));
+        assert_eq!(deck.flashcards[1].question, "Next");
+    }
+
+    #[test]
+    fn test_single_top_heading_is_title() {
+        let deck = parse_markdown_deck("# Networking\n\n## Q1\nA1\n\n## Q2\nA2\n").unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("Networking"));
+        assert_eq!(deck.flashcards.len(), 2);
+        assert_eq!(deck.flashcards[0].question, "Q1");
+    }
+
+    #[test]
+    fn test_trailing_card_metadata() {
+        let content = "## What does AODV stand for?\n\nAd hoc On-Demand Distance Vector\nSource: RFC 3561\n\nTags: routing, #manet\nHint: reactive\nDifficulty: hard\n\n## Q2\nNote: this stays in the answer\n";
+        let deck = parse_markdown_deck(content).unwrap();
+        let card = &deck.flashcards[0];
+        assert_eq!(
+            card.answer,
+            "Ad hoc On-Demand Distance Vector\nSource: RFC 3561"
+        );
+        assert_eq!(card.meta.tags, vec!["routing", "manet"]);
+        assert_eq!(card.meta.hint.as_deref(), Some("reactive"));
+        assert_eq!(card.meta.difficulty.as_deref(), Some("hard"));
+        assert_eq!(card.meta.source, None);
+        assert_eq!(deck.flashcards[1].answer, "Note: this stays in the answer");
+    }
+
+    #[test]
+    fn test_card_errors() {
+        let err = parse_markdown_deck("Just some notes\n").unwrap_err();
+        assert!(err.message.contains("no cards"));
+
+        let err = parse_markdown_deck("## Q1\nA1\n\n## Q2\n\n").unwrap_err();
+        assert_eq!(err.line, 4);
+        assert!(err.message.contains("Q2"));
+    }
+
+    #[test]
+    fn test_crlf_and_bom() {
+        let deck = parse_markdown_deck("\u{feff}---\r\ntitle: T\r\n---\r\n## Q\r\nA\r\n").unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("T"));
+        assert_eq!(deck.flashcards[0].answer, "A");
+    }
+
+    #[test]
+    fn test_write_markdown_deck_round_trips() {
+        let mut first = flashcard(
+            "What does\nAODV stand for?".to_string(),
+            "**Ad hoc On-Demand Distance Vector**\n\n- reactive".to_string(),
+            CardMeta::default(),
+        );
+        first.meta.tags = vec!["routing".to_string(), "manet".to_string()];
+        first.meta.hint = Some("reactive".to_string());
+        let deck = Deck {
+            meta: DeckMeta {
+                title: Some("Wireless".to_string()),
+                description: None,
+                default_mode: DeckMode::Due,
+                rubric: Some("Be lenient.\nRequire the layer.".to_string()),
+                sources: vec!["manet.pdf".to_string(), "notes/routing.md".to_string()],
+            },
+            flashcards: vec![
+                first,
+                flashcard("Q2".to_string(), "A2".to_string(), CardMeta::default()),
+            ],
+        };
+
+        let parsed = parse_markdown_deck(&write_markdown_deck(&deck)).unwrap();
+        assert_eq!(parsed.meta, deck.meta);
+        assert_eq!(parsed.flashcards.len(), 2);
+        assert_eq!(parsed.flashcards[0].question, "What does AODV stand for?");
+        assert_eq!(parsed.flashcards[0].answer, deck.flashcards[0].answer);
+        assert_eq!(parsed.flashcards[0].meta, deck.flashcards[0].meta);
+        assert_eq!(parsed.flashcards[1].answer, "A2");
+    }
+}
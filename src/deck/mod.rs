@@ -0,0 +1,188 @@
+pub mod discovery;
+pub mod markdown;
+pub mod structured;
+
+use crate::csv::load_csv;
+use crate::models::Flashcard;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub use discovery::{DeckEntry, DeckTreeRow, deck_tree_rows, discover_decks};
+pub use markdown::{load_markdown_deck, parse_markdown_deck, write_markdown_deck};
+pub use structured::{StructuredFormat, load_structured_deck, parse_structured_deck};
+
+/// Which quiz Enter starts for a deck in the menu
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeckMode {
+    /// Every card in the deck, shuffled
+    #[default]
+    All,
+    /// Only the cards the scheduler has due today
+    Due,
+}
+
+impl DeckMode {
+    pub const VARIANTS: [DeckMode; 2] = [DeckMode::All, DeckMode::Due];
+
+    /// The name decks use for this mode
+    pub fn name(self) -> &'static str {
+        match self {
+            DeckMode::All => "all",
+            DeckMode::Due => "due",
+        }
+    }
+
+    /// The mode names, each in `quote`s, for error messages: `'all' or 'due'`
+    pub fn expected(quote: char) -> String {
+        let names: Vec<String> = Self::VARIANTS
+            .iter()
+            .map(|m| format!("{quote}{}{quote}", m.name()))
+            .collect();
+        match names.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "all" | "full" | "quiz" => Some(DeckMode::All),
+            "due" | "review" => Some(DeckMode::Due),
+            _ => None,
+        }
+    }
+}
+
+/// Deck-level metadata, declared in Markdown front matter or at the top of a JSON/TOML
+/// deck. CSV decks always use the defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeckMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub default_mode: DeckMode,
+    /// Extra grading instructions passed to the AI evaluator for every card
+    pub rubric: Option<String>,
+    /// Documents the cards were written from, relative to the deck file; passages found
+    /// in them are given to the AI evaluator and chat
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Deck {
+    pub meta: DeckMeta,
+    pub flashcards: Vec<Flashcard>,
+}
+
+/// A deck parse error with the (1-based) line it occurred on
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeckError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DeckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for DeckError {}
+
+/// Whether `path` has an extension of a deck format the app can load.
+pub fn is_deck_file(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref(),
+        Some("csv" | "tsv" | "md" | "json" | "toml")
+    )
+}
+
+/// Check that `name` names a deck file inside a deck folder rather than a path that
+/// could lead out of it.
+pub fn check_deck_name(name: &str) -> Result<(), String> {
+    if matches!(name.trim(), "" | "." | "..") || name.contains(['/', '\\']) {
+        return Err(format!("\"{}\" is not a valid deck name", name));
+    }
+    Ok(())
+}
+
+/// Load a deck file, picking the format from its extension.
+pub fn load_deck(path: &Path) -> io::Result<Deck> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("md") => load_markdown_deck(path),
+        _ if StructuredFormat::for_path(path).is_some() => load_structured_deck(path),
+        _ => Ok(Deck {
+            meta: DeckMeta::default(),
+            flashcards: load_csv(path)?,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_check_deck_name() {
+        assert!(check_deck_name("Networking 101").is_ok());
+        assert!(check_deck_name("v1.2").is_ok());
+        for name in ["", " ", ".", "..", "../x", "../../x", "a/b", "a\\b"] {
+            assert!(check_deck_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_deck_mode_parse() {
+        assert_eq!(DeckMode::parse("Due"), Some(DeckMode::Due));
+        assert_eq!(DeckMode::parse(" all "), Some(DeckMode::All));
+        assert_eq!(DeckMode::parse("cram"), None);
+        for mode in DeckMode::VARIANTS {
+            assert_eq!(DeckMode::parse(mode.name()), Some(mode));
+        }
+        assert_eq!(DeckMode::expected('\''), "'all' or 'due'");
+    }
+
+    #[test]
+    fn test_is_deck_file() {
+        assert!(is_deck_file(Path::new("flashcards/a.csv")));
+        assert!(is_deck_file(Path::new("flashcards/a.TSV")));
+        assert!(is_deck_file(Path::new("flashcards/a.md")));
+        assert!(is_deck_file(Path::new("flashcards/a.json")));
+        assert!(is_deck_file(Path::new("flashcards/a.toml")));
+        assert!(!is_deck_file(Path::new("flashcards/a.txt")));
+        assert!(!is_deck_file(Path::new("flashcards/md")));
+    }
+
+    #[test]
+    fn test_load_deck_dispatches_on_extension() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let csv_path = dir.path().join("deck.csv");
+        let mut file = std::fs::File::create(&csv_path).unwrap();
+        writeln!(file, "What is 2+2?,Four").unwrap();
+        let deck = load_deck(&csv_path).unwrap();
+        assert_eq!(deck.meta, DeckMeta::default());
+        assert_eq!(deck.flashcards.len(), 1);
+
+        let md_path = dir.path().join("deck.md");
+        let mut file = std::fs::File::create(&md_path).unwrap();
+        write!(file, "---\ntitle: Maths\n---\n\n## What is 2+2?\n\nFour\n").unwrap();
+        let deck = load_deck(&md_path).unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("Maths"));
+        assert_eq!(deck.flashcards[0].answer, "Four");
+
+        let toml_path = dir.path().join("deck.toml");
+        std::fs::write(
+            &toml_path,
+            "schema_version = 1\n[[cards]]\nquestion = \"Q\"\nanswers = [\"A\", \"B\"]\n",
+        )
+        .unwrap();
+        let deck = load_deck(&toml_path).unwrap();
+        assert_eq!(deck.flashcards[0].meta.accepted_answers, vec!["B"]);
+    }
+}
@@ -0,0 +1,599 @@
+//! JSON and TOML deck format.
+//!
+//! ```toml
+//! schema_version = 1
+//! title = "Wireless Networks"
+//! mode = "due"
+//! rubric = "Accept answers that name the protocol even without the acronym."
+//! sources = ["notes/manet.pdf"]
+//!
+//! [[cards]]
+//! question = "What does AODV stand for?"
+//! answers = ["Ad hoc On-Demand Distance Vector", "AODV routing"]
+//! grading_notes = "The word 'routing' is optional."
+//! tags = ["routing", "manet"]
+//! media = ["img/aodv.png"]
+//! ```
+//!
+//! The JSON form has the same fields (see `schemas/deck.v1.schema.json`). A card has either
+//! a single `answer` or a list of `answers`, the first of which is shown as the answer and
+//! the rest accepted as equally correct. Unknown fields are rejected so that typos do not
+//! silently drop data. Media paths are relative to the deck file.
+
+use super::{Deck, DeckMeta, DeckMode};
+use crate::csv::flashcard;
+use crate::models::CardMeta;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The newest `schema_version` this build understands
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// JSON Schema describing version 1 of the format
+pub const JSON_SCHEMA: &str = include_str!("../../schemas/deck.v1.schema.json");
+
+const DECK_FIELDS: &[&str] = &[
+    "$schema",
+    "schema_version",
+    "title",
+    "description",
+    "mode",
+    "rubric",
+    "sources",
+    "cards",
+];
+
+const CARD_FIELDS: &[&str] = &[
+    "question",
+    "answer",
+    "answers",
+    "grading_notes",
+    "tags",
+    "hint",
+    "source",
+    "difficulty",
+    "media",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    Json,
+    Toml,
+}
+
+impl StructuredFormat {
+    pub fn for_path(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("json") => Some(StructuredFormat::Json),
+            Some("toml") => Some(StructuredFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A validation finding. `location` is either `line L, column C` for syntax errors or
+/// the path of the offending value, such as `cards[2].answers[0]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+fn error(location: impl Into<String>, message: impl Into<String>) -> Issue {
+    Issue {
+        severity: Severity::Error,
+        location: location.into(),
+        message: message.into(),
+    }
+}
+
+/// Load a JSON or TOML deck. All schema errors are reported together, one per line,
+/// in an `InvalidData` error.
+pub fn load_structured_deck(path: &Path) -> io::Result<Deck> {
+    let format = StructuredFormat::for_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a .json or .toml deck", path.display()),
+        )
+    })?;
+    let content = fs::read_to_string(path)?;
+    parse_structured_deck(&content, format).map_err(|issues| {
+        let lines: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        io::Error::new(io::ErrorKind::InvalidData, lines.join("\n"))
+    })
+}
+
+/// Parse a JSON or TOML deck, returning every schema error found.
+pub fn parse_structured_deck(content: &str, format: StructuredFormat) -> Result<Deck, Vec<Issue>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let value = parse_value(content, format).map_err(|issue| vec![issue])?;
+
+    let mut issues = Vec::new();
+    let deck = read_deck(&value, &mut issues);
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        Err(issues)
+    } else {
+        Ok(deck)
+    }
+}
+
+/// Validate a deck file: schema errors, plus warnings for media files that do not
+/// exist next to the deck.
+pub fn validate_structured_deck(path: &Path) -> io::Result<Vec<Issue>> {
+    let format = StructuredFormat::for_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a .json or .toml deck", path.display()),
+        )
+    })?;
+    let content = fs::read_to_string(path)?;
+    let deck = match parse_structured_deck(&content, format) {
+        Ok(deck) => deck,
+        Err(issues) => return Ok(issues),
+    };
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut issues = Vec::new();
+    for (i, card) in deck.flashcards.iter().enumerate() {
+        for (j, media) in card.meta.media.iter().enumerate() {
+            if !base.join(media).exists() {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    location: format!("cards[{}].media[{}]", i, j),
+                    message: format!("file '{}' not found", media),
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn parse_value(content: &str, format: StructuredFormat) -> Result<Value, Issue> {
+    match format {
+        StructuredFormat::Json => serde_json::from_str(content).map_err(|e| {
+            let message = e.to_string();
+            // serde_json appends " at line L column C"; the location is reported separately
+            let message = message
+                .rsplit_once(" at line ")
+                .map_or(message.as_str(), |(m, _)| m)
+                .to_string();
+            error(format!("line {}, column {}", e.line(), e.column()), message)
+        }),
+        StructuredFormat::Toml => {
+            let value: toml::Value = toml::from_str(content).map_err(|e| {
+                let location = match e.span() {
+                    Some(span) => {
+                        let (line, column) = line_column(content, span.start);
+                        format!("line {}, column {}", line, column)
+                    }
+                    None => "document".to_string(),
+                };
+                error(location, e.message().trim())
+            })?;
+            serde_json::to_value(value).map_err(|e| error("document", e.to_string()))
+        }
+    }
+}
+
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+fn read_deck(value: &Value, issues: &mut Vec<Issue>) -> Deck {
+    let mut deck = Deck::default();
+    let Some(object) = value.as_object() else {
+        issues.push(error(
+            "document",
+            "expected a table/object at the top level",
+        ));
+        return deck;
+    };
+    check_fields(object, DECK_FIELDS, "", issues);
+
+    match object.get("schema_version") {
+        None => issues.push(error("schema_version", "missing required field")),
+        Some(version) => match version.as_u64() {
+            Some(v) if (1..=SCHEMA_VERSION).contains(&v) => {}
+            Some(v) if v > SCHEMA_VERSION => issues.push(error(
+                "schema_version",
+                format!(
+                    "unsupported version {} (this build supports up to {})",
+                    v, SCHEMA_VERSION
+                ),
+            )),
+            _ => issues.push(error("schema_version", "expected a positive integer")),
+        },
+    }
+
+    if let Some(schema) = object.get("$schema")
+        && !schema.is_string()
+    {
+        issues.push(error("$schema", "expected a string"));
+    }
+    deck.meta = DeckMeta {
+        title: optional_string(object, "title", "title", issues),
+        description: optional_string(object, "description", "description", issues),
+        default_mode: match optional_string(object, "mode", "mode", issues) {
+            Some(mode) => DeckMode::parse(&mode).unwrap_or_else(|| {
+                issues.push(error(
+                    "mode",
+                    format!(
+                        "unknown mode '{}' (expected {})",
+                        mode,
+                        DeckMode::expected('\'')
+                    ),
+                ));
+                DeckMode::default()
+            }),
+            None => DeckMode::default(),
+        },
+        rubric: optional_string(object, "rubric", "rubric", issues),
+        sources: string_list(object, "sources", "sources", issues),
+    };
+
+    match object.get("cards") {
+        None => issues.push(error("cards", "missing required field")),
+        Some(Value::Array(cards)) if cards.is_empty() => {
+            issues.push(error("cards", "the deck has no cards"))
+        }
+        Some(Value::Array(cards)) => {
+            for (i, card) in cards.iter().enumerate() {
+                if let Some(flashcard) = read_card(card, &format!("cards[{}]", i), issues) {
+                    deck.flashcards.push(flashcard);
+                }
+            }
+        }
+        Some(_) => issues.push(error("cards", "expected an array of cards")),
+    }
+
+    deck
+}
+
+fn read_card(
+    value: &Value,
+    path: &str,
+    issues: &mut Vec<Issue>,
+) -> Option<crate::models::Flashcard> {
+    let Some(object) = value.as_object() else {
+        issues.push(error(path, "expected a table/object"));
+        return None;
+    };
+    let errors_before = error_count(issues);
+    check_fields(object, CARD_FIELDS, path, issues);
+
+    let field = |name: &str| format!("{}.{}", path, name);
+    let question = optional_string(object, "question", &field("question"), issues);
+    if question.is_none() && !object.contains_key("question") {
+        issues.push(error(field("question"), "missing required field"));
+    }
+
+    let answer = optional_string(object, "answer", &field("answer"), issues);
+    let answers = string_list(object, "answers", &field("answers"), issues);
+    let mut answers = match (answer, object.contains_key("answers")) {
+        (Some(_), true) => {
+            issues.push(error(path, "use either 'answer' or 'answers', not both"));
+            Vec::new()
+        }
+        (Some(answer), false) => vec![answer],
+        (None, true) => {
+            if answers.is_empty() && error_count(issues) == errors_before {
+                issues.push(error(field("answers"), "expected at least one answer"));
+            }
+            answers
+        }
+        (None, false) => {
+            if !object.contains_key("answer") {
+                issues.push(error(path, "missing 'answer' (or 'answers')"));
+            }
+            Vec::new()
+        }
+    };
+
+    let meta = CardMeta {
+        tags: string_list(object, "tags", &field("tags"), issues),
+        hint: optional_string(object, "hint", &field("hint"), issues),
+        source: optional_string(object, "source", &field("source"), issues),
+        difficulty: optional_string(object, "difficulty", &field("difficulty"), issues),
+        accepted_answers: if answers.len() > 1 {
+            answers.split_off(1)
+        } else {
+            Vec::new()
+        },
+        grading_notes: optional_string(object, "grading_notes", &field("grading_notes"), issues),
+        media: string_list(object, "media", &field("media"), issues),
+    };
+
+    if error_count(issues) > errors_before {
+        return None;
+    }
+    Some(flashcard(question?, answers.pop()?, meta))
+}
+
+fn error_count(issues: &[Issue]) -> usize {
+    issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count()
+}
+
+fn check_fields(
+    object: &Map<String, Value>,
+    allowed: &[&str],
+    path: &str,
+    issues: &mut Vec<Issue>,
+) {
+    for key in object.keys() {
+        if !allowed.contains(&key.as_str()) {
+            let location = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            issues.push(error(
+                location,
+                format!("unknown field (expected one of: {})", allowed.join(", ")),
+            ));
+        }
+    }
+}
+
+/// A non-blank string field, trimmed. Absent fields are `None`; blank or non-string
+/// values are reported.
+fn optional_string(
+    object: &Map<String, Value>,
+    key: &str,
+    location: &str,
+    issues: &mut Vec<Issue>,
+) -> Option<String> {
+    match object.get(key)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::String(_) => {
+            issues.push(error(location, "must not be empty"));
+            None
+        }
+        _ => {
+            issues.push(error(location, "expected a string"));
+            None
+        }
+    }
+}
+
+/// An array of non-blank strings. Absent fields are empty.
+fn string_list(
+    object: &Map<String, Value>,
+    key: &str,
+    location: &str,
+    issues: &mut Vec<Issue>,
+) -> Vec<String> {
+    let Some(value) = object.get(key) else {
+        return Vec::new();
+    };
+    let Some(items) = value.as_array() else {
+        issues.push(error(location, "expected an array of strings"));
+        return Vec::new();
+    };
+
+    let mut list = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        match item.as_str().map(str::trim) {
+            Some("") => issues.push(error(format!("{}[{}]", location, i), "must not be empty")),
+            Some(s) => list.push(s.to_string()),
+            None => issues.push(error(format!("{}[{}]", location, i), "expected a string")),
+        }
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(content: &str, format: StructuredFormat) -> Vec<String> {
+        parse_structured_deck(content, format)
+            .unwrap_err()
+            .iter()
+            .map(|i| format!("{}: {}", i.location, i.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_json_deck() {
+        let deck = parse_structured_deck(
+            r#"{
+                "schema_version": 1,
+                "title": "Wireless",
+                "mode": "due",
+                "rubric": "Be lenient",
+                "sources": ["manet.pdf"],
+                "cards": [
+                    {"question": "What does AODV stand for?",
+                     "answers": ["Ad hoc On-Demand Distance Vector", "AODV routing"],
+                     "grading_notes": "Routing is optional",
+                     "tags": ["routing"],
+                     "media": ["img/aodv.png"]},
+                    {"question": "What is 2+2?", "answer": "Four", "hint": "Even"}
+                ]
+            }"#,
+            StructuredFormat::Json,
+        )
+        .unwrap();
+
+        assert_eq!(deck.meta.title.as_deref(), Some("Wireless"));
+        assert_eq!(deck.meta.default_mode, DeckMode::Due);
+        assert_eq!(deck.meta.rubric.as_deref(), Some("Be lenient"));
+        assert_eq!(deck.meta.sources, vec!["manet.pdf"]);
+        assert_eq!(deck.flashcards.len(), 2);
+
+        let card = &deck.flashcards[0];
+        assert_eq!(card.answer, "Ad hoc On-Demand Distance Vector");
+        assert_eq!(card.meta.accepted_answers, vec!["AODV routing"]);
+        assert_eq!(
+            card.meta.grading_notes.as_deref(),
+            Some("Routing is optional")
+        );
+        assert_eq!(card.meta.tags, vec!["routing"]);
+        assert_eq!(card.meta.media, vec!["img/aodv.png"]);
+        assert_eq!(deck.flashcards[1].answer, "Four");
+        assert!(deck.flashcards[1].meta.accepted_answers.is_empty());
+    }
+
+    #[test]
+    fn test_parse_toml_deck() {
+        let deck = parse_structured_deck(
+            r#"schema_version = 1
+
+[[cards]]
+question = "Q1"
+answers = ["A1", "A1b"]
+
+[[cards]]
+question = "Q2"
+answer = """
+Multi
+line"""
+"#,
+            StructuredFormat::Toml,
+        )
+        .unwrap();
+
+        assert_eq!(deck.meta, DeckMeta::default());
+        assert_eq!(deck.flashcards[0].meta.accepted_answers, vec!["A1b"]);
+        assert_eq!(deck.flashcards[1].answer, "Multi\nline");
+    }
+
+    #[test]
+    fn test_schema_errors_have_paths() {
+        let errors = errors(
+            r#"{"schema_version": 1, "titel": "x", "cards": [
+                {"question": "Q1", "answer": "A1"},
+                {"qestion": "Q2", "answer": "A2"},
+                {"question": "Q3", "answer": "A3", "answers": ["B"]},
+                {"question": "Q4", "answers": []},
+                {"question": "Q5", "answer": "A5", "tags": ["ok", 3]}
+            ]}"#,
+            StructuredFormat::Json,
+        );
+
+        assert!(errors[0].starts_with("titel: unknown field"));
+        assert!(errors[1].starts_with("cards[1].qestion: unknown field"));
+        assert_eq!(errors[2], "cards[1].question: missing required field");
+        assert_eq!(
+            errors[3],
+            "cards[2]: use either 'answer' or 'answers', not both"
+        );
+        assert_eq!(errors[4], "cards[3].answers: expected at least one answer");
+        assert_eq!(errors[5], "cards[4].tags[1]: expected a string");
+        assert_eq!(errors.len(), 6);
+    }
+
+    #[test]
+    fn test_schema_version_is_checked() {
+        assert_eq!(
+            errors(
+                r#"{"cards": [{"question": "Q", "answer": "A"}]}"#,
+                StructuredFormat::Json
+            ),
+            vec!["schema_version: missing required field"]
+        );
+        assert_eq!(
+            errors(
+                "schema_version = 2\n[[cards]]\nquestion = \"Q\"\nanswer = \"A\"\n",
+                StructuredFormat::Toml
+            ),
+            vec!["schema_version: unsupported version 2 (this build supports up to 1)"]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_have_line_and_column() {
+        let json = errors(
+            "{\n  \"schema_version\": 1,\n  \"cards\": [}\n",
+            StructuredFormat::Json,
+        );
+        assert_eq!(json.len(), 1);
+        assert!(json[0].starts_with("line 3, column 13: "), "{}", json[0]);
+
+        let toml = errors("schema_version = 1\ncards = [\n", StructuredFormat::Toml);
+        assert_eq!(toml.len(), 1);
+        assert!(toml[0].starts_with("line 3, column 1: "), "{}", toml[0]);
+    }
+
+    #[test]
+    fn test_validate_warns_about_missing_media() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("present.png"), b"").unwrap();
+        let path = dir.path().join("deck.json");
+        std::fs::write(
+            &path,
+            r#"{"schema_version": 1, "cards": [
+                {"question": "Q", "answer": "A", "media": ["present.png", "missing.png"]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let issues = validate_structured_deck(&path).unwrap();
+        assert_eq!(
+            issues,
+            vec![Issue {
+                severity: Severity::Warning,
+                location: "cards[0].media[1]".to_string(),
+                message: "file 'missing.png' not found".to_string(),
+            }]
+        );
+        assert!(load_structured_deck(&path).is_ok());
+    }
+
+    #[test]
+    fn test_published_schema_matches_fields() {
+        let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+        let deck_fields: Vec<&str> = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let card_fields: Vec<&str> = schema["$defs"]["card"]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+
+        for field in DECK_FIELDS {
+            assert!(deck_fields.contains(field), "{} missing from schema", field);
+        }
+        for field in CARD_FIELDS {
+            assert!(card_fields.contains(field), "{} missing from schema", field);
+        }
+        assert_eq!(deck_fields.len(), DECK_FIELDS.len());
+        assert_eq!(card_fields.len(), CARD_FIELDS.len());
+    }
+}
@@ -0,0 +1,143 @@
+use super::extract::Document;
+
+/// Size of a passage sent to the model, in characters
+pub const CHUNK_CHARS: usize = 6000;
+/// How much of the end of a passage the next one repeats
+pub const CHUNK_OVERLAP: usize = 500;
+
+/// A passage of a document, small enough for one generation request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    /// Where the passage is in the document, see [`Document::location`]
+    pub location: String,
+}
+
+/// Split a document into passages of at most `size` characters. Each passage ends at a
+/// paragraph break, else after a sentence, else at a space, if there is one in its
+/// second half; the next one starts `overlap` characters before that end (at the start
+/// of a word), so that an idea cut by one boundary is whole in one of the two passages.
+pub fn chunk_document(document: &Document, size: usize, overlap: usize) -> Vec<Chunk> {
+    let text = &document.text;
+    let size = size.max(2);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = chars_after(text, start, size);
+        if end < text.len() {
+            end = break_before(text, chars_after(text, start, size / 2), end);
+        }
+        if end <= start {
+            end = chars_after(text, start, 1);
+        }
+
+        let passage = &text[start..end];
+        let trimmed = passage.trim();
+        if !trimmed.is_empty() {
+            let first = start + (passage.len() - passage.trim_start().len());
+            chunks.push(Chunk {
+                text: trimmed.to_string(),
+                location: document.location(first, first + trimmed.len()),
+            });
+        }
+        if end >= text.len() {
+            break;
+        }
+
+        let mut next = chars_before(text, end, overlap).max(chars_after(text, start, 1));
+        next = match text[next..end]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+        {
+            Some((i, c)) => next + i + c.len_utf8(),
+            None => end,
+        };
+        start = next;
+    }
+    chunks
+}
+
+/// The best place in `text[min..max]` to end a passage: after the last paragraph
+/// break, sentence end or whitespace, or `max` if there is none.
+fn break_before(text: &str, min: usize, max: usize) -> usize {
+    let window = &text[min..max];
+    if let Some(i) = window.rfind("\n\n") {
+        return min + i + 2;
+    }
+    let sentence_end = window
+        .char_indices()
+        .zip(window.chars().skip(1))
+        .filter(|((_, c), next)| matches!(c, '.' | '?' | '!') && next.is_whitespace())
+        .map(|((i, _), _)| i + 1)
+        .last();
+    if let Some(i) = sentence_end {
+        return min + i;
+    }
+    match window.rfind(char::is_whitespace) {
+        Some(i) if i > 0 => min + i,
+        _ => max,
+    }
+}
+
+/// The byte offset `chars` characters after `from`, or the end of `text`.
+fn chars_after(text: &str, from: usize, chars: usize) -> usize {
+    text[from..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| from + i)
+}
+
+/// The byte offset `chars` characters before `to`, or the start of `text`.
+fn chars_before(text: &str, to: usize, chars: usize) -> usize {
+    match chars {
+        0 => to,
+        _ => text[..to]
+            .char_indices()
+            .rev()
+            .nth(chars - 1)
+            .map_or(0, |(i, _)| i),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_overlap_and_end_at_breaks() {
+        let paragraph = "AODV finds routes on demand. DSR caches the routes it learns.";
+        let text = [paragraph; 6].join("\n\n");
+        let document = Document::from_text("notes.txt", &text);
+        let chunks = chunk_document(&document, 150, 40);
+
+        assert!(chunks.len() >= 3);
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= 150, "{:?}", chunk.text);
+            assert!(chunk.text.ends_with('.'), "{:?}", chunk.text);
+        }
+        // Each passage repeats the end of the one before
+        for pair in chunks.windows(2) {
+            let tail = pair[0].text.split_whitespace().last().unwrap();
+            assert!(pair[1].text.contains(tail), "{:?}", pair);
+        }
+        assert_eq!(chunks[0].location, "lines 1-3");
+        assert!(chunks.last().unwrap().location.ends_with("-11"));
+
+        let whole = chunk_document(&document, 10_000, 500);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].text, text);
+    }
+
+    #[test]
+    fn test_chunks_of_unbroken_text() {
+        let document = Document::from_text("x.txt", &"é".repeat(40));
+        let chunks = chunk_document(&document, 15, 4);
+        // Sizes are in characters, not bytes
+        assert!(chunks
+            .iter()
+            .all(|c| !c.text.is_empty() && c.text.chars().count() <= 15));
+        assert_eq!(chunks[0].text.chars().count(), 15);
+        let covered: usize = chunks.iter().map(|c| c.text.chars().count()).sum();
+        assert!(covered >= 40);
+    }
+}
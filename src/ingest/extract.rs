@@ -0,0 +1,222 @@
+use std::any::Any;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+/// The text of a document and what its passages are cited by
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    /// File name, used in citations
+    pub name: String,
+    pub text: String,
+    /// Byte offsets in `text` where each page starts, for PDFs. Empty for text files,
+    /// whose passages are cited by line.
+    pub page_starts: Vec<usize>,
+}
+
+impl Document {
+    /// A document cited by line.
+    pub fn from_text(name: &str, text: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            text: text.replace("\r\n", "\n"),
+            page_starts: Vec::new(),
+        }
+    }
+
+    /// A document cited by page, with blank lines within each page collapsed.
+    pub fn from_pages(name: &str, pages: &[String]) -> Self {
+        let mut text = String::new();
+        let mut page_starts = Vec::new();
+        for page in pages {
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            page_starts.push(text.len());
+            text.push_str(&tidy_page(page));
+        }
+        Self {
+            name: name.to_string(),
+            text,
+            page_starts,
+        }
+    }
+
+    /// Where `text[start..end]` is: `p. 3`, `pp. 3-4` or `lines 10-52`.
+    pub fn location(&self, start: usize, end: usize) -> String {
+        let last = end.max(start + 1) - 1;
+        if self.page_starts.is_empty() {
+            let bytes = &self.text.as_bytes()[..end.min(self.text.len())];
+            let line = |offset: usize| {
+                bytes[..offset.min(bytes.len())]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count()
+                    + 1
+            };
+            return match (line(start), line(last)) {
+                (first, last) if first == last => format!("line {}", first),
+                (first, last) => format!("lines {}-{}", first, last),
+            };
+        }
+        let page = |offset: usize| self.page_starts.partition_point(|&p| p <= offset).max(1);
+        match (page(start), page(last)) {
+            (first, last) if first == last => format!("p. {}", first),
+            (first, last) => format!("pp. {}-{}", first, last),
+        }
+    }
+}
+
+/// Trim the ends of lines and keep at most one blank line between paragraphs.
+fn tidy_page(page: &str) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+    for line in page.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+/// Whether cards can be generated from `path`: plain text, Markdown or PDF.
+pub fn is_document_file(path: &Path) -> bool {
+    matches!(
+        extension(path).as_deref(),
+        Some("txt" | "text" | "md" | "markdown" | "pdf")
+    )
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+/// Read the text of a plain text, Markdown or PDF file.
+pub fn read_document(path: &Path) -> Result<Document, String> {
+    if !is_document_file(path) {
+        return Err(format!(
+            "{} is not a text, Markdown or PDF file",
+            path.display()
+        ));
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let bytes = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+    let document = if extension(path).as_deref() == Some("pdf") {
+        // The PDF reader panics on some malformed files
+        let pages = panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(&bytes))
+            .map_err(panic_message)
+            .and_then(|pages| pages.map_err(|e| e.to_string()))
+            .map_err(|e| format!("Cannot read the text of {}: {}", path.display(), e))?;
+        Document::from_pages(&name, &pages)
+    } else {
+        Document::from_text(&name, &String::from_utf8_lossy(&bytes))
+    };
+
+    if document.text.trim().is_empty() {
+        return Err(format!(
+            "{} has no text (a scanned PDF needs OCR first)",
+            path.display()
+        ));
+    }
+    Ok(document)
+}
+
+/// [`read_document`] on a blocking thread, so that a large PDF does not hold up the
+/// caller's runtime.
+pub async fn read_document_in_background(path: PathBuf) -> Result<Document, String> {
+    let shown = path.display().to_string();
+    tokio::task::spawn_blocking(move || read_document(&path))
+        .await
+        .unwrap_or_else(|e| Err(format!("Cannot read {}: {}", shown, e)))
+}
+
+/// What a panic caught in the PDF reader said.
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "no message".to_string(),
+        },
+    };
+    format!("the PDF reader failed ({})", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations_by_line_and_page() {
+        let notes = Document::from_text(
+            "notes.md",
+            "# MANETs\r\n\r\nAODV finds routes.\r\nDSR caches them.\r\n",
+        );
+        let aodv = notes.text.find("AODV").unwrap();
+        assert_eq!(notes.location(aodv, aodv + 4), "line 3");
+        assert_eq!(notes.location(0, notes.text.len()), "lines 1-4");
+
+        let slides = Document::from_pages(
+            "slides.pdf",
+            &["Routing\n\n\n\nAODV   \n".to_string(), "DSR\n".to_string()],
+        );
+        assert_eq!(slides.text, "Routing\n\nAODV\n\nDSR");
+        assert_eq!(slides.page_starts, vec![0, 15]);
+        assert_eq!(slides.location(0, 7), "p. 1");
+        assert_eq!(slides.location(9, slides.text.len()), "pp. 1-2");
+    }
+
+    #[test]
+    fn test_read_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "AODV finds routes on demand.\n").unwrap();
+        let document = read_document(&path).unwrap();
+        assert_eq!(document.name, "notes.txt");
+        assert!(document.page_starts.is_empty());
+
+        fs::write(dir.path().join("empty.md"), "\n  \n").unwrap();
+        let error = read_document(&dir.path().join("empty.md")).unwrap_err();
+        assert!(error.contains("has no text"), "{}", error);
+        let error = read_document(&dir.path().join("broken.pdf")).unwrap_err();
+        assert!(error.starts_with("Cannot read"), "{}", error);
+        assert!(read_document(&dir.path().join("deck.csv")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_pdf_by_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manet.pdf");
+        fs::write(&path, include_bytes!("testdata/manet.pdf")).unwrap();
+
+        let document = read_document_in_background(path).await.unwrap();
+        assert_eq!(document.name, "manet.pdf");
+        assert_eq!(document.page_starts.len(), 2);
+        let aodv = document.text.find("AODV finds routes on demand.").unwrap();
+        let dsr = document.text.find("DSR caches the routes").unwrap();
+        assert_eq!(document.location(aodv, aodv + 4), "p. 1");
+        assert_eq!(document.location(dsr, dsr + 3), "p. 2");
+        assert_eq!(document.location(aodv, dsr + 3), "pp. 1-2");
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let panic = panic::catch_unwind(|| panic!("bad {}", "xref")).unwrap_err();
+        assert_eq!(panic_message(panic), "the PDF reader failed (bad xref)");
+        let panic = panic::catch_unwind(|| panic!("no font")).unwrap_err();
+        assert_eq!(panic_message(panic), "the PDF reader failed (no font)");
+    }
+}
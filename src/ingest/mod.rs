@@ -0,0 +1,196 @@
+//! Proposed decks from documents. A plain text, Markdown or PDF file is read and cut
+//! into overlapping passages; the AI worker writes cards from each passage, citing the
+//! words it used; the review screen keeps, edits or drops each card before the accepted
+//! ones are written as a Markdown deck. The same passages, cut smaller, are indexed for
+//! [`retrieval`] when a deck names its source documents.
+
+pub mod chunk;
+pub mod extract;
+pub mod retrieval;
+pub mod review;
+
+use crate::ai::generator::GeneratedCard;
+use crate::csv::flashcard;
+use crate::deck::{check_deck_name, write_markdown_deck, Deck, DeckMeta};
+use crate::models::CardMeta;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub use chunk::{chunk_document, Chunk, CHUNK_CHARS, CHUNK_OVERLAP};
+pub use extract::{is_document_file, read_document, read_document_in_background, Document};
+pub use retrieval::{index_sources, resolve_sources, retrieve_passages, PASSAGES_PER_CARD};
+pub use review::{CardDecision, GenerationReview, ReviewAction, ReviewCard, ReviewStage};
+
+/// Cards asked for from each passage
+pub const CARDS_PER_CHUNK: usize = 5;
+
+/// Write the cards as a Markdown deck `<deck_name>.md` in `dir` that names `source` as its
+/// source document, each card with its citation of the document as the card's source.
+/// The source is named relative to `dir`, so that the two can be moved together.
+/// An existing deck file is never overwritten.
+pub fn write_generated_deck(
+    dir: &Path,
+    deck_name: &str,
+    source: &Path,
+    cards: &[GeneratedCard],
+) -> Result<PathBuf, String> {
+    check_deck_name(deck_name)?;
+    let path = dir.join(format!("{}.md", deck_name));
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    // Both sides canonical, so that a symlink on one side only cannot skew the relative
+    // path; a source that cannot be resolved is named by its absolute path
+    let absolute = std::path::absolute(source).unwrap_or_else(|_| source.to_path_buf());
+    let source_name = match (fs::canonicalize(dir), fs::canonicalize(source)) {
+        (Ok(dir), Ok(source)) => relative_path(&source, &dir).unwrap_or(source),
+        _ => absolute,
+    };
+    let document = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let deck = Deck {
+        meta: DeckMeta {
+            title: Some(deck_name.to_string()),
+            description: Some(format!("Generated from {}", document)),
+            sources: vec![source_name.to_string_lossy().to_string()],
+            ..Default::default()
+        },
+        flashcards: cards
+            .iter()
+            .map(|card| {
+                let meta = CardMeta {
+                    source: Some(card.citation(&document)),
+                    ..Default::default()
+                };
+                flashcard(card.question.clone(), card.answer.clone(), meta)
+            })
+            .collect(),
+    };
+    fs::write(&path, write_markdown_deck(&deck))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// `path` relative to the folder `base`, both absolute, going up with `..` where needed.
+/// `None` when they have no root in common, such as on two Windows drives.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let mut relative: PathBuf = base[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    relative.extend(&path[common..]);
+    Some(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_generated_deck() {
+        let card = GeneratedCard {
+            question: "What does AODV stand for?".to_string(),
+            answer: "Ad hoc On-Demand Distance Vector".to_string(),
+            quote: "AODV (Ad hoc On-Demand Distance Vector)".to_string(),
+            location: "p. 4".to_string(),
+            quote_verified: true,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let decks = dir.path().join("decks");
+        let source = dir.path().join("courses/manet.pdf");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "").unwrap();
+        let path =
+            write_generated_deck(&decks, "manet", &source, std::slice::from_ref(&card)).unwrap();
+
+        let deck = crate::deck::load_deck(&path).unwrap();
+        assert_eq!(deck.meta.title.as_deref(), Some("manet"));
+        assert_eq!(deck.meta.sources, vec!["../courses/manet.pdf"]);
+        assert_eq!(
+            retrieval::resolve_sources(&path, &deck.meta.sources),
+            vec![fs::canonicalize(&decks)
+                .unwrap()
+                .join("../courses/manet.pdf")]
+        );
+        assert_eq!(deck.flashcards.len(), 1);
+        assert_eq!(deck.flashcards[0].question, card.question);
+        assert_eq!(deck.flashcards[0].answer, card.answer);
+        assert_eq!(
+            deck.flashcards[0].meta.source.as_deref(),
+            Some("manet.pdf, p. 4: \"AODV (Ad hoc On-Demand Distance Vector)\"")
+        );
+
+        assert!(write_generated_deck(
+            &decks,
+            "manet",
+            Path::new("manet.pdf"),
+            std::slice::from_ref(&card)
+        )
+        .is_err());
+        for name in ["", "..", "../manet", "a/b", "a\\b"] {
+            let error = write_generated_deck(&decks, name, &source, std::slice::from_ref(&card))
+                .unwrap_err();
+            assert!(error.contains("not a valid deck name"), "{}", error);
+        }
+
+        // A source that no longer exists keeps its absolute path
+        let missing = dir.path().join("gone.pdf");
+        let path =
+            write_generated_deck(&decks, "gone", &missing, std::slice::from_ref(&card)).unwrap();
+        let deck = crate::deck::load_deck(&path).unwrap();
+        assert_eq!(
+            deck.meta.sources,
+            vec![std::path::absolute(&missing).unwrap().to_string_lossy()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_generated_deck_resolves_symlinked_source() {
+        let card = GeneratedCard {
+            question: "What does AODV stand for?".to_string(),
+            answer: "Ad hoc On-Demand Distance Vector".to_string(),
+            quote: "AODV".to_string(),
+            location: "p. 4".to_string(),
+            quote_verified: true,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let decks = dir.path().join("decks");
+        fs::write(dir.path().join("manet.pdf"), "").unwrap();
+        // The source is reached through a link back to the folder it is in
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("link")).unwrap();
+        let source = dir.path().join("link/manet.pdf");
+
+        let path = write_generated_deck(&decks, "manet", &source, &[card]).unwrap();
+        let deck = crate::deck::load_deck(&path).unwrap();
+        assert_eq!(deck.meta.sources, vec!["../manet.pdf"]);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let relative = |path: &str, base: &str| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(
+            relative("/home/me/notes/manet.pdf", "/home/me/decks"),
+            Some(PathBuf::from("../notes/manet.pdf"))
+        );
+        assert_eq!(
+            relative("/home/me/decks/manet.pdf", "/home/me/decks"),
+            Some(PathBuf::from("manet.pdf"))
+        );
+        assert_eq!(
+            relative("/srv/manet.pdf", "/home/me/decks"),
+            Some(PathBuf::from("../../../srv/manet.pdf"))
+        );
+        assert_eq!(relative("notes/manet.pdf", "/home/me/decks"), None);
+    }
+}
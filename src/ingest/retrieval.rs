@@ -0,0 +1,195 @@
+//! Passages of a deck's source documents for grading and chat. The documents named in
+//! the deck's `sources` are cut into short passages and kept in the app database's
+//! full-text index (see [`crate::db::sources`]); the passages that best match a card,
+//! ranked by BM25, go into its evaluation and chat prompts.
+
+use super::chunk::chunk_document;
+use super::extract::read_document;
+use crate::ai::SourcePassage;
+use crate::config::expand_home;
+use crate::db::sources;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Size of an indexed passage, in characters
+pub const PASSAGE_CHARS: usize = 1200;
+/// How much of the end of a passage the next one repeats
+pub const PASSAGE_OVERLAP: usize = 200;
+/// Passages given to the model for each card
+pub const PASSAGES_PER_CARD: usize = 3;
+
+/// Words too common to tell passages apart
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "can", "do", "does", "for", "from", "how",
+    "in", "is", "it", "its", "of", "on", "or", "that", "the", "this", "to", "was", "what", "when",
+    "where", "which", "who", "why", "with",
+];
+
+/// The paths of a deck's `sources`, which are relative to the folder of the deck file.
+pub fn resolve_sources(deck_path: &Path, sources: &[String]) -> Vec<PathBuf> {
+    let dir = deck_path.parent().unwrap_or(Path::new(""));
+    sources
+        .iter()
+        .map(|source| dir.join(expand_home(Path::new(source))))
+        .collect()
+}
+
+/// Make sure each of the deck's documents is in the index, reading again those whose
+/// size or modification time changed since they were indexed, and drop the documents no
+/// deck names any more. Returns the ids of the documents in the index and a message for
+/// each one that could not be read.
+pub fn index_sources(
+    conn: &Connection,
+    deck_name: &str,
+    paths: &[PathBuf],
+) -> (Vec<i64>, Vec<String>) {
+    let mut ids = Vec::new();
+    let mut problems = Vec::new();
+    for path in paths {
+        match index_source(conn, path) {
+            Ok(id) => ids.push(id),
+            Err(e) => problems.push(e),
+        }
+    }
+    if let Err(e) = sources::set_deck_documents(conn, deck_name, &ids) {
+        problems.push(format!("Cannot update the source index: {}", e));
+    }
+    (ids, problems)
+}
+
+fn index_source(conn: &Connection, path: &Path) -> Result<i64, String> {
+    let path =
+        fs::canonicalize(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let metadata =
+        fs::metadata(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let modified_at = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let key = path.to_string_lossy();
+
+    let unchanged = sources::find_document(conn, &key)
+        .map_err(|e| e.to_string())?
+        .filter(|d| d.size == metadata.len() && d.modified_at == modified_at);
+    if let Some(document) = unchanged {
+        return Ok(document.id);
+    }
+
+    let document = read_document(&path)?;
+    let passages: Vec<(String, String)> = chunk_document(&document, PASSAGE_CHARS, PASSAGE_OVERLAP)
+        .into_iter()
+        .map(|chunk| (chunk.location, chunk.text))
+        .collect();
+    sources::replace_document(
+        conn,
+        &key,
+        &document.name,
+        metadata.len(),
+        modified_at,
+        &passages,
+    )
+    .map_err(|e| format!("Cannot index {}: {}", path.display(), e))
+}
+
+/// An FTS5 query matching passages with any of the distinctive words of `text`.
+fn match_query(text: &str) -> String {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .filter(|word| seen.insert(word.clone()))
+        .map(|word| format!("\"{}\"", word))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+/// The passages of the given documents that best match a card, best first.
+pub fn retrieve_passages(
+    conn: &Connection,
+    document_ids: &[i64],
+    question: &str,
+    answer: &str,
+    limit: usize,
+) -> rusqlite::Result<Vec<SourcePassage>> {
+    let query = match_query(&format!("{} {}", question, answer));
+    Ok(sources::search_passages(conn, document_ids, &query, limit)?
+        .into_iter()
+        .map(|hit| SourcePassage {
+            document: hit.document,
+            location: hit.location,
+            text: hit.text,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations_for_test;
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(
+            match_query("What does AODV stand for? AODV's routes"),
+            "\"aodv\" OR \"stand\" OR \"routes\""
+        );
+        assert_eq!(match_query("What is it?"), "");
+    }
+
+    #[test]
+    fn test_index_and_retrieve_sources() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations_for_test(&mut conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("notes")).unwrap();
+        fs::write(
+            dir.path().join("notes/manet.md"),
+            "# Routing\n\nAODV discovers routes on demand.\n\nOLSR uses multipoint relays.\n",
+        )
+        .unwrap();
+
+        let deck = dir.path().join("manet.md");
+        let paths = resolve_sources(
+            &deck,
+            &["notes/manet.md".to_string(), "missing.pdf".to_string()],
+        );
+        assert_eq!(paths[0], dir.path().join("notes/manet.md"));
+
+        let (ids, problems) = index_sources(&conn, "manet", &paths);
+        assert_eq!(ids.len(), 1);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("missing.pdf"), "{:?}", problems);
+        // An unchanged document keeps its entry
+        assert_eq!(index_sources(&conn, "manet", &paths).0, ids);
+
+        let passages = retrieve_passages(
+            &conn,
+            &ids,
+            "Which protocol uses multipoint relays?",
+            "OLSR",
+            3,
+        )
+        .unwrap();
+        assert_eq!(passages.len(), 1);
+        assert_eq!(passages[0].document, "manet.md");
+        assert_eq!(passages[0].location, "lines 1-5");
+        assert!(
+            retrieve_passages(&conn, &ids, "What is mitosis?", "Cell division", 3)
+                .unwrap()
+                .is_empty()
+        );
+
+        // Once the deck stops naming the document, it leaves the index
+        assert!(index_sources(&conn, "manet", &[]).0.is_empty());
+        assert!(
+            sources::find_document(&conn, &paths[0].canonicalize().unwrap().to_string_lossy())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
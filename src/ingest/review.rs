@@ -0,0 +1,428 @@
+//! The state of the document review screen: the path of the document is typed in, then
+//! cards arrive passage by passage while each one is accepted, edited or rejected.
+
+use crate::ai::generator::GeneratedCard;
+use crate::models::{AiResponse, RequestId};
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardDecision {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewCard {
+    pub card: GeneratedCard,
+    pub decision: CardDecision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStage {
+    /// Typing the path of the document
+    ChoosingFile,
+    /// The document is being read
+    Reading,
+    /// Cards are still arriving
+    Generating,
+    /// Every passage has been processed, or generation stopped
+    Done,
+}
+
+/// What is being edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditField {
+    Question,
+    Answer,
+    DeckName,
+}
+
+/// What the app should do after the review screen handled a key
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewAction {
+    None,
+    /// Read this document and start generating
+    Generate(PathBuf),
+    /// Write the accepted cards as a deck
+    Save,
+    /// Leave, dropping the cards
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationReview {
+    pub stage: ReviewStage,
+    /// The path typed on the first screen
+    pub path_input: String,
+    /// Where the document was read from, named as the source of the deck
+    pub source: PathBuf,
+    /// File name of the document, cited by the cards
+    pub document: String,
+    /// Name of the deck file to write, without extension
+    pub deck_name: String,
+    pub request_id: Option<RequestId>,
+    pub chunks_done: usize,
+    pub chunks_total: usize,
+    pub failed_chunks: usize,
+    pub cards: Vec<ReviewCard>,
+    /// Index into `cards`
+    pub selected: usize,
+    /// The field being edited and its text so far
+    pub editing: Option<(EditField, String)>,
+    /// The last problem or notice to show
+    pub message: Option<String>,
+}
+
+impl Default for GenerationReview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A question with case, punctuation and spacing ignored, to spot the same card written
+/// from two overlapping passages.
+fn question_key(question: &str) -> String {
+    question
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl GenerationReview {
+    pub fn new() -> Self {
+        Self {
+            stage: ReviewStage::ChoosingFile,
+            path_input: String::new(),
+            source: PathBuf::new(),
+            document: String::new(),
+            deck_name: String::new(),
+            request_id: None,
+            chunks_done: 0,
+            chunks_total: 0,
+            failed_chunks: 0,
+            cards: Vec::new(),
+            selected: 0,
+            editing: None,
+            message: None,
+        }
+    }
+
+    /// Wait for the document typed in to be read; its cards will be asked for as
+    /// `request_id`.
+    pub fn read(&mut self, request_id: RequestId) {
+        self.stage = ReviewStage::Reading;
+        self.request_id = Some(request_id);
+        self.message = None;
+    }
+
+    /// Whether the document being read is the one for `request_id`.
+    pub fn is_reading(&self, request_id: RequestId) -> bool {
+        self.stage == ReviewStage::Reading && self.request_id == Some(request_id)
+    }
+
+    /// Go back to typing the path, showing why the document could not be used.
+    pub fn fail(&mut self, message: String) {
+        self.stage = ReviewStage::ChoosingFile;
+        self.request_id = None;
+        self.message = Some(message);
+    }
+
+    /// Wait for the cards of `request_id`, written from `chunks` passages of the document
+    /// at `source`.
+    pub fn start(&mut self, source: &Path, deck_name: &str, chunks: usize, request_id: RequestId) {
+        self.stage = ReviewStage::Generating;
+        self.source = source.to_path_buf();
+        self.document = source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.deck_name = deck_name.to_string();
+        self.request_id = Some(request_id);
+        self.chunks_total = chunks;
+        self.message = None;
+    }
+
+    /// Whether the request still has passages to go.
+    pub fn is_generating(&self) -> bool {
+        self.stage == ReviewStage::Generating
+    }
+
+    pub fn accepted_cards(&self) -> Vec<GeneratedCard> {
+        self.cards
+            .iter()
+            .filter(|c| c.decision == CardDecision::Accepted)
+            .map(|c| c.card.clone())
+            .collect()
+    }
+
+    pub fn count(&self, decision: CardDecision) -> usize {
+        self.cards.iter().filter(|c| c.decision == decision).count()
+    }
+
+    /// Add the cards of one passage, skipping those already proposed. Responses to other
+    /// requests are ignored.
+    pub fn handle_response(&mut self, response: AiResponse) {
+        let AiResponse::GeneratedCards {
+            request_id,
+            chunk,
+            chunks,
+            result,
+        } = response
+        else {
+            return;
+        };
+        if Some(request_id) != self.request_id {
+            return;
+        }
+
+        self.chunks_done = chunk + 1;
+        match result {
+            Ok(cards) => {
+                let mut seen: HashSet<String> = self
+                    .cards
+                    .iter()
+                    .map(|c| question_key(&c.card.question))
+                    .collect();
+                for card in cards {
+                    if seen.insert(question_key(&card.question)) {
+                        self.cards.push(ReviewCard {
+                            card,
+                            decision: CardDecision::Pending,
+                        });
+                    }
+                }
+            }
+            Err(error) if error.is_permanent() => {
+                self.failed_chunks += chunks - chunk;
+                self.stage = ReviewStage::Done;
+                self.message = Some(format!("Generation stopped: {}", error));
+                return;
+            }
+            Err(error) => {
+                self.failed_chunks += 1;
+                self.message = Some(format!("Passage {} skipped: {}", chunk + 1, error));
+            }
+        }
+        if self.chunks_done >= chunks {
+            self.stage = ReviewStage::Done;
+        }
+    }
+
+    fn decide(&mut self, decision: CardDecision) {
+        if let Some(card) = self.cards.get_mut(self.selected) {
+            card.decision = decision;
+            self.selected = (self.selected + 1).min(self.cards.len() - 1);
+        }
+    }
+
+    /// Enter finishes the question and moves on to the answer, which Enter finishes too,
+    /// accepting the edited card. Esc abandons the field being edited.
+    fn handle_edit_key(&mut self, key: KeyEvent) {
+        let Some((field, text)) = &mut self.editing else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                let field = *field;
+                let text = text.trim().to_string();
+                self.editing = None;
+                if text.is_empty() {
+                    self.message = Some("The text cannot be empty".to_string());
+                    return;
+                }
+                self.message = None;
+                match (field, self.cards.get_mut(self.selected)) {
+                    (EditField::DeckName, _) => self.deck_name = text,
+                    (EditField::Question, Some(card)) => {
+                        card.card.question = text;
+                        self.editing = Some((EditField::Answer, card.card.answer.clone()));
+                    }
+                    (EditField::Answer, Some(card)) => {
+                        card.card.answer = text;
+                        card.decision = CardDecision::Accepted;
+                    }
+                    _ => {}
+                }
+            }
+            KeyCode::Esc => self.editing = None,
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) => text.push(c),
+            _ => {}
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> ReviewAction {
+        if self.stage == ReviewStage::ChoosingFile {
+            match key.code {
+                KeyCode::Enter if !self.path_input.trim().is_empty() => {
+                    return ReviewAction::Generate(PathBuf::from(self.path_input.trim()));
+                }
+                KeyCode::Esc => return ReviewAction::Close,
+                KeyCode::Backspace => {
+                    self.path_input.pop();
+                }
+                KeyCode::Char(c) => self.path_input.push(c),
+                _ => {}
+            }
+            return ReviewAction::None;
+        }
+        if self.stage == ReviewStage::Reading {
+            return match key.code {
+                KeyCode::Esc => ReviewAction::Close,
+                _ => ReviewAction::None,
+            };
+        }
+        if self.editing.is_some() {
+            self.handle_edit_key(key);
+            return ReviewAction::None;
+        }
+
+        match key.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.cards.len().saturating_sub(1));
+            }
+            KeyCode::Char('a') => self.decide(CardDecision::Accepted),
+            KeyCode::Char('x') => self.decide(CardDecision::Rejected),
+            KeyCode::Char('A') => {
+                for card in &mut self.cards {
+                    if card.decision == CardDecision::Pending {
+                        card.decision = CardDecision::Accepted;
+                    }
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('e') => {
+                if let Some(card) = self.cards.get(self.selected) {
+                    self.editing = Some((EditField::Question, card.card.question.clone()));
+                }
+            }
+            KeyCode::Char('n') => {
+                self.editing = Some((EditField::DeckName, self.deck_name.clone()));
+            }
+            KeyCode::Char('s') => {
+                if self.count(CardDecision::Accepted) == 0 {
+                    self.message = Some("Accept at least one card before saving".to_string());
+                } else {
+                    return ReviewAction::Save;
+                }
+            }
+            KeyCode::Esc => return ReviewAction::Close,
+            _ => {}
+        }
+        ReviewAction::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AiError;
+    use crossterm::event::KeyModifiers;
+
+    fn press(review: &mut GenerationReview, code: KeyCode) -> ReviewAction {
+        review.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(review: &mut GenerationReview, text: &str) {
+        for c in text.chars() {
+            press(review, KeyCode::Char(c));
+        }
+    }
+
+    fn card(question: &str) -> GeneratedCard {
+        GeneratedCard {
+            question: question.to_string(),
+            answer: format!("Answer to {}", question),
+            quote: String::new(),
+            location: "line 1".to_string(),
+            quote_verified: false,
+        }
+    }
+
+    fn generated(chunk: usize, result: Result<Vec<GeneratedCard>, AiError>) -> AiResponse {
+        AiResponse::GeneratedCards {
+            request_id: RequestId(7),
+            chunk,
+            chunks: 3,
+            result,
+        }
+    }
+
+    #[test]
+    fn test_reviewing_generated_cards() {
+        let mut review = GenerationReview::new();
+        type_text(&mut review, "~/notes.md ");
+        assert_eq!(
+            press(&mut review, KeyCode::Enter),
+            ReviewAction::Generate(PathBuf::from("~/notes.md"))
+        );
+        review.read(RequestId(6));
+        assert_eq!(press(&mut review, KeyCode::Char('s')), ReviewAction::None);
+        review.fail("Cannot read ~/notes.md".to_string());
+        assert_eq!(review.stage, ReviewStage::ChoosingFile);
+        assert_eq!(review.path_input, "~/notes.md ");
+
+        review.read(RequestId(7));
+        assert!(!review.is_reading(RequestId(6)));
+        assert!(review.is_reading(RequestId(7)));
+        review.start(Path::new("/home/me/notes.md"), "notes", 3, RequestId(7));
+        assert_eq!(review.document, "notes.md");
+
+        review.handle_response(generated(
+            0,
+            Ok(vec![card("What is AODV?"), card("What is DSR?")]),
+        ));
+        // The overlap between passages proposes the same card again
+        review.handle_response(generated(
+            1,
+            Ok(vec![card("what is  AODV"), card("What is OLSR?")]),
+        ));
+        review.handle_response(AiResponse::GeneratedCards {
+            request_id: RequestId(8),
+            chunk: 2,
+            chunks: 3,
+            result: Ok(vec![card("Stale")]),
+        });
+        assert_eq!(review.cards.len(), 3);
+        assert!(review.is_generating());
+
+        assert_eq!(press(&mut review, KeyCode::Char('s')), ReviewAction::None);
+        assert!(review.message.is_some());
+        press(&mut review, KeyCode::Char('x'));
+        assert_eq!(review.cards[0].decision, CardDecision::Rejected);
+        assert_eq!(review.selected, 1);
+
+        press(&mut review, KeyCode::Char('e'));
+        type_text(&mut review, " again?");
+        press(&mut review, KeyCode::Enter);
+        press(&mut review, KeyCode::Backspace);
+        type_text(&mut review, ", edited");
+        press(&mut review, KeyCode::Enter);
+        assert_eq!(review.cards[1].card.question, "What is DSR? again?");
+        assert_eq!(review.cards[1].card.answer, "Answer to What is DSR, edited");
+        assert_eq!(review.cards[1].decision, CardDecision::Accepted);
+
+        press(&mut review, KeyCode::Char('n'));
+        type_text(&mut review, "-ai");
+        press(&mut review, KeyCode::Enter);
+        assert_eq!(review.deck_name, "notes-ai");
+
+        review.handle_response(generated(2, Err(AiError::Auth("bad key".to_string()))));
+        assert_eq!(review.stage, ReviewStage::Done);
+        assert_eq!(review.failed_chunks, 1);
+
+        press(&mut review, KeyCode::Char('A'));
+        let accepted = review.accepted_cards();
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(accepted[1].question, "What is OLSR?");
+        assert_eq!(press(&mut review, KeyCode::Char('s')), ReviewAction::Save);
+        assert_eq!(press(&mut review, KeyCode::Esc), ReviewAction::Close);
+    }
+}
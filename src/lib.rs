@@ -1,21 +1,29 @@
 pub mod ai;
 pub mod ai_worker;
+pub mod anki;
+pub mod config;
 pub mod csv;
 pub mod db;
+pub mod deck;
 pub mod file_io;
+pub mod ingest;
 pub mod logger;
 pub mod models;
+pub mod scheduler;
 pub mod session;
+pub mod settings;
 pub mod ui;
 pub mod ui_tests;
 pub mod utils;
 
 // Re-exports for convenience
 pub use ai::{
-    evaluate_answer, AIEvaluationResult, AIFeedback, ModelConfig, OpenRouterClient, DEFAULT_MODEL,
+    evaluate_answer, AIEvaluationResult, AIFeedback, AiConfig, AiProvider, ModelConfig, OpenRouterClient,
+    DEFAULT_MODEL,
 };
-pub use csv::{get_csv_files, load_csv};
+pub use csv::load_csv;
 pub use db::flashcard;
+pub use deck::{discover_decks, load_deck, Deck, DeckEntry, DeckMeta};
 pub use models::{AppState, Flashcard, QuizSession};
 pub use session::handle_quiz_input;
 pub use ui::{
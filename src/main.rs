@@ -7,34 +7,74 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use interactive_flashcards::db::{self, flashcard, session};
+use interactive_flashcards::db::{self, ai_usage, card, deck, flashcard, session};
 use rand::seq::SliceRandom;
 use ratatui::{backend::CrosstermBackend, Terminal};
+use std::collections::HashSet;
 use std::io;
+use std::path::{Path, PathBuf};
 
 use futures::StreamExt;
 use tokio::sync::mpsc;
-use tokio::time::{self, Duration};
 
 use interactive_flashcards::{
-    ai_worker,
+    ai::{cache, fake_server, usage, AiConfig, AiError},
+    ai_worker, anki,
+    csv::validate_csv_deck,
     db::session::SessionSummary,
-    draw_menu, draw_quit_confirmation, draw_quiz, draw_summary, get_csv_files, handle_quiz_input,
-    load_csv, logger,
+    config,
+    deck::{deck_tree_rows, discover_decks, is_deck_file, structured, DeckEntry, DeckMeta, DeckMode, DeckTreeRow},
+    draw_menu, draw_quit_confirmation, draw_quiz, draw_summary, handle_quiz_input,
+    ingest::{self, GenerationReview, ReviewAction},
+    load_deck, logger,
     models::{
-        AiRequest, AiResponse, AppState, Flashcard, QuizSession, UiMenuState, UiQuizState, UiState,
-        UiStateTypes,
+        AiHealth, AiRequest, AiResponse, AppState, CardMeta, Flashcard, QuizSession, RequestId,
+        UiMenuState, UiQuizState, UiState, UiStateTypes,
     },
+    scheduler,
+    session::SourcePassages,
+    settings::{SettingsAction, SettingsForm},
+    ui::{draw_review, draw_settings},
     utils::apply_scroll_with_bounds,
 };
 
 const SCROLL_LINES_PER_EVENT: i16 = 5;
+/// Requests the AI worker can have queued: room for every answer of a large session
+/// resumed at once
+const AI_QUEUE_CAPACITY: usize = 512;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     logger::init();
+
+    let (deck_dir_args, args) = split_deck_dir_args(std::env::args().skip(1).collect());
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let deck_dirs = config::resolve_deck_dirs(
+        &deck_dir_args,
+        std::env::var_os(config::DECK_DIRS_ENV).as_deref(),
+        &config,
+    );
+    match args.first().map(String::as_str) {
+        Some("optimize") => return run_optimize(args.get(1).map(String::as_str)),
+        Some("missed") => return run_missed_report(args.get(1).map(String::as_str)),
+        Some("import") => return run_import(&args[1..], &deck_dirs[0]),
+        Some("export") => return run_export(&args[1..], &deck_dirs),
+        Some("validate") => return run_validate(&args[1..]),
+        Some("fake-ai") => return run_fake_ai(&args[1..]).await,
+        Some("cache") => return run_cache(&args[1..], &config.ai),
+        Some("usage") => return run_usage(&config.ai),
+        _ => {}
+    }
+
     logger::log("Application started");
 
+    install_panic_hook();
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(
@@ -48,34 +88,55 @@ async fn main() -> io::Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     let mut app_state = AppState::Menu;
-    let raw_csv_files = get_csv_files();
-    let mut csv_files: Vec<(std::path::PathBuf, Option<db::session::DeckStatus>)> =
-        raw_csv_files.into_iter().map(|p| (p, None)).collect();
+    let mut decks = discover_decks(&deck_dirs);
+    let mut deck_statuses: Vec<Option<db::session::DeckStatus>> = vec![None; decks.len()];
+    // Folders of the deck tree whose decks are hidden in the menu
+    let mut collapsed_groups: HashSet<String> = HashSet::new();
+    let mut deck_rows = deck_tree_rows(&decks, &collapsed_groups);
+    // Index into `deck_rows`
     let mut selected_file_index: usize = 0;
     let mut quiz_session: Option<QuizSession> = None;
-    let ai_enabled = std::env::var("OPENROUTER_API_KEY").is_ok();
+    let mut ai_config = config.ai;
+    let mut ai_enabled = ai_config.unavailable_reason().is_none();
+    // Whether the backend answers and accepts the key, checked in the background
+    let mut ai_health = AiHealth::Checking;
+    // Number of the latest health check; results of earlier ones are stale
+    let mut health_check: u64 = 0;
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    if ai_enabled {
+        spawn_health_check(&ai_config, health_check, &health_tx);
+    }
+    // One AI worker serves every quiz session and document for the whole run
+    let (ai_tx, request_rx) = mpsc::channel::<AiRequest>(AI_QUEUE_CAPACITY);
+    let (response_tx, mut ai_rx) = mpsc::channel::<AiResponse>(AI_QUEUE_CAPACITY);
+    let _ai_handle = ai_worker::spawn_ai_worker(ai_config.clone(), response_tx, request_rx);
 
     // Session history state - load at startup
     let mut sessions: Vec<SessionSummary> = Vec::new();
     let mut selected_session_index: usize = 0;
     let mut focused_panel: usize = 0; // 0 = CSV, 1 = Sessions
     let mut _delete_confirm: bool = false;
+    // One-line message under the menu title (load errors, scheduler changes)
+    let mut menu_status: Option<String> = None;
+    // Tag a new session is restricted to, with the name of the deck (or folder) it applies to
+    let mut tag_filter: Option<(String, String)> = None;
+    // The AI settings screen, while it is open
+    let mut settings_form: Option<SettingsForm> = None;
+    // The document review screen while it is open
+    let mut generation: Option<GenerationReview> = None;
+    // Source passages of the quiz's cards, found in the background
+    let (passages_tx, mut passages_rx) = mpsc::channel(1);
+    // Documents read in the background for the review screen, with the request they are for
+    let (document_tx, mut document_rx) = mpsc::channel(1);
 
     // Load sessions at startup
     if let Ok(conn) = db::init_db() {
         sessions = session::list_sessions(&conn).unwrap_or_default();
-        for (path, status) in csv_files.iter_mut() {
-            let deck_name = path
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            *status = session::get_last_session_status(&conn, &deck_name).ok();
-        }
+        refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
     }
 
-    // Create async event stream and timeout timer for event-driven architecture
+    // Create async event stream for event-driven architecture
     let mut event_stream = EventStream::new();
-    let mut ai_timeout_interval = time::interval(Duration::from_secs(30));
 
     // Track UI state to avoid unnecessary redraws
     let mut last_ui_state = UiState {
@@ -85,6 +146,18 @@ async fn main() -> io::Result<()> {
     let mut is_first_draw = true; // Ensure UI draws on application startup
 
     loop {
+        let selected_deck_name = selected_decks(&decks, &deck_rows, selected_file_index).map(|(name, _)| name);
+
+        // Send the session assessment once its evaluations are in; redraw if sending failed
+        if app_state == AppState::Summary
+            && let Some(ref mut session) = quiz_session
+            && session.request_assessment_when_ready() {
+                last_ui_state = UiState {
+                    app_state: AppState::Menu,
+                    current: None,
+                };
+            }
+
         // Check if UI needs updating based on state changes
         let current_ui_state = match app_state {
             AppState::Menu | AppState::MenuDeleteConfirm => UiState {
@@ -94,6 +167,10 @@ async fn main() -> io::Result<()> {
                     selected_session_index,
                     focused_panel,
                     sessions_count: sessions.len(),
+                    deck_rows: deck_rows.len(),
+                    status_message: menu_status.clone(),
+                    tag_filter: active_tag_filter(&tag_filter, selected_deck_name.as_deref()).map(String::from),
+                    ai_health: ai_health.clone(),
                 })),
             },
             AppState::Quiz => {
@@ -102,12 +179,12 @@ async fn main() -> io::Result<()> {
                     let quiz_state = UiQuizState {
                         current_index: session.current_index,
                         showing_answer: session.showing_answer,
-                        ai_evaluation_in_progress: session.ai_evaluation_in_progress,
+                        evaluations_pending: session.evaluations_pending(),
                         input_buffer_len: session.input_buffer.len(),
                         cursor_position: session.cursor_position,
                         input_scroll_y: session.input_scroll_y,
                         feedback_scroll_y: session.feedback_scroll_y,
-                        has_ai_error: session.last_ai_error.is_some(),
+                        evaluations_failed: session.evaluations_failed(),
                         questions_answered: session.questions_answered,
                         ai_feedback_count: session
                             .flashcards
@@ -128,9 +205,10 @@ async fn main() -> io::Result<()> {
                         chat_is_loading: session
                             .chat_state
                             .as_ref()
-                            .map(|c| c.is_loading)
+                            .map(|c| c.is_loading())
                             .unwrap_or(false),
                         chat_scroll_y: session.chat_state.as_ref().map(|c| c.scroll_y).unwrap_or(0),
+                        hint_revealed: session.hints_revealed.contains(&session.current_index),
                     };
                     UiState {
                         app_state: AppState::Quiz,
@@ -151,6 +229,14 @@ async fn main() -> io::Result<()> {
                 app_state: AppState::Summary,
                 current: None,
             },
+            AppState::Settings => UiState {
+                app_state: AppState::Settings,
+                current: settings_form.clone().map(|form| UiStateTypes::Settings(Box::new(form))),
+            },
+            AppState::Generate => UiState {
+                app_state: AppState::Generate,
+                current: generation.clone().map(|review| UiStateTypes::Generate(Box::new(review))),
+            },
         };
 
         // Always draw on first iteration, then only redraw if state has changed
@@ -160,22 +246,32 @@ async fn main() -> io::Result<()> {
             terminal.draw(|f| match app_state {
                 AppState::Menu => draw_menu(
                     f,
-                    &csv_files,
+                    &decks,
+                    &deck_statuses,
+                    &deck_rows,
                     selected_file_index,
                     &sessions,
                     selected_session_index,
                     focused_panel,
-                    ai_enabled,
+                    &ai_config,
+                    &ai_health,
+                    menu_status.as_deref(),
+                    active_tag_filter(&tag_filter, selected_deck_name.as_deref()),
                 ),
                 AppState::MenuDeleteConfirm => {
                     draw_menu(
                         f,
-                        &csv_files,
+                        &decks,
+                        &deck_statuses,
+                        &deck_rows,
                         selected_file_index,
                         &sessions,
                         selected_session_index,
                         focused_panel,
-                        ai_enabled,
+                        &ai_config,
+                        &ai_health,
+                        None,
+                        active_tag_filter(&tag_filter, selected_deck_name.as_deref()),
                     );
                     interactive_flashcards::draw_delete_confirmation(f);
                 }
@@ -186,56 +282,19 @@ async fn main() -> io::Result<()> {
                     }
                 }
                 AppState::QuizQuitConfirm => draw_quit_confirmation(f),
+                AppState::Settings => {
+                    if let Some(form) = &settings_form {
+                        draw_settings(f, form);
+                    }
+                }
+                AppState::Generate => {
+                    if let Some(review) = &generation {
+                        draw_review(f, review);
+                    }
+                }
                 AppState::Summary => {
                     if let Some(ref mut session) = quiz_session {
                         draw_summary(f, session);
-                        // Trigger session assessment if not already loading
-                        if session.assessment_loading
-                            && session.session_assessment.is_none()
-                            && session.assessment_error.is_none()
-                            && let Some(session_id) = session.session_id {
-                                let deck_name = session.deck_name.clone();
-                                let flashcards: Vec<_> = session
-                                    .flashcards
-                                    .iter()
-                                    .map(|fc| {
-                                        (
-                                            fc.question.clone(),
-                                            fc.answer.clone(),
-                                            fc.user_answer.clone(),
-                                            fc.ai_feedback.clone(),
-                                        )
-                                    })
-                                    .collect();
-
-                                if let Some(ref ai_tx) = session.ai_tx {
-                                    let request = AiRequest::EvaluateSession {
-                                        session_id,
-                                        deck_name,
-                                        flashcards,
-                                    };
-                                    let _ = ai_tx.try_send(request);
-                                    logger::log("Triggered session assessment request");
-                                } else if session.ai_enabled {
-                                    // AI is enabled but no channel - create one
-                                    let (request_tx, request_rx) = mpsc::channel::<AiRequest>(32);
-                                    let (response_tx, response_rx) =
-                                        mpsc::channel::<AiResponse>(32);
-                                    let _ai_handle =
-                                        ai_worker::spawn_ai_worker(response_tx, request_rx);
-
-                                    let request = AiRequest::EvaluateSession {
-                                        session_id,
-                                        deck_name,
-                                        flashcards,
-                                    };
-                                    let _ = request_tx.try_send(request);
-
-                                    session.ai_tx = Some(request_tx);
-                                    session.ai_rx = Some(response_rx);
-                                    logger::log("Created new AI channel for session assessment");
-                                }
-                            }
                     }
                 }
             })?;
@@ -254,6 +313,9 @@ async fn main() -> io::Result<()> {
                         {
                             break;
                         }
+                        if app_state == AppState::Menu {
+                            menu_status = None;
+                        }
                         match app_state {
                             AppState::Menu => match key.code {
                                 KeyCode::Char('1') => {
@@ -271,7 +333,7 @@ async fn main() -> io::Result<()> {
                                 }
                                 KeyCode::Down => {
                                     if focused_panel == 0 {
-                                        if selected_file_index < csv_files.len().saturating_sub(1) {
+                                        if selected_file_index < deck_rows.len().saturating_sub(1) {
                                             selected_file_index += 1;
                                         }
                                     } else if !sessions.is_empty() && selected_session_index < sessions.len().saturating_sub(1) {
@@ -280,84 +342,47 @@ async fn main() -> io::Result<()> {
                                 }
                                 KeyCode::Enter => {
                                     if focused_panel == 0 {
-                                        // CSV panel - start new quiz
-                                        if !csv_files.is_empty()
-                                            && let Ok(flashcards) = load_csv(&csv_files[selected_file_index].0) {
-                                            let deck_name = csv_files[selected_file_index].0
-                                                .file_stem().map(|s| s.to_string_lossy().to_string())
-                                                .unwrap_or_else(|| "unknown_deck".to_string());
-                                            let mut cards = flashcards;
-                                            cards.shuffle(&mut rand::thread_rng());
-
-                                            let conn = match db::init_db() {
-                                                Ok(conn) => conn,
+                                        // Deck panel - start a new quiz over the selected deck, or the whole folder
+                                        if let Some((deck_name, entries)) = selected_decks(&decks, &deck_rows, selected_file_index) {
+                                            let (mut flashcards, deck_meta) = match load_selection(&deck_name, &entries) {
+                                                Ok(loaded) => loaded,
                                                 Err(e) => {
-                                                    eprintln!("Failed to initialize database: {}", e);
-                                                    return Ok(());
+                                                    menu_status = Some(e);
+                                                    continue;
                                                 }
                                             };
-
-                                            let session_id = match session::create_session(&conn, &deck_name, cards.len()) {
-                                                Ok(id) => id,
-                                                Err(e) => {
-                                                    eprintln!("Failed to create session: {}", e);
-                                                    return Ok(());
+                                            if let Some(tag) = active_tag_filter(&tag_filter, Some(&deck_name)) {
+                                                flashcards.retain(|(_, c)| c.meta.has_tag(tag));
+                                                if flashcards.is_empty() {
+                                                    menu_status = Some(format!("No cards tagged #{} in {}", tag, deck_name));
+                                                    continue;
                                                 }
-                                            };
-
-                                            let flashcards_data: Vec<(String, String)> = cards.iter()
-                                                .map(|c| (c.question.clone(), c.answer.clone()))
-                                                .collect();
-
-                                            match flashcard::initialize_flashcards(&conn, session_id, &flashcards_data) {
-                                                Ok(ids) => {
-                                                    for (card, id) in cards.iter_mut().zip(ids) {
-                                                        card.id = Some(id);
+                                            }
+                                            if deck_meta.default_mode == DeckMode::Due {
+                                                // The deck asks to start on today's review instead of the full deck
+                                                let due = db::init_db().and_then(|conn| filter_due(&conn, flashcards));
+                                                flashcards = match due {
+                                                    Ok(due) if due.is_empty() => {
+                                                        menu_status = Some(format!("No cards due today in {}", deck_name));
+                                                        continue;
                                                     }
+                                                    Ok(due) => due,
+                                                    Err(e) => {
+                                                        menu_status = Some(format!("Cannot load due cards for {}: {}", deck_name, e));
+                                                        continue;
+                                                    }
+                                                };
+                                            }
+                                            match start_quiz_session(deck_name, flashcards, deck_meta, ai_enabled.then(|| ai_tx.clone()), &passages_tx) {
+                                                Ok(session) => {
+                                                    quiz_session = Some(session);
+                                                    app_state = AppState::Quiz;
                                                 }
                                                 Err(e) => {
-                                                    eprintln!("Failed to initialize flashcards: {}", e);
+                                                    eprintln!("{}", e);
                                                     return Ok(());
                                                 }
                                             }
-
-                                            // Create async channels for this quiz session (buffered)
-                                            let (request_tx, request_rx) = mpsc::channel::<AiRequest>(32);
-                                            let (response_tx, response_rx) = mpsc::channel::<AiResponse>(32);
-
-                                            // Spawn AI worker if enabled
-                                            if ai_enabled {
-                                                let _ai_handle = ai_worker::spawn_ai_worker(response_tx, request_rx);
-                                            }
-
-                                            let questions_total = cards.len();
-                                            quiz_session = Some(QuizSession {
-                                                flashcards: cards,
-                                                current_index: 0,
-                                                deck_name,
-                                                showing_answer: false,
-                                                input_buffer: String::new(),
-                                                cursor_position: 0,
-                                                session_id: Some(session_id),
-                                                questions_total,
-                                                questions_answered: 0,
-                                                ai_enabled,
-                                                ai_evaluation_in_progress: false,
-                                                ai_last_evaluated_index: None,
-                                                ai_evaluation_start_time: None,
-                                                last_ai_error: None,
-                                                ai_tx: if ai_enabled { Some(request_tx) } else { None },
-                                                ai_rx: if ai_enabled { Some(response_rx) } else { None },
-                                                input_scroll_y: 0,
-                                                feedback_scroll_y: 0,
-                                                session_assessment: None,
-                                                assessment_loading: false,
-                                                assessment_error: None,
-                                                assessment_scroll_y: 0,
-                                                chat_state: None,
-                                            });
-
-                                            app_state = AppState::Quiz;
                                         }
                                     } else {
                                         // Sessions panel - resume session
@@ -374,6 +399,7 @@ async fn main() -> io::Result<()> {
                                                         ai_feedback: fc.ai_feedback,
                                                         written_to_file: true,
                                                         id: Some(fc.id),
+                                                        meta: fc.meta.clone(),
                                                     })
                                                     .collect();
 
@@ -396,13 +422,8 @@ async fn main() -> io::Result<()> {
                                                     }
                                                 }
 
-                                                let (request_tx, request_rx) = mpsc::channel::<AiRequest>(32);
-                                                let (response_tx, response_rx) = mpsc::channel::<AiResponse>(32);
-
-                                                if ai_enabled {
-                                                    let _ai_handle = ai_worker::spawn_ai_worker(response_tx, request_rx);
-                                                }
-
+                                                let deck_meta = resumed_deck_meta(&decks, &session_data.deck_name);
+                                                let ai_usage = ai_usage::session_totals(&conn, session_id).unwrap_or_default();
                                                 quiz_session = Some(QuizSession {
                                                     flashcards: cards,
                                                     current_index: resume_index,
@@ -414,31 +435,156 @@ async fn main() -> io::Result<()> {
                                                     questions_total: session_data.questions_total,
                                                     questions_answered: session_data.questions_answered,
                                                     ai_enabled,
-                                                    ai_evaluation_in_progress: false,
-                                                    ai_last_evaluated_index: None,
-                                                    ai_evaluation_start_time: None,
-                                                    last_ai_error: None,
-                                                    ai_tx: if ai_enabled { Some(request_tx) } else { None },
-                                                    ai_rx: if ai_enabled { Some(response_rx) } else { None },
+                                                    ai_tx: ai_enabled.then(|| ai_tx.clone()),
                                                     input_scroll_y: 0,
                                                     feedback_scroll_y: 0,
                                                     session_assessment: None,
                                                     assessment_loading: false,
+                                                    assessment_requested: false,
                                                     assessment_error: None,
                                                     assessment_scroll_y: 0,
+                                                    ai_unavailable: None,
                                                     chat_state: None,
+                                                    deck_meta,
+                                                    ai_usage,
+                                                    source_passages: Vec::new(),
+                                                    hints_revealed: Default::default(),
+                                                    evaluations: Default::default(),
                                                 });
+                                                // Grade the answers left unevaluated when the session was quit
+                                                if let Some(session) = &mut quiz_session {
+                                                    session.retrieve_source_passages(&passages_tx);
+                                                    session.queue_unevaluated();
+                                                }
 
                                                 app_state = AppState::Quiz;
                                             }
                                         }
                                     }
                                 }
-                                KeyCode::Char('d') => {
-                                    if focused_panel == 1 && !sessions.is_empty() {
-                                        app_state = AppState::MenuDeleteConfirm;
+                                KeyCode::Char('r') if focused_panel == 0 && !deck_rows.is_empty() => {
+                                    // Review only the cards scheduled for today
+                                    let Some((deck_name, entries)) = selected_decks(&decks, &deck_rows, selected_file_index) else {
+                                        continue;
+                                    };
+                                    let (mut flashcards, deck_meta) = match load_selection(&deck_name, &entries) {
+                                        Ok(loaded) => loaded,
+                                        Err(e) => {
+                                            menu_status = Some(e);
+                                            continue;
+                                        }
+                                    };
+                                    if let Some(tag) = active_tag_filter(&tag_filter, Some(&deck_name)) {
+                                        flashcards.retain(|(_, c)| c.meta.has_tag(tag));
+                                    }
+                                    if let Ok(conn) = db::init_db()
+                                        && let Ok(due) = filter_due(&conn, flashcards)
+                                    {
+                                        if due.is_empty() {
+                                            menu_status = Some(format!("No cards due today in {}", deck_name));
+                                            continue;
+                                        }
+                                        match start_quiz_session(deck_name, due, deck_meta, ai_enabled.then(|| ai_tx.clone()), &passages_tx) {
+                                            Ok(session) => {
+                                                quiz_session = Some(session);
+                                                app_state = AppState::Quiz;
+                                            }
+                                            Err(e) => {
+                                                eprintln!("{}", e);
+                                                return Ok(());
+                                            }
+                                        }
+                                    }
+                                }
+                                KeyCode::Char('t') if focused_panel == 0 && !deck_rows.is_empty() => {
+                                    // Cycle the session tag filter: all cards -> each tag -> all cards
+                                    let Some((deck_name, entries)) = selected_decks(&decks, &deck_rows, selected_file_index) else {
+                                        continue;
+                                    };
+                                    match load_selection(&deck_name, &entries) {
+                                        Ok((flashcards, _)) => {
+                                            let tags = deck_tags(flashcards.iter().map(|(_, c)| c));
+                                            let next = match active_tag_filter(&tag_filter, Some(&deck_name)) {
+                                                None => tags.first().cloned(),
+                                                Some(current) => tags
+                                                    .iter()
+                                                    .position(|t| t == current)
+                                                    .and_then(|i| tags.get(i + 1))
+                                                    .cloned(),
+                                            };
+                                            menu_status = Some(match &next {
+                                                Some(tag) => format!("{}: only cards tagged #{}", deck_name, tag),
+                                                None if tags.is_empty() => format!("{} has no tagged cards", deck_name),
+                                                None => format!("{}: all cards", deck_name),
+                                            });
+                                            tag_filter = next.map(|tag| (deck_name, tag));
+                                        }
+                                        Err(e) => {
+                                            menu_status = Some(e);
+                                        }
+                                    }
+                                }
+                                KeyCode::Char('s') if focused_panel == 0 && !deck_rows.is_empty() => {
+                                    // Toggle the deck between SM-2 and FSRS scheduling
+                                    let Some(DeckTreeRow::Deck { index, .. }) = deck_rows.get(selected_file_index) else {
+                                        menu_status = Some("Select a deck to change its scheduler".to_string());
+                                        continue;
+                                    };
+                                    let deck_name = decks[*index].name.clone();
+                                    if let Ok(conn) = db::init_db()
+                                        && let Ok(settings) = deck::get_deck_settings(&conn, &deck_name)
+                                    {
+                                        let scheduler = settings.scheduler.toggled();
+                                        match deck::set_scheduler(&conn, &deck_name, scheduler) {
+                                            Ok(()) => {
+                                                menu_status = Some(format!("{} now uses {} scheduling", deck_name, scheduler.label()));
+                                            }
+                                            Err(e) => {
+                                                logger::log(&format!("Failed to change scheduler for '{}': {}", deck_name, e));
+                                            }
+                                        }
+                                        refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
+                                    }
+                                }
+                                KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') if focused_panel == 0 => {
+                                    // Collapse/expand folders; Left on a deck jumps to its folder
+                                    let group = match deck_rows.get(selected_file_index) {
+                                        Some(DeckTreeRow::Folder { group, collapsed, .. }) => {
+                                            let collapse = match key.code {
+                                                KeyCode::Left => true,
+                                                KeyCode::Right => false,
+                                                _ => !collapsed,
+                                            };
+                                            if collapse {
+                                                collapsed_groups.insert(group.clone());
+                                            } else {
+                                                collapsed_groups.remove(group);
+                                            }
+                                            group.clone()
+                                        }
+                                        Some(DeckTreeRow::Deck { index, .. }) if key.code == KeyCode::Left => {
+                                            decks[*index].group().to_string()
+                                        }
+                                        _ => continue,
+                                    };
+                                    deck_rows = deck_tree_rows(&decks, &collapsed_groups);
+                                    if let Some(row) = deck_rows.iter().position(
+                                        |r| matches!(r, DeckTreeRow::Folder { group: g, .. } if *g == group),
+                                    ) {
+                                        selected_file_index = row;
                                     }
                                 }
+                                KeyCode::Char('d') if focused_panel == 1 && !sessions.is_empty() => {
+                                    app_state = AppState::MenuDeleteConfirm;
+                                }
+                                KeyCode::Char('a') => {
+                                    settings_form = Some(SettingsForm::new(&ai_config));
+                                    app_state = AppState::Settings;
+                                }
+                                KeyCode::Char('g') => {
+                                    generation = Some(GenerationReview::new());
+                                    app_state = AppState::Generate;
+                                }
                                 KeyCode::Esc => break,
                                 _ => {}
                             },
@@ -451,10 +597,7 @@ async fn main() -> io::Result<()> {
                                                 eprintln!("Failed to delete session: {}", e);
                                             }
                                             sessions = session::list_sessions(&conn).unwrap_or_default();
-                                            for (path, status) in csv_files.iter_mut() {
-                                                let deck_name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
-                                                *status = session::get_last_session_status(&conn, &deck_name).ok();
-                                            }
+                                            refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
                                             if selected_session_index >= sessions.len() && !sessions.is_empty() {
                                                 selected_session_index = sessions.len() - 1;
                                             }
@@ -476,6 +619,95 @@ async fn main() -> io::Result<()> {
                                     }
                                 }
                             }
+                            AppState::Settings => {
+                                let Some(form) = &mut settings_form else {
+                                    continue;
+                                };
+                                match form.handle_key(key) {
+                                    SettingsAction::Save => {
+                                        let path = config::config_path();
+                                        match config::save_model_settings(&path, &form.config) {
+                                            Ok(()) => {
+                                                // Sessions started from now on use the new models
+                                                ai_config = form.config.clone();
+                                                ai_enabled = ai_config.unavailable_reason().is_none();
+                                                health_check += 1;
+                                                ai_health = AiHealth::Checking;
+                                                if ai_enabled {
+                                                    spawn_health_check(&ai_config, health_check, &health_tx);
+                                                }
+                                                let config = Box::new(ai_config.clone());
+                                                if let Err(e) = ai_tx.try_send(AiRequest::Reconfigure { config }) {
+                                                    logger::log(&format!("Failed to reconfigure the AI worker: {}", e));
+                                                }
+                                                menu_status = Some(format!("AI settings saved to {}", path.display()));
+                                                settings_form = None;
+                                                app_state = AppState::Menu;
+                                            }
+                                            Err(e) => {
+                                                form.error = Some(format!("Cannot save the settings: {}", e));
+                                            }
+                                        }
+                                    }
+                                    SettingsAction::Close => {
+                                        settings_form = None;
+                                        app_state = AppState::Menu;
+                                    }
+                                    SettingsAction::None => {}
+                                }
+                            }
+                            AppState::Generate => {
+                                let Some(review) = &mut generation else {
+                                    continue;
+                                };
+                                let close = match review.handle_key(key) {
+                                    ReviewAction::Generate(path) => {
+                                        if let Some(reason) = ai_config.unavailable_reason() {
+                                            review.message = Some(format!("Cards cannot be generated: {}", reason));
+                                        } else {
+                                            let request_id = RequestId::next();
+                                            review.read(request_id);
+                                            let document_tx = document_tx.clone();
+                                            tokio::spawn(async move {
+                                                let path = config::expand_home(&path);
+                                                let document = ingest::read_document_in_background(path.clone()).await;
+                                                let _ = document_tx.send((request_id, path, document)).await;
+                                            });
+                                        }
+                                        false
+                                    }
+                                    ReviewAction::Save => {
+                                        let cards = review.accepted_cards();
+                                        match ingest::write_generated_deck(&deck_dirs[0], &review.deck_name, &review.source, &cards) {
+                                            Ok(path) => {
+                                                menu_status = Some(format!("Saved {} cards to {}", cards.len(), path.display()));
+                                                decks = discover_decks(&deck_dirs);
+                                                deck_rows = deck_tree_rows(&decks, &collapsed_groups);
+                                                deck_statuses = vec![None; decks.len()];
+                                                if let Ok(conn) = db::init_db() {
+                                                    refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
+                                                }
+                                                true
+                                            }
+                                            Err(e) => {
+                                                review.message = Some(format!("{} (press n to choose another name)", e));
+                                                false
+                                            }
+                                        }
+                                    }
+                                    ReviewAction::Close => true,
+                                    ReviewAction::None => false,
+                                };
+                                if close {
+                                    // Passages not reached yet are not worth paying for
+                                    if let Some(request_id) = review.request_id
+                                        && review.is_generating() {
+                                        let _ = ai_tx.try_send(AiRequest::Cancel { request_id });
+                                    }
+                                    generation = None;
+                                    app_state = AppState::Menu;
+                                }
+                            }
                             AppState::QuizQuitConfirm => match key.code {
                                 KeyCode::Char('y') => {
                                     app_state = AppState::Menu;
@@ -483,10 +715,7 @@ async fn main() -> io::Result<()> {
                                     // Refresh sessions list and deck status
                                     if let Ok(conn) = db::init_db() {
                                         sessions = session::list_sessions(&conn).unwrap_or_default();
-                                        for (path, status) in csv_files.iter_mut() {
-                                            let deck_name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
-                                            *status = session::get_last_session_status(&conn, &deck_name).ok();
-                                        }
+                                        refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
                                     }
                                 }
                                 KeyCode::Char('n') => {
@@ -501,54 +730,24 @@ async fn main() -> io::Result<()> {
                                     // Refresh sessions list and deck status
                                     if let Ok(conn) = db::init_db() {
                                         sessions = session::list_sessions(&conn).unwrap_or_default();
-                                        for (path, status) in csv_files.iter_mut() {
-                                            let deck_name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
-                                            *status = session::get_last_session_status(&conn, &deck_name).ok();
-                                        }
+                                        refresh_deck_statuses(&conn, &decks, &mut deck_statuses);
                                     }
                                 },
                                 KeyCode::Char('r') | KeyCode::Char('R') => {
+                                    // Retry the assessment. The event loop sends it once no evaluation is
+                                    // pending, so it never sees a card before its grade arrives
                                     if let Some(ref mut session) = quiz_session
+                                        && !session.assessment_loading
+                                        && session.can_request_assessment()
                                         && (session.session_assessment.is_none() || session.assessment_error.is_some()) {
-                                            // Retry assessment
                                             session.assessment_loading = true;
+                                            session.assessment_requested = false;
                                             session.assessment_error = None;
-
-                                            if let Some(session_id) = session.session_id {
-                                                let deck_name = session.deck_name.clone();
-                                                let flashcards: Vec<_> = session.flashcards.iter().map(|fc| {
-                                                    (
-                                                        fc.question.clone(),
-                                                        fc.answer.clone(),
-                                                        fc.user_answer.clone(),
-                                                        fc.ai_feedback.clone(),
-                                                    )
-                                                }).collect();
-
-                                                if let Some(ref ai_tx) = session.ai_tx {
-                                                    let request = AiRequest::EvaluateSession {
-                                                        session_id,
-                                                        deck_name,
-                                                        flashcards,
-                                                    };
-                                                    let _ = ai_tx.try_send(request);
-                                                } else if session.ai_enabled {
-                                                    // Create new channel if needed
-                                                    let (request_tx, request_rx) = mpsc::channel::<AiRequest>(32);
-                                                    let (response_tx, response_rx) = mpsc::channel::<AiResponse>(32);
-                                                    let _ai_handle = ai_worker::spawn_ai_worker(response_tx, request_rx);
-
-                                                    let request = AiRequest::EvaluateSession {
-                                                        session_id,
-                                                        deck_name,
-                                                        flashcards,
-                                                    };
-                                                    let _ = request_tx.try_send(request);
-
-                                                    session.ai_tx = Some(request_tx);
-                                                    session.ai_rx = Some(response_rx);
-                                                }
-                                            }
+                                            session.session_assessment = None;
+                                            last_ui_state = UiState {
+                                                app_state: AppState::Menu,
+                                                current: None,
+                                            };
                                         }
                                 },
                                 KeyCode::Esc => break,
@@ -560,7 +759,7 @@ async fn main() -> io::Result<()> {
                         if let AppState::Quiz = app_state
                             && let Some(session) = &mut quiz_session {
                             if let Some(ref mut chat) = session.chat_state {
-                                if !chat.read_only && !chat.is_loading {
+                                if !chat.read_only && !chat.is_loading() {
                                     for ch in text.chars() {
                                         chat.input_buffer.insert(chat.cursor_position, ch);
                                         chat.cursor_position += 1;
@@ -652,22 +851,42 @@ async fn main() -> io::Result<()> {
                 }
             },
 
-            // Async AI response receiving
-            Some(response) = async {
+
+            Some((session_id, passages)) = passages_rx.recv() => {
                 if let Some(session) = &mut quiz_session {
-                    if let Some(rx) = &mut session.ai_rx {
-                        rx.recv().await
-                    } else {
-                        std::future::pending().await
-                    }
-                } else {
-                    std::future::pending().await
+                    session.set_source_passages(session_id, passages);
+                }
+            }
+
+            // A document for the review screen, unless the review was left or moved on
+            Some((request_id, path, document)) = document_rx.recv() => {
+                if let Some(review) = &mut generation
+                    && review.is_reading(request_id)
+                    && let Err(e) = document.and_then(|document| start_generation(&path, document, request_id, review, &ai_tx)) {
+                    review.fail(e);
                 }
-            } => {
-                // Process the AI response immediately
-                if let Some(mut session) = quiz_session.take() {
+            }
+
+            Some((check, health)) = health_rx.recv() => {
+                if check != health_check {
+                    continue;
+                }
+                ai_health = match health {
+                    Ok(()) => AiHealth::Ready,
+                    Err(e) => AiHealth::Unavailable(e.to_string()),
+                };
+            }
+
+            // Async AI response receiving: generated cards go to the review screen,
+            // everything else to the quiz session
+            Some(response) = ai_rx.recv() => {
+                if let AiResponse::GeneratedCards { .. } = response {
+                    if let Some(review) = &mut generation {
+                        review.handle_response(response);
+                    }
+                } else if let Some(session) = &mut quiz_session {
+                    // Process the AI response immediately
                     session.process_ai_responses(response);
-                    quiz_session = Some(session);
                     // Force UI redraw for immediate AI feedback display
                     last_ui_state = UiState {
                         app_state: AppState::Menu,
@@ -675,29 +894,6 @@ async fn main() -> io::Result<()> {
                     };
                 }
             }
-
-            // AI evaluation timeout checking (every 30 seconds)
-            _ = ai_timeout_interval.tick() => {
-                // Check for AI evaluation timeouts
-                if let Some(mut session) = quiz_session.take() {
-                    if session.ai_evaluation_in_progress
-                        && let Some(start_time) = session.ai_evaluation_start_time
-                            && start_time.elapsed() > Duration::from_secs(30) {
-                                session.last_ai_error = Some(
-                                    "AI evaluation timed out - press Ctrl+E to retry".to_string(),
-                                );
-                                session.ai_evaluation_in_progress = false;
-                                logger::log("AI evaluation timed out after 30 seconds");
-
-                                // Force UI redraw for timeout message
-                                last_ui_state = UiState {
-                                    app_state: AppState::Menu,
-                                    current: None,
-                                };
-                            }
-                    quiz_session = Some(session);
-                }
-            }
         }
     }
 
@@ -713,3 +909,673 @@ async fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+/// Leave the full-screen terminal mode before a panic of the UI thread is reported, so
+/// that the message can be read and the shell is usable again. Panics on other threads
+/// (AI requests, reading documents) are caught where they happen, so they only go to
+/// the log instead of being drawn over the screen.
+fn install_panic_hook() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() != Some("main") {
+            logger::log(&format!("Caught panic: {}", info));
+            return;
+        }
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableBracketedPaste,
+            DisableMouseCapture,
+            DisableFocusChange,
+            crossterm::cursor::Show
+        );
+        let _ = disable_raw_mode();
+        report(info);
+    }));
+}
+
+/// `interactive-flashcards optimize <deck>`: refit the deck's FSRS parameters
+/// from its stored review log and save them for future scheduling.
+fn run_optimize(deck_name: Option<&str>) -> io::Result<()> {
+    let deck_name = match deck_name {
+        Some(name) => name,
+        None => {
+            eprintln!("Usage: interactive-flashcards optimize <deck>");
+            std::process::exit(2);
+        }
+    };
+
+    let conn = db::init_db().map_err(io::Error::other)?;
+    let histories = card::get_review_histories(&conn, deck_name).map_err(io::Error::other)?;
+    let settings = deck::get_deck_settings(&conn, deck_name).map_err(io::Error::other)?;
+
+    match scheduler::fsrs::optimize(&settings.fsrs_params, &histories) {
+        Ok(params) => {
+            deck::save_fsrs_params(&conn, deck_name, &params).map_err(io::Error::other)?;
+            println!(
+                "Optimized FSRS parameters for '{}' from {} cards:",
+                deck_name,
+                histories.len()
+            );
+            println!("{:?}", params.weights);
+            if settings.scheduler != scheduler::SchedulerKind::Fsrs {
+                println!("Note: this deck uses {}; press 's' on it in the menu to switch to FSRS.", settings.scheduler.label());
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `interactive-flashcards missed [deck]`: list the cards answered incorrectly most often.
+fn run_missed_report(deck_name: Option<&str>) -> io::Result<()> {
+    const REPORT_LIMIT: usize = 20;
+
+    let conn = db::init_db().map_err(io::Error::other)?;
+    let cards =
+        card::get_most_missed_cards(&conn, deck_name, REPORT_LIMIT).map_err(io::Error::other)?;
+
+    if cards.is_empty() {
+        println!("No missed cards yet.");
+        return Ok(());
+    }
+
+    for (i, stats) in cards.iter().enumerate() {
+        println!(
+            "{:>2}. [{}] missed {}/{} ({:.0}%), avg score {:.0}%",
+            i + 1,
+            stats.deck_name,
+            stats.times_missed,
+            stats.times_answered,
+            stats.miss_rate() * 100.0,
+            stats.average_score * 100.0
+        );
+        println!("    Q: {}", stats.question);
+        println!("    A: {}", stats.answer);
+    }
+
+    Ok(())
+}
+
+/// `interactive-flashcards import <file.apkg|file.colpkg> [--name <deck>] [--history]`:
+/// convert an Anki package into a Markdown deck in the flashcards directory, optionally
+/// replaying its review history into the scheduler.
+fn run_import(args: &[String], deck_dir: &Path) -> io::Result<()> {
+    fn usage() -> ! {
+        eprintln!("Usage: interactive-flashcards import <file.apkg|file.colpkg> [--name <deck>] [--history]");
+        std::process::exit(2);
+    }
+
+    let mut path = None;
+    let mut deck_name = None;
+    let mut with_history = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => with_history = true,
+            "--name" => deck_name = Some(args.next().cloned().unwrap_or_else(|| usage())),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let deck_name = deck_name.unwrap_or_else(|| deck_name_from_path(&path));
+
+    let import = match anki::read_apkg(&path) {
+        Ok(import) if import.cards.is_empty() => {
+            eprintln!("{} has no notes that can become flashcards", path.display());
+            std::process::exit(1);
+        }
+        Ok(import) => import,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let deck_path = match anki::write_native_deck(deck_dir, &deck_name, &import) {
+        Ok(deck_path) => deck_path,
+        Err(e) => {
+            eprintln!("{} (choose another name with --name)", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Imported {} cards into {}", import.cards.len(), deck_path.display());
+    if !import.media.is_empty() {
+        println!("Copied {} media files next to the deck", import.media.len());
+    }
+    if import.skipped_notes > 0 {
+        println!("Skipped {} notes with an empty front or back", import.skipped_notes);
+    }
+
+    if with_history {
+        let conn = db::init_db().map_err(io::Error::other)?;
+        let recorded = anki::import_review_history(&conn, &deck_name, &import.cards)
+            .map_err(io::Error::other)?;
+        println!("Replayed {} reviews into the scheduler", recorded);
+    }
+
+    Ok(())
+}
+
+/// `interactive-flashcards export (<deck> | --missed <session-id>) [--output <file>] [--explanations]`:
+/// export a deck, or the cards missed in one session, as an Anki `.apkg` or text (`.tsv`) file.
+fn run_export(args: &[String], deck_dirs: &[PathBuf]) -> io::Result<()> {
+    fn usage() -> ! {
+        eprintln!("Usage: interactive-flashcards export (<deck> | --missed <session-id>) [--output <file.apkg|file.tsv>] [--explanations]");
+        std::process::exit(2);
+    }
+
+    let mut deck_arg = None;
+    let mut missed_session = None;
+    let mut output = None;
+    let mut with_explanations = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--explanations" => with_explanations = true,
+            "--output" => output = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--missed" => {
+                missed_session = Some(args.next().and_then(|id| id.parse::<u64>().ok()).unwrap_or_else(|| usage()))
+            }
+            _ if deck_arg.is_none() => deck_arg = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+
+    let conn = db::init_db().map_err(io::Error::other)?;
+    let (deck_name, cards, default_output) = match (missed_session, deck_arg) {
+        (Some(session_id), None) => {
+            let deck_name = match session::get_session_detail(&conn, session_id).map_err(io::Error::other)? {
+                Some((session_data, _)) => session_data.deck_name,
+                None => {
+                    eprintln!("No session with id {}", session_id);
+                    std::process::exit(1);
+                }
+            };
+            let cards = anki::missed_session_cards(&conn, session_id, with_explanations).map_err(io::Error::other)?;
+            let default_output = format!("{}-session-{}-missed.apkg", deck_name.trim_end_matches('/').replace('/', "-"), session_id);
+            (deck_name, cards, default_output)
+        }
+        (None, Some(deck_name)) => {
+            let path = match discover_decks(deck_dirs).into_iter().find(|d| d.name == deck_name) {
+                Some(entry) => entry.path,
+                None => {
+                    eprintln!("No deck named '{}' in {}", deck_name, display_dirs(deck_dirs));
+                    std::process::exit(1);
+                }
+            };
+            let flashcards = load_deck(&path)?.flashcards;
+            let cards =
+                anki::deck_export_cards(&conn, &deck_name, &flashcards, with_explanations).map_err(io::Error::other)?;
+            let default_output = format!("{}.apkg", deck_name.replace('/', "-"));
+            (deck_name, cards, default_output)
+        }
+        _ => usage(),
+    };
+
+    if cards.is_empty() {
+        println!("Nothing to export.");
+        return Ok(());
+    }
+
+    let output = output.unwrap_or_else(|| PathBuf::from(default_output));
+    match anki::ExportFormat::for_path(&output) {
+        Some(anki::ExportFormat::Apkg) => {
+            if let Err(e) = anki::write_apkg(&output, &deck_name, &cards) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(anki::ExportFormat::Tsv) => {
+            let mut writer = io::BufWriter::new(std::fs::File::create(&output)?);
+            anki::write_tsv(&mut writer, &cards)?;
+            io::Write::flush(&mut writer)?;
+        }
+        None => {
+            eprintln!("Unsupported export file {}: use .apkg, .tsv or .txt", output.display());
+            std::process::exit(2);
+        }
+    }
+    println!("Exported {} cards to {}", cards.len(), output.display());
+
+    Ok(())
+}
+
+/// `cache [--clear]`: show how well the evaluation cache is doing, or empty it.
+fn run_cache(args: &[String], ai_config: &AiConfig) -> io::Result<()> {
+    let clear = match args {
+        [] => false,
+        [flag] if flag == "--clear" => true,
+        _ => {
+            eprintln!("Usage: interactive-flashcards cache [--clear]");
+            std::process::exit(2);
+        }
+    };
+
+    let conn = db::init_db().map_err(io::Error::other)?;
+    if clear {
+        let removed = db::evaluation_cache::clear(&conn).map_err(io::Error::other)?;
+        println!("Removed {} cached evaluations.", removed);
+        return Ok(());
+    }
+
+    let ttl = ai_config.cache_ttl(cache::DEFAULT_TTL_DAYS);
+    let ttl_secs = ttl.map_or(0, |ttl| ttl.as_secs());
+    let stats = db::evaluation_cache::stats(&conn, ttl_secs).map_err(io::Error::other)?;
+    match ttl {
+        Some(ttl) => println!(
+            "Evaluations are reused for {} days.",
+            ttl.as_secs() / (24 * 60 * 60)
+        ),
+        None => println!("The evaluation cache is off (ai.cache_ttl_days = 0)."),
+    }
+    println!("Entries: {} ({} expired)", stats.entries, stats.expired);
+    println!(
+        "Lookups: {} hits, {} misses ({:.0}% hit rate)",
+        stats.hits,
+        stats.misses,
+        stats.hit_rate() * 100.0
+    );
+    Ok(())
+}
+
+/// `usage`: AI calls, tokens and cost per deck, and this month's spending.
+fn run_usage(ai_config: &AiConfig) -> io::Result<()> {
+    let conn = db::init_db().map_err(io::Error::other)?;
+    let decks = ai_usage::deck_totals(&conn).map_err(io::Error::other)?;
+    if decks.is_empty() {
+        println!("No AI calls recorded yet.");
+    }
+    for (deck_name, totals) in &decks {
+        println!("{:<40} {}", deck_name, totals);
+    }
+
+    let spent = ai_usage::cost_since(&conn, usage::month_start()).map_err(io::Error::other)?;
+    match ai_config.monthly_budget {
+        Some(budget) => println!("This month: ${:.2} of the ${:.2} budget", spent, budget),
+        None => println!("This month: ${:.2} (no monthly_budget set)", spent),
+    }
+    Ok(())
+}
+
+/// `fake-ai [--port <port>] [--script <file.json>]`: serve scripted chat completions on
+/// localhost so the AI features can be exercised without a real backend.
+async fn run_fake_ai(args: &[String]) -> io::Result<()> {
+    fn usage() -> ! {
+        eprintln!("Usage: interactive-flashcards fake-ai [--port <port>] [--script <file.json>]");
+        std::process::exit(2);
+    }
+
+    let mut port: u16 = 8787;
+    let mut script = vec![fake_server::FakeReply::content(&fake_server::sample_evaluation())];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args
+                    .next()
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--script" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let parsed = std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| fake_server::parse_script(&content));
+                script = match parsed {
+                    Ok(script) => script,
+                    Err(e) => {
+                        eprintln!("{}: {}", path, e);
+                        std::process::exit(1);
+                    }
+                };
+            }
+            _ => usage(),
+        }
+    }
+
+    let server = fake_server::FakeAiServer::bind(([127, 0, 0, 1], port).into(), script).await?;
+    println!("Fake AI server listening on {}", server.base_url());
+    println!("Point the app at it with this in config.toml:\n");
+    println!("[ai]\nprovider = \"openai\"\nbase_url = \"{}\"\nmodel = \"fake\"", server.base_url());
+    server.wait().await;
+    Ok(())
+}
+
+/// `validate [--schema] <deck>...`: check deck files without starting the TUI. JSON/TOML
+/// decks get every schema error and missing-media warning; other formats report the
+/// first error their loader hits.
+fn run_validate(args: &[String]) -> io::Result<()> {
+    fn usage() -> ! {
+        eprintln!("Usage: interactive-flashcards validate [--schema] <deck>...");
+        std::process::exit(2);
+    }
+
+    if args.iter().any(|a| a == "--schema") {
+        print!("{}", structured::JSON_SCHEMA);
+        return Ok(());
+    }
+    if args.is_empty() {
+        usage();
+    }
+
+    let mut failed = false;
+    for arg in args {
+        let path = Path::new(arg);
+        if !is_deck_file(path) {
+            eprintln!(
+                "{}: not a deck file (.csv, .tsv, .md, .json or .toml)",
+                path.display()
+            );
+            failed = true;
+            continue;
+        }
+
+        let structured_deck = structured::StructuredFormat::for_path(path).is_some();
+        let markdown_deck = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("md"));
+        if !markdown_deck {
+            let issues = if structured_deck {
+                structured::validate_structured_deck(path)
+            } else {
+                validate_csv_deck(path)
+            };
+            let issues = match issues {
+                Ok(issues) => issues,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    failed = true;
+                    continue;
+                }
+            };
+            for issue in &issues {
+                eprintln!("{}: {}", path.display(), issue);
+            }
+            if issues
+                .iter()
+                .any(|i| i.severity == structured::Severity::Error)
+            {
+                failed = true;
+                continue;
+            }
+        }
+
+        match load_deck(path) {
+            Ok(deck) => println!("{}: ok ({} cards)", path.display(), deck.flashcards.len()),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The tag filter, if it was chosen for the currently selected deck (or folder).
+fn active_tag_filter<'a>(tag_filter: &'a Option<(String, String)>, deck_name: Option<&str>) -> Option<&'a str> {
+    tag_filter
+        .as_ref()
+        .filter(|(name, _)| Some(name.as_str()) == deck_name)
+        .map(|(_, tag)| tag.as_str())
+}
+
+/// Distinct tags used in a deck, sorted case-insensitively.
+fn deck_tags<'a>(cards: impl Iterator<Item = &'a Flashcard>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in cards.flat_map(|c| &c.meta.tags) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.sort_by_key(|t| t.to_lowercase());
+    tags
+}
+
+/// Take the `--decks <dir>` options (usable before or after a command) out of the arguments.
+fn split_deck_dir_args(args: Vec<String>) -> (Vec<PathBuf>, Vec<String>) {
+    let mut deck_dirs = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--decks" {
+            match args.next() {
+                Some(dir) => deck_dirs.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("--decks needs a directory");
+                    std::process::exit(2);
+                }
+            }
+        } else if let Some(dir) = arg.strip_prefix("--decks=") {
+            deck_dirs.push(PathBuf::from(dir));
+        } else {
+            rest.push(arg);
+        }
+    }
+    (deck_dirs, rest)
+}
+
+/// The session name and decks of the selected menu row: the deck itself, or every deck
+/// in a folder, studied together as `<folder>/`.
+fn selected_decks<'a>(
+    decks: &'a [DeckEntry],
+    rows: &[DeckTreeRow],
+    selected: usize,
+) -> Option<(String, Vec<&'a DeckEntry>)> {
+    match rows.get(selected)? {
+        DeckTreeRow::Deck { index, .. } => Some((decks[*index].name.clone(), vec![&decks[*index]])),
+        DeckTreeRow::Folder { group, .. } => Some((
+            format!("{}/", group),
+            decks.iter().filter(|d| d.in_group(group)).collect(),
+        )),
+    }
+}
+
+/// Load the cards of the selected decks, each paired with the name of its deck. A single
+/// deck keeps its metadata; a folder is titled after itself. Either way the `sources` are
+/// the paths of the source documents of every deck.
+fn load_selection(name: &str, entries: &[&DeckEntry]) -> Result<(Vec<(String, Flashcard)>, DeckMeta), String> {
+    let mut cards = Vec::new();
+    let mut meta = DeckMeta {
+        title: Some(name.to_string()),
+        description: Some(format!("{} decks", entries.len())),
+        ..Default::default()
+    };
+    for entry in entries {
+        let deck = load_deck(&entry.path).map_err(|e| format!("Cannot load {}: {}", entry.name, e))?;
+        let sources = ingest::resolve_sources(&entry.path, &deck.meta.sources);
+        if entries.len() == 1 {
+            meta = deck.meta;
+            meta.sources.clear();
+        }
+        for source in sources {
+            let source = source.to_string_lossy().to_string();
+            if !meta.sources.contains(&source) {
+                meta.sources.push(source);
+            }
+        }
+        cards.extend(deck.flashcards.into_iter().map(|c| (entry.name.clone(), c)));
+    }
+    Ok((cards, meta))
+}
+
+/// Keep the cards due by the end of today, each checked against the schedule of its own deck.
+fn filter_due(conn: &rusqlite::Connection, cards: Vec<(String, Flashcard)>) -> rusqlite::Result<Vec<(String, Flashcard)>> {
+    let until = scheduler::end_of_today();
+    let mut by_deck: Vec<(String, Vec<Flashcard>)> = Vec::new();
+    for (deck_name, card) in cards {
+        match by_deck.iter_mut().find(|(name, _)| *name == deck_name) {
+            Some((_, deck_cards)) => deck_cards.push(card),
+            None => by_deck.push((deck_name, vec![card])),
+        }
+    }
+
+    let mut due = Vec::new();
+    for (deck_name, deck_cards) in by_deck {
+        let deck_due = card::filter_due_cards(conn, &deck_name, deck_cards, until)?;
+        due.extend(deck_due.into_iter().map(|c| (deck_name.clone(), c)));
+    }
+    Ok(due)
+}
+
+fn display_dirs(dirs: &[PathBuf]) -> String {
+    dirs.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn deck_name_from_path(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown_deck".to_string())
+}
+
+/// Check in the background whether the backend of `ai_config` answers and accepts the
+/// key. The result comes back on `health_tx` with its `check` number.
+fn spawn_health_check(ai_config: &AiConfig, check: u64, health_tx: &mpsc::Sender<(u64, Result<(), AiError>)>) {
+    let ai_config = ai_config.clone();
+    let health_tx = health_tx.clone();
+    tokio::spawn(async move {
+        let _ = health_tx.send((check, ai_worker::check_health(&ai_config).await)).await;
+    });
+}
+
+/// Cut the document read from `path` into passages and ask the AI worker for cards from
+/// each as `request_id`; the review shows them as they arrive.
+fn start_generation(
+    path: &Path,
+    document: ingest::Document,
+    request_id: RequestId,
+    review: &mut GenerationReview,
+    ai_tx: &mpsc::Sender<AiRequest>,
+) -> Result<(), String> {
+    let chunks = ingest::chunk_document(&document, ingest::CHUNK_CHARS, ingest::CHUNK_OVERLAP);
+    let deck_name = deck_name_from_path(path);
+    logger::log(&format!("Generating cards from {} ({} passages)", path.display(), chunks.len()));
+
+    let source = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    review.start(&source, &deck_name, chunks.len(), request_id);
+    let request = AiRequest::GenerateCards {
+        request_id,
+        deck_name,
+        document: document.name,
+        chunks,
+    };
+    ai_tx
+        .try_send(request)
+        .map_err(|e| format!("Cannot start generating: {}", e))
+}
+
+/// Front-matter metadata of the menu deck a resumed session came from, if it is still there.
+fn resumed_deck_meta(decks: &[DeckEntry], deck_name: &str) -> DeckMeta {
+    decks
+        .iter()
+        .find(|d| d.name == deck_name)
+        .and_then(|entry| {
+            let mut meta = load_deck(&entry.path).ok()?.meta;
+            meta.sources = ingest::resolve_sources(&entry.path, &meta.sources)
+                .iter()
+                .map(|source| source.to_string_lossy().to_string())
+                .collect();
+            Some(meta)
+        })
+        .unwrap_or_default()
+}
+
+/// Reload the last-session status and today's due count for every deck in the menu.
+fn refresh_deck_statuses(
+    conn: &rusqlite::Connection,
+    decks: &[DeckEntry],
+    statuses: &mut [Option<db::session::DeckStatus>],
+) {
+    let until = scheduler::end_of_today();
+    for (entry, status) in decks.iter().zip(statuses.iter_mut()) {
+        let deck_name = &entry.name;
+        *status = session::get_last_session_status(conn, deck_name)
+            .ok()
+            .map(|mut s| {
+                s.due_count = load_deck(&entry.path)
+                    .ok()
+                    .and_then(|d| card::filter_due_cards(conn, deck_name, d.flashcards, until).ok())
+                    .map(|due| due.len())
+                    .unwrap_or(0);
+                s.scheduler = deck::get_deck_settings(conn, deck_name)
+                    .map(|settings| settings.scheduler)
+                    .unwrap_or_default();
+                s
+            });
+    }
+}
+
+/// Create the database session for a deck (or folder) and build a fresh quiz over `cards`,
+/// each paired with the name of the deck it belongs to.
+fn start_quiz_session(
+    deck_name: String,
+    mut deck_cards: Vec<(String, Flashcard)>,
+    deck_meta: DeckMeta,
+    ai_tx: Option<mpsc::Sender<AiRequest>>,
+    passages_tx: &mpsc::Sender<SourcePassages>,
+) -> Result<QuizSession, String> {
+    let ai_enabled = ai_tx.is_some();
+    deck_cards.shuffle(&mut rand::thread_rng());
+    let (card_decks, mut cards): (Vec<String>, Vec<Flashcard>) = deck_cards.into_iter().unzip();
+
+    let conn = db::init_db().map_err(|e| format!("Failed to initialize database: {}", e))?;
+
+    let session_id = session::create_session(&conn, &deck_name, cards.len())
+        .map_err(|e| format!("Failed to create session: {}", e))?;
+
+    let flashcards_data: Vec<(String, String)> = cards
+        .iter()
+        .map(|c| (c.question.clone(), c.answer.clone()))
+        .collect();
+
+    let ids = flashcard::initialize_flashcards_from_decks(&conn, session_id, &flashcards_data, &card_decks)
+        .map_err(|e| format!("Failed to initialize flashcards: {}", e))?;
+    for (card, id) in cards.iter_mut().zip(ids) {
+        card.id = Some(id);
+    }
+    // Keep the optional deck columns of the persistent cards in sync with the deck file
+    let metas: Vec<(u64, &CardMeta)> = cards
+        .iter()
+        .filter_map(|card| Some((card.id?, &card.meta)))
+        .collect();
+    if let Err(e) = flashcard::sync_card_meta(&conn, &metas) {
+        logger::log(&format!("Failed to save card metadata: {}", e));
+    }
+
+    let questions_total = cards.len();
+    let session = QuizSession {
+        flashcards: cards,
+        current_index: 0,
+        deck_name,
+        showing_answer: false,
+        input_buffer: String::new(),
+        cursor_position: 0,
+        session_id: Some(session_id),
+        questions_total,
+        questions_answered: 0,
+        ai_enabled,
+        ai_tx,
+        input_scroll_y: 0,
+        feedback_scroll_y: 0,
+        session_assessment: None,
+        assessment_loading: false,
+        assessment_requested: false,
+        assessment_error: None,
+        assessment_scroll_y: 0,
+        ai_unavailable: None,
+        chat_state: None,
+        deck_meta,
+        ai_usage: Default::default(),
+        source_passages: Vec::new(),
+        hints_revealed: Default::default(),
+        evaluations: Default::default(),
+    };
+    session.retrieve_source_passages(passages_tx);
+    Ok(session)
+}
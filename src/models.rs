@@ -1,5 +1,9 @@
-use crate::ai::AIFeedback;
+use crate::ai::{AIFeedback, SourcePassage};
+use crate::db::ai_usage::UsageTotals;
+use crate::deck::DeckMeta;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
 
@@ -45,7 +49,8 @@ pub struct ChatState {
     pub input_buffer: String,
     pub cursor_position: usize,
     pub scroll_y: u16,
-    pub is_loading: bool,
+    /// The chat request awaiting a reply, if any
+    pub pending_request: Option<RequestId>,
     pub error: Option<String>,
     pub read_only: bool,
     /// Cached rendered lines for display - rebuilt only when messages change
@@ -56,6 +61,77 @@ pub struct ChatState {
     pub max_scroll: u16,
     /// Scroll position for the input box (follows cursor)
     pub input_scroll_y: u16,
+    /// The assistant reply being streamed, shown after the cached messages until complete
+    pub streaming_reply: Option<String>,
+}
+
+impl ChatState {
+    /// Whether a reply is on its way.
+    pub fn is_loading(&self) -> bool {
+        self.pending_request.is_some()
+    }
+}
+
+/// Optional deck columns attached to a card
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CardMeta {
+    pub tags: Vec<String>,
+    pub hint: Option<String>,
+    pub source: Option<String>,
+    pub difficulty: Option<String>,
+    /// Other answers that count as correct besides the card's answer
+    pub accepted_answers: Vec<String>,
+    /// Card-specific instructions for the AI grader
+    pub grading_notes: Option<String>,
+    /// Files the card refers to, relative to the deck file
+    pub media: Vec<String>,
+}
+
+impl CardMeta {
+    /// Split a tags cell on `,`, `;` or `|`, dropping empty entries and a leading `#`.
+    pub fn parse_tags(value: &str) -> Vec<String> {
+        value
+            .split([',', ';', '|'])
+            .map(|t| t.trim().trim_start_matches('#').trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+}
+
+/// Identifies one AI request, so that it can be cancelled and its late responses told
+/// apart from those of a newer request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+impl RequestId {
+    /// An id not handed out before in this process.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        RequestId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Where a card's AI evaluation stands
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum EvaluationStatus {
+    /// Not waiting for an evaluation (the card may or may not have feedback)
+    #[default]
+    Idle,
+    /// Queued or running in the AI worker, with the explanation streamed so far
+    Pending {
+        request_id: RequestId,
+        explanation: String,
+    },
+    /// The last evaluation failed with this message; Ctrl+E retries it
+    Failed(String),
+    /// Not waiting; the feedback was reused from the evaluation cache and Ctrl+R asks
+    /// the model again
+    Cached,
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +142,7 @@ pub struct Flashcard {
     pub ai_feedback: Option<AIFeedback>,
     pub written_to_file: bool,
     pub id: Option<u64>,
+    pub meta: CardMeta,
 }
 
 #[derive(Debug)]
@@ -80,19 +157,30 @@ pub struct QuizSession {
     pub questions_total: usize,
     pub questions_answered: usize,
     pub ai_enabled: bool,
-    pub ai_evaluation_in_progress: bool,
-    pub ai_last_evaluated_index: Option<usize>,
-    pub ai_evaluation_start_time: Option<std::time::Instant>,
-    pub last_ai_error: Option<String>,
     pub ai_tx: Option<mpsc::Sender<AiRequest>>,
-    pub ai_rx: Option<mpsc::Receiver<AiResponse>>,
     pub input_scroll_y: u16,
     pub feedback_scroll_y: u16,
     pub session_assessment: Option<SessionAssessment>,
     pub assessment_loading: bool,
+    /// Whether the assessment being loaded has been sent to the AI worker
+    pub assessment_requested: bool,
     pub assessment_error: Option<String>,
     pub assessment_scroll_y: u16,
+    /// Why every AI request will fail (e.g. a rejected API key), until one succeeds again
+    pub ai_unavailable: Option<String>,
     pub chat_state: Option<ChatState>,
+    /// Front-matter metadata of the deck the session was started from
+    pub deck_meta: DeckMeta,
+    /// Tokens and cost of the session's AI calls so far
+    pub ai_usage: UsageTotals,
+    /// Passages of the deck's source documents for each card, by card index; empty when
+    /// the deck names none
+    pub source_passages: Vec<Vec<SourcePassage>>,
+    /// Indices of the cards whose hint has been revealed
+    pub hints_revealed: HashSet<usize>,
+    /// Where each card's AI evaluation stands, by card index; cards not in the map are
+    /// [`EvaluationStatus::Idle`]
+    pub evaluations: HashMap<usize, EvaluationStatus>,
 }
 
 impl QuizSession {
@@ -127,6 +215,51 @@ impl QuizSession {
 
         (answered_count, average_score * 100.0)
     }
+
+    /// Where the AI evaluation of the card at `index` stands.
+    pub fn evaluation(&self, index: usize) -> &EvaluationStatus {
+        self.evaluations
+            .get(&index)
+            .unwrap_or(&EvaluationStatus::Idle)
+    }
+
+    pub fn set_evaluation(&mut self, index: usize, status: EvaluationStatus) {
+        if status == EvaluationStatus::Idle {
+            self.evaluations.remove(&index);
+        } else {
+            self.evaluations.insert(index, status);
+        }
+    }
+
+    /// Whether the AI can assess this session: that needs the AI worker and a session
+    /// recorded in the database.
+    pub fn can_request_assessment(&self) -> bool {
+        self.ai_tx.is_some() && self.session_id.is_some()
+    }
+
+    /// Cards whose AI evaluation is queued or running.
+    pub fn evaluations_pending(&self) -> usize {
+        self.evaluations
+            .values()
+            .filter(|e| matches!(e, EvaluationStatus::Pending { .. }))
+            .count()
+    }
+
+    /// Cards whose last AI evaluation failed.
+    pub fn evaluations_failed(&self) -> usize {
+        self.evaluations
+            .values()
+            .filter(|e| matches!(e, EvaluationStatus::Failed(_)))
+            .count()
+    }
+
+    /// Cards whose feedback came from the evaluation cache.
+    pub fn evaluations_cached(&self) -> usize {
+        self.evaluations
+            .values()
+            .filter(|e| **e == EvaluationStatus::Cached)
+            .count()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -192,9 +325,7 @@ impl AsyncQuizSession {
     /// Check if there are pending AI responses
     pub async fn has_pending_ai_responses(&self) -> bool {
         let session = self.inner.read().await;
-        // For now, just check if AI evaluation is in progress
-        // In a real implementation, we'd check the channel length
-        session.ai_evaluation_in_progress
+        session.evaluations_pending() > 0
     }
 
     /// Get the current display state for UI rendering
@@ -208,10 +339,18 @@ impl AsyncQuizSession {
 #[derive(Debug)]
 pub enum AiRequest {
     Evaluate {
+        request_id: RequestId,
         flashcard_index: usize,
+        /// The stored session the answer belongs to, for usage accounting
+        session_id: Option<u64>,
+        /// Picks the deck's prompt template overrides
+        deck_name: String,
+        /// Ask the model even if the evaluation cache has an answer, replacing it
+        force: bool,
         question: String,
         correct_answer: String,
         user_answer: String,
+        grading: crate::ai::GradingContext,
     },
     EvaluateSession {
         session_id: u64,
@@ -219,36 +358,90 @@ pub enum AiRequest {
         flashcards: Vec<(String, String, Option<String>, Option<AIFeedback>)>,
     },
     Chat {
+        request_id: RequestId,
         flashcard_id: u64,
         session_id: u64,
+        deck_name: String,
         question: String,
         correct_answer: String,
         user_answer: String,
         initial_feedback: String,
         conversation_history: Vec<(String, String)>,
         user_message: String,
+        /// Passages of the deck's source documents about the card
+        passages: Vec<SourcePassage>,
+    },
+    /// Write flashcards from each passage of a document in turn; a `GeneratedCards`
+    /// response follows for every passage until one fails for good
+    GenerateCards {
+        request_id: RequestId,
+        /// The deck being written, which picks its prompt template overrides
+        deck_name: String,
+        /// File name of the document, quoted in the prompt
+        document: String,
+        chunks: Vec<crate::ingest::Chunk>,
     },
+    /// Abort an evaluation, chat or generation request still queued or running; no
+    /// response follows
+    Cancel { request_id: RequestId },
+    /// Run later requests with this configuration, rebuilding the client if it changed
+    Reconfigure { config: Box<crate::ai::AiConfig> },
 }
 
 #[derive(Debug)]
 pub enum AiResponse {
     Evaluation {
+        request_id: RequestId,
         flashcard_index: usize,
         result: crate::ai::AIEvaluationResult,
     },
     SessionAssessment {
         session_id: u64,
-        result: Result<SessionAssessment, String>,
+        result: Result<SessionAssessment, crate::ai::AiError>,
     },
     Error {
+        request_id: RequestId,
         flashcard_index: usize,
-        error: String,
+        error: crate::ai::AiError,
     },
     ChatReply {
+        request_id: RequestId,
         flashcard_id: u64,
         message: Option<String>,
         error: Option<String>,
     },
+    /// The next fragment of a chat reply being streamed; `ChatReply` follows with the whole reply
+    ChatDelta {
+        request_id: RequestId,
+        flashcard_id: u64,
+        delta: String,
+    },
+    /// The explanation of an evaluation being streamed, as far as it has arrived
+    EvaluationDelta {
+        request_id: RequestId,
+        flashcard_index: usize,
+        explanation: String,
+    },
+    /// The cards written from passage `chunk` (counting from 0) of the `chunks` in a
+    /// document, or why there are none
+    GeneratedCards {
+        request_id: RequestId,
+        chunk: usize,
+        chunks: usize,
+        result: Result<Vec<crate::ai::generator::GeneratedCard>, crate::ai::AiError>,
+    },
+    /// A call to the model finished; sent for every call, including retries and repairs
+    Usage {
+        record: crate::db::ai_usage::UsageRecord,
+    },
+}
+
+/// What the startup check found out about the configured AI backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum AiHealth {
+    Checking,
+    Ready,
+    Unavailable(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -261,6 +454,8 @@ pub struct UiState {
 pub enum UiStateTypes {
     Menu(UiMenuState),
     Quiz(UiQuizState),
+    Settings(Box<crate::settings::SettingsForm>),
+    Generate(Box<crate::ingest::GenerationReview>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -269,18 +464,23 @@ pub struct UiMenuState {
     pub selected_session_index: usize,
     pub focused_panel: usize, // 0 = CSV, 1 = Sessions
     pub sessions_count: usize,
+    /// Visible rows of the deck tree (changes when a folder is collapsed or expanded)
+    pub deck_rows: usize,
+    pub status_message: Option<String>,
+    pub tag_filter: Option<String>,
+    pub ai_health: AiHealth,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct UiQuizState {
     pub current_index: usize,
     pub showing_answer: bool,
-    pub ai_evaluation_in_progress: bool,
+    pub evaluations_pending: usize,
     pub input_buffer_len: usize,
     pub cursor_position: usize,
     pub input_scroll_y: u16,
     pub feedback_scroll_y: u16,
-    pub has_ai_error: bool,
+    pub evaluations_failed: usize,
     pub questions_answered: usize,
     pub ai_feedback_count: usize,
     pub chat_open: bool,
@@ -288,6 +488,7 @@ pub struct UiQuizState {
     pub chat_input_len: usize,
     pub chat_is_loading: bool,
     pub chat_scroll_y: u16,
+    pub hint_revealed: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -297,6 +498,8 @@ pub enum AppState {
     Quiz,
     QuizQuitConfirm,
     Summary,
+    Settings,
+    Generate,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -332,19 +535,21 @@ mod tests {
             questions_total: flashcards.len(),
             questions_answered: 0, // This is updated during quiz, but calculate_stats relies on user_answer present
             ai_enabled: true,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: None,
-            ai_rx: None,
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         }
     }
 
@@ -364,6 +569,7 @@ mod tests {
                 }),
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
             Flashcard {
                 question: "Q2".to_string(),
@@ -378,6 +584,7 @@ mod tests {
                 }),
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
         ];
         let session = create_test_session(flashcards);
@@ -402,6 +609,7 @@ mod tests {
                 }),
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
             Flashcard {
                 question: "Q2".to_string(),
@@ -416,6 +624,7 @@ mod tests {
                 }),
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
         ];
         let session = create_test_session(flashcards);
@@ -440,6 +649,7 @@ mod tests {
                 }),
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
             Flashcard {
                 question: "Q2".to_string(),
@@ -448,6 +658,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             },
         ];
         let session = create_test_session(flashcards);
@@ -464,4 +675,26 @@ mod tests {
         assert_eq!(answered, 0);
         assert_eq!(score, 0.0);
     }
+
+    #[test]
+    fn test_evaluations_are_kept_by_card_index() {
+        let mut session = create_test_session(vec![]);
+        assert_eq!(session.evaluation(3), &EvaluationStatus::Idle);
+
+        session.set_evaluation(
+            3,
+            EvaluationStatus::Pending {
+                request_id: RequestId(1),
+                explanation: String::new(),
+            },
+        );
+        session.set_evaluation(5, EvaluationStatus::Failed("timed out".to_string()));
+        assert_eq!(session.evaluations_pending(), 1);
+        assert_eq!(session.evaluations_failed(), 1);
+
+        // Idle cards are not stored
+        session.set_evaluation(3, EvaluationStatus::Idle);
+        assert_eq!(session.evaluations_pending(), 0);
+        assert!(!session.evaluations.contains_key(&3));
+    }
 }
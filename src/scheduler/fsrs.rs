@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+
+/// FSRS-4.5 default weights, fitted by the FSRS authors on a large public review dataset
+pub const DEFAULT_WEIGHTS: [f32; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+/// Probability of recall the scheduler aims for when picking the next interval
+pub const DEFAULT_DESIRED_RETENTION: f32 = 0.9;
+/// Minimum number of graded re-reviews needed before the optimizer will refit weights
+pub const MIN_REVIEWS_FOR_OPTIMIZER: usize = 20;
+
+const DECAY: f32 = -0.5;
+const FACTOR: f32 = 19.0 / 81.0;
+const MIN_DIFFICULTY: f32 = 1.0;
+const MAX_DIFFICULTY: f32 = 10.0;
+const MIN_STABILITY: f32 = 0.01;
+const MAX_INTERVAL_DAYS: u32 = 36_500;
+
+/// FSRS answer grade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Grade {
+    /// Map an AI correctness score (0.0 - 1.0) onto the four FSRS grades.
+    pub fn from_score(score: f32) -> Self {
+        if score < 0.4 {
+            Grade::Again
+        } else if score < 0.7 {
+            Grade::Hard
+        } else if score < 0.9 {
+            Grade::Good
+        } else {
+            Grade::Easy
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 | 1 => Grade::Again,
+            2 => Grade::Hard,
+            3 => Grade::Good,
+            _ => Grade::Easy,
+        }
+    }
+
+    fn value(self) -> f32 {
+        self as u8 as f32
+    }
+}
+
+/// Memory state of a card: stability is the interval (in days) at which recall
+/// probability drops to 90%, difficulty ranges from 1 (easy) to 10 (hard).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FsrsState {
+    pub stability: f32,
+    pub difficulty: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FsrsParams {
+    pub weights: [f32; 17],
+    pub desired_retention: f32,
+}
+
+impl Default for FsrsParams {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: DEFAULT_DESIRED_RETENTION,
+        }
+    }
+}
+
+/// Probability of recalling a card `elapsed_days` after its last review.
+pub fn retrievability(elapsed_days: f32, stability: f32) -> f32 {
+    (1.0 + FACTOR * elapsed_days.max(0.0) / stability.max(MIN_STABILITY)).powf(DECAY)
+}
+
+impl FsrsParams {
+    /// Days until recall probability decays to the desired retention.
+    pub fn next_interval(&self, stability: f32) -> u32 {
+        let retention = self.desired_retention.clamp(0.5, 0.99);
+        let interval = stability / FACTOR * (retention.powf(1.0 / DECAY) - 1.0);
+        (interval.round() as u32).clamp(1, MAX_INTERVAL_DAYS)
+    }
+
+    fn initial_difficulty(&self, grade: Grade) -> f32 {
+        let w = &self.weights;
+        (w[4] - (grade.value() - 3.0) * w[5]).clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    fn initial_state(&self, grade: Grade) -> FsrsState {
+        FsrsState {
+            stability: self.weights[grade as usize - 1].max(MIN_STABILITY),
+            difficulty: self.initial_difficulty(grade),
+        }
+    }
+
+    fn next_difficulty(&self, difficulty: f32, grade: Grade) -> f32 {
+        let w = &self.weights;
+        let next = difficulty - w[6] * (grade.value() - 3.0);
+        // Mean reversion towards the difficulty of a "Good" first answer
+        let reverted = w[7] * self.initial_difficulty(Grade::Good) + (1.0 - w[7]) * next;
+        reverted.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    fn recall_stability(&self, state: FsrsState, r: f32, grade: Grade) -> f32 {
+        let w = &self.weights;
+        let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+        let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+        state.stability
+            * (w[8].exp()
+                * (11.0 - state.difficulty)
+                * state.stability.powf(-w[9])
+                * ((w[10] * (1.0 - r)).exp() - 1.0)
+                * hard_penalty
+                * easy_bonus
+                + 1.0)
+    }
+
+    fn forget_stability(&self, state: FsrsState, r: f32) -> f32 {
+        let w = &self.weights;
+        w[11]
+            * state.difficulty.powf(-w[12])
+            * ((state.stability + 1.0).powf(w[13]) - 1.0)
+            * (w[14] * (1.0 - r)).exp()
+    }
+
+    /// Apply one review. `state` is `None` for a card that has never been reviewed.
+    pub fn next_state(
+        &self,
+        state: Option<FsrsState>,
+        elapsed_days: f32,
+        grade: Grade,
+    ) -> FsrsState {
+        let state = match state {
+            Some(s) => s,
+            None => return self.initial_state(grade),
+        };
+
+        let r = retrievability(elapsed_days, state.stability);
+        let stability = if grade == Grade::Again {
+            self.forget_stability(state, r).min(state.stability)
+        } else {
+            self.recall_stability(state, r, grade)
+        };
+
+        FsrsState {
+            stability: stability.max(MIN_STABILITY),
+            difficulty: self.next_difficulty(state.difficulty, grade),
+        }
+    }
+}
+
+/// One card's review history: (days since the previous review, grade), oldest first
+pub type ReviewHistory = Vec<(f32, Grade)>;
+
+fn log_loss(params: &FsrsParams, histories: &[ReviewHistory]) -> (f64, usize) {
+    let mut loss = 0.0f64;
+    let mut count = 0;
+
+    for history in histories {
+        let mut state: Option<FsrsState> = None;
+        for &(elapsed_days, grade) in history {
+            if let Some(s) = state {
+                let predicted =
+                    retrievability(elapsed_days, s.stability).clamp(1e-4, 1.0 - 1e-4) as f64;
+                let recalled = grade != Grade::Again;
+                loss -= if recalled {
+                    predicted.ln()
+                } else {
+                    (1.0 - predicted).ln()
+                };
+                count += 1;
+            }
+            state = Some(params.next_state(state, elapsed_days, grade));
+        }
+    }
+
+    (loss, count)
+}
+
+fn weight_bounds(index: usize) -> (f32, f32) {
+    match index {
+        0..=3 => (0.01, 100.0),
+        4 => (1.0, 10.0),
+        7 => (0.0, 0.5),
+        15 => (0.0, 1.0),
+        16 => (1.0, 6.0),
+        _ => (0.0, 5.0),
+    }
+}
+
+/// Refit the weights to a deck's review log by minimising the log loss of the
+/// predicted recall probability (coordinate descent with a shrinking step size).
+pub fn optimize(params: &FsrsParams, histories: &[ReviewHistory]) -> Result<FsrsParams, String> {
+    let (mut best_loss, count) = log_loss(params, histories);
+    if count < MIN_REVIEWS_FOR_OPTIMIZER {
+        return Err(format!(
+            "Not enough review history to optimize: {} re-reviews, need at least {}",
+            count, MIN_REVIEWS_FOR_OPTIMIZER
+        ));
+    }
+
+    let mut best = params.clone();
+    let mut step = 0.2f32;
+
+    while step > 0.005 {
+        let mut improved = false;
+        for i in 0..best.weights.len() {
+            let (lo, hi) = weight_bounds(i);
+            for direction in [1.0f32, -1.0] {
+                let mut candidate = best.clone();
+                let current = candidate.weights[i];
+                let delta = if current.abs() > 1e-3 {
+                    current.abs() * step
+                } else {
+                    step
+                };
+                candidate.weights[i] = (current + direction * delta).clamp(lo, hi);
+                if candidate.weights[i] == current {
+                    continue;
+                }
+
+                let (loss, _) = log_loss(&candidate, histories);
+                if loss < best_loss - 1e-9 {
+                    best_loss = loss;
+                    best = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grade_from_score() {
+        assert_eq!(Grade::from_score(0.0), Grade::Again);
+        assert_eq!(Grade::from_score(0.5), Grade::Hard);
+        assert_eq!(Grade::from_score(0.8), Grade::Good);
+        assert_eq!(Grade::from_score(1.0), Grade::Easy);
+    }
+
+    #[test]
+    fn test_retrievability_at_stability_is_ninety_percent() {
+        assert!((retrievability(0.0, 5.0) - 1.0).abs() < 1e-6);
+        assert!((retrievability(10.0, 10.0) - 0.9).abs() < 1e-4);
+        assert!(retrievability(30.0, 10.0) < 0.9);
+    }
+
+    #[test]
+    fn test_first_review_uses_initial_stability() {
+        let params = FsrsParams::default();
+        let state = params.next_state(None, 0.0, Grade::Good);
+        assert_eq!(state.stability, DEFAULT_WEIGHTS[2]);
+        assert_eq!(state.difficulty, DEFAULT_WEIGHTS[4]);
+
+        let easy = params.next_state(None, 0.0, Grade::Easy);
+        assert!(easy.stability > state.stability);
+        assert!(easy.difficulty < state.difficulty);
+    }
+
+    #[test]
+    fn test_successful_recall_grows_stability() {
+        let params = FsrsParams::default();
+        let state = params.next_state(None, 0.0, Grade::Good);
+        let next = params.next_state(Some(state), 3.0, Grade::Good);
+        assert!(next.stability > state.stability);
+    }
+
+    #[test]
+    fn test_lapse_shrinks_stability_and_raises_difficulty() {
+        let params = FsrsParams::default();
+        let state = FsrsState {
+            stability: 20.0,
+            difficulty: 5.0,
+        };
+        let next = params.next_state(Some(state), 20.0, Grade::Again);
+        assert!(next.stability < state.stability);
+        assert!(next.difficulty > state.difficulty);
+    }
+
+    #[test]
+    fn test_next_interval_matches_stability_at_default_retention() {
+        let params = FsrsParams::default();
+        assert_eq!(params.next_interval(10.0), 10);
+        assert_eq!(params.next_interval(0.1), 1);
+    }
+
+    #[test]
+    fn test_optimize_requires_history() {
+        let histories = vec![vec![(0.0, Grade::Good), (1.0, Grade::Good)]];
+        assert!(optimize(&FsrsParams::default(), &histories).is_err());
+    }
+
+    #[test]
+    fn test_optimize_does_not_increase_loss() {
+        // A learner who forgets everything reviewed after more than two days
+        let histories: Vec<ReviewHistory> = (0..15)
+            .map(|i| {
+                let gap = (i % 5) as f32 + 1.0;
+                vec![
+                    (0.0, Grade::Good),
+                    (gap, if gap > 2.0 { Grade::Again } else { Grade::Good }),
+                    (gap, if gap > 2.0 { Grade::Again } else { Grade::Good }),
+                ]
+            })
+            .collect();
+
+        let defaults = FsrsParams::default();
+        let optimized = optimize(&defaults, &histories).unwrap();
+        let (before, _) = log_loss(&defaults, &histories);
+        let (after, _) = log_loss(&optimized, &histories);
+        assert!(after < before);
+    }
+}
@@ -0,0 +1,57 @@
+pub mod fsrs;
+pub mod sm2;
+
+pub use fsrs::{FsrsParams, FsrsState, Grade};
+pub use sm2::{quality_from_score, Sm2State};
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Scheduling algorithm used to pick the next review date of a deck's cards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedulerKind {
+    #[default]
+    Sm2,
+    Fsrs,
+}
+
+impl SchedulerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SchedulerKind::Sm2 => "sm2",
+            SchedulerKind::Fsrs => "fsrs",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sm2" | "sm-2" => Some(SchedulerKind::Sm2),
+            "fsrs" => Some(SchedulerKind::Fsrs),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SchedulerKind::Sm2 => "SM-2",
+            SchedulerKind::Fsrs => "FSRS",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            SchedulerKind::Sm2 => SchedulerKind::Fsrs,
+            SchedulerKind::Fsrs => SchedulerKind::Sm2,
+        }
+    }
+}
+
+/// Unix timestamp of the last second of the current local day.
+/// Cards due at any point today are considered due for a "due today" quiz.
+pub fn end_of_today() -> u64 {
+    let now = chrono::Local::now();
+    now.date_naive()
+        .and_hms_opt(23, 59, 59)
+        .and_then(|dt| dt.and_local_timezone(chrono::Local).latest())
+        .map(|dt| dt.timestamp() as u64)
+        .unwrap_or_else(|| now.timestamp() as u64)
+}
@@ -0,0 +1,126 @@
+/// Starting ease factor for a card that has never been reviewed
+pub const DEFAULT_EASE_FACTOR: f32 = 2.5;
+/// SM-2 never lets the ease factor drop below this value
+pub const MIN_EASE_FACTOR: f32 = 1.3;
+/// Lowest quality grade that still counts as a successful recall
+pub const PASSING_QUALITY: u8 = 3;
+
+/// Per-card SM-2 scheduling state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sm2State {
+    pub ease_factor: f32,
+    pub interval_days: u32,
+    pub repetitions: u32,
+}
+
+impl Default for Sm2State {
+    fn default() -> Self {
+        Self {
+            ease_factor: DEFAULT_EASE_FACTOR,
+            interval_days: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+/// Convert an AI correctness score (0.0 - 1.0) into an SM-2 quality grade (0 - 5).
+pub fn quality_from_score(score: f32) -> u8 {
+    (score.clamp(0.0, 1.0) * 5.0).round() as u8
+}
+
+impl Sm2State {
+    /// Apply one review with the given quality grade and return the next state.
+    ///
+    /// Failed recalls (quality < 3) restart the repetition count with a one day
+    /// interval; the ease factor is updated on every review as in the original
+    /// SuperMemo 2 algorithm.
+    pub fn review(&self, quality: u8) -> Sm2State {
+        let quality = quality.min(5);
+        let q = (5 - quality) as f32;
+        let ease_factor = (self.ease_factor + (0.1 - q * (0.08 + q * 0.02))).max(MIN_EASE_FACTOR);
+
+        if quality < PASSING_QUALITY {
+            return Sm2State {
+                ease_factor,
+                interval_days: 1,
+                repetitions: 0,
+            };
+        }
+
+        let repetitions = self.repetitions + 1;
+        let interval_days = match repetitions {
+            1 => 1,
+            2 => 6,
+            _ => ((self.interval_days.max(1) as f32) * self.ease_factor).round() as u32,
+        };
+
+        Sm2State {
+            ease_factor,
+            interval_days,
+            repetitions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_from_score() {
+        assert_eq!(quality_from_score(0.0), 0);
+        assert_eq!(quality_from_score(0.5), 3);
+        assert_eq!(quality_from_score(0.85), 4);
+        assert_eq!(quality_from_score(1.0), 5);
+        assert_eq!(quality_from_score(1.7), 5);
+        assert_eq!(quality_from_score(-0.3), 0);
+    }
+
+    #[test]
+    fn test_first_reviews_follow_fixed_intervals() {
+        let state = Sm2State::default();
+
+        let first = state.review(5);
+        assert_eq!(first.repetitions, 1);
+        assert_eq!(first.interval_days, 1);
+
+        let second = first.review(5);
+        assert_eq!(second.repetitions, 2);
+        assert_eq!(second.interval_days, 6);
+
+        let third = second.review(5);
+        assert_eq!(third.repetitions, 3);
+        assert_eq!(
+            third.interval_days,
+            (6.0 * second.ease_factor).round() as u32
+        );
+    }
+
+    #[test]
+    fn test_perfect_recall_increases_ease() {
+        let next = Sm2State::default().review(5);
+        assert!((next.ease_factor - 2.6).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_failed_recall_resets_repetitions() {
+        let state = Sm2State {
+            ease_factor: 2.5,
+            interval_days: 15,
+            repetitions: 4,
+        };
+        let next = state.review(1);
+        assert_eq!(next.repetitions, 0);
+        assert_eq!(next.interval_days, 1);
+        assert!(next.ease_factor < state.ease_factor);
+    }
+
+    #[test]
+    fn test_ease_factor_never_below_minimum() {
+        let mut state = Sm2State::default();
+        for _ in 0..20 {
+            state = state.review(0);
+        }
+        assert_eq!(state.ease_factor, MIN_EASE_FACTOR);
+    }
+}
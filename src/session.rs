@@ -1,10 +1,52 @@
-use crate::db::{self, chat, flashcard, session};
+use crate::ai::{AIFeedback, GradingContext, SourcePassage};
+use crate::db::{self, card, chat, flashcard, session};
+use crate::ingest;
 use crate::logger;
 use crate::models::{
-    AiRequest, AiResponse, AppState, ChatMessage, ChatRole, ChatState, QuizSession,
+    AiRequest, AiResponse, AppState, ChatMessage, ChatRole, ChatState, EvaluationStatus,
+    QuizSession, RequestId,
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::io;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// The source passages of each card of a session, by session id
+pub type SourcePassages = (u64, Vec<Vec<SourcePassage>>);
+
+/// Index the documents at `paths`, which `deck_name` names, and find the passages that best match each card's
+/// question and answer. Nothing is found when no document could be indexed.
+fn find_source_passages(
+    conn: &rusqlite::Connection,
+    deck_name: &str,
+    paths: &[PathBuf],
+    cards: &[(String, String)],
+) -> Vec<Vec<SourcePassage>> {
+    let (document_ids, problems) = ingest::index_sources(conn, deck_name, paths);
+    for problem in problems {
+        logger::log(&problem);
+    }
+    if document_ids.is_empty() {
+        return Vec::new();
+    }
+    cards
+        .iter()
+        .map(|(question, answer)| {
+            ingest::retrieve_passages(
+                conn,
+                &document_ids,
+                question,
+                answer,
+                ingest::PASSAGES_PER_CARD,
+            )
+            .unwrap_or_else(|e| {
+                logger::log(&format!("Cannot search the source documents: {}", e));
+                Vec::new()
+            })
+        })
+        .collect()
+}
 
 pub fn handle_quiz_input(
     session: &mut QuizSession,
@@ -24,7 +66,6 @@ pub fn handle_quiz_input(
                     session.showing_answer = session.flashcards[session.current_index]
                         .user_answer
                         .is_some();
-                    session.last_ai_error = None;
                     if !session.showing_answer {
                         // Restore input buffer for unanswered questions
                         session.input_buffer = session.flashcards[session.current_index]
@@ -45,7 +86,6 @@ pub fn handle_quiz_input(
                     session.showing_answer = session.flashcards[session.current_index]
                         .user_answer
                         .is_some();
-                    session.last_ai_error = None;
                     if !session.showing_answer {
                         // Restore input buffer for unanswered questions
                         session.input_buffer = session.flashcards[session.current_index]
@@ -106,13 +146,14 @@ pub fn handle_quiz_input(
                         }
                     }
 
-                    session.last_ai_error = None;
                     session.input_buffer.clear();
                     session.cursor_position = 0;
                     session.showing_answer = true;
 
                     if session.ai_enabled {
                         session.request_ai_evaluation(session.current_index);
+                    } else {
+                        session.evaluate_offline(session.current_index);
                     }
 
                     Ok(())
@@ -141,6 +182,12 @@ pub fn handle_quiz_input(
                 }
                 Ok(())
             }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if session.flashcards[session.current_index].meta.hint.is_some() {
+                    session.hints_revealed.insert(session.current_index);
+                }
+                Ok(())
+            }
             KeyCode::Char(c) => {
                 session.input_buffer.insert(session.cursor_position, c);
                 session.cursor_position += 1;
@@ -161,7 +208,6 @@ pub fn handle_quiz_input(
                     session.showing_answer = session.flashcards[session.current_index]
                         .user_answer
                         .is_some();
-                    session.last_ai_error = None;
                     if !session.showing_answer {
                         // Restore input buffer for unanswered questions
                         session.input_buffer = session.flashcards[session.current_index]
@@ -182,7 +228,6 @@ pub fn handle_quiz_input(
                     session.showing_answer = session.flashcards[session.current_index]
                         .user_answer
                         .is_some();
-                    session.last_ai_error = None;
                     if !session.showing_answer {
                         // Restore input buffer for unanswered questions
                         session.input_buffer = session.flashcards[session.current_index]
@@ -203,7 +248,6 @@ pub fn handle_quiz_input(
                     session.showing_answer = session.flashcards[session.current_index]
                         .user_answer
                         .is_some();
-                    session.last_ai_error = None;
                     if !session.showing_answer {
                         // Restore input buffer for unanswered questions
                         session.input_buffer = session.flashcards[session.current_index]
@@ -220,25 +264,27 @@ pub fn handle_quiz_input(
                             let _ = session::complete_session(&conn, session_id);
                         }
                     *app_state = AppState::Summary;
-                    session.assessment_loading = true;
+                    // Offline the summary shows the local stats only
+                    session.assessment_loading = session.can_request_assessment();
                     session.assessment_error = None;
                 }
                 Ok(())
             }
             KeyCode::Char('e') => {
                 if key.modifiers.contains(KeyModifiers::CONTROL) && session.ai_enabled {
-                    session.last_ai_error = None;
                     session.manual_trigger_ai_evaluation();
                 }
                 Ok(())
             }
             KeyCode::Char('x') => {
-                if key.modifiers.contains(KeyModifiers::CONTROL)
-                    && session.ai_enabled
-                    && session.ai_evaluation_in_progress
-                {
-                    session.ai_evaluation_in_progress = false;
-                    session.last_ai_error = Some("Evaluation cancelled".to_string());
+                if key.modifiers.contains(KeyModifiers::CONTROL) && session.ai_enabled {
+                    session.cancel_ai_evaluation(session.current_index);
+                }
+                Ok(())
+            }
+            KeyCode::Char('r') => {
+                if key.modifiers.contains(KeyModifiers::CONTROL) && session.ai_enabled {
+                    session.force_ai_evaluation(session.current_index);
                 }
                 Ok(())
             }
@@ -259,17 +305,25 @@ pub fn handle_quiz_input(
 }
 
 impl QuizSession {
+    /// Queue an AI evaluation of a card's answer. Cards already queued are skipped; the
+    /// worker runs a few evaluations at a time, so the user can keep answering.
     pub fn request_ai_evaluation(&mut self, flashcard_index: usize) {
-        if !self.ai_enabled || self.ai_evaluation_in_progress {
-            return;
-        }
+        self.queue_evaluation(flashcard_index, false);
+    }
 
-        if let Some(last_idx) = self.ai_last_evaluated_index
-            && last_idx == flashcard_index {
-                return;
-            }
+    /// Queue an evaluation that skips the evaluation cache and replaces its entry.
+    pub fn force_ai_evaluation(&mut self, flashcard_index: usize) {
+        self.queue_evaluation(flashcard_index, true);
+    }
 
+    fn queue_evaluation(&mut self, flashcard_index: usize, force: bool) {
         let flashcard = &self.flashcards[flashcard_index];
+        if !self.ai_enabled
+            || matches!(self.evaluation(flashcard_index), EvaluationStatus::Pending { .. })
+        {
+            return;
+        }
+
         let user_answer = match &flashcard.user_answer {
             Some(ans) => ans.clone(),
             None => return,
@@ -279,28 +333,229 @@ impl QuizSession {
             return;
         }
 
-        self.last_ai_error = None; // Clear any previous error before starting new evaluation
-        self.ai_evaluation_start_time = Some(std::time::Instant::now()); // Track when evaluation started
         logger::log(&format!(
             "Sending AI request for flashcard {}",
             flashcard_index
         ));
 
-        if let Some(ai_tx) = self.ai_tx.clone() {
-            let request = AiRequest::Evaluate {
-                flashcard_index,
-                question: flashcard.question.clone(),
-                correct_answer: flashcard.answer.clone(),
-                user_answer: user_answer.clone(),
+        let request_id = RequestId::next();
+        let sent = self.send_ai_request(AiRequest::Evaluate {
+            request_id,
+            flashcard_index,
+            session_id: self.session_id,
+            deck_name: self.deck_name.clone(),
+            question: flashcard.question.clone(),
+            correct_answer: flashcard.answer.clone(),
+            user_answer,
+            grading: self.grading_context(flashcard_index),
+            force,
+        });
+        let status = match sent {
+            Ok(()) => {
+                logger::log("AI request sent through async channel");
+                EvaluationStatus::Pending {
+                    request_id,
+                    explanation: String::new(),
+                }
+            }
+            Err(e) => {
+                logger::log(&format!("Cannot send AI request: {}", e));
+                EvaluationStatus::Failed(format!(
+                    "AI evaluation failed: {} - press Ctrl+E to retry",
+                    e
+                ))
+            }
+        };
+        self.set_evaluation(flashcard_index, status);
+    }
+
+    /// Stop waiting for a card's evaluation and abort it in the worker. Any response
+    /// that was already on its way is ignored.
+    pub fn cancel_ai_evaluation(&mut self, flashcard_index: usize) {
+        let EvaluationStatus::Pending { request_id, .. } = *self.evaluation(flashcard_index) else {
+            return;
+        };
+        self.set_evaluation(
+            flashcard_index,
+            EvaluationStatus::Failed("Evaluation cancelled".to_string()),
+        );
+        logger::log(&format!(
+            "Cancelling AI request {} for flashcard {}",
+            request_id.0, flashcard_index
+        ));
+        if let Err(e) = self.send_ai_request(AiRequest::Cancel { request_id }) {
+            logger::log(&format!("Cannot cancel AI request {}: {}", request_id.0, e));
+        }
+    }
+
+    /// Whether a chat reply belongs to the request the open chat is waiting for.
+    fn awaits_chat_reply(&self, request_id: RequestId) -> bool {
+        self.chat_state
+            .as_ref()
+            .is_some_and(|chat| chat.pending_request == Some(request_id))
+    }
+
+    /// Queue a request for the AI worker. The request is queued before this returns, so
+    /// the worker sees requests in the order they were made and a cancellation never
+    /// overtakes the request it cancels.
+    fn send_ai_request(&self, request: AiRequest) -> Result<(), String> {
+        let Some(ai_tx) = &self.ai_tx else {
+            return Ok(());
+        };
+        ai_tx.try_send(request).map_err(|e| match e {
+            TrySendError::Full(_) => "too many AI requests queued".to_string(),
+            TrySendError::Closed(_) => "the AI worker has stopped".to_string(),
+        })
+    }
+
+    /// Whether a response to `request_id` is the one the card is waiting for, rather than
+    /// a late reply to a request that was cancelled or superseded.
+    fn awaits_evaluation(&self, flashcard_index: usize, request_id: RequestId) -> bool {
+        let awaited = matches!(
+            self.evaluation(flashcard_index),
+            EvaluationStatus::Pending { request_id: pending, .. } if *pending == request_id
+        );
+        if !awaited {
+            logger::log(&format!(
+                "Ignoring stale response to AI request {} for flashcard {}",
+                request_id.0, flashcard_index
+            ));
+        }
+        awaited
+    }
+
+    /// Queue every answered card that has no AI feedback yet or whose evaluation failed,
+    /// e.g. answers left unevaluated when a session was quit.
+    pub fn queue_unevaluated(&mut self) {
+        for index in 0..self.flashcards.len() {
+            if self.flashcards[index].ai_feedback.is_none()
+                || matches!(self.evaluation(index), EvaluationStatus::Failed(_))
+            {
+                self.request_ai_evaluation(index);
+            }
+        }
+    }
+
+    /// Send the session assessment once every queued evaluation has come back, so the
+    /// assessment sees every grade. Does nothing once it has been requested. Returns
+    /// whether the summary changed because the request could not be sent.
+    pub fn request_assessment_when_ready(&mut self) -> bool {
+        if !self.assessment_loading
+            || self.assessment_requested
+            || self.session_assessment.is_some()
+            || self.assessment_error.is_some()
+            || self.evaluations_pending() > 0
+        {
+            return false;
+        }
+        let Some(session_id) = self.session_id else {
+            return false;
+        };
+        let flashcards = self
+            .flashcards
+            .iter()
+            .map(|fc| {
+                (
+                    fc.question.clone(),
+                    fc.answer.clone(),
+                    fc.user_answer.clone(),
+                    fc.ai_feedback.clone(),
+                )
+            })
+            .collect();
+        let request = AiRequest::EvaluateSession {
+            session_id,
+            deck_name: self.deck_name.clone(),
+            flashcards,
+        };
+        match self.send_ai_request(request) {
+            Ok(()) => {
+                self.assessment_requested = true;
+                logger::log("Triggered session assessment request");
+                false
+            }
+            Err(e) => {
+                self.assessment_loading = false;
+                self.assessment_error = Some(format!("Cannot request the assessment: {}", e));
+                true
+            }
+        }
+    }
+
+    fn grading_context(&self, flashcard_index: usize) -> GradingContext {
+        let flashcard = &self.flashcards[flashcard_index];
+        GradingContext {
+            rubric: self.deck_meta.rubric.clone(),
+            accepted_answers: flashcard.meta.accepted_answers.clone(),
+            notes: flashcard.meta.grading_notes.clone(),
+            passages: self.passages(flashcard_index).to_vec(),
+        }
+    }
+
+    /// The passages of the deck's source documents found for a card.
+    pub fn passages(&self, flashcard_index: usize) -> &[SourcePassage] {
+        self.source_passages
+            .get(flashcard_index)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Index the deck's source documents and look up the passages that best match each
+    /// card, for the AI to grade and discuss answers against. This runs on a blocking
+    /// thread while the quiz goes on; the passages arrive on `passages_tx` for
+    /// [`Self::set_source_passages`]. Documents that cannot be read are logged and left out.
+    pub fn retrieve_source_passages(&self, passages_tx: &mpsc::Sender<SourcePassages>) {
+        if !self.ai_enabled || self.deck_meta.sources.is_empty() {
+            return;
+        }
+        // Without a session there is no database to keep the index in
+        let Some(session_id) = self.session_id else {
+            return;
+        };
+        let paths: Vec<PathBuf> = self.deck_meta.sources.iter().map(PathBuf::from).collect();
+        let cards: Vec<(String, String)> = self
+            .flashcards
+            .iter()
+            .map(|card| (card.question.clone(), card.answer.clone()))
+            .collect();
+        let deck_name = self.deck_name.clone();
+        let passages_tx = passages_tx.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = match db::init_db() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    logger::log(&format!("Cannot open the source index: {}", e));
+                    return;
+                }
             };
-            tokio::spawn(async move {
-                let _ = ai_tx.send(request).await;
-            });
-            logger::log("AI request sent through async channel");
+            let passages = find_source_passages(&conn, &deck_name, &paths, &cards);
+            if !passages.is_empty() {
+                let _ = passages_tx.blocking_send((session_id, passages));
+            }
+        });
+    }
+
+    /// Use the passages found by [`Self::retrieve_source_passages`], unless they are for
+    /// another session.
+    pub fn set_source_passages(&mut self, session_id: u64, passages: Vec<Vec<SourcePassage>>) {
+        if self.session_id == Some(session_id) && passages.len() == self.flashcards.len() {
+            self.source_passages = passages;
         }
+    }
 
-        self.ai_evaluation_in_progress = true;
-        logger::log("Set ai_evaluation_in_progress = true");
+    /// Grade an answer with the built-in heuristics, for when no AI backend is available.
+    pub fn evaluate_offline(&mut self, flashcard_index: usize) {
+        let flashcard = &self.flashcards[flashcard_index];
+        let Some(user_answer) = &flashcard.user_answer else {
+            return;
+        };
+        let feedback = crate::ai::evaluate_offline(
+            &flashcard.question,
+            &flashcard.answer,
+            user_answer,
+            &self.grading_context(flashcard_index),
+        );
+        self.apply_feedback(flashcard_index, feedback);
     }
 
     pub fn open_chat(&mut self) {
@@ -338,23 +593,29 @@ impl QuizSession {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
     }
 
     pub fn close_chat(&mut self) {
-        self.chat_state = None;
+        // A reply still on its way would be lost anyway
+        if let Some(request_id) = self.chat_state.take().and_then(|c| c.pending_request)
+            && let Err(e) = self.send_ai_request(AiRequest::Cancel { request_id })
+        {
+            logger::log(&format!("Cannot cancel AI request {}: {}", request_id.0, e));
+        }
     }
 
     pub fn send_chat_message(&mut self) {
         let chat = match &mut self.chat_state {
-            Some(c) if !c.read_only && !c.is_loading => c,
+            Some(c) if !c.read_only && !c.is_loading() => c,
             _ => return,
         };
 
@@ -388,7 +649,8 @@ impl QuizSession {
 
         chat.input_buffer.clear();
         chat.cursor_position = 0;
-        chat.is_loading = true;
+        let request_id = RequestId::next();
+        chat.pending_request = Some(request_id);
         chat.error = None;
 
         // Build conversation history for the AI
@@ -416,20 +678,24 @@ impl QuizSession {
             .map(|m| (m.role.as_str().to_string(), m.content.clone()))
             .collect();
 
-        if let Some(ai_tx) = self.ai_tx.clone() {
-            let request = AiRequest::Chat {
-                flashcard_id,
-                session_id,
-                question,
-                correct_answer,
-                user_answer,
-                initial_feedback,
-                conversation_history,
-                user_message: user_msg,
-            };
-            tokio::spawn(async move {
-                let _ = ai_tx.send(request).await;
-            });
+        let sent = self.send_ai_request(AiRequest::Chat {
+            request_id,
+            flashcard_id,
+            session_id,
+            deck_name: self.deck_name.clone(),
+            question,
+            correct_answer,
+            user_answer,
+            initial_feedback,
+            conversation_history,
+            user_message: user_msg,
+            passages: self.passages(self.current_index).to_vec(),
+        });
+        if let Err(e) = sent
+            && let Some(chat) = &mut self.chat_state
+        {
+            chat.pending_request = None;
+            chat.error = Some(format!("Cannot send the message: {}", e));
         }
     }
 
@@ -444,9 +710,11 @@ impl QuizSession {
             _ => return,
         };
 
-        chat.is_loading = false;
+        chat.pending_request = None;
 
-        if let Some(reply) = message {
+        // A reply cut off by an error is kept as far as it was streamed
+        let partial = chat.streaming_reply.take().filter(|p| !p.is_empty());
+        if let Some(reply) = message.or(partial) {
             let order = chat.messages.len() as u32;
 
             if let Ok(conn) = db::init_db() {
@@ -481,10 +749,10 @@ impl QuizSession {
 
         match key.code {
             KeyCode::Esc => {
-                self.chat_state = None;
+                self.close_chat();
             }
             KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.chat_state = None;
+                self.close_chat();
             }
             _ if chat.read_only => {
                 // Read-only: only allow scroll (with bounds checking)
@@ -498,12 +766,10 @@ impl QuizSession {
                     _ => {}
                 }
             }
-            KeyCode::Enter => {
-                if !chat.is_loading {
-                    let _ = chat;
-                    self.send_chat_message();
-                }
+            KeyCode::Enter if !chat.is_loading() => {
+                self.send_chat_message();
             }
+            KeyCode::Enter => {}
             KeyCode::Up => {
                 if let Some(c) = &mut self.chat_state
                     && c.scroll_y > 0 {
@@ -537,7 +803,7 @@ impl QuizSession {
             }
             KeyCode::Char(ch) => {
                 if let Some(c) = &mut self.chat_state
-                    && !c.is_loading {
+                    && !c.is_loading() {
                         c.input_buffer.insert(c.cursor_position, ch);
                         c.cursor_position += 1;
                     }
@@ -547,51 +813,93 @@ impl QuizSession {
     }
 
     pub fn manual_trigger_ai_evaluation(&mut self) {
-        self.ai_evaluation_in_progress = false;
         if self.ai_enabled {
             self.request_ai_evaluation(self.current_index);
         }
     }
 
+    /// Feed a graded answer into the card's spaced-repetition schedule.
+    fn record_card_review(&self, conn: &rusqlite::Connection, flashcard_index: usize) {
+        let flashcard = &self.flashcards[flashcard_index];
+        let score = match &flashcard.ai_feedback {
+            Some(feedback) => feedback.correctness_score,
+            None => return,
+        };
+
+        let linked_card = match flashcard.id {
+            Some(fid) => flashcard::get_card_id(conn, fid),
+            None => Ok(None),
+        };
+        let result = linked_card
+            .and_then(|card_id| match card_id {
+                Some(id) => Ok(id),
+                None => card::upsert_card(
+                    conn,
+                    &self.deck_name,
+                    &flashcard.question,
+                    &flashcard.answer,
+                ),
+            })
+            .and_then(|card_id| {
+                card::record_review(conn, card_id, self.session_id, flashcard.id, score)
+            });
+        if let Err(e) = result {
+            logger::log(&format!(
+                "Failed to record review for flashcard {}: {}",
+                flashcard_index, e
+            ));
+        }
+    }
+
     pub fn process_ai_responses(&mut self, response: AiResponse) {
         let (flashcard_index, feedback) = match response {
             AiResponse::Evaluation {
+                request_id,
                 flashcard_index,
                 result,
             } => {
+                if !self.awaits_evaluation(flashcard_index, request_id) {
+                    return;
+                }
                 logger::log(&format!(
                     "Received evaluation for flashcard {}: score {:.2}",
                     flashcard_index, result.feedback.correctness_score
                 ));
-                self.ai_last_evaluated_index = Some(flashcard_index);
-                self.ai_evaluation_in_progress = false;
-                self.last_ai_error = None; // Clear any previous error so feedback can display
-                logger::log("Set ai_evaluation_in_progress = false (success)");
-                (flashcard_index, Some(result.feedback))
+                self.set_evaluation(
+                    flashcard_index,
+                    if result.cached {
+                        EvaluationStatus::Cached
+                    } else {
+                        EvaluationStatus::Idle
+                    },
+                );
+                self.ai_unavailable = None;
+                (flashcard_index, result.feedback)
             }
             AiResponse::Error {
+                request_id,
                 flashcard_index,
                 error,
             } => {
+                if !self.awaits_evaluation(flashcard_index, request_id) {
+                    return;
+                }
                 logger::log(&format!(
                     "Received error for flashcard {}: {}",
                     flashcard_index, error
                 ));
-                self.ai_evaluation_in_progress = false;
-                self.last_ai_error = Some(error.clone());
-                logger::log("Set ai_evaluation_in_progress = false (error)");
-                (
-                    flashcard_index,
-                    Some(crate::ai::AIFeedback {
-                        is_correct: false,
-                        correctness_score: 0.0,
-                        corrections: vec![],
-                        explanation: format!("Error: {}", error),
-                        suggestions: vec![],
-                    }),
-                )
+                let message = if error.is_permanent() {
+                    self.ai_unavailable = Some(error.to_string());
+                    format!("AI evaluation failed: {}", error)
+                } else {
+                    format!("AI evaluation failed: {} - press Ctrl+E to retry", error)
+                };
+                // Any earlier feedback stays; the card shows the failure until retried
+                self.set_evaluation(flashcard_index, EvaluationStatus::Failed(message));
+                return;
             }
             AiResponse::ChatReply {
+                request_id,
                 flashcard_id,
                 message,
                 error,
@@ -600,13 +908,53 @@ impl QuizSession {
                     "Received chat reply for flashcard {}",
                     flashcard_id
                 ));
-                self.process_chat_response(flashcard_id, message, error);
+                if self.awaits_chat_reply(request_id) {
+                    self.process_chat_response(flashcard_id, message, error);
+                }
                 return;
             }
-            AiResponse::SessionAssessment {
-                session_id: _,
-                result,
+            AiResponse::ChatDelta {
+                request_id,
+                flashcard_id,
+                delta,
+            } => {
+                if self.awaits_chat_reply(request_id)
+                    && let Some(chat) = &mut self.chat_state
+                    && chat.flashcard_id == flashcard_id
+                {
+                    chat.streaming_reply
+                        .get_or_insert_with(String::new)
+                        .push_str(&delta);
+                }
+                return;
+            }
+            AiResponse::EvaluationDelta {
+                request_id,
+                flashcard_index,
+                explanation,
             } => {
+                if let Some(EvaluationStatus::Pending {
+                    request_id: pending,
+                    explanation: partial,
+                }) = self.evaluations.get_mut(&flashcard_index)
+                    && *pending == request_id
+                {
+                    *partial = explanation;
+                }
+                return;
+            }
+            AiResponse::Usage { record } => {
+                // The worker is shared: calls made for other sessions or for card
+                // generation are not this session's
+                if record.session_id == self.session_id {
+                    self.ai_usage.add(&record);
+                }
+                return;
+            }
+            AiResponse::SessionAssessment { session_id, result } => {
+                if Some(session_id) != self.session_id {
+                    return;
+                }
                 logger::log("Received session assessment response");
                 self.assessment_loading = false;
                 match result {
@@ -616,15 +964,26 @@ impl QuizSession {
                         logger::log("Session assessment loaded successfully");
                     }
                     Err(error) => {
-                        self.session_assessment = None;
-                        self.assessment_error = Some(error.clone());
                         logger::log(&format!("Session assessment error: {}", error));
+                        if error.is_permanent() {
+                            self.ai_unavailable = Some(error.to_string());
+                        }
+                        self.session_assessment = None;
+                        self.assessment_error =
+                            Some(format!("Session assessment failed: {}", error));
                     }
                 }
                 return; // Session assessment doesn't update flashcard feedback
             }
+            // Only the document review screen asks for generated cards
+            AiResponse::GeneratedCards { .. } => return,
         };
-        self.flashcards[flashcard_index].ai_feedback = feedback;
+        self.apply_feedback(flashcard_index, feedback);
+    }
+
+    /// Store a card's feedback and persist it with the session progress.
+    fn apply_feedback(&mut self, flashcard_index: usize, feedback: AIFeedback) {
+        self.flashcards[flashcard_index].ai_feedback = Some(feedback);
 
         if let Some(session_id) = self.session_id
             && let Ok(ref conn) = db::init_db() {
@@ -655,6 +1014,8 @@ impl QuizSession {
                     self.flashcards[flashcard_index].written_to_file = true;
                 }
 
+                self.record_card_review(conn, flashcard_index);
+
                 let (answered, score) = self.calculate_stats();
                 if let Err(e) = session::update_progress(conn, session_id, answered, score) {
                     crate::logger::log(&format!("Failed to update session progress: {}", e));
@@ -666,8 +1027,10 @@ impl QuizSession {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ai::AiError;
     use crate::{AppState, Flashcard, QuizSession};
     use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::collections::HashMap;
     #[test]
     fn test_input_buffer_operations() {
         let mut buffer = String::new();
@@ -676,7 +1039,7 @@ mod tests {
         assert_eq!(buffer, "Hi");
         buffer.pop();
         assert_eq!(buffer, "H");
-        assert!(buffer.trim().is_empty() == false);
+        assert!(!buffer.trim().is_empty());
     }
 
     #[test]
@@ -802,6 +1165,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -812,19 +1176,21 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -861,6 +1227,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -871,29 +1238,33 @@ mod tests {
             questions_total: 1,
             questions_answered: 1,
             ai_enabled: true,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
         let ctrl_e = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL);
         let _ = handle_quiz_input(&mut session, ctrl_e, app_state);
 
-        // Should trigger evaluation and clear errors
-        assert!(session.ai_evaluation_in_progress);
-        assert!(session.last_ai_error.is_none());
+        // Should queue the card for evaluation
+        assert!(matches!(
+            session.evaluation(0),
+            EvaluationStatus::Pending { explanation, .. } if explanation.is_empty()
+        ));
     }
 
     #[test]
@@ -906,6 +1277,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -916,20 +1288,28 @@ mod tests {
             questions_total: 1,
             questions_answered: 1,
             ai_enabled: true,
-            ai_evaluation_in_progress: true,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: None,
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: HashMap::from([(
+                0,
+                EvaluationStatus::Pending {
+                    request_id: RequestId(1),
+                    explanation: String::new(),
+                },
+            )]),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -937,10 +1317,9 @@ mod tests {
         let _ = handle_quiz_input(&mut session, ctrl_x, app_state);
 
         // Should cancel evaluation and show message
-        assert!(!session.ai_evaluation_in_progress);
         assert_eq!(
-            session.last_ai_error,
-            Some("Evaluation cancelled".to_string())
+            session.evaluation(0),
+            &EvaluationStatus::Failed("Evaluation cancelled".to_string())
         );
     }
 
@@ -954,6 +1333,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -964,20 +1344,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: true,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: None,
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1002,6 +1384,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1012,20 +1395,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 1,
             ai_enabled: false, // AI disabled
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: None,
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1033,7 +1418,64 @@ mod tests {
         let _ = handle_quiz_input(&mut session, ctrl_e, app_state);
 
         // Should not trigger evaluation when AI disabled
-        assert!(!session.ai_evaluation_in_progress);
+        assert_eq!(session.evaluations_pending(), 0);
+    }
+
+    #[test]
+    fn test_answers_graded_offline_when_ai_disabled() {
+        let mut session = QuizSession {
+            flashcards: vec![Flashcard {
+                question: "What does AODV stand for?".to_string(),
+                answer: "Ad hoc On-Demand Distance Vector".to_string(),
+                user_answer: None,
+                ai_feedback: None,
+                written_to_file: false,
+                id: None,
+                meta: Default::default(),
+            }],
+            current_index: 0,
+            deck_name: "Test".to_string(),
+            showing_answer: false,
+            input_buffer: "ad hoc on demand distance vectr".to_string(),
+            cursor_position: 0,
+            session_id: None,
+            questions_total: 1,
+            questions_answered: 0,
+            ai_enabled: false,
+            ai_tx: None,
+
+            input_scroll_y: 0,
+            feedback_scroll_y: 0,
+            session_assessment: None,
+            assessment_loading: false,
+            assessment_requested: false,
+            assessment_error: None,
+            assessment_scroll_y: 0,
+            ai_unavailable: None,
+            chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
+        };
+        let app_state = &mut AppState::Quiz;
+
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        handle_quiz_input(&mut session, enter, app_state).unwrap();
+
+        let feedback = session.flashcards[0].ai_feedback.as_ref().unwrap();
+        assert!(feedback.is_correct);
+        assert_eq!(session.evaluations_pending(), 0);
+        let (answered, score) = session.calculate_stats();
+        assert_eq!(answered, 1);
+        assert!(score > 70.0, "{}", score);
+
+        // The summary does not wait for an assessment nobody will send
+        handle_quiz_input(&mut session, enter, app_state).unwrap();
+        assert_eq!(*app_state, AppState::Summary);
+        assert!(!session.assessment_loading);
+        assert!(!session.request_assessment_when_ready());
     }
 
     #[test]
@@ -1046,6 +1488,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1056,20 +1499,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 1,
             ai_enabled: true,
-            ai_evaluation_in_progress: false, // No evaluation in progress
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: None,
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1077,8 +1522,7 @@ mod tests {
         let _ = handle_quiz_input(&mut session, ctrl_x, app_state);
 
         // Should not do anything when no evaluation is in progress
-        assert!(!session.ai_evaluation_in_progress);
-        assert!(session.last_ai_error.is_none());
+        assert_eq!(session.evaluation(0), &EvaluationStatus::Idle);
     }
 
     #[test]
@@ -1094,6 +1538,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1104,20 +1549,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1160,6 +1607,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1170,20 +1618,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1216,6 +1666,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1226,20 +1677,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1266,6 +1719,74 @@ mod tests {
         assert_eq!(session.cursor_position, 0);
     }
 
+    #[test]
+    fn test_ctrl_g_reveals_hint() {
+        use tokio::sync::mpsc;
+
+        let (tx, _rx) = mpsc::channel(32);
+        let mut session = QuizSession {
+            flashcards: vec![
+                Flashcard {
+                    question: "Test?".to_string(),
+                    answer: "Answer".to_string(),
+                    user_answer: None,
+                    ai_feedback: None,
+                    written_to_file: false,
+                    id: None,
+                    meta: crate::models::CardMeta {
+                        hint: Some("Starts with A".to_string()),
+                        ..Default::default()
+                    },
+                },
+                Flashcard {
+                    question: "No hint?".to_string(),
+                    answer: "Answer".to_string(),
+                    user_answer: None,
+                    ai_feedback: None,
+                    written_to_file: false,
+                    id: None,
+                    meta: Default::default(),
+                },
+            ],
+            current_index: 0,
+            deck_name: "Test".to_string(),
+            showing_answer: false,
+            input_buffer: String::new(),
+            cursor_position: 0,
+            session_id: None,
+            questions_total: 2,
+            questions_answered: 0,
+            ai_enabled: false,
+            ai_tx: Some(tx),
+
+            input_scroll_y: 0,
+            feedback_scroll_y: 0,
+            session_assessment: None,
+            assessment_loading: false,
+            assessment_requested: false,
+            assessment_error: None,
+            assessment_scroll_y: 0,
+            ai_unavailable: None,
+            chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
+        };
+        let app_state = &mut AppState::Quiz;
+
+        let ctrl_g = KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL);
+        let _ = handle_quiz_input(&mut session, ctrl_g, app_state);
+        assert!(session.hints_revealed.contains(&0));
+        assert!(session.input_buffer.is_empty());
+
+        // Cards without a hint stay unchanged
+        session.current_index = 1;
+        let _ = handle_quiz_input(&mut session, ctrl_g, app_state);
+        assert!(!session.hints_revealed.contains(&1));
+    }
+
     #[test]
     fn test_ctrl_enter_inserts_newline() {
         use tokio::sync::mpsc;
@@ -1279,6 +1800,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1289,20 +1811,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1329,6 +1853,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1339,20 +1864,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1378,6 +1905,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1388,20 +1916,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1431,6 +1961,7 @@ mod tests {
                     ai_feedback: None,
                     written_to_file: false,
                     id: None,
+                    meta: Default::default(),
                 },
                 Flashcard {
                     question: "Q2?".to_string(),
@@ -1439,6 +1970,7 @@ mod tests {
                     ai_feedback: None,
                     written_to_file: false,
                     id: None,
+                    meta: Default::default(),
                 },
             ],
             current_index: 0,
@@ -1450,20 +1982,22 @@ mod tests {
             questions_total: 2,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1495,6 +2029,7 @@ mod tests {
                 ai_feedback: None,
                 written_to_file: false,
                 id: None,
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1505,20 +2040,22 @@ mod tests {
             questions_total: 1,
             questions_answered: 0,
             ai_enabled: false,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1570,6 +2107,7 @@ mod tests {
                     }),
                     written_to_file: false,
                     id: None,
+                    meta: Default::default(),
                 },
                 Flashcard {
                     question: "Q2?".to_string(),
@@ -1578,6 +2116,7 @@ mod tests {
                     ai_feedback: None,
                     written_to_file: false,
                     id: None,
+                    meta: Default::default(),
                 },
                 Flashcard {
                     question: "Q3?".to_string(),
@@ -1592,6 +2131,7 @@ mod tests {
                     }),
                     written_to_file: false,
                     id: None,
+                    meta: Default::default(),
                 },
             ],
             current_index: 0,
@@ -1603,20 +2143,22 @@ mod tests {
             questions_total: 3,
             questions_answered: 2,
             ai_enabled: true,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
 
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
         };
         let app_state = &mut AppState::Quiz;
 
@@ -1668,6 +2210,7 @@ mod tests {
                 }),
                 written_to_file: true,
                 id: Some(1),
+                meta: Default::default(),
             }],
             current_index: 0,
             deck_name: "Test".to_string(),
@@ -1678,20 +2221,385 @@ mod tests {
             questions_total: 1,
             questions_answered: 1,
             ai_enabled: true,
-            ai_evaluation_in_progress: false,
-            ai_last_evaluated_index: None,
-            ai_evaluation_start_time: None,
-            last_ai_error: None,
             ai_tx: Some(tx),
-            ai_rx: None,
             input_scroll_y: 0,
             feedback_scroll_y: 0,
             session_assessment: None,
             assessment_loading: false,
+            assessment_requested: false,
             assessment_error: None,
             assessment_scroll_y: 0,
+            ai_unavailable: None,
             chat_state: None,
+            deck_meta: Default::default(),
+            ai_usage: Default::default(),
+            source_passages: Vec::new(),
+            hints_revealed: Default::default(),
+            evaluations: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_unevaluated_requests_missing_grades() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let mut session = create_session_with_feedback();
+        session.ai_tx = Some(tx);
+        let graded = session.flashcards[0].clone();
+        let mut ungraded = graded.clone();
+        ungraded.ai_feedback = None;
+        let failed = graded.clone();
+        let mut unanswered = ungraded.clone();
+        unanswered.user_answer = None;
+        session.flashcards = vec![graded, ungraded, failed, unanswered];
+        session.set_evaluation(2, EvaluationStatus::Failed("timed out".to_string()));
+
+        session.queue_unevaluated();
+
+        let pending: Vec<_> = (0..session.flashcards.len())
+            .map(|i| matches!(session.evaluation(i), EvaluationStatus::Pending { .. }))
+            .collect();
+        assert_eq!(pending, vec![false, true, true, false]);
+        let mut queued = Vec::new();
+        for _ in 0..2 {
+            match rx.recv().await {
+                Some(AiRequest::Evaluate {
+                    flashcard_index, ..
+                }) => queued.push(flashcard_index),
+                other => panic!("expected an evaluation request, got {:?}", other),
+            }
         }
+        queued.sort();
+        assert_eq!(queued, vec![1, 2]);
+
+        // A card already in the queue is not sent again
+        session.request_ai_evaluation(1);
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_evaluation_ignores_late_response() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let mut session = create_session_with_feedback();
+        session.session_id = None;
+        session.ai_tx = Some(tx);
+
+        session.request_ai_evaluation(0);
+        let Some(AiRequest::Evaluate { request_id, .. }) = rx.recv().await else {
+            panic!("expected an evaluation request");
+        };
+        session.cancel_ai_evaluation(0);
+        match rx.recv().await {
+            Some(AiRequest::Cancel {
+                request_id: cancelled,
+            }) => {
+                assert_eq!(cancelled, request_id)
+            }
+            other => panic!("expected a cancellation, got {:?}", other),
+        }
+
+        // The reply of the cancelled request arrives anyway
+        let mut late = session.flashcards[0].ai_feedback.clone().unwrap();
+        late.correctness_score = 0.1;
+        session.process_ai_responses(AiResponse::Evaluation {
+            request_id,
+            flashcard_index: 0,
+            result: crate::ai::AIEvaluationResult {
+                feedback: late,
+                raw_response: String::new(),
+                cached: false,
+            },
+        });
+        let card = &session.flashcards[0];
+        assert_eq!(card.ai_feedback.as_ref().unwrap().correctness_score, 0.8);
+        assert_eq!(
+            session.evaluation(0),
+            &EvaluationStatus::Failed("Evaluation cancelled".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_right_after_evaluate_reaches_the_worker_second() {
+        use crate::ai::fake_server::{sample_evaluation, FakeAiServer, FakeReply};
+        use std::time::Duration;
+
+        let server = FakeAiServer::start(vec![
+            FakeReply::content(&sample_evaluation()).after(Duration::from_millis(200))
+        ])
+        .await
+        .unwrap();
+        let config = crate::ai::AiConfig {
+            provider: crate::ai::ProviderKind::OpenAi,
+            base_url: Some(server.base_url()),
+            model: Some("fake-model".to_string()),
+            prompt_dir: Some(std::env::temp_dir().join("no-such-prompt-dir")),
+            ..Default::default()
+        };
+        let (request_tx, request_rx) = tokio::sync::mpsc::channel(32);
+        let (response_tx, mut response_rx) = tokio::sync::mpsc::channel(32);
+        crate::ai_worker::spawn_ai_worker_with(config, None, None, response_tx, request_rx);
+        let mut session = create_session_with_feedback();
+        session.session_id = None;
+        session.ai_tx = Some(request_tx);
+
+        // Back to back, without giving the runtime a chance to run in between
+        session.request_ai_evaluation(0);
+        session.cancel_ai_evaluation(0);
+
+        let answered = tokio::time::timeout(Duration::from_secs(1), async {
+            while let Some(response) = response_rx.recv().await {
+                if let AiResponse::Evaluation { .. } | AiResponse::Error { .. } = response {
+                    return Some(response);
+                }
+            }
+            None
+        })
+        .await;
+        assert!(
+            !matches!(answered, Ok(Some(_))),
+            "the cancelled evaluation answered: {:?}",
+            answered
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_carries_source_passages() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let mut session = create_session_with_feedback();
+        session.session_id = None;
+        session.ai_tx = Some(tx);
+        let passage = SourcePassage {
+            document: "manet.pdf".to_string(),
+            location: "p. 4".to_string(),
+            text: "AODV discovers routes on demand.".to_string(),
+        };
+        session.source_passages = vec![vec![passage.clone()]];
+
+        session.request_ai_evaluation(0);
+        match rx.recv().await {
+            Some(AiRequest::Evaluate { grading, .. }) => {
+                assert_eq!(grading.passages, vec![passage])
+            }
+            other => panic!("expected an evaluation request, got {:?}", other),
+        }
+        assert!(session.passages(1).is_empty());
+    }
+
+    #[test]
+    fn test_source_passages_are_found_per_card_and_kept_per_session() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::db::run_migrations_for_test(&mut conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("manet.md");
+        std::fs::write(&notes, "AODV discovers routes on demand.\n").unwrap();
+
+        let mut session = create_session_with_feedback();
+        let cards: Vec<(String, String)> = session
+            .flashcards
+            .iter()
+            .map(|card| (card.question.clone(), card.answer.clone()))
+            .collect();
+        let passages = find_source_passages(&conn, "rust", &[notes], &cards);
+        assert_eq!(passages.len(), cards.len());
+        let gone = [dir.path().join("gone.md")];
+        assert!(find_source_passages(&conn, "rust", &gone, &cards).is_empty());
+
+        // Passages looked up for an earlier session are dropped
+        session.session_id = Some(1);
+        session.set_source_passages(2, passages.clone());
+        assert!(session.source_passages.is_empty());
+        session.set_source_passages(1, passages.clone());
+        assert_eq!(session.source_passages, passages);
+    }
+
+    #[tokio::test]
+    async fn test_ctrl_r_re_evaluates_cached_feedback() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let mut session = create_session_with_feedback();
+        session.session_id = None;
+        session.ai_tx = Some(tx);
+
+        session.request_ai_evaluation(0);
+        let Some(AiRequest::Evaluate {
+            request_id, force, ..
+        }) = rx.recv().await
+        else {
+            panic!("expected an evaluation request");
+        };
+        assert!(!force);
+        let feedback = session.flashcards[0].ai_feedback.clone().unwrap();
+        session.process_ai_responses(AiResponse::Evaluation {
+            request_id,
+            flashcard_index: 0,
+            result: crate::ai::AIEvaluationResult {
+                feedback,
+                raw_response: String::new(),
+                cached: true,
+            },
+        });
+        assert_eq!(session.evaluation(0), &EvaluationStatus::Cached);
+        assert_eq!(session.evaluations_cached(), 1);
+
+        let ctrl_r = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+        let _ = handle_quiz_input(&mut session, ctrl_r, &mut AppState::Quiz);
+        match rx.recv().await {
+            Some(AiRequest::Evaluate { force, .. }) => assert!(force),
+            other => panic!("expected an evaluation request, got {:?}", other),
+        }
+        assert_eq!(session.evaluations_cached(), 0);
+        assert_eq!(session.evaluations_pending(), 1);
+    }
+
+    #[test]
+    fn test_usage_reports_add_up() {
+        let mut session = create_session_with_feedback();
+        for cost in [0.01, 0.02] {
+            session.process_ai_responses(AiResponse::Usage {
+                record: crate::db::ai_usage::UsageRecord {
+                    session_id: session.session_id,
+                    kind: "evaluation".to_string(),
+                    prompt_tokens: 100,
+                    completion_tokens: 20,
+                    latency_ms: 300,
+                    cost,
+                    ..Default::default()
+                },
+            });
+        }
+        // A call made for card generation
+        session.process_ai_responses(AiResponse::Usage {
+            record: crate::db::ai_usage::UsageRecord {
+                kind: "generation".to_string(),
+                prompt_tokens: 1000,
+                cost: 1.0,
+                ..Default::default()
+            },
+        });
+        let usage = session.ai_usage;
+        assert_eq!((usage.calls, usage.tokens(), usage.average_latency_ms()), (2, 240, 300));
+        assert!((usage.cost - 0.03).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_session_assessment_is_requested_once() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let mut session = create_session_with_feedback();
+        session.ai_tx = Some(tx);
+        session.assessment_loading = true;
+
+        // Waits for evaluations still in flight
+        session.set_evaluation(
+            0,
+            EvaluationStatus::Pending {
+                request_id: RequestId(1),
+                explanation: String::new(),
+            },
+        );
+        session.request_assessment_when_ready();
+        assert!(rx.try_recv().is_err());
+        session.set_evaluation(0, EvaluationStatus::Idle);
+
+        // Usage reports and scrolling while the assessment loads do not send it again
+        for _ in 0..3 {
+            session.request_assessment_when_ready();
+            session.process_ai_responses(AiResponse::Usage {
+                record: crate::db::ai_usage::UsageRecord {
+                    session_id: session.session_id,
+                    kind: "evaluation".to_string(),
+                    ..Default::default()
+                },
+            });
+            session.assessment_scroll_y += 1;
+        }
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(AiRequest::EvaluateSession { session_id: 1, .. })
+        ));
+        assert!(rx.try_recv().is_err());
+        assert!(session.assessment_loading);
+    }
+
+    #[test]
+    fn test_failed_evaluation_keeps_earlier_feedback() {
+        let mut session = create_session_with_feedback();
+        let request_id = RequestId(1);
+        session.set_evaluation(
+            0,
+            EvaluationStatus::Pending {
+                request_id,
+                explanation: String::new(),
+            },
+        );
+
+        session.process_ai_responses(AiResponse::EvaluationDelta {
+            request_id,
+            flashcard_index: 0,
+            explanation: "Partly".to_string(),
+        });
+        assert_eq!(
+            session.evaluation(0),
+            &EvaluationStatus::Pending {
+                request_id,
+                explanation: "Partly".to_string()
+            }
+        );
+
+        session.process_ai_responses(AiResponse::Error {
+            request_id,
+            flashcard_index: 0,
+            error: AiError::RateLimited("slow down".to_string()),
+        });
+        assert_eq!(
+            session.evaluation(0),
+            &EvaluationStatus::Failed(
+                "AI evaluation failed: rate limited by the provider: slow down - press Ctrl+E to retry"
+                    .to_string()
+            )
+        );
+        let feedback = session.flashcards[0].ai_feedback.as_ref().unwrap();
+        assert_eq!(feedback.correctness_score, 0.8);
+        assert_eq!(session.evaluations_failed(), 1);
+        assert_eq!(session.evaluations_pending(), 0);
+    }
+
+    #[test]
+    fn test_rejected_key_marks_ai_unavailable() {
+        let mut session = create_session_with_feedback();
+        session.session_id = None;
+        let request_id = RequestId(1);
+        session.set_evaluation(
+            0,
+            EvaluationStatus::Pending {
+                request_id,
+                explanation: String::new(),
+            },
+        );
+        session.process_ai_responses(AiResponse::Error {
+            request_id,
+            flashcard_index: 0,
+            error: AiError::Auth("invalid api key".to_string()),
+        });
+        let reason = session.ai_unavailable.clone().unwrap();
+        assert!(reason.contains("rejected the API key"), "{}", reason);
+
+        let request_id = RequestId(2);
+        session.set_evaluation(
+            0,
+            EvaluationStatus::Pending {
+                request_id,
+                explanation: String::new(),
+            },
+        );
+        session.process_ai_responses(AiResponse::Evaluation {
+            request_id,
+            flashcard_index: 0,
+            result: crate::ai::AIEvaluationResult {
+                feedback: session.flashcards[0].ai_feedback.clone().unwrap(),
+                raw_response: String::new(),
+                cached: false,
+            },
+        });
+        assert_eq!(session.ai_unavailable, None);
     }
 
     #[test]
@@ -1706,7 +2614,7 @@ mod tests {
         let chat = session.chat_state.as_ref().unwrap();
         assert_eq!(chat.flashcard_id, 1);
         assert_eq!(chat.session_id, 1);
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
     }
 
     #[test]
@@ -1755,13 +2663,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::empty());
@@ -1780,13 +2689,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let ctrl_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL);
@@ -1805,13 +2715,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let h = KeyEvent::new(KeyCode::Char('H'), KeyModifiers::empty());
@@ -1834,13 +2745,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let h = KeyEvent::new(KeyCode::Char('H'), KeyModifiers::empty());
@@ -1860,13 +2772,14 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 5,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let bs = KeyEvent::new(KeyCode::Backspace, KeyModifiers::empty());
@@ -1887,13 +2800,14 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 5,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let left = KeyEvent::new(KeyCode::Left, KeyModifiers::empty());
@@ -1925,13 +2839,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 10,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 100, // Allow scrolling for test
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let up = KeyEvent::new(KeyCode::Up, KeyModifiers::empty());
@@ -1960,13 +2875,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: true,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let h = KeyEvent::new(KeyCode::Char('H'), KeyModifiers::empty());
@@ -1986,13 +2902,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 10,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: true,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 100, // Allow scrolling for test
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let up = KeyEvent::new(KeyCode::Up, KeyModifiers::empty());
@@ -2014,13 +2931,14 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: true,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::empty());
@@ -2043,19 +2961,20 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.process_chat_response(1, Some("Here is more info.".to_string()), None);
 
         let chat = session.chat_state.as_ref().unwrap();
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
         assert_eq!(chat.messages.len(), 2);
         assert_eq!(chat.messages[1].role, ChatRole::Assistant);
         assert_eq!(chat.messages[1].content, "Here is more info.");
@@ -2077,23 +2996,64 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.process_chat_response(1, None, Some("Timeout".to_string()));
 
         let chat = session.chat_state.as_ref().unwrap();
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
         assert_eq!(chat.messages.len(), 1); // No assistant message added
         assert_eq!(chat.error, Some("Timeout".to_string()));
     }
 
+    #[test]
+    fn test_streamed_chat_reply_kept_on_error() {
+        let mut session = create_session_with_feedback();
+        session.chat_state = Some(ChatState {
+            flashcard_id: 1,
+            session_id: 1,
+            messages: vec![],
+            input_buffer: String::new(),
+            cursor_position: 0,
+            scroll_y: 0,
+            pending_request: Some(RequestId(1)),
+            error: None,
+            read_only: false,
+            rendered_lines_cache: Vec::new(),
+            cached_message_count: 0,
+            max_scroll: 0,
+            input_scroll_y: 0,
+            streaming_reply: None,
+        });
+
+        for (request_id, delta) in [(1, "It is "), (2, "ignored "), (1, "a routing")] {
+            session.process_ai_responses(AiResponse::ChatDelta {
+                request_id: RequestId(request_id),
+                flashcard_id: 1,
+                delta: delta.to_string(),
+            });
+        }
+        let chat = session.chat_state.as_ref().unwrap();
+        assert_eq!(chat.streaming_reply.as_deref(), Some("It is a routing"));
+        assert!(chat.messages.is_empty());
+
+        session.process_chat_response(1, None, Some("Connection reset".to_string()));
+
+        let chat = session.chat_state.as_ref().unwrap();
+        assert!(chat.streaming_reply.is_none());
+        assert_eq!(chat.messages.len(), 1);
+        assert_eq!(chat.messages[0].content, "It is a routing");
+        assert_eq!(chat.error.as_deref(), Some("Connection reset"));
+    }
+
     #[test]
     fn test_process_chat_response_wrong_flashcard_id_ignored() {
         let mut session = create_session_with_feedback();
@@ -2104,20 +3064,21 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         // Response for a different flashcard should be ignored
         session.process_chat_response(999, Some("Reply".to_string()), None);
 
         let chat = session.chat_state.as_ref().unwrap();
-        assert!(chat.is_loading); // Still loading, wasn't processed
+        assert!(chat.is_loading()); // Still loading, wasn't processed
         assert!(chat.messages.is_empty());
     }
 
@@ -2141,13 +3102,14 @@ mod tests {
             input_buffer: "partial message".to_string(),
             cursor_position: 15,
             scroll_y: 3,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.close_chat();
@@ -2164,20 +3126,21 @@ mod tests {
             input_buffer: "   ".to_string(), // whitespace only
             cursor_position: 3,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.send_chat_message();
 
         let chat = session.chat_state.as_ref().unwrap();
         assert!(chat.messages.is_empty());
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
     }
 
     #[test]
@@ -2190,20 +3153,21 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 5,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: true,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.send_chat_message();
 
         let chat = session.chat_state.as_ref().unwrap();
         assert!(chat.messages.is_empty());
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
     }
 
     #[test]
@@ -2216,13 +3180,14 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 5,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         session.send_chat_message();
@@ -2233,7 +3198,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_chat_enter_sends_message() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
         let mut session = create_session_with_feedback();
+        session.ai_tx = Some(tx);
         session.chat_state = Some(ChatState {
             flashcard_id: 1,
             session_id: 1,
@@ -2241,13 +3208,14 @@ mod tests {
             input_buffer: "Tell me more".to_string(),
             cursor_position: 12,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
@@ -2259,7 +3227,21 @@ mod tests {
         assert_eq!(chat.messages[0].content, "Tell me more");
         assert!(chat.input_buffer.is_empty());
         assert_eq!(chat.cursor_position, 0);
-        assert!(chat.is_loading);
+        assert!(chat.is_loading());
+        assert!(matches!(rx.try_recv(), Ok(AiRequest::Chat { .. })));
+
+        // With the worker gone the message cannot be sent, and the chat says so
+        drop(rx);
+        let chat = session.chat_state.as_mut().unwrap();
+        chat.pending_request = None;
+        chat.input_buffer = "Anyone there?".to_string();
+        session.handle_chat_input(enter);
+        let chat = session.chat_state.as_ref().unwrap();
+        assert!(!chat.is_loading());
+        assert_eq!(
+            chat.error.as_deref(),
+            Some("Cannot send the message: the AI worker has stopped")
+        );
     }
 
     #[test]
@@ -2272,13 +3254,14 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 5,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
@@ -2310,13 +3293,14 @@ mod tests {
             input_buffer: "Hllo".to_string(),
             cursor_position: 1, // between 'H' and 'l'
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let e = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::empty());
@@ -2337,13 +3321,14 @@ mod tests {
             input_buffer: "Hello".to_string(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: false,
+            pending_request: None,
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let bs = KeyEvent::new(KeyCode::Backspace, KeyModifiers::empty());
@@ -2371,16 +3356,18 @@ mod tests {
             input_buffer: String::new(),
             cursor_position: 0,
             scroll_y: 0,
-            is_loading: true,
+            pending_request: Some(RequestId(1)),
             error: None,
             read_only: false,
             rendered_lines_cache: Vec::new(),
             cached_message_count: 0,
             max_scroll: 0,
             input_scroll_y: 0,
+            streaming_reply: None,
         });
 
         let response = AiResponse::ChatReply {
+            request_id: RequestId(1),
             flashcard_id: 1,
             message: Some("AI answer".to_string()),
             error: None,
@@ -2389,7 +3376,7 @@ mod tests {
         session.process_ai_responses(response);
 
         let chat = session.chat_state.as_ref().unwrap();
-        assert!(!chat.is_loading);
+        assert!(!chat.is_loading());
         assert_eq!(chat.messages.len(), 2);
         assert_eq!(chat.messages[1].content, "AI answer");
     }
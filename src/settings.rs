@@ -0,0 +1,261 @@
+//! The AI settings screen: the model, temperature and max tokens of each kind of
+//! request and the fallback models, edited on a copy of the `[ai]` config and written
+//! back with [`crate::config::save_model_settings`].
+
+use crate::ai::templates::PromptKind;
+use crate::ai::AiConfig;
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// One editable row of the settings screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    Model(PromptKind),
+    Temperature(PromptKind),
+    MaxTokens(PromptKind),
+    FallbackModels,
+}
+
+impl SettingsField {
+    /// The rows in screen order.
+    pub fn all() -> Vec<SettingsField> {
+        PromptKind::ALL
+            .into_iter()
+            .flat_map(|kind| {
+                [
+                    SettingsField::Model(kind),
+                    SettingsField::Temperature(kind),
+                    SettingsField::MaxTokens(kind),
+                ]
+            })
+            .chain([SettingsField::FallbackModels])
+            .collect()
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SettingsField::Model(_) => "Model",
+            SettingsField::Temperature(_) => "Temperature",
+            SettingsField::MaxTokens(_) => "Max tokens",
+            SettingsField::FallbackModels => "Fallback models",
+        }
+    }
+}
+
+/// What the app should do after the settings screen handled a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsAction {
+    None,
+    /// Write the settings to the config file and use them
+    Save,
+    /// Leave without saving
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsForm {
+    /// The `[ai]` config with the edits made so far
+    pub config: AiConfig,
+    /// Index into [`SettingsField::all`]
+    pub selected: usize,
+    /// The text of the selected field while it is being edited
+    pub editing: Option<String>,
+    /// Why the last edit or save was refused
+    pub error: Option<String>,
+    /// Whether there are edits not saved yet
+    pub changed: bool,
+}
+
+impl SettingsForm {
+    pub fn new(config: &AiConfig) -> Self {
+        Self {
+            config: config.clone(),
+            selected: 0,
+            editing: None,
+            error: None,
+            changed: false,
+        }
+    }
+
+    pub fn selected_field(&self) -> SettingsField {
+        SettingsField::all()[self.selected]
+    }
+
+    /// The value set for `field` as it is edited, empty when unset.
+    pub fn value(&self, field: SettingsField) -> String {
+        match field {
+            SettingsField::Model(kind) => self.config.task(kind).model.clone().unwrap_or_default(),
+            SettingsField::Temperature(kind) => self
+                .config
+                .task(kind)
+                .temperature
+                .map(|t| t.to_string())
+                .unwrap_or_default(),
+            SettingsField::MaxTokens(kind) => self
+                .config
+                .task(kind)
+                .max_tokens
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            SettingsField::FallbackModels => self.config.fallback_models.join(", "),
+        }
+    }
+
+    /// What applies while `field` is unset.
+    pub fn default_value(&self, field: SettingsField) -> String {
+        match field {
+            SettingsField::Model(_) => self.config.model().to_string(),
+            SettingsField::Temperature(_) | SettingsField::MaxTokens(_) => "default".to_string(),
+            SettingsField::FallbackModels => "none".to_string(),
+        }
+    }
+
+    /// Set `field` from the text typed for it; empty text unsets it.
+    fn set(&mut self, field: SettingsField, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        match field {
+            SettingsField::Model(kind) => {
+                self.config.task_mut(kind).model = (!text.is_empty()).then(|| text.to_string());
+            }
+            SettingsField::Temperature(kind) => {
+                let temperature = match text {
+                    "" => None,
+                    _ => match text.parse::<f32>() {
+                        Ok(t) if (0.0..=2.0).contains(&t) => Some(t),
+                        _ => return Err("Temperature must be a number from 0 to 2".to_string()),
+                    },
+                };
+                self.config.task_mut(kind).temperature = temperature;
+            }
+            SettingsField::MaxTokens(kind) => {
+                let max_tokens = match text {
+                    "" => None,
+                    _ => match text.parse::<u32>() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => return Err("Max tokens must be a whole number above 0".to_string()),
+                    },
+                };
+                self.config.task_mut(kind).max_tokens = max_tokens;
+            }
+            SettingsField::FallbackModels => {
+                self.config.fallback_models = text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|m| !m.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> SettingsAction {
+        let field = self.selected_field();
+        if let Some(text) = &mut self.editing {
+            match key.code {
+                KeyCode::Enter => {
+                    let text = self.editing.take().unwrap_or_default();
+                    if let Err(e) = self.set(field, &text) {
+                        self.error = Some(e);
+                        self.editing = Some(text);
+                    } else {
+                        self.error = None;
+                    }
+                }
+                KeyCode::Esc => {
+                    self.editing = None;
+                    self.error = None;
+                }
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            }
+            return SettingsAction::None;
+        }
+
+        match key.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(SettingsField::all().len() - 1);
+            }
+            KeyCode::Enter => {
+                self.editing = Some(self.value(field));
+                self.error = None;
+            }
+            KeyCode::Delete | KeyCode::Backspace => {
+                let _ = self.set(field, "");
+            }
+            KeyCode::Char('s') => return SettingsAction::Save,
+            KeyCode::Esc => return SettingsAction::Close,
+            _ => {}
+        }
+        SettingsAction::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(form: &mut SettingsForm, code: KeyCode) -> SettingsAction {
+        form.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(form: &mut SettingsForm, text: &str) {
+        for c in text.chars() {
+            press(form, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_editing_fields() {
+        let mut form = SettingsForm::new(&AiConfig::default());
+        assert_eq!(
+            form.selected_field(),
+            SettingsField::Model(PromptKind::Evaluation)
+        );
+        assert_eq!(
+            form.default_value(form.selected_field()),
+            crate::ai::DEFAULT_MODEL
+        );
+
+        press(&mut form, KeyCode::Enter);
+        type_text(&mut form, "openai/gpt-4o-mini");
+        press(&mut form, KeyCode::Enter);
+        assert_eq!(
+            form.config.evaluation.model.as_deref(),
+            Some("openai/gpt-4o-mini")
+        );
+
+        press(&mut form, KeyCode::Down);
+        press(&mut form, KeyCode::Enter);
+        type_text(&mut form, "3");
+        press(&mut form, KeyCode::Enter);
+        assert!(form.error.is_some());
+        assert_eq!(form.editing.as_deref(), Some("3"));
+        press(&mut form, KeyCode::Backspace);
+        type_text(&mut form, "0.3");
+        press(&mut form, KeyCode::Enter);
+        assert_eq!(form.config.evaluation.temperature, Some(0.3));
+        assert_eq!(form.error, None);
+        assert_eq!(form.value(form.selected_field()), "0.3");
+
+        for _ in 0..20 {
+            press(&mut form, KeyCode::Down);
+        }
+        assert_eq!(form.selected_field(), SettingsField::FallbackModels);
+        press(&mut form, KeyCode::Enter);
+        type_text(&mut form, "a, ,b");
+        press(&mut form, KeyCode::Enter);
+        assert_eq!(form.config.fallback_models, vec!["a", "b"]);
+        press(&mut form, KeyCode::Delete);
+        assert!(form.config.fallback_models.is_empty());
+
+        assert!(form.changed);
+        assert_eq!(press(&mut form, KeyCode::Char('s')), SettingsAction::Save);
+        assert_eq!(press(&mut form, KeyCode::Esc), SettingsAction::Close);
+    }
+}
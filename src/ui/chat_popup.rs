@@ -28,6 +28,22 @@ fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
+/// An assistant reply: the heading and the indented, rendered markdown.
+fn assistant_lines(content: &str) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        "AI:",
+        Style::default()
+            .fg(Color::Green)
+            .add_modifier(Modifier::BOLD),
+    ))];
+    for line in render_markdown(content) {
+        let mut indented_spans: Vec<Span<'static>> = vec![Span::from("  ")];
+        indented_spans.extend(line.spans);
+        lines.push(Line::from(indented_spans));
+    }
+    lines
+}
+
 /// Rebuild the rendered lines cache from messages.
 /// This is the expensive operation (markdown parsing) that we want to avoid on every frame.
 pub fn rebuild_chat_cache(chat: &mut ChatState) {
@@ -48,18 +64,7 @@ pub fn rebuild_chat_cache(chat: &mut ChatState) {
                 lines.push(Line::from(""));
             }
             ChatRole::Assistant => {
-                lines.push(Line::from(Span::styled(
-                    "AI:",
-                    Style::default()
-                        .fg(Color::Green)
-                        .add_modifier(Modifier::BOLD),
-                )));
-                let rendered = render_markdown(&msg.content);
-                for line in rendered {
-                    let mut indented_spans: Vec<Span<'static>> = vec![Span::from("  ")];
-                    indented_spans.extend(line.spans);
-                    lines.push(Line::from(indented_spans));
-                }
+                lines.extend(assistant_lines(&msg.content));
                 lines.push(Line::from(""));
             }
             ChatRole::System => {
@@ -106,8 +111,11 @@ pub fn draw_chat_popup(f: &mut Frame, chat: &mut ChatState, question_number: usi
     // Start with cached lines (clone is cheap - just reference counting for the inner strings)
     let mut message_lines: Vec<Line<'static>> = chat.rendered_lines_cache.clone();
 
-    // Add dynamic elements (loading indicator, errors) - these are cheap
-    if chat.is_loading {
+    // Add dynamic elements (the reply being streamed, loading indicator, errors)
+    let streaming = chat.streaming_reply.as_deref().filter(|r| !r.is_empty());
+    if let Some(reply) = streaming {
+        message_lines.extend(assistant_lines(reply));
+    } else if chat.is_loading() {
         message_lines.push(Line::from(Span::styled(
             "AI is thinking...",
             Style::default()
@@ -143,7 +151,7 @@ pub fn draw_chat_popup(f: &mut Frame, chat: &mut ChatState, question_number: usi
     chat.max_scroll = max_scroll;
 
     // Auto-scroll to bottom when loading, otherwise use user's scroll position
-    let scroll = if chat.is_loading {
+    let scroll = if chat.is_loading() {
         max_scroll
     } else {
         chat.scroll_y.min(max_scroll)
@@ -163,7 +171,7 @@ pub fn draw_chat_popup(f: &mut Frame, chat: &mut ChatState, question_number: usi
 
     // Input area (hidden in read-only mode)
     if !chat.read_only {
-        let input_text = if chat.input_buffer.is_empty() && !chat.is_loading {
+        let input_text = if chat.input_buffer.is_empty() && !chat.is_loading() {
             Text::from(Span::styled(
                 "Type your message...",
                 Style::default().fg(Color::DarkGray),
@@ -197,7 +205,7 @@ pub fn draw_chat_popup(f: &mut Frame, chat: &mut ChatState, question_number: usi
                 Block::default()
                     .borders(Borders::ALL)
                     .title(" Message ")
-                    .border_style(if chat.is_loading {
+                    .border_style(if chat.is_loading() {
                         Style::default().fg(Color::DarkGray)
                     } else {
                         Style::default().fg(Color::Yellow)
@@ -206,7 +214,7 @@ pub fn draw_chat_popup(f: &mut Frame, chat: &mut ChatState, question_number: usi
         f.render_widget(input_widget, chunks[1]);
 
         // Set cursor in input area (adjusted for scroll)
-        if !chat.is_loading {
+        if !chat.is_loading() {
             let cursor_x = chunks[1].x + 1 + cursor_col as u16;
             let cursor_y =
                 chunks[1].y + 1 + (cursor_line as u16).saturating_sub(chat.input_scroll_y);
@@ -5,10 +5,11 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph},
     Frame,
 };
-use std::path::PathBuf;
-
-use crate::ai::DEFAULT_MODEL;
+use crate::ai::AiConfig;
 use crate::db::session::{DeckStatus, SessionSummary};
+use crate::deck::{DeckEntry, DeckTreeRow};
+use crate::models::AiHealth;
+use crate::scheduler::SchedulerKind;
 
 fn format_session_date(timestamp: u64) -> String {
     use std::time::{Duration, UNIX_EPOCH};
@@ -60,14 +61,69 @@ fn draw_panel_header(area: ratatui::layout::Rect, title: &str, focused: bool, f:
     f.render_widget(header, area);
 }
 
+/// A folder row of the deck tree: its name, deck count when collapsed and the total
+/// number of cards due across its decks.
+fn folder_item<'a>(
+    group: &str,
+    collapsed: bool,
+    indent: &str,
+    decks: &[DeckEntry],
+    deck_statuses: &[Option<DeckStatus>],
+    style: Style,
+    tag_filter: Option<&str>,
+) -> ListItem<'a> {
+    let name = group.rsplit('/').next().unwrap_or(group);
+    let marker = if collapsed { "▸" } else { "▾" };
+    let mut spans = vec![Span::styled(
+        format!("{}{} {}/", indent, marker, name),
+        style,
+    )];
+
+    if let Some(tag) = tag_filter {
+        spans.push(Span::styled(
+            format!(" #{}", tag),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+
+    let in_group: Vec<usize> = (0..decks.len())
+        .filter(|&i| decks[i].in_group(group))
+        .collect();
+    if collapsed {
+        spans.push(Span::styled(
+            format!(" ({} decks)", in_group.len()),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let due: usize = in_group
+        .iter()
+        .filter_map(|&i| deck_statuses[i].as_ref())
+        .map(|s| s.due_count)
+        .sum();
+    if due > 0 {
+        spans.push(Span::styled(
+            format!(" {} due", due),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+
+    ListItem::new(Line::from(spans))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_menu(
     f: &mut Frame,
-    csv_files: &[(PathBuf, Option<DeckStatus>)],
+    decks: &[DeckEntry],
+    deck_statuses: &[Option<DeckStatus>],
+    deck_rows: &[DeckTreeRow],
     selected_file_index: usize,
     sessions: &[SessionSummary],
     selected_session_index: usize,
     focused_panel: usize,
-    ai_enabled: bool,
+    ai_config: &AiConfig,
+    ai_health: &AiHealth,
+    status_message: Option<&str>,
+    tag_filter: Option<&str>,
 ) {
     let area = f.area();
 
@@ -82,6 +138,15 @@ pub fn draw_menu(
         ])
         .split(area);
 
+    let mut title_block = Block::default().borders(Borders::ALL);
+    if let Some(message) = status_message {
+        title_block = title_block.title_bottom(
+            Line::from(format!(" {} ", message))
+                .style(Style::default().fg(Color::Yellow))
+                .centered(),
+        );
+    }
+
     let title = Paragraph::new("Interactive Flashcards v0.1.0")
         .style(
             Style::default()
@@ -89,7 +154,7 @@ pub fn draw_menu(
                 .add_modifier(Modifier::BOLD),
         )
         .alignment(Alignment::Center)
-        .block(Block::default().borders(Borders::ALL));
+        .block(title_block);
     f.render_widget(title, chunks[0]);
 
     let csv_chunks = Layout::default()
@@ -102,20 +167,19 @@ pub fn draw_menu(
         .constraints([Constraint::Length(1), Constraint::Min(1)])
         .split(chunks[2]);
 
-    draw_panel_header(csv_chunks[0], "[1] CSV Files", focused_panel == 0, f);
+    draw_panel_header(csv_chunks[0], "[1] Decks", focused_panel == 0, f);
 
-    let csv_items: Vec<ListItem> = if csv_files.is_empty() {
-        vec![ListItem::new("No CSV files found").style(
+    let csv_items: Vec<ListItem> = if deck_rows.is_empty() {
+        vec![ListItem::new("No decks found").style(
             Style::default()
                 .fg(Color::DarkGray)
                 .add_modifier(Modifier::ITALIC),
         )]
     } else {
-        csv_files
+        deck_rows
             .iter()
             .enumerate()
-            .map(|(i, (path, status))| {
-                let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            .map(|(i, row)| {
                 let selected = i == selected_file_index && focused_panel == 0;
                 let base_style = if selected {
                     Style::default()
@@ -124,8 +188,39 @@ pub fn draw_menu(
                 } else {
                     Style::default()
                 };
-
-                let mut spans = vec![Span::styled(name, base_style)];
+                let indent = "  ".repeat(row.depth());
+
+                let index = match row {
+                    DeckTreeRow::Folder {
+                        group, collapsed, ..
+                    } => {
+                        return folder_item(
+                            group,
+                            *collapsed,
+                            &indent,
+                            decks,
+                            deck_statuses,
+                            base_style,
+                            (i == selected_file_index).then_some(tag_filter).flatten(),
+                        );
+                    }
+                    DeckTreeRow::Deck { index, .. } => *index,
+                };
+                let status = &deck_statuses[index];
+
+                let mut spans = vec![Span::styled(
+                    format!("{}{}", indent, decks[index].label()),
+                    base_style,
+                )];
+
+                if i == selected_file_index
+                    && let Some(tag) = tag_filter
+                {
+                    spans.push(Span::styled(
+                        format!(" #{}", tag),
+                        Style::default().fg(Color::Magenta),
+                    ));
+                }
 
                 if let Some(s) = status {
                     let dim = if selected {
@@ -147,6 +242,19 @@ pub fn draw_menu(
                         spans.push(Span::styled(" [Ongoing]", ongoing_style));
                     }
 
+                    if s.scheduler == SchedulerKind::Fsrs {
+                        spans.push(Span::styled(" [FSRS]", dim));
+                    }
+
+                    if s.due_count > 0 {
+                        let due_style = if selected {
+                            Style::default().fg(Color::Yellow)
+                        } else {
+                            Style::default().fg(Color::Magenta)
+                        };
+                        spans.push(Span::styled(format!(" {} due", s.due_count), due_style));
+                    }
+
                     if s.times_studied > 0 {
                         spans.push(Span::styled(format!(" {}x", s.times_studied), dim));
 
@@ -218,28 +326,27 @@ pub fn draw_menu(
         .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
         .split(chunks[3]);
 
-    let ai_status_content = if ai_enabled {
-        vec![
-            Line::from("AI: Enabled"),
-            Line::from(format!("Model: {}", DEFAULT_MODEL)),
-        ]
-    } else {
-        vec![
-            Line::from("AI: Disabled"),
-            Line::from("Set OPENROUTER_API_KEY"),
-        ]
+    let (ai_status_content, ai_color) = match ai_config.unavailable_reason() {
+        None => {
+            let label = ai_config.provider.label();
+            let (status, color) = match ai_health {
+                AiHealth::Checking => (format!("AI: {} (checking...)", label), Color::Yellow),
+                AiHealth::Ready => (format!("AI: {} ✓", label), Color::Green),
+                AiHealth::Unavailable(reason) => {
+                    (format!("AI: {} ✗ {}", label, reason), Color::Red)
+                }
+            };
+            let model = format!("Model: {}", ai_config.model());
+            (vec![Line::from(status), Line::from(model)], color)
+        }
+        Some(reason) => (
+            vec![Line::from("AI: Offline grading"), Line::from(reason)],
+            Color::Yellow,
+        ),
     };
 
     let ai_status = Paragraph::new(ai_status_content)
-        .style(
-            Style::default()
-                .fg(if ai_enabled {
-                    Color::Green
-                } else {
-                    Color::Yellow
-                })
-                .add_modifier(Modifier::BOLD),
-        )
+        .style(Style::default().fg(ai_color).add_modifier(Modifier::BOLD))
         .alignment(Alignment::Left)
         .block(Block::default().borders(Borders::ALL).title("AI Status"));
     f.render_widget(ai_status, help_chunks[0]);
@@ -261,11 +368,26 @@ pub fn draw_menu(
         }),
     ];
 
+    if focused_panel == 0 {
+        spans.push(Span::styled("←/→", key_style));
+        spans.push(Span::from(" Fold  "));
+        spans.push(Span::styled("r", key_style));
+        spans.push(Span::from(" Review Due  "));
+        spans.push(Span::styled("t", key_style));
+        spans.push(Span::from(" Tag Filter  "));
+        spans.push(Span::styled("s", key_style));
+        spans.push(Span::from(" SM-2/FSRS  "));
+    }
+
     if focused_panel == 1 {
         spans.push(Span::styled("d", key_style));
         spans.push(Span::from(" Delete  "));
     }
 
+    spans.push(Span::styled("g", key_style));
+    spans.push(Span::from(" Generate  "));
+    spans.push(Span::styled("a", key_style));
+    spans.push(Span::from(" AI Settings  "));
     spans.push(Span::styled("Esc", key_style));
     spans.push(Span::from(" Quit"));
 
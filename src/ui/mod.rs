@@ -2,12 +2,16 @@ pub mod chat_popup;
 pub mod layout;
 mod menu;
 mod quiz;
+mod review;
 mod sessions;
+mod settings;
 mod summary;
 
 pub use chat_popup::draw_chat_popup;
 pub use layout::{calculate_quiz_chunks, calculate_summary_chunks};
 pub use menu::{draw_delete_confirmation, draw_menu};
 pub use quiz::{draw_quit_confirmation, draw_quiz};
+pub use review::draw_review;
 pub use sessions::format_session_date;
+pub use settings::draw_settings;
 pub use summary::draw_summary;
@@ -1,4 +1,5 @@
-use crate::models::QuizSession;
+use crate::ai::cited_passages;
+use crate::models::{EvaluationStatus, QuizSession};
 use crate::ui::layout::calculate_quiz_chunks;
 use crate::utils::{calculate_max_scroll, estimate_text_height, render_markdown};
 use ratatui::{
@@ -9,6 +10,24 @@ use ratatui::{
     Frame,
 };
 
+/// Characters of a cited passage shown beneath the explanation
+const EXCERPT_CHARS: usize = 160;
+
+/// The start of a passage on one line, cut at a word when it is longer than
+/// [`EXCERPT_CHARS`].
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= EXCERPT_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(EXCERPT_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(end) => &cut[..end],
+        None => &cut,
+    };
+    format!("{}…", cut)
+}
+
 pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str>) {
     let layout = calculate_quiz_chunks(f.area());
 
@@ -17,20 +36,58 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
         "Question {} / {} - {}",
         session.current_index + 1,
         session.flashcards.len(),
-        session.deck_name
+        session.deck_meta.title.as_deref().unwrap_or(&session.deck_name)
     );
 
-    let header = Paragraph::new(progress)
+    let mut header_spans = vec![Span::from(progress)];
+    if let Some(difficulty) = &flashcard.meta.difficulty {
+        header_spans.push(Span::styled(
+            format!("  [{}]", difficulty),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if !flashcard.meta.tags.is_empty() {
+        let tags: Vec<String> = flashcard.meta.tags.iter().map(|t| format!("#{}", t)).collect();
+        header_spans.push(Span::styled(
+            format!("  {}", tags.join(" ")),
+            Style::default()
+                .fg(Color::Magenta)
+                .remove_modifier(Modifier::BOLD),
+        ));
+    }
+
+    if session.ai_enabled {
+        header_spans.extend(evaluation_status_spans(session));
+    }
+
+    let header = Paragraph::new(Line::from(header_spans))
         .style(
             Style::default()
                 .fg(Color::Cyan)
                 .add_modifier(Modifier::BOLD),
         )
         .alignment(ratatui::layout::Alignment::Center)
-        .block(Block::default().borders(Borders::ALL));
+        .block(Block::default().borders(Borders::ALL).title(Span::styled(
+            session.deck_meta.description.clone().unwrap_or_default(),
+            Style::default().fg(Color::DarkGray),
+        )));
     f.render_widget(header, layout.header_area);
 
-    let question_text = Text::from(flashcard.question.as_str());
+    let mut question_text = Text::from(flashcard.question.as_str());
+    if session.hints_revealed.contains(&session.current_index)
+        && let Some(hint) = &flashcard.meta.hint
+    {
+        question_text.push_line(Line::from(""));
+        question_text.push_line(Line::from(vec![
+            Span::styled(
+                "Hint: ",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(hint.as_str(), Style::default().fg(Color::Yellow)),
+        ]));
+    }
     let question = Paragraph::new(question_text)
         .wrap(Wrap { trim: true })
         .block(Block::default().borders(Borders::ALL).title("Question"));
@@ -51,7 +108,30 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
                 .add_modifier(Modifier::BOLD),
         )));
         text.push_line(Line::from(""));
-        text.push_line(Line::from(flashcard.answer.as_str()));
+        for line in render_markdown(&flashcard.answer) {
+            text.push_line(line);
+        }
+        if !flashcard.meta.accepted_answers.is_empty() {
+            text.push_line(Line::from(Span::styled(
+                format!(
+                    "Also accepted: {}",
+                    flashcard.meta.accepted_answers.join(" / ")
+                ),
+                Style::default().fg(Color::Green),
+            )));
+        }
+        if !flashcard.meta.media.is_empty() {
+            text.push_line(Line::from(Span::styled(
+                format!("Media: {}", flashcard.meta.media.join(", ")),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+        if let Some(source) = &flashcard.meta.source {
+            text.push_line(Line::from(Span::styled(
+                format!("Source: {}", source),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
         if let Some(user_answer) = &flashcard.user_answer {
             text.push_line(Line::from(""));
             text.push_line(Line::from(Span::styled(
@@ -64,10 +144,36 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
         }
 
         // Add AI feedback, error, or loading in the same area
-        if let Some(feedback) = &flashcard.ai_feedback {
+        let failure = match session.evaluation(session.current_index) {
+            EvaluationStatus::Failed(error) => Some(error.as_str()),
+            _ => ai_error,
+        };
+        if let Some(error) = failure {
+            text.push_line(Line::from(""));
+            text.push_line(Line::from(Span::styled(
+                error.to_string(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+
+        if let EvaluationStatus::Pending { explanation, .. } = session.evaluation(session.current_index)
+            && session.ai_enabled
+        {
+            text.push_line(Line::from(""));
+            text.push_line(Line::from("AI is evaluating your answer..."));
+            if !explanation.is_empty() {
+                text.push_line(Line::from(""));
+                text.push_line(Line::from("Explanation:"));
+                text.extend(render_markdown(explanation));
+            }
+        } else if let Some(feedback) = &flashcard.ai_feedback {
             text.push_line(Line::from(""));
             text.push_line(Line::from(Span::styled(
-                "AI Evaluation:",
+                if session.ai_enabled {
+                    "AI Evaluation:"
+                } else {
+                    "Offline Evaluation:"
+                },
                 Style::default().add_modifier(Modifier::BOLD),
             )));
             text.push_line(Line::from(format!(
@@ -95,6 +201,22 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
             let rendered_explanation = render_markdown(&feedback.explanation);
             text.extend(rendered_explanation);
 
+            let passages = session.passages(session.current_index);
+            if session.ai_enabled && !passages.is_empty() {
+                text.push_line(Line::from(""));
+                text.push_line(Line::from("Sources:"));
+                for (number, passage) in cited_passages(&feedback.explanation, passages) {
+                    text.push_line(Line::from(Span::styled(
+                        format!("[{}] {}", number, passage.citation()),
+                        Style::default().fg(Color::Cyan),
+                    )));
+                    text.push_line(Line::from(Span::styled(
+                        excerpt(&passage.text),
+                        Style::default().fg(Color::DarkGray),
+                    )));
+                }
+            }
+
             if !feedback.suggestions.is_empty() {
                 text.push_line(Line::from(""));
                 text.push_line(Line::from("Suggestions:"));
@@ -102,12 +224,6 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
                     text.push_line(Line::from(format!("• {}", suggestion)));
                 }
             }
-        } else if let Some(error) = ai_error {
-            text.push_line(Line::from(""));
-            text.push_line(Line::from(error));
-        } else if session.ai_enabled && session.ai_evaluation_in_progress {
-            text.push_line(Line::from(""));
-            text.push_line(Line::from("AI is evaluating your answer..."));
         }
 
         text
@@ -224,6 +340,21 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
         ),
         Span::from(" Exit App"),
     ];
+    if !session.showing_answer
+        && flashcard.meta.hint.is_some()
+        && !session.hints_revealed.contains(&session.current_index)
+    {
+        ctrl_spans.extend([
+            Span::from("  "),
+            Span::styled(
+                "Ctrl+G",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::from(" Hint"),
+        ]);
+    }
     if session.ai_enabled {
         ctrl_spans.extend([
             Span::from("  "),
@@ -240,7 +371,14 @@ pub fn draw_quiz(f: &mut Frame, session: &mut QuizSession, ai_error: Option<&str
                     .fg(Color::Cyan)
                     .add_modifier(Modifier::BOLD),
             ),
-            Span::from(" Cancel"),
+            Span::from(" Cancel  "),
+            Span::styled(
+                "Ctrl+R",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::from(" Re-evaluate (skip cache)"),
         ]);
         if session.showing_answer {
             let has_feedback = session.flashcards[session.current_index]
@@ -326,3 +464,46 @@ pub fn draw_quit_confirmation(f: &mut Frame) {
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(help, chunks[2]);
 }
+
+/// The current card's evaluation status and, when any, the size of the evaluation queue
+/// and the session's AI usage so far.
+fn evaluation_status_spans(session: &QuizSession) -> Vec<Span<'static>> {
+    let flashcard = &session.flashcards[session.current_index];
+    let mut spans = Vec::new();
+    let status = match (session.evaluation(session.current_index), &flashcard.ai_feedback) {
+        (EvaluationStatus::Pending { .. }, _) => Some(("  ⟳ evaluating", Color::Yellow)),
+        (EvaluationStatus::Failed(_), _) => Some(("  ✗ evaluation failed", Color::Red)),
+        (EvaluationStatus::Idle, Some(_)) => Some(("  ✓ evaluated", Color::Green)),
+        (EvaluationStatus::Idle, None) => None,
+        (EvaluationStatus::Cached, _) => Some(("  ✓ evaluated (cached)", Color::Green)),
+    };
+    if let Some((label, color)) = status {
+        spans.push(Span::styled(label, Style::default().fg(color)));
+    }
+
+    let pending = session.evaluations_pending();
+    let failed = session.evaluations_failed();
+    if pending > 0 || failed > 0 {
+        spans.push(Span::styled(
+            format!("  (AI: {} pending, {} failed)", pending, failed),
+            Style::default()
+                .fg(Color::DarkGray)
+                .remove_modifier(Modifier::BOLD),
+        ));
+    }
+    if session.ai_usage.calls > 0 {
+        spans.push(Span::styled(
+            format!("  [{}]", session.ai_usage),
+            Style::default()
+                .fg(Color::DarkGray)
+                .remove_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(reason) = &session.ai_unavailable {
+        spans.push(Span::styled(
+            format!("  ⚠ AI unavailable: {}", reason),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    spans
+}
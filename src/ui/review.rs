@@ -0,0 +1,224 @@
+use crate::ingest::review::EditField;
+use crate::ingest::{CardDecision, GenerationReview, ReviewStage};
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+fn help_line<'a>(keys: &[(&'a str, &'a str)]) -> Line<'a> {
+    let key_style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    for (key, action) in keys {
+        spans.push(Span::styled(*key, key_style));
+        spans.push(Span::from(format!(" {}  ", action)));
+    }
+    Line::from(spans)
+}
+
+/// The document review screen: first a prompt for the document's path, then the
+/// progress through its passages, the proposed cards with what was decided for each, and
+/// the selected card with its citation.
+pub fn draw_review(f: &mut Frame, review: &GenerationReview) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let title = match review.stage {
+        ReviewStage::ChoosingFile | ReviewStage::Reading => {
+            "Generate a deck from a document".to_string()
+        }
+        _ => format!("Cards from {} → {}.md", review.document, review.deck_name),
+    };
+    let title = Paragraph::new(title)
+        .style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let message = review
+        .message
+        .as_deref()
+        .map(|m| Line::styled(m, Style::default().fg(Color::Red)));
+
+    if matches!(
+        review.stage,
+        ReviewStage::ChoosingFile | ReviewStage::Reading
+    ) {
+        let prompt = Paragraph::new(vec![
+            Line::from("Path of a text, Markdown or PDF file:"),
+            Line::from(""),
+            Line::styled(
+                format!("{}█", review.path_input),
+                Style::default().fg(Color::Yellow),
+            ),
+        ])
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(prompt, chunks[1]);
+
+        let help = match review.stage {
+            ReviewStage::Reading => Line::from("Reading the document..."),
+            _ => message.unwrap_or_else(|| help_line(&[("Enter", "Generate"), ("Esc", "Back")])),
+        };
+        let help = Paragraph::new(help)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(help, chunks[2]);
+        return;
+    }
+
+    let body = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(chunks[1]);
+
+    let state = match review.stage {
+        ReviewStage::Generating => "generating",
+        _ => "done",
+    };
+    let mut label = format!(
+        "Passage {}/{}, {} cards, {} accepted ({})",
+        review.chunks_done,
+        review.chunks_total,
+        review.cards.len(),
+        review.count(CardDecision::Accepted),
+        state
+    );
+    if review.failed_chunks > 0 {
+        label.push_str(&format!(", {} passages failed", review.failed_chunks));
+    }
+    let ratio = match review.chunks_total {
+        0 => 1.0,
+        total => review.chunks_done as f64 / total as f64,
+    };
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL))
+        .gauge_style(Style::default().fg(Color::Cyan))
+        .ratio(ratio.min(1.0))
+        .label(label);
+    f.render_widget(gauge, body[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(body[1]);
+
+    let items: Vec<ListItem> = review
+        .cards
+        .iter()
+        .map(|card| {
+            let (mark, color) = match card.decision {
+                CardDecision::Pending => ("[ ]", Color::White),
+                CardDecision::Accepted => ("[✓]", Color::Green),
+                CardDecision::Rejected => ("[✗]", Color::DarkGray),
+            };
+            let mut spans = vec![
+                Span::styled(format!("{} ", mark), Style::default().fg(color)),
+                Span::styled(card.card.question.clone(), Style::default().fg(color)),
+            ];
+            if !card.card.quote_verified {
+                spans.push(Span::styled(" ⚠", Style::default().fg(Color::Yellow)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let waiting = items.is_empty();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Proposed cards")
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        );
+    let mut list_state = ListState::default();
+    if !waiting {
+        list_state.select(Some(review.selected));
+    }
+    f.render_stateful_widget(list, columns[0], &mut list_state);
+
+    let label_style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let editing = |field: EditField| match &review.editing {
+        Some((f, text)) if *f == field => Some(format!("{}█", text)),
+        _ => None,
+    };
+    let mut lines = Vec::new();
+    if let Some(card) = review.cards.get(review.selected) {
+        let card = &card.card;
+        lines.push(Line::styled("Question", label_style));
+        lines.push(match editing(EditField::Question) {
+            Some(text) => Line::styled(text, Style::default().fg(Color::Yellow)),
+            None => Line::from(card.question.clone()),
+        });
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Answer", label_style));
+        match editing(EditField::Answer) {
+            Some(text) => lines.push(Line::styled(text, Style::default().fg(Color::Yellow))),
+            None => lines.extend(card.answer.lines().map(|l| Line::from(l.to_string()))),
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Source", label_style));
+        lines.push(Line::styled(
+            card.citation(&review.document),
+            Style::default().fg(Color::Gray),
+        ));
+        if !card.quote_verified {
+            lines.push(Line::styled(
+                "⚠ The quote is not in the passage; check the card against the document",
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+    } else if review.is_generating() {
+        lines.push(Line::from("Waiting for the first cards..."));
+    } else {
+        lines.push(Line::from("No cards were generated."));
+    }
+    if let Some(text) = editing(EditField::DeckName) {
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Deck name", label_style));
+        lines.push(Line::styled(text, Style::default().fg(Color::Yellow)));
+    }
+    let detail = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Card"));
+    f.render_widget(detail, columns[1]);
+
+    let help = match (message, &review.editing) {
+        (Some(message), _) => message,
+        (None, Some(_)) => help_line(&[("Enter", "Next / Apply"), ("Esc", "Cancel")]),
+        (None, None) => help_line(&[
+            ("↑/↓", "Navigate"),
+            ("a", "Accept"),
+            ("x", "Reject"),
+            ("A", "Accept rest"),
+            ("e", "Edit"),
+            ("n", "Deck name"),
+            ("s", "Save"),
+            ("Esc", "Back"),
+        ]),
+    };
+    let help = Paragraph::new(help)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(help, chunks[2]);
+}
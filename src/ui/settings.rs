@@ -0,0 +1,126 @@
+use crate::ai::templates::PromptKind;
+use crate::settings::{SettingsField, SettingsForm};
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+    Frame,
+};
+
+fn kind_title(kind: PromptKind) -> &'static str {
+    match kind {
+        PromptKind::Evaluation => "Answer evaluation",
+        PromptKind::Assessment => "Session assessment",
+        PromptKind::Chat => "Follow-up chat",
+        PromptKind::Generation => "Card generation",
+    }
+}
+
+/// The AI settings screen: one row per setting, grouped by the kind of request, with
+/// unset values showing the default that applies in their place.
+pub fn draw_settings(f: &mut Frame, form: &SettingsForm) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(if form.changed {
+        "AI Settings (unsaved changes)"
+    } else {
+        "AI Settings"
+    })
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )
+    .alignment(Alignment::Center)
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let heading = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let mut items = Vec::new();
+    for (i, field) in SettingsField::all().into_iter().enumerate() {
+        match field {
+            SettingsField::Model(kind) => {
+                items.push(ListItem::new(Line::styled(kind_title(kind), heading)));
+            }
+            SettingsField::FallbackModels => {
+                items.push(ListItem::new(Line::styled("Fallback", heading)));
+            }
+            _ => {}
+        }
+
+        let selected = i == form.selected;
+        let label_style = if selected {
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let mut spans = vec![Span::styled(
+            format!("  {:<18}", field.label()),
+            label_style,
+        )];
+        match &form.editing {
+            Some(text) if selected => {
+                spans.push(Span::styled(
+                    format!("{}█", text),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            _ => match form.value(field) {
+                value if value.is_empty() => spans.push(Span::styled(
+                    format!("{} (default)", form.default_value(field)),
+                    Style::default().fg(Color::DarkGray),
+                )),
+                value => spans.push(Span::from(value)),
+            },
+        }
+        items.push(ListItem::new(Line::from(spans)));
+    }
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(list, chunks[1]);
+
+    let key_style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let help = match (&form.error, &form.editing) {
+        (Some(error), _) => Line::styled(error.as_str(), Style::default().fg(Color::Red)),
+        (None, Some(_)) => Line::from(vec![
+            Span::styled("Enter", key_style),
+            Span::from(" Apply  "),
+            Span::styled("Esc", key_style),
+            Span::from(" Cancel  (leave empty for the default)"),
+        ]),
+        (None, None) => Line::from(vec![
+            Span::styled("↑/↓", key_style),
+            Span::from(" Navigate  "),
+            Span::styled("Enter", key_style),
+            Span::from(" Edit  "),
+            Span::styled("Del", key_style),
+            Span::from(" Reset  "),
+            Span::styled("s", key_style),
+            Span::from(" Save  "),
+            Span::styled("Esc", key_style),
+            Span::from(" Back"),
+        ]),
+    };
+    let help = Paragraph::new(help)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(help, chunks[2]);
+}
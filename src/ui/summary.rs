@@ -51,10 +51,47 @@ pub fn draw_summary(f: &mut Frame, session: &mut QuizSession) {
                 .add_modifier(Modifier::BOLD),
         ),
     ]));
+    let failed = session.evaluations_failed();
+    if failed > 0 {
+        assessment_text.push_line(Line::from(Span::styled(
+            format!("AI evaluation failed for {} answers", failed),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    if session.ai_usage.calls > 0 {
+        assessment_text.push_line(Line::from(Span::styled(
+            format!(
+                "AI usage: {}, {} ms per call",
+                session.ai_usage,
+                session.ai_usage.average_latency_ms()
+            ),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    let cached = session.evaluations_cached();
+    if cached > 0 {
+        assessment_text.push_line(Line::from(Span::styled(
+            format!("{} answers graded from the evaluation cache", cached),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    if let Some(reason) = &session.ai_unavailable {
+        assessment_text.push_line(Line::from(Span::styled(
+            format!("AI unavailable: {}", reason),
+            Style::default().fg(Color::Red),
+        )));
+    }
     assessment_text.push_line(Line::from(""));
 
     if session.assessment_loading {
-        let loading_text = Paragraph::new("Analyzing session...")
+        // The assessment is requested once the evaluation queue has drained
+        let pending = session.evaluations_pending();
+        let message = if pending > 0 {
+            format!("Waiting for {} AI evaluations...", pending)
+        } else {
+            "Analyzing session...".to_string()
+        };
+        let loading_text = Paragraph::new(message)
             .style(
                 Style::default()
                     .fg(Color::Yellow)
@@ -174,9 +211,13 @@ pub fn draw_summary(f: &mut Frame, session: &mut QuizSession) {
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(help_text, layout.assessment_help);
     } else {
-        let no_assessment = Paragraph::new("No analysis available")
-            .style(Style::default().fg(Color::DarkGray))
-            .alignment(Alignment::Center)
+        // Without the AI the local stats are the whole summary
+        assessment_text.push_line(Line::from(Span::styled(
+            "No analysis available",
+            Style::default().fg(Color::DarkGray),
+        )));
+        let no_assessment = Paragraph::new(assessment_text)
+            .wrap(Wrap { trim: true })
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(no_assessment, layout.assessment_content);
 
@@ -2,6 +2,7 @@ use ratatui::text::Text;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub mod markdown;
+pub mod text;
 pub use markdown::render_markdown;
 
 /// Convert a byte index to a character index within a string.
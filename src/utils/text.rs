@@ -0,0 +1,104 @@
+/// Collapse whitespace and lowercase text so trivial edits (extra spaces,
+/// capitalisation, trailing newline) do not change a card's identity.
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Stable 64-bit FNV-1a hash of the normalized question and answer, as hex.
+///
+/// Unlike `std::hash::DefaultHasher` the result is identical across builds,
+/// so it can be persisted in the database.
+pub fn content_hash(question: &str, answer: &str) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let content = format!(
+        "{}\u{1f}{}",
+        normalize_text(question),
+        normalize_text(answer)
+    );
+    let hash = content.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Levenshtein edit distance between two strings, counted in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() {
+        return b.len();
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+/// Similarity of two texts after normalization, from 0.0 (unrelated) to 1.0 (identical).
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let a = normalize_text(a);
+    let b = normalize_text(b);
+    let max_len = a.chars().count().max(b.chars().count());
+    if max_len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f32 / max_len as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash_ignores_whitespace_and_case() {
+        assert_eq!(
+            content_hash("What is  Rust?", "A language"),
+            content_hash("what is rust?\n", "a LANGUAGE")
+        );
+        assert_ne!(
+            content_hash("What is Rust?", "A language"),
+            content_hash("What is Rust?", "A game")
+        );
+        // Field boundary is part of the hash
+        assert_ne!(content_hash("ab", "c"), content_hash("a", "bc"));
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash("", ""), "af63d24c8601db8e");
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("Same", "same "), 1.0);
+        assert!(
+            similarity(
+                "What is the capital of France?",
+                "What's the capital of France?"
+            ) > 0.85
+        );
+        assert!(similarity("What is Rust?", "Who wrote Hamlet?") < 0.5);
+    }
+}